@@ -0,0 +1,46 @@
+"""Time chat_completion with a large message list against a local mock server.
+
+The server listens on a unix socket so TCP delayed-ACK stalls don't drown out the
+client-side cost being measured.
+
+Run from the repository root after building the extension:
+
+    python python/benchmarks/bench_large_context.py [messages] [calls]
+"""
+import os
+import statistics
+import sys
+import tempfile
+import time
+
+sys.path.insert(0, os.path.join(os.path.dirname(__file__), "..", "tests"))
+
+from conftest import MockServer  # noqa: E402
+from secure_openaiapi import SecureClient, SecureMessage  # noqa: E402
+
+
+def main(message_count=5000, calls=20):
+    messages = [
+        SecureMessage(b"user" if i % 2 else b"assistant", [{"type": "text", "text": b"x" * 400}])
+        for i in range(message_count)
+    ]
+    socket_path = os.path.join(tempfile.mkdtemp(), "bench.sock")
+    server = MockServer(unix_path=socket_path).start()
+    try:
+        client = SecureClient(f"unix://{socket_path}".encode(), b"bench-key")
+        client.chat_completion(messages, model="bench")
+        timings = []
+        for _ in range(calls):
+            started = time.perf_counter()
+            client.chat_completion(messages, model="bench")
+            timings.append(time.perf_counter() - started)
+    finally:
+        server.stop()
+    print(
+        f"{message_count} messages x {calls} calls: "
+        f"median {statistics.median(timings) * 1000:.1f} ms, min {min(timings) * 1000:.1f} ms"
+    )
+
+
+if __name__ == "__main__":
+    main(*(int(arg) for arg in sys.argv[1:]))
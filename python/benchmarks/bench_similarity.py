@@ -0,0 +1,94 @@
+"""Time top_k over SecureFloatArray vectors against a numpy baseline.
+
+The corpus comes from a mock embeddings endpoint, base64-encoded as the API sends it.
+numpy computes the same cosine scores over a plain float32 matrix; without numpy the
+baseline is pure Python.
+
+Run from the repository root after building the extension:
+
+    python python/benchmarks/bench_similarity.py [vectors] [dimensions] [rounds]
+"""
+import base64
+import json
+import math
+import os
+import random
+import statistics
+import struct
+import sys
+import time
+
+sys.path.insert(0, os.path.join(os.path.dirname(__file__), "..", "tests"))
+
+from conftest import MockServer  # noqa: E402
+from secure_openaiapi import SecureClient, top_k  # noqa: E402
+
+
+def embed(values):
+    """Fetches `values` back as SecureFloatArrays through the embeddings endpoint."""
+
+    def handler(request):
+        inputs = json.loads(request["body"])["input"]
+        data = [
+            {"index": i, "embedding": base64.b64encode(struct.pack(f"<{len(values[int(text)])}f", *values[int(text)])).decode()}
+            for i, text in enumerate(inputs)
+        ]
+        return 200, {}, json.dumps({"data": data}).encode()
+
+    server = MockServer(handler).start()
+    try:
+        client = SecureClient(server.base_url.encode(), b"bench-key", allow_insecure_http=True)
+        inputs = [str(i) for i in range(len(values))]
+        return client.embeddings(inputs, "bench", encoding_format="base64", secure_output=True).embeddings
+    finally:
+        server.stop()
+
+
+def timed(f, rounds):
+    timings = []
+    for _ in range(rounds):
+        started = time.perf_counter()
+        result = f()
+        timings.append(time.perf_counter() - started)
+    return statistics.median(timings), result
+
+
+def main(count=10000, dimensions=1536, rounds=10):
+    rng = random.Random(0)
+    values = [[rng.uniform(-1, 1) for _ in range(dimensions)] for _ in range(count + 1)]
+    arrays = embed(values)
+    query, corpus = arrays[0], arrays[1:]
+
+    ours, hits = timed(lambda: top_k(query, corpus, 10), rounds)
+    print(f"top_k over {count} x {dimensions}: median {ours * 1000:.1f} ms")
+
+    try:
+        import numpy
+    except ImportError:
+        numpy = None
+    if numpy is not None:
+        matrix = numpy.array(values[1:], dtype=numpy.float32)
+        q = numpy.array(values[0], dtype=numpy.float32)
+
+        def baseline():
+            scores = matrix @ q / (numpy.linalg.norm(matrix, axis=1) * numpy.linalg.norm(q))
+            best = numpy.argpartition(-scores, 10)[:10]
+            return best[numpy.argsort(-scores[best])]
+
+        label = "numpy"
+    else:
+        q = values[0]
+        q_norm = math.sqrt(sum(x * x for x in q))
+
+        def baseline():
+            scores = [sum(x * y for x, y in zip(q, v)) / (q_norm * math.sqrt(sum(y * y for y in v))) for v in values[1:]]
+            return sorted(range(count), key=lambda i: -scores[i])[:10]
+
+        label = "pure Python"
+    theirs, best = timed(baseline, max(1, rounds // 5) if numpy is None else rounds)
+    assert [index for index, _ in hits] == [int(index) for index in best]
+    print(f"{label} baseline: median {theirs * 1000:.1f} ms ({theirs / ours:.1f}x top_k)")
+
+
+if __name__ == "__main__":
+    main(*(int(arg) for arg in sys.argv[1:]))
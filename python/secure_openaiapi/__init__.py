@@ -1,6 +1,94 @@
 """
 A secure Python wrapper for OpenAI-compatible APIs, implemented in Rust.
 """
-from .secure_openaiapi import SecureClient, SecureBytes, SecureMessage
+from .secure_openaiapi import (
+    AsyncSecureClient,
+    SecureClient,
+    SecureClientRouter,
+    SecureConversation,
+    SecureBytes,
+    SecureEmbeddings,
+    SecureFloatArray,
+    SecureMessage,
+    SecureRawResponse,
+    SecureResponse,
+    SecureTemplate,
+    SecureToolCall,
+    HmacSigner,
+    APIError,
+    BadRequestError,
+    AuthenticationError,
+    PermissionDeniedError,
+    NotFoundError,
+    RateLimitError,
+    InternalServerError,
+    ResponseTooLargeError,
+    TruncatedResponseError,
+    ContentFilterError,
+    RefusalError,
+    ToolCallNotSupportedError,
+    PrivacyModeError,
+    ModerationBlockedError,
+    DeadlineExceededError,
+    MemoryLockError,
+    MemoryCorruptionError,
+    disable_core_dumps,
+    set_fork_policy,
+    strict_memory,
+    memory_report,
+    set_canaries,
+    derive_key,
+    encrypt_keyfile,
+    load_keyfile,
+    store_in_keyring,
+    image_to_data_url,
+    top_k,
+    enable_logging,
+    verify_audit_log,
+)
 
-__all__ = ["SecureClient", "SecureBytes", "SecureMessage"]
+__all__ = [
+    "AsyncSecureClient",
+    "SecureClient",
+    "SecureClientRouter",
+    "SecureConversation",
+    "SecureBytes",
+    "SecureEmbeddings",
+    "SecureFloatArray",
+    "SecureMessage",
+    "SecureRawResponse",
+    "SecureResponse",
+    "SecureTemplate",
+    "SecureToolCall",
+    "HmacSigner",
+    "APIError",
+    "BadRequestError",
+    "AuthenticationError",
+    "PermissionDeniedError",
+    "NotFoundError",
+    "RateLimitError",
+    "InternalServerError",
+    "ResponseTooLargeError",
+    "TruncatedResponseError",
+    "ContentFilterError",
+    "RefusalError",
+    "ToolCallNotSupportedError",
+    "PrivacyModeError",
+    "ModerationBlockedError",
+    "DeadlineExceededError",
+    "MemoryLockError",
+    "MemoryCorruptionError",
+    "disable_core_dumps",
+    "set_fork_policy",
+    "strict_memory",
+    "memory_report",
+    "set_canaries",
+    "derive_key",
+    "encrypt_keyfile",
+    "load_keyfile",
+    "store_in_keyring",
+    "image_to_data_url",
+    "top_k",
+    "enable_logging",
+    "verify_audit_log",
+]
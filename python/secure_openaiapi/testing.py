@@ -0,0 +1,6 @@
+"""
+Helpers for testing code built on secure_openaiapi without a real provider.
+"""
+from .secure_openaiapi import MockOpenAIServer
+
+__all__ = ["MockOpenAIServer"]
@@ -0,0 +1,165 @@
+import json
+import queue
+import socket
+import socketserver
+import threading
+from http.server import BaseHTTPRequestHandler, ThreadingHTTPServer
+
+import pytest
+
+from secure_openaiapi import SecureClient, SecureMessage
+from secure_openaiapi import secure_openaiapi as native
+
+# `_corrupt_canary` and `_locked_memory_contains` only exist in builds with the crate's
+# test-hooks feature: maturin develop --features test-hooks.
+needs_test_hooks = pytest.mark.skipif(
+    not hasattr(native, "_locked_memory_contains"), reason="needs a build with the test-hooks feature"
+)
+
+
+def completion_body(content="Hello!", **extra):
+    body = {
+        "id": "chatcmpl-test",
+        "object": "chat.completion",
+        "choices": [{"index": 0, "message": {"role": "assistant", "content": content}, "finish_reason": "stop"}],
+    }
+    body.update(extra)
+    return json.dumps(body).encode()
+
+
+def default_handler(request):
+    return 200, {}, completion_body()
+
+
+class MockServer:
+    """A tiny local HTTP server imitating an OpenAI-compatible endpoint.
+
+    The server runs on a background thread; the client releases the GIL while it
+    waits on the network, so both sides make progress. `handler` receives the
+    recorded request dict and returns (status, headers, body bytes). The body may
+    also be an iterable of byte chunks, each sent and flushed as it is produced.
+    """
+
+    def __init__(self, handler=default_handler, unix_path=None):
+        self._handler = handler
+        self._unix_path = unix_path
+        if unix_path:
+            self._socket = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+            self._socket.bind(unix_path)
+        else:
+            self._socket = socket.socket(socket.AF_INET, socket.SOCK_STREAM)
+            self._socket.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEADDR, 1)
+            self._socket.bind(("127.0.0.1", 0))
+        self._socket.listen(64)
+        self._queue = queue.SimpleQueue()
+        self._httpd = None
+        self._thread = threading.Thread(target=self._serve, daemon=True)
+        self._requests = []
+        self._ready = threading.Event()
+
+    @property
+    def base_url(self):
+        if self._unix_path:
+            return f"unix://{self._unix_path}"
+        host, port = self._socket.getsockname()
+        return f"http://{host}:{port}"
+
+    @property
+    def requests(self):
+        while not self._queue.empty():
+            self._requests.append(self._queue.get())
+        return self._requests
+
+    def json_body(self, index=-1):
+        return json.loads(self.requests[index]["body"])
+
+    def start(self):
+        self._thread.start()
+        self._ready.wait()
+        return self
+
+    def stop(self):
+        self._httpd.shutdown()
+        self._thread.join()
+        self._socket.close()
+
+    def _serve(self):
+        handler, queue = self._handler, self._queue
+
+        class Handler(BaseHTTPRequestHandler):
+            protocol_version = "HTTP/1.1"
+
+            def log_message(self, *args):
+                pass
+
+            def _handle(self):
+                length = int(self.headers.get("Content-Length", 0))
+                body = self.rfile.read(length) if length else b""
+                request = {
+                    "method": self.command,
+                    "path": self.path,
+                    "headers": {k.lower(): v for k, v in self.headers.items()},
+                    "body": body,
+                }
+                queue.put(request)
+                status, headers, payload = handler(request)
+                self.send_response(status)
+                for name, value in headers.items():
+                    self.send_header(name, value)
+                if not isinstance(payload, bytes):
+                    self.send_header("Transfer-Encoding", "chunked")
+                    self.end_headers()
+                    try:
+                        for chunk in payload:
+                            self.wfile.write(b"%x\r\n%s\r\n" % (len(chunk), chunk))
+                            self.wfile.flush()
+                        self.wfile.write(b"0\r\n\r\n")
+                    except (BrokenPipeError, ConnectionResetError):
+                        # The client hung up mid-stream, which some tests do on purpose.
+                        self.close_connection = True
+                    return
+                chunked = headers.get("Transfer-Encoding") == "chunked"
+                if not chunked and not any(name.lower() == "content-length" for name in headers):
+                    self.send_header("Content-Length", str(len(payload)))
+                self.end_headers()
+                if self.command == "HEAD":
+                    return
+                if chunked:
+                    self.wfile.write(b"%x\r\n%s\r\n0\r\n\r\n" % (len(payload), payload))
+                else:
+                    self.wfile.write(payload)
+
+            do_GET = do_POST = do_HEAD = _handle
+
+        server_class = socketserver.ThreadingUnixStreamServer if self._unix_path else ThreadingHTTPServer
+        httpd = server_class(self._socket.getsockname(), Handler, bind_and_activate=False)
+        httpd.socket.close()
+        httpd.socket = self._socket
+        httpd.daemon_threads = True
+        self._httpd = httpd
+        self._ready.set()
+        httpd.serve_forever()
+
+
+@pytest.fixture
+def mock_server():
+    """Factory fixture: `mock_server(handler)` starts a server answering with `handler`."""
+    servers = []
+
+    def start(handler=default_handler, unix_path=None):
+        server = MockServer(handler, unix_path).start()
+        servers.append(server)
+        return server
+
+    yield start
+    for server in servers:
+        server.stop()
+
+
+def client_for(server, api_key=b"test-key", client_class=SecureClient, **kwargs):
+    """A client talking plain HTTP to `server`, one of `mock_server`'s or anything with a `base_url`."""
+    return client_class(server.base_url.encode(), api_key, allow_insecure_http=True, **kwargs)
+
+
+def user_message(text=b"Hi"):
+    return SecureMessage(b"user", [{"type": "text", "text": text}])
@@ -0,0 +1,213 @@
+import json
+
+import pytest
+
+from conftest import user_message
+from secure_openaiapi import AsyncSecureClient, BadRequestError, SecureBytes, SecureClient, SecureMessage, TruncatedResponseError
+
+MESSAGE_RESPONSE = json.dumps(
+    {
+        "id": "msg_013Zva2CMHLNnXjNJJKqJ2EF",
+        "type": "message",
+        "role": "assistant",
+        "model": "claude-sonnet-4-20250514",
+        "content": [{"type": "text", "text": "Hello! "}, {"type": "text", "text": "How can I help?"}],
+        "stop_reason": "end_turn",
+        "stop_sequence": None,
+        "usage": {"input_tokens": 21, "cache_read_input_tokens": 4, "output_tokens": 9},
+    }
+).encode()
+
+STREAM_EVENTS = [
+    (
+        "message_start",
+        {
+            "type": "message_start",
+            "message": {
+                "id": "msg_stream",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-sonnet-4-20250514",
+                "content": [],
+                "stop_reason": None,
+                "usage": {"input_tokens": 25, "output_tokens": 1},
+            },
+        },
+    ),
+    ("content_block_start", {"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+    ("ping", {"type": "ping"}),
+    ("content_block_delta", {"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Hel"}}),
+    ("content_block_delta", {"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "lo!"}}),
+    ("content_block_stop", {"type": "content_block_stop", "index": 0}),
+    ("message_delta", {"type": "message_delta", "delta": {"stop_reason": "max_tokens", "stop_sequence": None}, "usage": {"output_tokens": 15}}),
+    ("message_stop", {"type": "message_stop"}),
+]
+
+
+def event_stream(events):
+    return b"".join(f"event: {name}\ndata: {json.dumps(data)}\n\n".encode() for name, data in events)
+
+
+def anthropic_client(server, **kwargs):
+    return SecureClient(server.base_url.encode(), b"sk-ant-test", anthropic=True, allow_insecure_http=True, **kwargs)
+
+
+def messages(*extra):
+    return [
+        SecureMessage(b"system", [{"type": "text", "text": b"Be brief."}]),
+        user_message(),
+        *extra,
+    ]
+
+
+def test_request_is_translated(mock_server):
+    server = mock_server(lambda request: (200, {"request-id": "req_011CQ"}, MESSAGE_RESPONSE))
+    client = anthropic_client(server)
+    image = SecureMessage(
+        b"user",
+        [
+            {"type": "text", "text": b"What is in these?"},
+            {"type": "image_url", "image_url": {"url": b"data:image/png;base64,iVBORw0KGgo="}},
+            {"type": "image_url", "image_url": {"url": b"https://example.com/cat.jpg"}},
+        ],
+    )
+    reply = SecureMessage(b"assistant", [{"type": "text", "text": b"Hello."}])
+    client.chat_completion(messages(reply, image), "claude-sonnet-4-20250514", stop="END", user="user-42", top_k=5)
+
+    request = server.requests[0]
+    assert request["path"] == "/v1/messages"
+    assert request["headers"]["x-api-key"] == "sk-ant-test"
+    assert request["headers"]["anthropic-version"] == "2023-06-01"
+    assert "authorization" not in request["headers"]
+    assert client.last_request_id() == "req_011CQ"
+    assert server.json_body() == {
+        "model": "claude-sonnet-4-20250514",
+        "system": [{"type": "text", "text": "Be brief."}],
+        "messages": [
+            {"role": "user", "content": [{"type": "text", "text": "Hi"}]},
+            {"role": "assistant", "content": [{"type": "text", "text": "Hello."}]},
+            {
+                "role": "user",
+                "content": [
+                    {"type": "text", "text": "What is in these?"},
+                    {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "iVBORw0KGgo="}},
+                    {"type": "image", "source": {"type": "url", "url": "https://example.com/cat.jpg"}},
+                ],
+            },
+        ],
+        "max_tokens": 4096,
+        "stop_sequences": ["END"],
+        "metadata": {"user_id": "user-42"},
+        "top_k": 5,
+    }
+
+
+def test_max_tokens_is_passed_on(mock_server):
+    server = mock_server(lambda request: (200, {}, MESSAGE_RESPONSE))
+    client = anthropic_client(server)
+    client.chat_completion(messages(), "claude-test", max_tokens=64)
+    client.chat_completion(messages(), "claude-test", max_completion_tokens=32)
+    assert server.json_body(0)["max_tokens"] == 64
+    assert server.json_body(1)["max_tokens"] == 32
+    anthropic_client(server).chat_completion(messages()[1:], "claude-test")
+    assert "system" not in server.json_body(2)
+
+
+def test_response_is_mapped(mock_server):
+    server = mock_server(lambda request: (200, {}, MESSAGE_RESPONSE))
+    response = anthropic_client(server).chat_completion_full(messages(), "claude-test")
+    assert bytes(response.content) == b"Hello! How can I help?"
+    assert response.finish_reason == "stop"
+    assert response.id == "msg_013Zva2CMHLNnXjNJJKqJ2EF"
+    assert response.model == "claude-sonnet-4-20250514"
+    assert response.usage["prompt_tokens"] == 21
+    assert response.usage["completion_tokens"] == 9
+    assert response.usage["total_tokens"] == 30
+    assert response.usage["cached_tokens"] == 4
+
+
+def test_stream_is_assembled(mock_server):
+    server = mock_server(lambda request: (200, {"Content-Type": "text/event-stream"}, event_stream(STREAM_EVENTS)))
+    client = anthropic_client(server)
+    response = client.chat_completion_full(messages(), "claude-test", stream=True)
+    assert server.json_body()["stream"] is True
+    assert bytes(response.content) == b"Hello!"
+    assert response.id == "msg_stream"
+    assert response.finish_reason == "length"
+    assert (response.usage["prompt_tokens"], response.usage["completion_tokens"]) == (25, 15)
+    with pytest.raises(TruncatedResponseError):
+        client.chat_completion(messages(), "claude-test", stream=True, strict=True)
+
+    error = [("error", {"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}})]
+    server = mock_server(lambda request: (200, {"Content-Type": "text/event-stream"}, event_stream(STREAM_EVENTS[:2] + error)))
+    with pytest.raises(IOError, match="mid-stream.*Overloaded"):
+        anthropic_client(server).chat_completion(messages(), "claude-test", stream=True)
+
+
+def test_error_responses_are_mapped(mock_server):
+    body = b'{"type":"error","error":{"type":"invalid_request_error","message":"max_tokens: must be positive"}}'
+    server = mock_server(lambda request: (400, {}, body))
+    with pytest.raises(BadRequestError, match="type invalid_request_error; server message withheld") as info:
+        anthropic_client(server).chat_completion(messages(), "claude-test")
+    assert info.value.type == "invalid_request_error"
+    with pytest.raises(BadRequestError, match="max_tokens: must be positive"):
+        anthropic_client(server, include_error_body=True).chat_completion(messages(), "claude-test")
+
+
+def test_redirects_never_carry_the_key_to_another_origin(mock_server):
+    target = mock_server(lambda request: (200, {}, MESSAGE_RESPONSE))
+    origin = mock_server(lambda request: (307, {"Location": target.base_url + request["path"]}, b""))
+    client = anthropic_client(origin, follow_redirects=3, default_headers={"X-Gateway-Secret": SecureBytes(b"gw-secret")})
+
+    with pytest.raises(IOError, match="another origin"):
+        client.chat_completion(messages(), "claude-test")
+    assert origin.requests[0]["headers"]["x-api-key"] == "sk-ant-test"
+    assert origin.requests[0]["headers"]["x-gateway-secret"] == "gw-secret"
+    assert target.requests == []
+
+
+@pytest.mark.parametrize(
+    "call, match",
+    [
+        (lambda c: c.chat_completion(messages(), "claude-test", logit_bias={"50256": -100}), "'logit_bias' is not supported"),
+        (lambda c: c.chat_completion(messages(), "claude-test", n=2), "n other than 1 is not supported"),
+        (lambda c: c.chat_completion(messages(), "claude-test", tools=[]), "'tools' is not supported"),
+        (lambda c: c.chat_completion([SecureMessage(b"tool", [{"type": "text", "text": b"42"}])], "claude-test"), "role 'tool'"),
+        (
+            lambda c: c.chat_completion(
+                [SecureMessage(b"system", [{"type": "image_url", "image_url": {"url": b"https://x/y.png"}}])], "claude-test"
+            ),
+            "system messages can only hold text",
+        ),
+        (
+            lambda c: c.chat_completion(
+                [SecureMessage(b"user", [{"type": "image_url", "image_url": {"url": b"data:image/png,raw"}}])], "claude-test"
+            ),
+            "must be base64-encoded",
+        ),
+    ],
+)
+def test_unsupported_requests_fail_locally(mock_server, call, match):
+    server = mock_server()
+    with pytest.raises(ValueError, match=match):
+        call(anthropic_client(server))
+    assert server.requests == []
+
+
+def test_anthropic_mode_validation():
+    url = b"https://api.anthropic.com"
+    with pytest.raises(ValueError, match="cannot be combined with provider 'groq'"):
+        SecureClient(url, b"sk-ant-test", anthropic=True, provider="groq")
+    with pytest.raises(ValueError, match="does not report system_fingerprint"):
+        SecureClient(url, b"sk-ant-test", anthropic=True, on_fingerprint_change=print)
+    SecureClient(url, b"sk-ant-test", provider="anthropic")
+    assert isinstance(AsyncSecureClient(url, b"sk-ant-test", anthropic=True), AsyncSecureClient)
+
+
+def test_default_system_prompt_goes_first(mock_server):
+    server = mock_server(lambda request: (200, {}, MESSAGE_RESPONSE))
+    client = anthropic_client(server, system_prompt=b"Follow the policy.")
+    client.chat_completion(messages()[1:], "claude-test", max_tokens=5)
+    assert server.json_body()["system"] == [{"type": "text", "text": "Follow the policy."}]
+    client.chat_completion(messages(), "claude-test", max_tokens=5)
+    assert server.json_body()["system"] == [{"type": "text", "text": "Be brief."}]
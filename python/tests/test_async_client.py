@@ -0,0 +1,135 @@
+import asyncio
+import json
+import time
+
+import pytest
+
+from conftest import client_for, completion_body, user_message
+from secure_openaiapi import AsyncSecureClient, SecureBytes
+
+
+def test_chat_completion_resolves_to_secure_bytes(mock_server):
+    server = mock_server()
+    client = client_for(server, client_class=AsyncSecureClient, default_model="gpt-test", defaults={"temperature": 0.2})
+
+    async def main():
+        return await client.chat_completion([user_message()], extra_headers={"X-Trace": "1"}, max_tokens=5)
+
+    content = asyncio.run(main())
+    assert isinstance(content, SecureBytes)
+    assert bytes(content) == b"Hello!"
+    assert server.json_body() == {
+        "messages": [{"role": "user", "content": "Hi"}],
+        "model": "gpt-test",
+        "temperature": 0.2,
+        "max_tokens": 5,
+    }
+    request = server.requests[-1]
+    assert request["headers"]["authorization"] == "Bearer test-key"
+    assert request["headers"]["x-trace"] == "1"
+
+
+def test_requests_run_concurrently(mock_server):
+    def slow_handler(request):
+        time.sleep(0.4)
+        return 200, {}, completion_body()
+
+    client = client_for(mock_server(slow_handler), client_class=AsyncSecureClient)
+
+    async def main():
+        calls = [client.chat_completion([user_message()], model="gpt-test") for _ in range(5)]
+        return await asyncio.gather(*calls)
+
+    started = time.monotonic()
+    results = asyncio.run(main())
+    assert [bytes(r) for r in results] == [b"Hello!"] * 5
+    assert time.monotonic() - started < 1.5
+
+
+def test_embeddings_resolve_in_input_order(mock_server):
+    def handler(request):
+        inputs = json.loads(request["body"])["input"]
+        data = [{"index": index, "embedding": [float(len(text))]} for index, text in enumerate(inputs)]
+        return 200, {}, json.dumps({"data": data, "usage": {"prompt_tokens": 1, "total_tokens": 1}}).encode()
+
+    client = client_for(mock_server(handler), client_class=AsyncSecureClient)
+
+    async def main():
+        return await client.embeddings(["a", "bb", "ccc"], "text-embedding-3-small", batch_size=2, secure_output=True)
+
+    result = asyncio.run(main())
+    assert [vector.tolist() for vector in result.embeddings] == [[1.0], [2.0], [3.0]]
+    assert result.requests == 2 and result.usage == {"prompt_tokens": 2, "total_tokens": 2}
+
+
+def test_cancellation_aborts_the_request(mock_server):
+    def slow_handler(request):
+        time.sleep(3)
+        return 200, {}, completion_body()
+
+    server = mock_server(slow_handler)
+    client = client_for(server, client_class=AsyncSecureClient)
+
+    async def main():
+        task = asyncio.ensure_future(client.chat_completion([user_message()], model="gpt-test"))
+        await asyncio.sleep(0.2)
+        task.cancel()
+        with pytest.raises(asyncio.CancelledError):
+            await task
+
+    started = time.monotonic()
+    asyncio.run(main())
+    assert time.monotonic() - started < 1
+    assert len(server.requests) == 1
+
+
+def test_errors_are_raised_from_the_awaitable(mock_server):
+    client = client_for(mock_server(lambda request: (500, {}, b"boom")), client_class=AsyncSecureClient)
+
+    async def main():
+        with pytest.raises(IOError, match="status 500"):
+            await client.chat_completion([user_message()], model="gpt-test")
+        with pytest.raises(ValueError, match="model is required"):
+            client.chat_completion([user_message()])
+
+    asyncio.run(main())
+
+
+def test_async_context_manager_closes_client(mock_server):
+    client = client_for(mock_server(), client_class=AsyncSecureClient)
+
+    async def main():
+        async with client as entered:
+            assert entered is client
+            await client.chat_completion([user_message()], model="gpt-test")
+
+    asyncio.run(main())
+    assert client.closed
+    with pytest.raises(RuntimeError, match="closed"):
+        client.last_request_id()
+
+
+def test_per_request_timeout(mock_server):
+    def slow_handler(request):
+        time.sleep(1)
+        return 200, {}, completion_body()
+
+    client = client_for(mock_server(slow_handler), client_class=AsyncSecureClient)
+
+    async def main():
+        with pytest.raises(TimeoutError, match="per-request timeout"):
+            await client.chat_completion([user_message()], model="gpt-test", timeout=0.2)
+
+    asyncio.run(main())
+
+
+def test_warm_up(mock_server):
+    server = mock_server()
+    client = client_for(server, client_class=AsyncSecureClient)
+
+    async def main():
+        await client.warm_up()
+
+    asyncio.run(main())
+    assert server.requests[-1]["method"] == "HEAD"
+    assert client.stats()["requests"] == 1
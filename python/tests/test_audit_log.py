@@ -0,0 +1,117 @@
+import json
+import os
+import stat
+
+import pytest
+
+from conftest import client_for, completion_body, user_message
+from secure_openaiapi import AsyncSecureClient, InternalServerError, SecureBytes, verify_audit_log
+
+KEY = SecureBytes(bytes(range(32)))
+SECRET_TEXT = b"audit-marker-91c2 do not leak"
+
+
+def write_log(mock_server, path, calls=3):
+    usage = {"prompt_tokens": 3, "completion_tokens": 5, "total_tokens": 8}
+    server = mock_server(lambda request: (200, {"x-request-id": "req_audit"}, completion_body("Answer 42", usage=usage)))
+    client = client_for(server, api_key=b"sk-audit-test")
+    client.enable_audit_log(str(path), KEY)
+    for _ in range(calls):
+        client.chat_completion([user_message(SECRET_TEXT)], "gpt-test")
+    client.close()
+
+
+def entries(path):
+    return [json.loads(line) for line in path.read_text().splitlines()]
+
+
+def test_entries_are_chained_and_verify(mock_server, tmp_path):
+    path = tmp_path / "audit.log"
+    write_log(mock_server, path)
+    first, second, third = entries(path)
+    assert (first["seq"], first["model"], first["status"], first["request_id"]) == (1, "gpt-test", 200, "req_audit")
+    assert first["endpoint"].endswith("/chat/completions")
+    assert (first["prompt_tokens"], first["completion_tokens"], first["total_tokens"], first["error"]) == (3, 5, 8, None)
+    assert first["prev"] == "00" * 32
+    assert (second["prev"], third["prev"]) == (first["mac"], second["mac"])
+    assert len(first["request_hash"]) == len(first["response_hash"]) == 64
+    if os.name == "posix":
+        assert stat.S_IMODE(path.stat().st_mode) == 0o600
+
+    report = verify_audit_log(str(path), KEY)
+    assert report == {"valid": True, "entries": 3, "broken_line": None, "reason": None, "last_mac": third["mac"]}
+
+
+def test_log_holds_no_content(mock_server, tmp_path):
+    path = tmp_path / "audit.log"
+    write_log(mock_server, path, calls=1)
+    text = path.read_bytes()
+    for secret in (SECRET_TEXT, b"Answer 42", b"sk-audit-test"):
+        assert secret not in text
+
+
+def test_failed_calls_are_logged(mock_server, tmp_path):
+    path = tmp_path / "audit.log"
+    server = mock_server(lambda request: (500, {}, b'{"error": {"message": "boom"}}'))
+    client = client_for(server, api_key=b"sk-audit-test", max_retries=0)
+    client.enable_audit_log(path, KEY)
+    with pytest.raises(InternalServerError):
+        client.chat_completion([user_message(SECRET_TEXT)], "gpt-test")
+    [entry] = entries(path)
+    assert (entry["status"], entry["error"]) == (500, "InternalServerError")
+    assert verify_audit_log(path, KEY)["valid"]
+
+
+def edit_field(lines):
+    entry = json.loads(lines[1])
+    lines[1] = lines[1].replace(f'"model":"{entry["model"]}"', '"model":"gpt-other"')
+    return lines
+
+
+@pytest.mark.parametrize(
+    "tamper, broken_line, reason",
+    [
+        (edit_field, 2, "MAC mismatch"),
+        (lambda lines: lines[:1] + lines[2:], 2, "chain broken"),
+        (lambda lines: [lines[1], lines[0], lines[2]], 1, "chain broken"),
+        (lambda lines: lines[:2] + ["garbage"], 3, "not an audit log entry"),
+    ],
+)
+def test_tampering_is_detected(mock_server, tmp_path, tamper, broken_line, reason):
+    path = tmp_path / "audit.log"
+    write_log(mock_server, path)
+    path.write_text("\n".join(tamper(path.read_text().splitlines())) + "\n")
+    report = verify_audit_log(path, KEY)
+    assert not report["valid"]
+    assert (report["broken_line"], report["entries"]) == (broken_line, broken_line - 1)
+    assert report["reason"].startswith(reason)
+
+
+def test_wrong_key_fails_verification(mock_server, tmp_path):
+    path = tmp_path / "audit.log"
+    write_log(mock_server, path)
+    report = verify_audit_log(path, SecureBytes(bytes(32)))
+    assert (report["valid"], report["broken_line"]) == (False, 1)
+    with pytest.raises(ValueError, match="hmac_key must be 32 bytes"):
+        verify_audit_log(path, SecureBytes(b"short"))
+
+
+def test_existing_log_is_continued_or_refused(mock_server, tmp_path):
+    path = tmp_path / "audit.log"
+    write_log(mock_server, path, calls=2)
+    write_log(mock_server, path, calls=1)
+    assert [entry["seq"] for entry in entries(path)] == [1, 2, 3]
+    assert verify_audit_log(path, KEY)["valid"]
+
+    path.write_text(path.read_text().replace('"seq":2', '"seq":7'))
+    client = client_for(mock_server(), api_key=b"sk-audit-test")
+    with pytest.raises(ValueError, match="fails verification at line 2"):
+        client.enable_audit_log(path, KEY)
+    with pytest.raises(ValueError, match="hmac_key must be 32 bytes"):
+        client.enable_audit_log(tmp_path / "other.log", SecureBytes(b"short"))
+
+
+def test_async_client_forwards_enable_audit_log(mock_server, tmp_path):
+    client = AsyncSecureClient(mock_server().base_url.encode(), b"sk-audit-test", allow_insecure_http=True)
+    client.enable_audit_log(tmp_path / "audit.log", KEY)
+    assert (tmp_path / "audit.log").exists()
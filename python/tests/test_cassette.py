@@ -0,0 +1,103 @@
+import json
+import os
+from types import SimpleNamespace
+
+import pytest
+
+from conftest import client_for, completion_body, user_message
+from secure_openaiapi import SecureBytes
+
+EVENT_STREAM = {"Content-Type": "text/event-stream"}
+# Nothing listens here, so a replay that went to the network would fail.
+OFFLINE = SimpleNamespace(base_url="http://127.0.0.1:9")
+CLIENT_OPTIONS = {"api_key": b"sk-cassette-key", "default_model": "gpt-test", "max_retries": 0}
+STREAM = (
+    b'data: {"choices":[{"index":0,"delta":{"content":"Str"},"finish_reason":null}]}\n\n'
+    b'data: {"choices":[{"index":0,"delta":{"content":"eamed"},"finish_reason":"stop"}]}\n\n'
+    b"data: [DONE]\n\n"
+)
+
+
+def record(mock_server, path, key):
+    answers = iter([completion_body("First answer"), completion_body("Second answer")])
+
+    def handler(request):
+        if json.loads(request["body"]).get("stream"):
+            return 200, EVENT_STREAM, [STREAM[:40], STREAM[40:]]
+        return 200, {"x-request-id": "req_recorded"}, next(answers)
+
+    server = mock_server(handler)
+    client = client_for(server, **CLIENT_OPTIONS)
+    client.record_to(path, key)
+    assert bytes(client.chat_completion([user_message(b"secret prompt")], temperature=0.2, max_tokens=5)) == b"First answer"
+    assert bytes(client.chat_completion([user_message(b"secret prompt")], temperature=0.2, max_tokens=5)) == b"Second answer"
+    tokens = []
+    client.stream_chat_with_events([user_message(b"stream it")], on_token=lambda token: tokens.append(bytes(token)))
+    assert tokens == [b"Str", b"eamed"]
+
+
+def test_cassette_round_trip_without_network(mock_server, tmp_path):
+    path, key = tmp_path / "chat.cassette", SecureBytes(os.urandom(32))
+    record(mock_server, path, key)
+
+    text = path.read_text()
+    assert "secret prompt" not in text and "answer" not in text and "sk-cassette-key" not in text
+    header, *interactions = [json.loads(line) for line in text.splitlines()]
+    assert header == {"cassette": "secure_openaiapi", "version": 1}
+    assert [(i["method"], i["path"], i["status"]) for i in interactions] == [("POST", "/openai/v1/chat/completions", 200)] * 3
+    assert interactions[0]["request_hash"] == interactions[1]["request_hash"] != interactions[2]["request_hash"]
+    assert interactions[0]["headers"]["x-request-id"] == "req_recorded"
+    assert oct(path.stat().st_mode & 0o777) == "0o600"
+
+    # Parameters given in another order still match.
+    client = client_for(OFFLINE, **CLIENT_OPTIONS)
+    client.replay_from(path, key)
+    assert bytes(client.chat_completion([user_message(b"secret prompt")], max_tokens=5, temperature=0.2)) == b"First answer"
+    assert bytes(client.chat_completion([user_message(b"secret prompt")], max_tokens=5, temperature=0.2)) == b"Second answer"
+    tokens = []
+    response = client.stream_chat_with_events([user_message(b"stream it")], on_token=lambda token: tokens.append(bytes(token)))
+    assert tokens == [b"Str", b"eamed"] and bytes(response.content) == b"Streamed"
+
+
+def test_replay_mismatches_name_both_hashes(mock_server, tmp_path):
+    path, key = tmp_path / "chat.cassette", SecureBytes(os.urandom(32))
+    record(mock_server, path, key)
+    recorded = [json.loads(line)["request_hash"] for line in path.read_text().splitlines()[1:]]
+
+    client = client_for(OFFLINE, **CLIENT_OPTIONS)
+    client.replay_from(path, key)
+    with pytest.raises(ConnectionError) as excinfo:
+        client.chat_completion([user_message(b"a different prompt")])
+    message = str(excinfo.value)
+    assert "no recorded interaction matches POST /openai/v1/chat/completions with request hash" in message
+    assert all(f"{hash} (POST /openai/v1/chat/completions)" in message for hash in recorded)
+    assert "different prompt" not in message
+
+    client.chat_completion([user_message(b"secret prompt")], temperature=0.2, max_tokens=5)
+    client.chat_completion([user_message(b"secret prompt")], temperature=0.2, max_tokens=5)
+    with pytest.raises(ConnectionError, match="expects one of: " + recorded[2]):
+        client.chat_completion([user_message(b"secret prompt")], temperature=0.2, max_tokens=5)
+
+
+def test_cassettes_refuse_wrong_keys_and_foreign_files(mock_server, tmp_path):
+    path, key = tmp_path / "chat.cassette", SecureBytes(os.urandom(32))
+    record(mock_server, path, key)
+    client = client_for(OFFLINE, **CLIENT_OPTIONS)
+    with pytest.raises(ValueError, match="cannot decrypt cassette: wrong key or corrupted file"):
+        client.replay_from(path, SecureBytes(os.urandom(32)))
+    with pytest.raises(ValueError, match="key must be 32 bytes"):
+        client.replay_from(path, SecureBytes(b"short"))
+
+    lines = path.read_text().splitlines()
+    future = tmp_path / "future.cassette"
+    future.write_text("\n".join(['{"cassette": "secure_openaiapi", "version": 2}', *lines[1:]]))
+    with pytest.raises(ValueError, match="unsupported cassette version 2"):
+        client.replay_from(future, key)
+    foreign = tmp_path / "notes.txt"
+    foreign.write_text("just some notes\n")
+    with pytest.raises(ValueError, match="is not a cassette"):
+        client.replay_from(foreign, key)
+    truncated = tmp_path / "truncated.cassette"
+    truncated.write_text("\n".join([*lines[:-1], lines[-1][:50]]))
+    with pytest.raises(ValueError, match="cassette line 4 is not a recorded interaction"):
+        client.replay_from(truncated, key)
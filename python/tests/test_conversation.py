@@ -0,0 +1,127 @@
+import json
+
+import pytest
+
+from conftest import client_for, completion_body
+from secure_openaiapi import APIError, SecureBytes, SecureConversation, SecureMessage
+
+
+def test_send_records_both_turns(mock_server):
+    answers = iter(["Paris.", "About 2.1 million."])
+    server = mock_server(lambda request: (200, {}, completion_body(next(answers))))
+    client = client_for(server, default_model="gpt-test")
+    conversation = SecureConversation(system="Answer briefly.")
+
+    assert bytes(conversation.send(client, "Capital of France?")) == b"Paris."
+    assert bytes(conversation.send(client, SecureBytes(b"Population?"), temperature=0)) == b"About 2.1 million."
+
+    first, second = (json.loads(request["body"]) for request in server.requests)
+    assert first["messages"] == [
+        {"role": "system", "content": "Answer briefly."},
+        {"role": "user", "content": "Capital of France?"},
+    ]
+    assert second["messages"] == first["messages"] + [
+        {"role": "assistant", "content": "Paris."},
+        {"role": "user", "content": "Population?"},
+    ]
+    assert second["temperature"] == 0
+    assert len(conversation) == 5
+    assert all(isinstance(message, SecureMessage) for message in conversation.messages())
+
+
+def test_tool_results_and_manual_turns(mock_server):
+    server = mock_server()
+    conversation = SecureConversation()
+    conversation.add_user(b"What's the weather?")
+    conversation.add_assistant("Let me check.")
+    conversation.add_tool_result("call_1", SecureBytes(b'{"temp": 21}'))
+    client_for(server, default_model="gpt-test").chat_completion(conversation.messages())
+
+    assert server.json_body()["messages"] == [
+        {"role": "user", "content": "What's the weather?"},
+        {"role": "assistant", "content": "Let me check."},
+        {"role": "tool", "content": '{"temp": 21}', "tool_call_id": "call_1"},
+    ]
+    with pytest.raises(TypeError, match="text must be str, bytes or SecureBytes"):
+        conversation.add_user(42)
+    assert len(conversation) == 3
+
+
+def test_failed_send_leaves_history_unchanged(mock_server):
+    server = mock_server(lambda request: (500, {}, b'{"error":{"message":"down"}}'))
+    conversation = SecureConversation()
+    conversation.add_user("Hi")
+    with pytest.raises(APIError):
+        conversation.send(client_for(server, default_model="gpt-test"), "Still there?")
+    assert len(conversation) == 1
+
+
+def test_approx_tokens_and_clear():
+    conversation = SecureConversation(system=b"Be brief.")
+    assert len(conversation) == 1
+    conversation.add_user(b"x" * 40)
+    messages = conversation.messages()
+    assert conversation.approx_tokens() == sum(message.approx_tokens() for message in messages)
+
+    conversation.clear()
+    assert len(conversation) == 0
+    assert conversation.approx_tokens() == 0
+    assert conversation.messages() == []
+    assert all(message.wiped for message in messages)
+
+
+def long_conversation():
+    conversation = SecureConversation(system=b"Be brief.")
+    for index in range(3):
+        conversation.add_user(b"question %d " % index + b"q" * 80)
+        conversation.add_assistant(b"answer %d " % index + b"a" * 80)
+    conversation.add_user(b"latest question")
+    return conversation
+
+
+def test_trim_to_drops_the_oldest_turns():
+    conversation = long_conversation()
+    messages = conversation.messages()
+    budget = sum(message.approx_tokens() for message in [messages[0], *messages[-3:]])
+
+    report = conversation.trim_to(budget)
+    assert report == {"removed": 4, "tokens_before": report["tokens_before"], "tokens_after": budget, "fits": True}
+    assert report["tokens_before"] > budget
+    assert conversation.messages() == [messages[0], *messages[-3:]]
+    assert all(message.wiped for message in messages[1:5])
+    assert conversation.trim_to(budget)["removed"] == 0
+
+    # The system prompt and the latest user turn survive any budget.
+    report = conversation.trim_to(1)
+    assert (report["removed"], report["fits"]) == (2, False)
+    assert conversation.messages() == [messages[0], messages[-1]]
+    with pytest.raises(ValueError, match="Unknown trim strategy 'newest'"):
+        conversation.trim_to(10, "newest")
+
+
+def test_truncate_middle_leaves_one_placeholder(mock_server):
+    conversation = long_conversation()
+    messages = conversation.messages()
+    # Each turn is about 30 tokens and the placeholder 20, so the first trim takes two turns.
+    report = conversation.trim_to(conversation.approx_tokens() - 30, strategy="truncate_middle")
+    assert (report["removed"], report["fits"]) == (2, True)
+    assert conversation.trim_to(conversation.approx_tokens() - 10, strategy="truncate_middle")["removed"] == 1
+    assert len(conversation) == 6
+
+    server = mock_server()
+    conversation.send(client_for(server, default_model="gpt-test"), "one more")
+    sent = server.json_body()["messages"]
+    assert sent[1] == {"role": "user", "content": "[3 earlier messages were removed to fit the context window]"}
+    assert sent[2]["content"] == "answer 1 " + "a" * 80
+    assert all(message.wiped for message in messages[1:4])
+
+
+def test_send_auto_trims_before_sending(mock_server):
+    server = mock_server()
+    conversation = long_conversation()
+    conversation.send(client_for(server, default_model="gpt-test"), "next question", auto_trim=60)
+    sent = server.json_body()["messages"]
+    assert sent[0] == {"role": "system", "content": "Be brief."}
+    assert sent[-1] == {"role": "user", "content": "next question"}
+    assert len(sent) < 9
+    assert "auto_trim" not in server.json_body()
@@ -0,0 +1,94 @@
+import os
+import stat
+
+import pytest
+
+from secure_openaiapi import SecureBytes, derive_key, encrypt_keyfile, load_keyfile
+
+# The cheapest costs libsodium allows, so the tests stay fast.
+FAST = {"ops_limit": 1, "mem_limit": 8192}
+SALT = bytes(range(16))
+
+
+def test_derive_key_is_deterministic():
+    key = derive_key(SecureBytes(b"correct horse"), SALT, **FAST)
+    assert isinstance(key, SecureBytes)
+    assert len(bytes(key)) == 32
+    assert bytes(derive_key(SecureBytes(b"correct horse"), SALT, **FAST)) == bytes(key)
+    assert bytes(derive_key(SecureBytes(b"correct horse"), bytes(16), **FAST)) != bytes(key)
+    assert bytes(derive_key(SecureBytes(b"battery staple"), SALT, **FAST)) != bytes(key)
+    assert bytes(derive_key(SecureBytes(b"correct horse"), SALT, ops_limit="interactive", mem_limit=8192)) != bytes(key)
+
+
+@pytest.mark.parametrize(
+    "salt, costs, message",
+    [
+        (b"short", FAST, "salt must be 16 bytes"),
+        (SALT, {"ops_limit": "extreme"}, "ops_limit must be 'interactive', 'moderate', 'sensitive' or an integer"),
+        (SALT, {"ops_limit": 0, "mem_limit": 8192}, "ops_limit must be between"),
+        (SALT, {"ops_limit": 1, "mem_limit": 1024}, "mem_limit must be between"),
+    ],
+)
+def test_derive_key_validates_arguments(salt, costs, message):
+    with pytest.raises(ValueError, match=message):
+        derive_key(SecureBytes(b"passphrase"), salt, **costs)
+
+
+def test_keyfile_round_trip(tmp_path):
+    path = tmp_path / "api.key"
+    encrypt_keyfile(str(path), SecureBytes(b"sk-live-secret"), SecureBytes(b"passphrase"), **FAST)
+    assert b"sk-live-secret" not in path.read_bytes()
+    assert path.read_bytes().startswith(b"SOAKEY\x01")
+    if os.name == "posix":
+        assert stat.S_IMODE(path.stat().st_mode) == 0o600
+
+    secret = load_keyfile(path, SecureBytes(b"passphrase"))
+    assert isinstance(secret, SecureBytes)
+    assert bytes(secret) == b"sk-live-secret"
+
+    # A fresh salt and nonce every time.
+    first = path.read_bytes()
+    encrypt_keyfile(path, SecureBytes(b"sk-live-secret"), SecureBytes(b"passphrase"), **FAST)
+    assert path.read_bytes() != first
+
+
+def flip(offset):
+    def corrupt(data):
+        data[offset] ^= 1
+        return data
+
+    return corrupt
+
+
+@pytest.mark.parametrize(
+    "passphrase, corrupt",
+    [
+        (b"wrong passphrase", lambda data: data),
+        (b"passphrase", flip(-1)),
+        (b"passphrase", flip(6)),
+        (b"passphrase", flip(7)),
+        (b"passphrase", flip(14)),
+        (b"passphrase", flip(15)),
+        (b"passphrase", flip(30)),
+        (b"passphrase", lambda data: data[:40]),
+        (b"passphrase", lambda data: b"not a keyfile"),
+        (b"passphrase", lambda data: data[:7] + b"\xff" * 16 + data[23:]),
+    ],
+)
+def test_keyfile_failures_are_indistinguishable(tmp_path, passphrase, corrupt):
+    path = tmp_path / "api.key"
+    encrypt_keyfile(path, SecureBytes(b"sk-live-secret"), SecureBytes(b"passphrase"), **FAST)
+    path.write_bytes(bytes(corrupt(bytearray(path.read_bytes()))))
+    with pytest.raises(ValueError) as error:
+        load_keyfile(path, SecureBytes(passphrase))
+    assert str(error.value) == "cannot decrypt keyfile: wrong passphrase or corrupted file"
+
+
+def test_keyfile_costs_are_capped(tmp_path):
+    with pytest.raises(ValueError, match="keyfiles allow at most ops_limit=64"):
+        encrypt_keyfile(tmp_path / "api.key", SecureBytes(b"secret"), SecureBytes(b"passphrase"), ops_limit=65, mem_limit=8192)
+
+
+def test_load_keyfile_missing_file(tmp_path):
+    with pytest.raises(FileNotFoundError):
+        load_keyfile(tmp_path / "missing.key", SecureBytes(b"passphrase"))
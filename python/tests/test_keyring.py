@@ -0,0 +1,63 @@
+import os
+import sys
+
+import pytest
+
+from secure_openaiapi import AsyncSecureClient, SecureBytes, SecureClient, store_in_keyring
+from secure_openaiapi import secure_openaiapi as native
+
+needs_memory_keyring = pytest.mark.skipif(
+    not hasattr(native, "_use_memory_keyring"), reason="needs a build with the test-hooks feature on macOS or Linux"
+)
+
+
+@pytest.fixture
+def memory_keyring():
+    native._use_memory_keyring(True)
+    yield
+    native._use_memory_keyring(False)
+
+
+@needs_memory_keyring
+def test_store_and_load(memory_keyring):
+    store_in_keyring("openai", "dev@example.com", SecureBytes(b"sk-from-keyring"))
+    secret = SecureBytes.from_keyring("openai", "dev@example.com")
+    assert isinstance(secret, SecureBytes)
+    assert bytes(secret) == b"sk-from-keyring"
+
+    store_in_keyring("openai", "dev@example.com", SecureBytes(b"sk-rotated"))
+    assert bytes(SecureBytes.from_keyring("openai", "dev@example.com")) == b"sk-rotated"
+
+
+@needs_memory_keyring
+def test_missing_entry_raises_key_error(memory_keyring):
+    with pytest.raises(KeyError, match="service 'openai', account 'nobody'"):
+        SecureBytes.from_keyring("openai", "nobody")
+
+
+@needs_memory_keyring
+def test_client_from_keyring(memory_keyring, mock_server):
+    store_in_keyring("openai", "dev", SecureBytes(b"sk-client-key"))
+    server = mock_server()
+    client = SecureClient.from_keyring(server.base_url.encode(), "openai", "dev", allow_insecure_http=True)
+    assert bytes(client.chat_completion([{"role": "user", "content": "Hi"}], "gpt-test")) == b"Hello!"
+    assert server.requests[-1]["headers"]["authorization"] == "Bearer sk-client-key"
+    assert isinstance(AsyncSecureClient.from_keyring(server.base_url.encode(), "openai", "dev", allow_insecure_http=True), AsyncSecureClient)
+    with pytest.raises(KeyError):
+        SecureClient.from_keyring(server.base_url.encode(), "openai", "missing")
+
+
+@pytest.mark.skipif(
+    os.name != "posix" or sys.platform == "darwin", reason="points the Secret Service client at a missing bus"
+)
+def test_keyring_failure_raises_os_error(tmp_path):
+    saved = os.environ.get("DBUS_SESSION_BUS_ADDRESS")
+    os.environ["DBUS_SESSION_BUS_ADDRESS"] = f"unix:path={tmp_path / 'no-bus'}"
+    try:
+        with pytest.raises(OSError, match="keyring error"):
+            SecureBytes.from_keyring("openai", "dev")
+    finally:
+        if saved is None:
+            os.environ.pop("DBUS_SESSION_BUS_ADDRESS")
+        else:
+            os.environ["DBUS_SESSION_BUS_ADDRESS"] = saved
@@ -0,0 +1,95 @@
+import logging
+
+import pytest
+
+from conftest import client_for, completion_body, user_message
+from secure_openaiapi import InternalServerError, enable_logging
+
+SECRET_TEXT = b"log-marker-7f3a do not leak"
+API_KEY = b"sk-log-test-key-0123456789"
+
+
+class ListHandler(logging.Handler):
+    def __init__(self):
+        super().__init__()
+        self.records = []
+
+    def emit(self, record):
+        self.records.append(record)
+
+
+@pytest.fixture
+def records():
+    handler = ListHandler()
+    logger = logging.getLogger("secure_openaiapi")
+    logger.addHandler(handler)
+    yield handler.records
+    logger.removeHandler(handler)
+    # Logging stays enabled for the rest of the session; keep it quiet.
+    logger.setLevel(logging.CRITICAL)
+
+
+def assert_no_secrets(record, *secrets):
+    rendered = repr(vars(record)) + record.getMessage()
+    for secret in secrets:
+        assert secret not in rendered
+
+
+def test_enable_logging_returns_the_logger():
+    logger = enable_logging("debug")
+    assert logger is logging.getLogger("secure_openaiapi")
+    assert logger.level == logging.DEBUG
+    assert enable_logging(logging.WARNING).level == logging.WARNING
+    logger.setLevel(logging.CRITICAL)
+
+
+def test_successful_call_is_logged(mock_server, records):
+    usage = {"prompt_tokens": 3, "completion_tokens": 5, "total_tokens": 8}
+    server = mock_server(lambda request: (200, {"x-request-id": "req_log"}, completion_body("Answer 42", usage=usage)))
+    enable_logging()
+    client_for(server, api_key=API_KEY).chat_completion([user_message(SECRET_TEXT)], "gpt-test")
+
+    [record] = records
+    assert record.levelno == logging.INFO
+    assert record.endpoint.endswith("/chat/completions")
+    assert record.getMessage().startswith(f"{record.endpoint} model=gpt-test status=200 latency=")
+    assert record.getMessage().endswith("attempts=1: ok")
+    assert (record.model, record.status, record.attempts) == ("gpt-test", 200, 1)
+    assert (record.prompt_tokens, record.completion_tokens, record.total_tokens) == (3, 5, 8)
+    assert record.request_id == "req_log"
+    assert record.error is None
+    assert record.latency > 0
+    assert not hasattr(record, "request_headers")
+    assert_no_secrets(record, SECRET_TEXT.decode(), API_KEY.decode(), "Answer 42")
+
+
+def test_debug_adds_header_names_only(mock_server, records):
+    server = mock_server(lambda request: (200, {"x-request-id": "req_log", "x-custom": "header-value"}, completion_body()))
+    enable_logging("DEBUG")
+    client_for(server, api_key=API_KEY, default_headers={"X-Tenant": b"tenant-secret"}).chat_completion([user_message(SECRET_TEXT)], "gpt-test")
+
+    [record] = records
+    assert "authorization" in record.request_headers
+    assert "x-tenant" in record.request_headers
+    assert "x-custom" in record.response_headers
+    assert_no_secrets(record, SECRET_TEXT.decode(), API_KEY.decode(), "tenant-secret", "header-value")
+
+
+def test_failures_and_retries_are_logged(mock_server, records):
+    statuses = iter([429, 500])
+    server = mock_server(lambda request: (next(statuses), {"retry-after-ms": "1"}, b'{"error": {"message": "echo log-marker-7f3a"}}'))
+    enable_logging()
+    with pytest.raises(InternalServerError):
+        client_for(server, api_key=API_KEY, max_retries=1).chat_completion([user_message(SECRET_TEXT)], "gpt-test")
+
+    [record] = records
+    assert record.levelno == logging.WARNING
+    assert (record.status, record.attempts, record.error) == (500, 2, "InternalServerError")
+    assert_no_secrets(record, "log-marker-7f3a", API_KEY.decode())
+
+
+def test_nothing_is_logged_below_the_level(mock_server, records):
+    server = mock_server()
+    enable_logging("WARNING")
+    client_for(server, api_key=API_KEY).chat_completion([user_message(SECRET_TEXT)], "gpt-test")
+    assert records == []
@@ -0,0 +1,109 @@
+import asyncio
+import hashlib
+import json
+import threading
+
+import pytest
+
+from conftest import completion_body
+from secure_openaiapi import AsyncSecureClient, InternalServerError, SecureClient, SecureMessage
+
+
+def content_hash(text):
+    return "blake2b:" + hashlib.blake2b(text, digest_size=32).hexdigest()
+
+
+def test_requests_reach_the_handler_with_hashed_content():
+    requests = []
+
+    def handler(request):
+        requests.append(request)
+        return 200, {}, completion_body("Hello!")
+
+    client = SecureClient.with_mock_transport(handler, api_key=b"sk-secret-key")
+    messages = [{"role": "system", "content": "Be brief."}, SecureMessage(b"user", [{"type": "text", "text": b"Hi"}, {"type": "image_url", "image_url": {"url": b"data:image/png;base64,AAAA"}}])]
+    response = client.chat_completion_full(messages, "gpt-test", temperature=0)
+    assert bytes(response.content) == b"Hello!"
+    [request] = requests
+    assert (request["method"], request["path"]) == ("POST", "/openai/v1/chat/completions")
+    assert "authorization" not in request["headers"] and request["headers"]["content-type"] == "application/json"
+    assert "sk-secret-key" not in json.dumps(request)
+    body = request["body"]
+    assert (body["model"], body["temperature"]) == ("gpt-test", 0)
+    assert body["messages"] == [
+        {"role": "system", "content": content_hash(b"Be brief.")},
+        {
+            "role": "user",
+            "content": [
+                {"type": "text", "text": content_hash(b"Hi")},
+                {"type": "image_url", "image_url": {"url": content_hash(b"data:image/png;base64,AAAA")}},
+            ],
+        },
+    ]
+
+
+def test_retries_and_error_redaction_run_against_the_handler():
+    statuses = iter([503, 200])
+
+    def handler(request):
+        status = next(statuses)
+        return status, {"Retry-After": "0"}, completion_body() if status == 200 else b"{}"
+
+    client = SecureClient.with_mock_transport(handler, max_retries=1)
+    assert bytes(client.chat_completion([{"role": "user", "content": "Hi"}], "gpt-test")) == b"Hello!"
+
+    envelope = {"error": {"message": "down, key sk-leaked-0123456789abcdef", "type": "server_error"}}
+    client = SecureClient.with_mock_transport(lambda request: (500, {}, json.dumps(envelope).encode()), max_retries=0)
+    with pytest.raises(InternalServerError) as excinfo:
+        client.chat_completion([{"role": "user", "content": "Hi"}], "gpt-test")
+    assert "down" in str(excinfo.value) and "sk-leaked-0123456789abcdef" not in str(excinfo.value)
+
+
+def test_handler_exceptions_fail_the_request():
+    def unreachable(request):
+        raise ConnectionRefusedError("nobody home")
+
+    def slow(request):
+        raise TimeoutError
+
+    with pytest.raises(ConnectionError, match="nobody home"):
+        SecureClient.with_mock_transport(unreachable, max_retries=0).chat_completion([{"role": "user", "content": "Hi"}], "gpt-test")
+    with pytest.raises(TimeoutError, match="timed out"):
+        SecureClient.with_mock_transport(slow, max_retries=0).chat_completion([{"role": "user", "content": "Hi"}], "gpt-test")
+    with pytest.raises(TypeError, match="handler must be callable"):
+        SecureClient.with_mock_transport("not a handler")
+
+
+def test_streams_are_pulled_from_the_handler_as_they_are_read():
+    first_token = threading.Event()
+    waited = []
+
+    def event(text):
+        return b"data: " + json.dumps({"choices": [{"index": 0, "delta": {"content": text}, "finish_reason": None}]}).encode() + b"\n\n"
+
+    def chunks():
+        yield event("Hel")
+        # The rest is only produced once the client has seen the first token.
+        waited.append(first_token.wait(5))
+        yield event("lo!") + b"data: [DONE]\n\n"
+
+    client = SecureClient.with_mock_transport(lambda request: (200, {"Content-Type": "text/event-stream"}, chunks()))
+    tokens = []
+
+    def on_token(token):
+        tokens.append(bytes(token))
+        first_token.set()
+
+    response = client.stream_chat_with_events([{"role": "user", "content": "Hi"}], "gpt-test", on_token=on_token)
+    assert waited == [True]
+    assert tokens == [b"Hel", b"lo!"] and bytes(response.content) == b"Hello!"
+
+
+def test_async_client_with_mock_transport():
+    client = AsyncSecureClient.with_mock_transport(lambda request: (200, {}, completion_body("Async!")))
+
+    async def main():
+        return await client.chat_completion([{"role": "user", "content": "Hi"}], "gpt-test")
+
+    content = asyncio.run(main())
+    assert bytes(content) == b"Async!"
@@ -0,0 +1,354 @@
+import pytest
+
+from conftest import client_for, user_message
+from secure_openaiapi import (
+    AsyncSecureClient,
+    AuthenticationError,
+    BadRequestError,
+    ContentFilterError,
+    NotFoundError,
+    PrivacyModeError,
+    SecureBytes,
+    SecureClient,
+)
+
+# Responses as the providers send them, trimmed to one choice.
+RECORDED = {
+    "openai": b'{"id":"chatcmpl-B9MBs8CjcvOU2jLn4n570S5qMJKcT","object":"chat.completion","created":1741569952,'
+    b'"model":"gpt-4o-2024-08-06","choices":[{"index":0,"message":{"role":"assistant","content":"Hello! How can I help?",'
+    b'"refusal":null,"annotations":[]},"logprobs":null,"finish_reason":"stop"}],"usage":{"prompt_tokens":19,'
+    b'"completion_tokens":10,"total_tokens":29,"prompt_tokens_details":{"cached_tokens":0,"audio_tokens":0},'
+    b'"completion_tokens_details":{"reasoning_tokens":0,"audio_tokens":0}},"service_tier":"default",'
+    b'"system_fingerprint":"fp_fc9f1d7035"}',
+    "groq": b'{"id":"chatcmpl-f51b2cd2-bef7-417e-964e-a08f0b513c22","object":"chat.completion","created":1730241104,'
+    b'"model":"llama3-8b-8192","choices":[{"index":0,"message":{"role":"assistant","content":"Hello! How can I help?"},'
+    b'"logprobs":null,"finish_reason":"stop"}],"usage":{"queue_time":0.037493756,"prompt_tokens":18,"prompt_time":0.000680594,'
+    b'"completion_tokens":7,"completion_time":0.463333333,"total_tokens":25,"total_time":0.464013927},'
+    b'"system_fingerprint":"fp_179b0f92c9","x_groq":{"id":"req_01jbd6g2qdfw2adyrt2az8hz4w"}}',
+    "openrouter": b'{"id":"gen-1741570160-3dLVBU8fjLhJ7f0C7bPm","provider":"OpenAI","model":"openai/gpt-4o","object":"chat.completion",'
+    b'"created":1741570160,"choices":[{"logprobs":null,"finish_reason":"stop","native_finish_reason":"stop","index":0,'
+    b'"message":{"role":"assistant","content":"Hello! How can I help?","refusal":null}}],'
+    b'"usage":{"prompt_tokens":19,"completion_tokens":10,"total_tokens":29}}',
+    "together": b'{"id":"8f4a3e2c1b9d7a6e-AMS","object":"chat.completion","created":1741570210,'
+    b'"model":"meta-llama/Llama-3.3-70B-Instruct-Turbo","prompt":[],"choices":[{"finish_reason":"eos","seed":1349348161,'
+    b'"logprobs":null,"index":0,"message":{"role":"assistant","content":"Hello! How can I help?","tool_calls":[]}}],'
+    b'"usage":{"prompt_tokens":41,"completion_tokens":10,"total_tokens":51}}',
+    "fireworks": b'{"id":"1e8d0c3a-8a4b-4f3e-9a1d-2b6c7d8e9f00","object":"chat.completion","created":1741570266,'
+    b'"model":"accounts/fireworks/models/llama-v3p1-8b-instruct","choices":[{"index":0,'
+    b'"message":{"role":"assistant","content":"Hello! How can I help?"},"finish_reason":"stop"}],'
+    b'"usage":{"prompt_tokens":17,"total_tokens":27,"completion_tokens":10}}',
+    "deepseek": b'{"id":"930c60df-bf64-41c9-a88e-3ec75f81e00e","object":"chat.completion","created":1741570301,'
+    b'"model":"deepseek-chat","choices":[{"index":0,"message":{"role":"assistant","content":"Hello! How can I help?"},'
+    b'"logprobs":null,"finish_reason":"stop"}],"usage":{"prompt_tokens":11,"completion_tokens":10,"total_tokens":21,'
+    b'"prompt_tokens_details":{"cached_tokens":0},"prompt_cache_hit_tokens":0,"prompt_cache_miss_tokens":11},'
+    b'"system_fingerprint":"fp_3a5770e1b4_prod0225"}',
+}
+
+# Groq reports the usage of a stream in `x_groq` on the last chunk.
+RECORDED_GROQ_STREAM = (
+    b'data: {"id":"chatcmpl-4c7a","object":"chat.completion.chunk","created":1730241104,"model":"llama3-8b-8192",'
+    b'"system_fingerprint":"fp_179b0f92c9","choices":[{"index":0,"delta":{"role":"assistant","content":""},'
+    b'"logprobs":null,"finish_reason":null}],"x_groq":{"id":"req_01jbd6g2qdfw2adyrt2az8hz4w"}}\n\n'
+    b'data: {"id":"chatcmpl-4c7a","object":"chat.completion.chunk","created":1730241104,"model":"llama3-8b-8192",'
+    b'"system_fingerprint":"fp_179b0f92c9","choices":[{"index":0,"delta":{"content":"Hello!"},"logprobs":null,'
+    b'"finish_reason":null}]}\n\n'
+    b'data: {"id":"chatcmpl-4c7a","object":"chat.completion.chunk","created":1730241104,"model":"llama3-8b-8192",'
+    b'"system_fingerprint":"fp_179b0f92c9","choices":[{"index":0,"delta":{},"logprobs":null,"finish_reason":"stop"}],'
+    b'"x_groq":{"id":"req_01jbd6g2qdfw2adyrt2az8hz4w","usage":{"queue_time":0.02,"prompt_tokens":18,'
+    b'"prompt_time":0.0007,"completion_tokens":3,"completion_time":0.002,"total_tokens":21,"total_time":0.0027}}}\n\n'
+    b"data: [DONE]\n\n"
+)
+
+# Together sends the usage next to the last choice rather than in a chunk of its own.
+RECORDED_TOGETHER_STREAM = (
+    b'data: {"id":"8f4a3e2c1b9d7a6e-AMS","object":"chat.completion.chunk","created":1741570210,'
+    b'"model":"meta-llama/Llama-3.3-70B-Instruct-Turbo","choices":[{"index":0,"text":"Hello!","logprobs":null,'
+    b'"finish_reason":null,"seed":null,"delta":{"token_id":9906,"role":"assistant","content":"Hello!","tool_calls":null}}],'
+    b'"usage":null}\n\n'
+    b'data: {"id":"8f4a3e2c1b9d7a6e-AMS","object":"chat.completion.chunk","created":1741570210,'
+    b'"model":"meta-llama/Llama-3.3-70B-Instruct-Turbo","choices":[{"index":0,"text":"","logprobs":null,'
+    b'"finish_reason":"eos","seed":1349348161,"delta":{"token_id":128009,"role":"assistant","content":"","tool_calls":null}}],'
+    b'"usage":{"prompt_tokens":41,"completion_tokens":3,"total_tokens":44}}\n\n'
+    b"data: [DONE]\n\n"
+)
+
+EXPECTED = {
+    "openai": ("/v1/chat/completions", "fp_fc9f1d7035", 29),
+    "groq": ("/openai/v1/chat/completions", "fp_179b0f92c9", 25),
+    "openrouter": ("/api/v1/chat/completions", None, 29),
+    "together": ("/v1/chat/completions", None, 51),
+    "fireworks": ("/inference/v1/chat/completions", None, 27),
+    "deepseek": ("/chat/completions", "fp_3a5770e1b4_prod0225", 21),
+}
+
+
+@pytest.mark.parametrize("provider", list(RECORDED))
+def test_recorded_responses_parse(mock_server, provider):
+    server = mock_server(lambda request: (200, {"Content-Type": "application/json"}, RECORDED[provider]))
+    response = client_for(server, provider=provider).chat_completion_full([user_message()], "some-model")
+    path, fingerprint, total_tokens = EXPECTED[provider]
+    assert server.requests[0]["path"] == path
+    assert server.requests[0]["headers"]["authorization"] == "Bearer test-key"
+    assert bytes(response.content) == b"Hello! How can I help?"
+    assert response.system_fingerprint == fingerprint
+    assert response.usage["total_tokens"] == total_tokens
+
+
+def test_groq_stream_usage_is_read_from_x_groq(mock_server):
+    server = mock_server(lambda request: (200, {"Content-Type": "text/event-stream"}, RECORDED_GROQ_STREAM))
+    response = client_for(server, provider="groq").chat_completion_full([user_message()], "llama3-8b-8192", stream=True)
+    assert bytes(response.content) == b"Hello!"
+    assert response.usage["prompt_tokens"] == 18
+    assert response.usage["total_tokens"] == 21
+
+    # Without the preset the nested usage is not looked for.
+    response = SecureClient(server.base_url.encode(), b"test-key", path_style="openai", allow_insecure_http=True).chat_completion_full(
+        [user_message()], "llama3-8b-8192", stream=True
+    )
+    assert response.usage is None
+
+
+def test_together_stream_usage_on_last_chunk(mock_server):
+    server = mock_server(lambda request: (200, {"Content-Type": "text/event-stream"}, RECORDED_TOGETHER_STREAM))
+    response = client_for(server, provider="together").chat_completion_full([user_message()], "some-model", stream=True)
+    assert bytes(response.content) == b"Hello!"
+    assert response.finish_reason == "eos"
+    assert response.usage["total_tokens"] == 44
+
+
+def test_openrouter_sends_attribution_headers(mock_server):
+    server = mock_server()
+    client_for(server, provider="openrouter").chat_completion([user_message()], "openai/gpt-4o")
+    headers = server.requests[0]["headers"]
+    assert headers["http-referer"] == "https://github.com/AIvantGuard-AG/secure_openaiapi"
+    assert headers["x-title"] == "secure_openaiapi"
+
+    client_for(server, provider="openrouter", default_headers={"X-Title": "my-app"}).chat_completion([user_message()], "openai/gpt-4o")
+    assert server.requests[1]["headers"]["x-title"] == "my-app"
+    assert "http-referer" in server.requests[1]["headers"]
+
+
+def test_privacy_mode_opts_out_per_provider(mock_server):
+    server = mock_server()
+    client = client_for(server, provider="openrouter", privacy_mode=True)
+    client.chat_completion([user_message()], "openai/gpt-4o")
+    assert server.json_body()["provider"] == {"data_collection": "deny"}
+    assert server.json_body()["store"] is False
+    client.chat_completion([user_message()], "openai/gpt-4o", provider={"order": ["openai"]})
+    assert server.json_body()["provider"] == {"order": ["openai"], "data_collection": "deny"}
+    with pytest.raises(PrivacyModeError, match="provider.data_collection=\"allow\""):
+        client.chat_completion([user_message()], "openai/gpt-4o", provider={"data_collection": "allow"})
+    assert len(server.requests) == 2
+
+    # Without a preset opt-out only store is sent, and not even that to Gemini, which rejects it.
+    client_for(server, provider="together", privacy_mode=True).chat_completion([user_message()], "some-model")
+    assert "provider" not in server.json_body() and server.json_body()["store"] is False
+    client_for(server, provider="gemini", privacy_mode=True).chat_completion([user_message()], "gemini-2.0-flash")
+    assert "store" not in server.json_body()
+
+
+def test_embeddings_paths_follow_the_preset(mock_server):
+    server = mock_server(lambda request: (200, {}, b'{"data": [{"index": 0, "embedding": [0.5]}]}'))
+    for provider, path in [("openai", "/v1/embeddings"), ("gemini", "/v1beta/openai/embeddings"), ("deepseek", "/embeddings")]:
+        assert client_for(server, provider=provider).embeddings("Hi", "embed-model").embeddings == [[0.5]]
+        assert server.requests[-1]["path"] == path
+    azure = SecureClient(server.base_url.encode(), b"azure-key", path_style="azure", allow_insecure_http=True)
+    assert azure.embeddings("Hi", "embed-prod").usage == {"prompt_tokens": None, "total_tokens": None}
+    assert server.requests[-1]["path"] == "/openai/deployments/embed-prod/embeddings?api-version=2024-10-21"
+    assert server.requests[-1]["headers"]["api-key"] == "azure-key"
+    client_for(server, provider="openrouter", privacy_mode=True).embeddings("Hi", "openai/text-embedding-3-small")
+    assert server.json_body()["provider"] == {"data_collection": "deny"} and "store" not in server.json_body()
+    with pytest.raises(ValueError, match="provider 'anthropic' has no embeddings endpoint"):
+        client_for(server, provider="anthropic").embeddings("Hi", "embed-model")
+
+
+def test_preset_validation():
+    url = b"https://api.example.com"
+    with pytest.raises(ValueError, match="Unknown provider 'acme': expected one of 'openai', 'groq'"):
+        SecureClient(url, b"test-key", provider="acme")
+    with pytest.raises(ValueError, match="path_style and api_version cannot be combined"):
+        SecureClient(url, b"test-key", provider="groq", path_style="openai")
+    with pytest.raises(ValueError, match="path_style and api_version cannot be combined"):
+        SecureClient(url, b"test-key", provider="groq", api_version="2024-10-21")
+    with pytest.raises(ValueError, match="does not report system_fingerprint"):
+        SecureClient(url, b"test-key", provider="together", on_fingerprint_change=print)
+    SecureClient(url, b"test-key", provider="deepseek", on_fingerprint_change=print)
+    assert isinstance(AsyncSecureClient(url, b"test-key", provider="fireworks"), AsyncSecureClient)
+
+
+GEMINI_SUCCESS = (
+    b'{"choices":[{"finish_reason":"STOP","index":0,"message":{"content":"Hello! How can I help?","role":"assistant"}}],'
+    b'"created":1741570512,"id":"EG_PZ6-bK8ryxN8PqbfRqAo","model":"gemini-2.0-flash","object":"chat.completion",'
+    b'"usage":{"completion_tokens":8,"prompt_tokens":4,"total_tokens":12}}'
+)
+
+GEMINI_SAFETY_BLOCK = (
+    b'{"choices":[{"finish_reason":"SAFETY","index":0,"message":{"role":"assistant"}}],'
+    b'"created":1741570540,"id":"HG_PZ9mUFuXxN8PqbfRqAo","model":"gemini-2.0-flash","object":"chat.completion",'
+    b'"usage":{"completion_tokens":0,"prompt_tokens":12,"total_tokens":12}}'
+)
+
+GEMINI_BAD_KEY = (
+    b'[{"error":{"code":400,"message":"API key not valid. Please pass a valid API key.","status":"INVALID_ARGUMENT",'
+    b'"details":[{"@type":"type.googleapis.com/google.rpc.ErrorInfo","reason":"API_KEY_INVALID",'
+    b'"domain":"googleapis.com","metadata":{"service":"generativelanguage.googleapis.com"}}]}}]'
+)
+
+
+def test_gemini_success_is_normalized(mock_server):
+    server = mock_server(lambda request: (200, {}, GEMINI_SUCCESS))
+    client = client_for(server, provider="gemini")
+    response = client.chat_completion_full([user_message()], "gemini-2.0-flash", user="user-42", store=True, temperature=0.5)
+    assert server.requests[0]["path"] == "/v1beta/openai/chat/completions"
+    assert server.json_body() == {"messages": [{"role": "user", "content": "Hi"}], "model": "gemini-2.0-flash", "temperature": 0.5}
+    assert bytes(response.content) == b"Hello! How can I help?"
+    assert response.finish_reason == "stop"
+    assert response.usage["total_tokens"] == 12
+    assert bytes(client.chat_completion([user_message()], "gemini-2.0-flash", strict=True)) == b"Hello! How can I help?"
+
+
+def test_gemini_safety_block_raises_content_filter_error(mock_server):
+    server = mock_server(lambda request: (200, {}, GEMINI_SAFETY_BLOCK))
+    client = client_for(server, provider="gemini")
+    for strict in (False, True):
+        with pytest.raises(ContentFilterError) as info:
+            client.chat_completion([user_message()], "gemini-2.0-flash", strict=strict)
+        assert info.value.finish_reason == "content_filter"
+        assert info.value.usage["prompt_tokens"] == 12
+
+
+def test_gemini_bad_key_raises_authentication_error(mock_server):
+    server = mock_server(lambda request: (400, {}, GEMINI_BAD_KEY))
+    with pytest.raises(AuthenticationError, match="API key not valid") as info:
+        client_for(server, provider="gemini").chat_completion([user_message()], "gemini-2.0-flash")
+    assert (info.value.status, info.value.type, info.value.code) == (400, "INVALID_ARGUMENT", "API_KEY_INVALID")
+
+    # Other Google errors keep the class of their status.
+    invalid = b'[{"error":{"code":400,"message":"Invalid value at temperature","status":"INVALID_ARGUMENT"}}]'
+    server = mock_server(lambda request: (400, {}, invalid))
+    with pytest.raises(BadRequestError, match="Invalid value at temperature") as info:
+        client_for(server, provider="gemini", include_error_body=True).chat_completion([user_message()], "gemini-2.0-flash")
+    assert (info.value.type, info.value.code) == ("INVALID_ARGUMENT", None)
+
+
+OLLAMA_RESPONSE = (
+    b'{"id":"chatcmpl-812","object":"chat.completion","created":1741570601,"model":"llama3.2","system_fingerprint":"fp_ollama",'
+    b'"choices":[{"index":0,"message":{"role":"assistant","content":"Hello! How can I help?"},"finish_reason":"stop"}],'
+    b'"usage":{"prompt_tokens":26,"completion_tokens":8,"total_tokens":34}}'
+)
+
+# Ollama reports an error that happens mid-stream as a plain string.
+OLLAMA_STREAM_ERROR = (
+    b'data: {"id":"chatcmpl-813","object":"chat.completion.chunk","created":1741570620,"model":"llama3.2",'
+    b'"system_fingerprint":"fp_ollama","choices":[{"index":0,"delta":{"role":"assistant","content":"Hel"},"finish_reason":null}]}\n\n'
+    b'data: {"error":"llama runner process has terminated: signal: killed"}\n\n'
+    b"data: [DONE]\n\n"
+)
+
+OLLAMA_MODEL_NOT_FOUND = b'{"error":{"message":"model \\"llama9\\" not found, try pulling it first","type":"api_error","param":null,"code":null}}'
+
+
+def test_ollama_runs_without_a_key(mock_server):
+    server = mock_server(lambda request: (200, {}, OLLAMA_RESPONSE))
+    client = SecureClient(server.base_url.encode(), b"", provider="ollama", allow_insecure_http=True)
+    options = {"num_ctx": 8192, "num_gpu": 1}
+    response = client.chat_completion_full([user_message()], "llama3.2", ollama_options=options, keep_alive="10m")
+    request = server.requests[0]
+    assert request["path"] == "/v1/chat/completions"
+    assert "authorization" not in request["headers"]
+    assert server.json_body()["options"] == options
+    assert server.json_body()["keep_alive"] == "10m"
+    assert "ollama_options" not in server.json_body()
+    assert response.system_fingerprint == "fp_ollama"
+
+    # A key is still sent when there is one, e.g. behind an authenticating proxy.
+    client_for(server, provider="ollama").chat_completion([user_message()], "llama3.2")
+    assert server.requests[1]["headers"]["authorization"] == "Bearer test-key"
+
+
+def test_ollama_stream_error_string(mock_server):
+    server = mock_server(lambda request: (200, {"Content-Type": "text/event-stream"}, OLLAMA_STREAM_ERROR))
+    with pytest.raises(IOError, match="mid-stream.*llama runner process has terminated"):
+        client_for(server, provider="ollama").chat_completion([user_message()], "llama3.2", stream=True)
+
+
+def test_ollama_missing_model_hints_at_pull(mock_server):
+    server = mock_server(lambda request: (404, {}, OLLAMA_MODEL_NOT_FOUND))
+    with pytest.raises(NotFoundError, match=r"server message withheld.*run `ollama pull llama9`"):
+        client_for(server, provider="ollama").chat_completion([user_message()], "llama9")
+    with pytest.raises(NotFoundError) as info:
+        client_for(server, provider="openai").chat_completion([user_message()], "llama9")
+    assert "ollama pull" not in str(info.value)
+
+
+# vLLM can return the content as a list of parts, as it was sent.
+VLLM_RESPONSE = (
+    b'{"id":"chatcmpl-9f2c","object":"chat.completion","created":1741570700,"model":"meta-llama/Llama-3.1-8B-Instruct",'
+    b'"choices":[{"index":0,"message":{"role":"assistant","content":[{"type":"text","text":"Hello! "},'
+    b'{"type":"image_url","image_url":{"url":"https://x/y.png"}},{"type":"text","text":"How can I help?"}],'
+    b'"tool_calls":[]},"logprobs":null,"finish_reason":"stop","stop_reason":null}],'
+    b'"usage":{"prompt_tokens":12,"total_tokens":20,"completion_tokens":8},"prompt_logprobs":null}'
+)
+
+LLAMACPP_STREAM = (
+    b'data: {"choices":[{"finish_reason":null,"index":0,"delta":{"content":[{"type":"text","text":"Hel"}]}}],'
+    b'"created":1741570750,"id":"chatcmpl-Xy1","model":"gpt-3.5-turbo","system_fingerprint":"b4870-a1b2c3d4",'
+    b'"object":"chat.completion.chunk"}\n\n'
+    b'data: {"choices":[{"finish_reason":"stop","index":0,"delta":{"content":"lo!"}}],"created":1741570750,'
+    b'"id":"chatcmpl-Xy1","model":"gpt-3.5-turbo","system_fingerprint":"b4870-a1b2c3d4","object":"chat.completion.chunk"}\n\n'
+    b"data: [DONE]\n\n"
+)
+
+
+def test_vllm_content_parts_are_joined(mock_server):
+    server = mock_server(lambda request: (200, {}, VLLM_RESPONSE))
+    client = SecureClient(server.base_url.encode(), b"", provider="vllm", allow_insecure_http=True)
+    response = client.chat_completion_full([user_message()], "meta-llama/Llama-3.1-8B-Instruct", top_k=40, min_p=0.05, repetition_penalty=1.1)
+    assert bytes(response.content) == b"Hello! How can I help?"
+    assert "authorization" not in server.requests[0]["headers"]
+    body = server.json_body()
+    assert (body["top_k"], body["min_p"], body["repetition_penalty"]) == (40, 0.05, 1.1)
+
+
+def test_llamacpp_stream_and_secure_grammar(mock_server):
+    server = mock_server(lambda request: (200, {"Content-Type": "text/event-stream"}, LLAMACPP_STREAM))
+    client = client_for(server, provider="llamacpp")
+    grammar = SecureBytes(b'root ::= "yes" | "no"\n')
+    response = client.chat_completion_full([user_message()], "local", stream=True, grammar=grammar)
+    assert bytes(response.content) == b"Hello!"
+    assert response.system_fingerprint == "b4870-a1b2c3d4"
+    assert server.json_body()["grammar"] == 'root ::= "yes" | "no"\n'
+    assert bytes(grammar) == b'root ::= "yes" | "no"\n'
+
+    client.chat_completion([user_message()], "local", stream=True, grammar='root ::= "ok"')
+    assert server.json_body(1)["grammar"] == 'root ::= "ok"'
+
+
+@pytest.mark.parametrize(
+    "params, match",
+    [
+        ({"top_k": 1.5}, "top_k must be an int"),
+        ({"top_k": -2}, "top_k must be an int"),
+        ({"min_p": 1.5}, "min_p must be a number between 0 and 1"),
+        ({"min_p": "0.1"}, "min_p must be a number between 0 and 1"),
+        ({"repetition_penalty": 0}, "repetition_penalty must be a positive number"),
+    ],
+)
+def test_sampling_extras_are_checked(mock_server, params, match):
+    server = mock_server()
+    client = client_for(server, provider="vllm")
+    with pytest.raises(ValueError, match=match):
+        client.chat_completion([user_message()], "local", **params)
+    with pytest.raises(ValueError, match=match):
+        client.with_defaults(**params)
+    assert server.requests == []
+
+
+def test_secure_grammar_is_per_call_only(mock_server):
+    server = mock_server()
+    client = client_for(server, provider="llamacpp")
+    with pytest.raises(ValueError, match="can only be passed per call"):
+        client.with_defaults(grammar=SecureBytes(b'root ::= "x"'))
+    with pytest.raises(ValueError, match="'grammar' is not supported"):
+        client_for(server, provider="anthropic").chat_completion([user_message()], "claude-test", grammar=SecureBytes(b'root ::= "x"'))
+    assert server.requests == []
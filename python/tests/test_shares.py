@@ -0,0 +1,62 @@
+import pytest
+
+from secure_openaiapi import AsyncSecureClient, SecureBytes, SecureClient
+
+SECRET = b"sk-live-0123456789abcdef"
+
+
+@pytest.mark.parametrize("n", [2, 3, 5])
+def test_split_and_combine(n):
+    shares = SecureBytes(SECRET).split(n)
+    assert len(shares) == n
+    assert all(isinstance(share, bytes) and SECRET not in share for share in shares)
+    assert len({share for share in shares}) == n
+    combined = SecureBytes.combine(list(reversed(shares)))
+    assert isinstance(combined, SecureBytes)
+    assert bytes(combined) == SECRET
+    # Every split draws fresh pads.
+    assert SecureBytes(SECRET).split(n) != shares
+
+
+def test_combine_rejects_bad_share_sets():
+    first, second, third = SecureBytes(SECRET).split(3)
+    other = SecureBytes(SECRET).split(3)
+    with pytest.raises(ValueError, match="need 3 of 3 shares, got 2"):
+        SecureBytes.combine([first, second])
+    with pytest.raises(ValueError, match="given twice"):
+        SecureBytes.combine([first, second, second])
+    with pytest.raises(ValueError, match="same split"):
+        SecureBytes.combine([first, second, other[2]])
+    with pytest.raises(ValueError, match="not a secret share"):
+        SecureBytes.combine([first, second, b"garbage"])
+    with pytest.raises(ValueError, match="no shares"):
+        SecureBytes.combine([])
+
+
+@pytest.mark.parametrize("n, k, error", [(1, None, ValueError), (256, None, ValueError), (3, 2, NotImplementedError)])
+def test_split_validates_arguments(n, k, error):
+    with pytest.raises(error):
+        SecureBytes(SECRET).split(n, k)
+
+
+def write_shares(tmp_path, n=2):
+    paths = []
+    for index, share in enumerate(SecureBytes(SECRET).split(n)):
+        path = tmp_path / f"share{index}"
+        path.write_bytes(share)
+        paths.append(str(path))
+    return paths
+
+
+def test_client_from_shares(mock_server, tmp_path):
+    server = mock_server()
+    client = SecureClient.from_shares(server.base_url.encode(), write_shares(tmp_path, 3), allow_insecure_http=True)
+    assert isinstance(client, SecureClient)
+    assert bytes(client.chat_completion([{"role": "user", "content": "Hi"}], "gpt-test")) == b"Hello!"
+    assert server.requests[-1]["headers"]["authorization"] == "Bearer " + SECRET.decode()
+
+    assert isinstance(AsyncSecureClient.from_shares(server.base_url.encode(), write_shares(tmp_path), allow_insecure_http=True), AsyncSecureClient)
+    with pytest.raises(ValueError, match="need 2 of 2"):
+        SecureClient.from_shares(server.base_url.encode(), write_shares(tmp_path)[:1])
+    with pytest.raises(FileNotFoundError):
+        SecureClient.from_shares(server.base_url.encode(), [str(tmp_path / "missing")])
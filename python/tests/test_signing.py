@@ -0,0 +1,83 @@
+import hashlib
+import hmac
+
+import pytest
+
+from conftest import client_for, user_message
+from secure_openaiapi import AsyncSecureClient, HmacSigner, SecureBytes
+
+SIGNING_KEY = b"gateway-signing-key"
+SECRET_TEXT = b"signing-marker-5e1a do not leak"
+
+
+def body_hash(body):
+    return hashlib.blake2b(body, digest_size=32).hexdigest()
+
+
+def test_callable_signer_sees_only_the_body_hash(mock_server):
+    server = mock_server()
+    calls = []
+
+    def signer(method, path, headers, digest):
+        calls.append((method, path, headers, digest))
+        return {"X-Gateway-Signature": SecureBytes(b"sig-" + digest.encode()[:8]), "X-Gateway-Key-Id": "key-1"}
+
+    client = client_for(server, signer=signer, default_headers={"X-Tenant": "eu", "X-Secret": SecureBytes(b"hidden")})
+    client.chat_completion([user_message(SECRET_TEXT)], "gpt-test")
+
+    ((method, path, headers, digest),) = calls
+    request = server.requests[0]
+    assert (method, path) == ("POST", request["path"]) == ("POST", "/openai/v1/chat/completions")
+    assert digest == body_hash(request["body"])
+    assert headers["x-tenant"] == "eu"
+    assert "authorization" not in headers and "x-secret" not in headers
+    assert SECRET_TEXT.decode() not in repr(calls)
+    assert request["headers"]["x-gateway-signature"] == "sig-" + digest[:8]
+    assert request["headers"]["x-gateway-key-id"] == "key-1"
+    assert request["headers"]["authorization"] == "Bearer test-key"
+
+
+def test_hmac_signer(mock_server):
+    server = mock_server()
+    signer = HmacSigner(SecureBytes(SIGNING_KEY), signed_headers=["content-type", "x-tenant", "x-missing"])
+    client = client_for(server, signer=signer, default_headers={"X-Tenant": "eu"})
+    client.chat_completion([user_message(SECRET_TEXT)], "gpt-test")
+
+    request = server.requests[0]
+    timestamp = request["headers"]["x-signature-timestamp"]
+    signed = "\n".join(
+        ["POST", "/openai/v1/chat/completions", timestamp, "content-type:application/json", "x-tenant:eu", "x-missing:", body_hash(request["body"])]
+    )
+    assert request["headers"]["x-signature"] == hmac.new(SIGNING_KEY, signed.encode(), hashlib.sha256).hexdigest()
+    assert request["headers"]["x-signed-headers"] == "content-type;x-tenant;x-missing"
+    assert repr(signer) == "HmacSigner(header='x-signature')"
+
+    custom = HmacSigner(SecureBytes(SIGNING_KEY), header="X-Auth", timestamp_header="X-Auth-Time")
+    client_for(server, signer=custom).chat_completion([user_message(SECRET_TEXT)], "gpt-test")
+    request = server.requests[1]
+    signed = "\n".join(["POST", "/openai/v1/chat/completions", request["headers"]["x-auth-time"], body_hash(request["body"])])
+    assert request["headers"]["x-auth"] == hmac.new(SIGNING_KEY, signed.encode(), hashlib.sha256).hexdigest()
+    assert "x-signed-headers" not in request["headers"]
+
+
+def test_signer_errors(mock_server):
+    server = mock_server()
+    with pytest.raises(TypeError, match="signer must be an HmacSigner, a callable or None"):
+        client_for(server, signer="not-a-signer")
+    with pytest.raises(ValueError, match="must not be empty"):
+        HmacSigner(SecureBytes(b""))
+    with pytest.raises(ValueError, match="Invalid header name in HmacSigner"):
+        HmacSigner(SecureBytes(SIGNING_KEY), header="bad header")
+
+    with pytest.raises(TypeError, match="signer must return a dict"):
+        client_for(server, signer=lambda *args: None).chat_completion([user_message(SECRET_TEXT)], "gpt-test")
+    with pytest.raises(ValueError, match="'Authorization' cannot be set through signer"):
+        client_for(server, signer=lambda *args: {"Authorization": "Bearer other"}).chat_completion([user_message(SECRET_TEXT)], "gpt-test")
+
+    def failing(*args):
+        raise RuntimeError("signing service unavailable")
+
+    with pytest.raises(RuntimeError, match="signing service unavailable"):
+        client_for(server, signer=failing).chat_completion([user_message(SECRET_TEXT)], "gpt-test")
+    assert server.requests == []
+    assert isinstance(AsyncSecureClient(b"https://gateway.example.com", b"sk-key", signer=HmacSigner(SecureBytes(SIGNING_KEY))), AsyncSecureClient)
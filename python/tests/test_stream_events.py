@@ -0,0 +1,148 @@
+import gzip
+import json
+import threading
+import time
+
+import pytest
+
+from conftest import client_for, user_message
+from secure_openaiapi import InternalServerError, SecureBytes, SecureClient, SecureToolCall
+
+EVENT_STREAM = {"Content-Type": "text/event-stream"}
+
+
+def event(chunk):
+    return b"data: " + json.dumps(chunk).encode() + b"\n\n"
+
+
+def delta(index=0, finish_reason=None, **fields):
+    return {"id": "chatcmpl-test", "model": "gpt-test", "choices": [{"index": index, "delta": fields, "finish_reason": finish_reason}]}
+
+
+USAGE = {"id": "chatcmpl-test", "choices": [], "usage": {"prompt_tokens": 4, "completion_tokens": 2, "total_tokens": 6}}
+
+
+def test_tokens_arrive_while_the_stream_is_read(mock_server):
+    first_token = threading.Event()
+    waited = []
+
+    def chunks():
+        yield event(delta(role="assistant", content="Hel"))
+        # The rest is only sent once the client has seen the first token.
+        waited.append(first_token.wait(5))
+        yield event(delta(content="lo!", finish_reason="stop")) + event(USAGE) + b"data: [DONE]\n\n"
+
+    server = mock_server(lambda request: (200, EVENT_STREAM, chunks()))
+    tokens, finished = [], []
+
+    def on_token(token):
+        assert isinstance(token, SecureBytes)
+        tokens.append(bytes(token))
+        first_token.set()
+
+    response = client_for(server, default_model="gpt-test").stream_chat_with_events(
+        [user_message()], on_token=on_token, on_finish=lambda *args: finished.append(args), temperature=0
+    )
+    assert waited == [True]
+    assert tokens == [b"Hel", b"lo!"]
+    assert bytes(response.content) == b"Hello!"
+    assert finished == [("stop", response.usage)]
+    assert response.usage["total_tokens"] == 6
+    body = server.json_body()
+    assert (body["stream"], body["temperature"]) == (True, 0)
+
+
+def test_tool_calls_are_reported_assembled(mock_server):
+    def call(index, id=None, name=None, arguments=""):
+        return {"index": index, **({"id": id, "type": "function"} if id else {}), "function": {"name": name, "arguments": arguments} if name else {"arguments": arguments}}
+
+    body = b"".join(
+        [
+            event(delta(tool_calls=[call(0, "call_a", "get_weather", '{"city": ')])),
+            event(delta(tool_calls=[call(1, "call_b", "get_time", '{"tz": "CET"}')])),
+            event(delta(tool_calls=[call(0, arguments='"Bern"}')])),
+            event(delta(finish_reason="tool_calls")),
+            b"data: [DONE]\n\n",
+        ]
+    )
+    server = mock_server(lambda request: (200, EVENT_STREAM, body))
+    tool_calls, tokens, finished = [], [], []
+    client_for(server, default_model="gpt-test").stream_chat_with_events(
+        [user_message()], on_token=tokens.append, on_tool_call=tool_calls.append, on_finish=lambda *args: finished.append(args)
+    )
+    assert tokens == []
+    assert all(isinstance(tool_call, SecureToolCall) for tool_call in tool_calls)
+    assert [(c.id, c.name, bytes(c.arguments)) for c in tool_calls] == [
+        ("call_a", "get_weather", b'{"city": "Bern"}'),
+        ("call_b", "get_time", b'{"tz": "CET"}'),
+    ]
+    assert finished == [("tool_calls", None)]
+
+
+def test_raising_callback_aborts_the_stream(mock_server):
+    stopped = threading.Event()
+
+    def chunks():
+        try:
+            yield event(delta(content="first"))
+            for _ in range(100):
+                time.sleep(0.05)
+                yield event(delta(content=" more"))
+        finally:
+            stopped.set()
+
+    server = mock_server(lambda request: (200, EVENT_STREAM, chunks()))
+    errors = []
+
+    def on_token(token):
+        raise RuntimeError("stop reading")
+
+    started = time.monotonic()
+    with pytest.raises(RuntimeError, match="stop reading"):
+        client_for(server, default_model="gpt-test").stream_chat_with_events([user_message()], on_token=on_token, on_error=errors.append)
+    assert time.monotonic() - started < 2
+    assert errors == []
+    # The connection was dropped, so the server fails to write the rest.
+    assert stopped.wait(3)
+
+
+def test_on_error_sees_the_exception_before_it_is_raised(mock_server):
+    server = mock_server(lambda request: (500, {}, b'{"error":{"message":"down"}}'))
+    errors, finished = [], []
+    with pytest.raises(InternalServerError) as info:
+        client_for(server, default_model="gpt-test").stream_chat_with_events([user_message()], on_error=errors.append, on_finish=finished.append)
+    assert errors == [info.value]
+    assert finished == []
+
+    def failing(error):
+        raise ValueError("handler broke")
+
+    with pytest.raises(ValueError, match="handler broke"):
+        client_for(server, default_model="gpt-test").stream_chat_with_events([user_message()], on_error=failing)
+    with pytest.raises(TypeError, match="on_token must be callable or None"):
+        client_for(server, default_model="gpt-test").stream_chat_with_events([user_message()], on_token="print")
+
+
+def test_compressed_and_anthropic_streams(mock_server):
+    body = event(delta(content="Hel")) + event(delta(content="lo!", finish_reason="stop")) + b"data: [DONE]\n\n"
+    server = mock_server(lambda request: (200, {**EVENT_STREAM, "Content-Encoding": "gzip"}, gzip.compress(body)))
+    tokens = []
+    client_for(server, default_model="gpt-test", compression=True).stream_chat_with_events([user_message()], on_token=lambda token: tokens.append(bytes(token)))
+    assert tokens == [b"Hel", b"lo!"]
+
+    events = [
+        ("message_start", {"type": "message_start", "message": {"id": "msg_1", "model": "claude-test", "usage": {"input_tokens": 3}}}),
+        ("content_block_delta", {"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Bon"}}),
+        ("content_block_delta", {"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "jour"}}),
+        ("message_delta", {"type": "message_delta", "delta": {"stop_reason": "end_turn"}, "usage": {"output_tokens": 2}}),
+    ]
+    stream = b"".join(f"event: {name}\ndata: {json.dumps(data)}\n\n".encode() for name, data in events)
+    server = mock_server(lambda request: (200, EVENT_STREAM, stream))
+    tokens, finished = [], []
+    client = SecureClient(server.base_url.encode(), b"sk-ant-test", anthropic=True, allow_insecure_http=True)
+    response = client.stream_chat_with_events(
+        [user_message()], "claude-test", on_token=lambda token: tokens.append(bytes(token)), on_finish=lambda *args: finished.append(args), max_tokens=5
+    )
+    assert tokens == [b"Bon", b"jour"]
+    assert bytes(response.content) == b"Bonjour"
+    assert finished == [("stop", response.usage)]
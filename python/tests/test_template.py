@@ -0,0 +1,53 @@
+import pytest
+
+from secure_openaiapi import SecureBytes, SecureClient, SecureMessage, SecureTemplate
+
+
+def test_render_fills_placeholders_from_any_value_type():
+    template = SecureTemplate("Dear {name}, your balance is {balance}. {{not a placeholder}} {name}!")
+    assert template.placeholders == ["name", "balance"]
+    assert repr(template) == "SecureTemplate(placeholders=['name', 'balance'])"
+
+    rendered = template.render(name="Zoë", balance=SecureBytes(b"$1,234"))
+    assert isinstance(rendered, SecureBytes)
+    assert bytes(rendered) == "Dear Zoë, your balance is $1,234. {not a placeholder} Zoë!".encode()
+    assert bytes(template.render(name=b"<b>Al</b>", balance="0")) == b"Dear <b>Al</b>, your balance is 0. {not a placeholder} <b>Al</b>!"
+    assert bytes(SecureTemplate("no placeholders").render()) == b"no placeholders"
+    assert bytes(SecureTemplate("{a}{b}").render(a="", b="")) == b""
+
+
+def test_render_errors():
+    template = SecureTemplate("Hello {name}")
+    with pytest.raises(KeyError, match="Missing value for placeholder 'name'"):
+        template.render()
+    with pytest.raises(ValueError, match="Unexpected value for 'extra'"):
+        template.render(name="x", extra="y")
+    with pytest.raises(TypeError, match="Value for 'name' must be str, bytes or SecureBytes"):
+        template.render(name=3)
+    wiped = SecureBytes(b"gone")
+    wiped.wipe()
+    with pytest.raises(ValueError, match="wiped"):
+        template.render(name=wiped)
+
+    for bad, match in [
+        ("unclosed {name", "Unclosed '{'"),
+        ("stray } brace", "Single '}'"),
+        ("{}", "Invalid placeholder '{}'"),
+        ("{first name}", "Invalid placeholder '{first name}'"),
+        ("{0}", "Invalid placeholder '{0}'"),
+    ]:
+        with pytest.raises(ValueError, match=match):
+            SecureTemplate(bad)
+
+
+def test_render_message(mock_server):
+    server = mock_server()
+    template = SecureTemplate("Summarize the account of {customer}.")
+    message = template.render_message(b"user", customer=SecureBytes(b"ACME Corp"))
+    assert isinstance(message, SecureMessage)
+    client = SecureClient(server.base_url.encode(), b"sk-test", allow_insecure_http=True)
+    client.chat_completion([message, template.render_message("assistant", customer="Globex")], "gpt-test")
+    assert server.json_body()["messages"] == [
+        {"role": "user", "content": "Summarize the account of ACME Corp."},
+        {"role": "assistant", "content": "Summarize the account of Globex."},
+    ]
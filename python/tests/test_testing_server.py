@@ -0,0 +1,92 @@
+import threading
+import time
+
+import pytest
+
+from conftest import client_for, user_message
+from secure_openaiapi import InternalServerError, RateLimitError
+from secure_openaiapi.testing import MockOpenAIServer
+
+
+def test_canned_responses_and_recorded_requests():
+    with MockOpenAIServer() as server:
+        assert server.base_url.startswith("http://127.0.0.1:")
+        server.add_completion("First", usage={"prompt_tokens": 3, "completion_tokens": 1, "total_tokens": 4})
+        client = client_for(server, default_model="gpt-test")
+        response = client.chat_completion_full([user_message(b"secret question")], temperature=0)
+        assert bytes(response.content) == b"First" and response.usage["total_tokens"] == 4
+        # Nothing left in the queue: the default answer.
+        assert bytes(client.chat_completion([user_message()])) == b"Hello!"
+
+        [first, second] = server.requests
+        assert (first["method"], first["path"]) == ("POST", "/openai/v1/chat/completions")
+        assert first["headers"]["authorization"] == "[redacted]"
+        assert b"sk-fixture-key" not in first["body"] and b"secret question" in first["body"]
+        assert server.json_body(0)["temperature"] == 0
+        assert server.json_body()["messages"] == [{"role": "user", "content": "Hi"}]
+    with pytest.raises(RuntimeError, match="not running"):
+        server.base_url
+
+
+def test_injected_errors_malformed_json_and_latency():
+    server = MockOpenAIServer(latency=0.2).start()
+    try:
+        server.add_error(429, "Slow down", type="rate_limit_error", headers={"Retry-After": "0"})
+        server.add_completion("After retry", latency=0)
+        started = time.monotonic()
+        assert bytes(client_for(server, default_model="gpt-test", max_retries=1).chat_completion([user_message()])) == b"After retry"
+        assert time.monotonic() - started >= 0.2
+        assert len(server.requests) == 2
+
+        server.add_error(503, "Overloaded", latency=0)
+        with pytest.raises(InternalServerError, match="Overloaded"):
+            client_for(server, default_model="gpt-test", max_retries=0).chat_completion([user_message()])
+        server.add_error(429, latency=0)
+        with pytest.raises(RateLimitError):
+            client_for(server, default_model="gpt-test", max_retries=0).chat_completion([user_message()])
+        server.add_response(b'{"choices": [', headers={"Content-Type": "application/json"}, latency=0)
+        with pytest.raises(ValueError):
+            client_for(server, default_model="gpt-test", max_retries=0).chat_completion([user_message()])
+    finally:
+        server.stop()
+    server.stop()
+
+
+def test_streams_arrive_token_by_token():
+    with MockOpenAIServer() as server:
+        server.add_stream(["Hel", "lo", "!"], delay=0.1, usage={"prompt_tokens": 2, "completion_tokens": 3, "total_tokens": 5})
+        arrivals = []
+        response = client_for(server, default_model="gpt-test").stream_chat_with_events([user_message()], on_token=lambda token: arrivals.append((bytes(token), time.monotonic())))
+        assert [token for token, _ in arrivals] == [b"Hel", b"lo", b"!"]
+        assert arrivals[-1][1] - arrivals[0][1] >= 0.15
+        assert bytes(response.content) == b"Hello!" and response.usage["total_tokens"] == 5
+        assert server.json_body()["stream"] is True
+
+
+def test_servers_run_side_by_side_and_restart():
+    servers = [MockOpenAIServer().start() for _ in range(4)]
+    try:
+        assert len({server.base_url for server in servers}) == 4
+        for index, server in enumerate(servers):
+            server.add_completion(f"from {index}")
+        results = {}
+
+        def ask(index):
+            results[index] = bytes(client_for(servers[index], default_model="gpt-test").chat_completion([user_message()]))
+
+        threads = [threading.Thread(target=ask, args=(index,)) for index in range(4)]
+        for thread in threads:
+            thread.start()
+        for thread in threads:
+            thread.join()
+        assert results == {index: f"from {index}".encode() for index in range(4)}
+    finally:
+        for server in servers:
+            server.stop()
+
+    server = servers[0]
+    server.start()
+    with pytest.raises(RuntimeError, match="already running"):
+        server.start()
+    assert bytes(client_for(server, default_model="gpt-test").chat_completion([user_message()])) == b"Hello!"
+    server.stop()
@@ -0,0 +1,197 @@
+import asyncio
+import json
+import threading
+import time
+
+import pytest
+
+from conftest import client_for, completion_body, user_message
+from secure_openaiapi import AsyncSecureClient, AuthenticationError, SecureBytes, SecureClient
+
+
+class Tokens:
+    """A token provider handing out `token-1`, `token-2`, ... each valid for `lifetime` seconds."""
+
+    def __init__(self, lifetime=3600, delay=0):
+        self.lifetime = lifetime
+        self.delay = delay
+        self.calls = 0
+
+    def __call__(self):
+        time.sleep(self.delay)
+        self.calls += 1
+        return SecureBytes(f"token-{self.calls}".encode()), time.time() + self.lifetime
+
+
+def test_token_is_cached_until_close_to_expiry(mock_server):
+    server = mock_server()
+    tokens = Tokens()
+    client = client_for(server, None, token_provider=tokens)
+    for _ in range(3):
+        client.chat_completion([user_message()], "gpt-test")
+    assert tokens.calls == 1
+    assert [request["headers"]["authorization"] for request in server.requests] == ["Bearer token-1"] * 3
+
+    # Within 60 seconds of its expiry a token is refreshed before every request.
+    expiring = Tokens(lifetime=30)
+    client = client_for(server, None, token_provider=expiring, encrypt_at_rest=True)
+    client.chat_completion([user_message()], "gpt-test")
+    client.chat_completion([user_message()], "gpt-test")
+    assert expiring.calls == 2
+    assert server.requests[-1]["headers"]["authorization"] == "Bearer token-2"
+
+
+def test_concurrent_requests_share_one_refresh(mock_server):
+    server = mock_server()
+    tokens = Tokens(delay=0.2)
+    client = client_for(server, None, token_provider=tokens)
+    threads = [threading.Thread(target=client.chat_completion, args=([user_message()], "gpt-test")) for _ in range(4)]
+    for thread in threads:
+        thread.start()
+    for thread in threads:
+        thread.join()
+    assert tokens.calls == 1
+    assert {request["headers"]["authorization"] for request in server.requests} == {"Bearer token-1"}
+
+
+def test_provider_failures_raise_authentication_error(mock_server):
+    server = mock_server()
+
+    def failing():
+        raise ConnectionError("identity endpoint unreachable")
+
+    with pytest.raises(AuthenticationError, match="token_provider failed with ConnectionError") as info:
+        client_for(server, None, token_provider=failing).chat_completion([user_message()], "gpt-test")
+    assert isinstance(info.value.__cause__, ConnectionError)
+    assert (info.value.status, info.value.model) == (None, "gpt-test")
+
+    for returned, cause in [
+        (b"token-without-expiry", TypeError),
+        ((b"token", "soon"), TypeError),
+        ((b"", time.time() + 3600), ValueError),
+        ((b"bad\ntoken", time.time() + 3600), ValueError),
+    ]:
+        with pytest.raises(AuthenticationError) as info:
+            client_for(server, None, token_provider=lambda: returned).chat_completion([user_message()], "gpt-test")
+        assert isinstance(info.value.__cause__, cause)
+    assert server.requests == []
+
+
+def test_token_is_redacted_from_errors(mock_server):
+    def echo_token(request):
+        token = request["headers"]["authorization"].removeprefix("Bearer ")
+        return 401, {}, json.dumps({"error": {"message": f"token {token} has expired"}}).encode()
+
+    server = mock_server(echo_token)
+    with pytest.raises(AuthenticationError) as info:
+        client_for(server, None, token_provider=Tokens()).chat_completion([user_message()], "gpt-test")
+    assert "token-2" not in str(info.value)
+
+
+def test_token_provider_validation():
+    url = b"https://gateway.example.com"
+    with pytest.raises(ValueError, match="not both"):
+        SecureClient(url, b"sk-key", token_provider=Tokens())
+    with pytest.raises(ValueError, match="api_key or token_provider is required"):
+        SecureClient(url)
+    with pytest.raises(TypeError, match="token_provider must be callable"):
+        SecureClient(url, token_provider="token")
+    client = SecureClient(url, token_provider=Tokens())
+    with pytest.raises(ValueError, match="set_api_key cannot be used"):
+        client.set_api_key(b"sk-key")
+    assert isinstance(AsyncSecureClient(url, token_provider=Tokens()), AsyncSecureClient)
+
+
+def reject_first_token(request):
+    if request["headers"]["authorization"] == "Bearer token-1":
+        return 401, {}, json.dumps({"error": {"message": "token expired", "type": "invalid_request_error"}}).encode()
+    return 200, {}, completion_body()
+
+
+def test_401_refreshes_the_token_and_retries_once(mock_server):
+    server = mock_server(reject_first_token)
+    tokens = Tokens()
+    client = client_for(server, None, token_provider=tokens)
+    records = []
+    client.set_audit_hook(records.append)
+    assert bytes(client.chat_completion([user_message()], "gpt-test")) == b"Hello!"
+
+    assert tokens.calls == 2
+    first, second = server.requests
+    assert (first["headers"]["authorization"], second["headers"]["authorization"]) == ("Bearer token-1", "Bearer token-2")
+    assert first["body"] == second["body"]
+    assert [(record["error"], record["token_refreshed"]) for record in records] == [("AuthenticationError", False), (None, True)]
+    assert client.stats()["token_refreshes"] == 1
+
+    # The fresh token is cached like the first one.
+    client.chat_completion([user_message()], "gpt-test")
+    assert tokens.calls == 2
+
+
+def test_401_after_a_refresh_is_raised(mock_server):
+    server = mock_server(lambda request: (401, {}, b'{"error":{"message":"not allowed"}}'))
+    tokens = Tokens()
+    with pytest.raises(AuthenticationError, match="status 401") as info:
+        client_for(server, None, token_provider=tokens).chat_completion([user_message()], "gpt-test")
+    assert info.value.status == 401
+    assert tokens.calls == 2
+    assert len(server.requests) == 2
+
+    # A static key is never refreshed.
+    client = SecureClient(server.base_url.encode(), b"sk-static", allow_insecure_http=True)
+    with pytest.raises(AuthenticationError):
+        client.chat_completion([user_message()], "gpt-test")
+    assert len(server.requests) == 3
+    assert client.stats()["token_refreshes"] == 0
+
+
+def test_async_401_refreshes_the_token(mock_server):
+    server = mock_server(reject_first_token)
+    tokens = Tokens()
+    client = AsyncSecureClient(server.base_url.encode(), token_provider=tokens, allow_insecure_http=True)
+
+    async def call():
+        return await client.chat_completion_full([user_message()], "gpt-test")
+
+    assert bytes(asyncio.run(call()).content) == b"Hello!"
+    assert tokens.calls == 2
+    assert client.stats()["token_refreshes"] == 1
+
+
+def test_chat_completion_many_refreshes_the_token(mock_server):
+    server = mock_server(reject_first_token)
+    tokens = Tokens()
+    results = client_for(server, None, token_provider=tokens).chat_completion_many([[user_message()], [user_message()]], "gpt-test", concurrency=1)
+    assert [bytes(result) for result in results] == [b"Hello!", b"Hello!"]
+    # Both items were built with token-1; the second one's retry finds token-2 cached.
+    assert tokens.calls == 2
+    authorizations = [request["headers"]["authorization"] for request in server.requests]
+    assert authorizations == ["Bearer token-1", "Bearer token-2"] * 2
+
+
+def test_embeddings_refresh_the_token(mock_server):
+    def handler(request):
+        if request["headers"]["authorization"] == "Bearer token-1":
+            return 401, {}, b'{"error":{"message":"token expired"}}'
+        inputs = json.loads(request["body"])["input"]
+        data = [{"object": "embedding", "index": index, "embedding": [1.0]} for index in range(len(inputs))]
+        return 200, {}, json.dumps({"object": "list", "data": data, "model": "text-embedding-3-small"}).encode()
+
+    server = mock_server(handler)
+    tokens = Tokens()
+    client = client_for(server, None, token_provider=tokens)
+    result = client.embeddings(["a", "b", "c"], "text-embedding-3-small", batch_size=2)
+    assert result.embeddings == [[1.0]] * 3
+    assert tokens.calls == 2
+    assert [json.loads(request["body"])["input"] for request in server.requests] == [["a", "b"], ["a", "b"], ["c"]]
+    assert [request["headers"]["authorization"] for request in server.requests][1:] == ["Bearer token-2"] * 2
+    assert client.stats()["token_refreshes"] == 1
+
+    server.requests.clear()
+    async_client = AsyncSecureClient(server.base_url.encode(), token_provider=Tokens(), allow_insecure_http=True)
+
+    async def embed():
+        return await async_client.embeddings(["a"], "text-embedding-3-small")
+
+    assert asyncio.run(embed()).embeddings == [[1.0]]
+    assert len(server.requests) == 2
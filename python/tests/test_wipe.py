@@ -0,0 +1,172 @@
+import pytest
+
+from conftest import needs_test_hooks, user_message
+from secure_openaiapi import (
+    SecureBytes,
+    SecureClient,
+    SecureMessage,
+    derive_key,
+    encrypt_keyfile,
+    memory_report,
+    store_in_keyring,
+)
+
+WIPED = "secret has been wiped"
+
+
+def test_wiped_bytes_cannot_be_used(tmp_path):
+    secret = SecureBytes(b"sk-wipe-me")
+    assert not secret.wiped
+    secret.wipe()
+    assert secret.wiped
+    secret.wipe()
+    uses = [
+        bytes,
+        str,
+        lambda s: s.split(2),
+        lambda s: s.check_integrity(),
+        lambda s: SecureClient(b"https://api.example.com/v1", s),
+        lambda s: SecureClient(s, b"sk-key"),
+        lambda s: derive_key(s, bytes(16), ops_limit=1, mem_limit=8192),
+        lambda s: encrypt_keyfile(tmp_path / "key", s, SecureBytes(b"passphrase"), ops_limit=1, mem_limit=8192),
+        lambda s: store_in_keyring("service", "account", s),
+    ]
+    for use in uses:
+        with pytest.raises(ValueError, match=WIPED):
+            use(secret)
+    assert repr(secret) == "SecureBytes(b'****')"
+
+
+def test_wiped_message_cannot_be_sent(mock_server):
+    server = mock_server()
+    client = SecureClient(server.base_url.encode(), b"sk-key", allow_insecure_http=True)
+    message = SecureMessage(b"user", [{"type": "text", "text": b"first"}, {"type": "image_url", "image_url": {"url": b"https://x/y.png"}}])
+    assert message.approx_tokens() > 0
+    message.wipe()
+    message.wipe()
+    assert message.wiped
+    with pytest.raises(ValueError, match=WIPED):
+        message.approx_tokens()
+    for send in (client.chat_completion, client.chat_completion_full):
+        with pytest.raises(ValueError, match=WIPED):
+            send([user_message(), message], "gpt-test")
+    with pytest.raises(ValueError, match=WIPED):
+        client.chat_completion_many([[user_message()], [message]], "gpt-test")
+    assert server.requests == []
+
+
+CLIENT_USES = {
+    "chat_completion": lambda c: c.chat_completion([user_message()], "gpt-test"),
+    "chat_completion_full": lambda c: c.chat_completion_full([user_message()], "gpt-test"),
+    "chat_completion_many": lambda c: c.chat_completion_many([[user_message()]], "gpt-test"),
+    "set_api_key": lambda c: c.set_api_key(b"sk-new"),
+    "set_base_url": lambda c: c.set_base_url(b"https://api.example.com/v1"),
+    "with_defaults": lambda c: c.with_defaults(temperature=0),
+    "last_idempotency_key": lambda c: c.last_idempotency_key(),
+    "last_request_id": lambda c: c.last_request_id(),
+    "last_rate_limits": lambda c: c.last_rate_limits(),
+    "set_audit_hook": lambda c: c.set_audit_hook(print),
+    "enable_audit_log": lambda c: c.enable_audit_log("unused-audit.log", SecureBytes(bytes(32))),
+    "rate_limit": lambda c: c.rate_limit(rpm=10),
+    "warm_up": lambda c: c.warm_up(),
+    "enable_cache": lambda c: c.enable_cache(10, 60),
+    "__enter__": lambda c: c.__enter__(),
+}
+
+
+@pytest.mark.parametrize("method, use", list(CLIENT_USES.items()))
+def test_wiped_client_cannot_be_used(mock_server, method, use):
+    server = mock_server()
+    client = SecureClient(server.base_url.encode(), b"sk-key", allow_insecure_http=True)
+    view = client.with_defaults(temperature=1)
+    client.wipe()
+    client.wipe()
+    assert client.wiped and client.closed and view.wiped
+    with pytest.raises(ValueError, match=WIPED):
+        use(client)
+    with pytest.raises(ValueError, match=WIPED):
+        view.chat_completion([user_message()], "gpt-test")
+    assert server.requests == []
+
+
+def test_wiped_client_keeps_metadata():
+    client = SecureClient(b"https://api.example.com/v1", b"sk-key", default_model="gpt-test")
+    client.wipe()
+    assert client.default_model == "gpt-test"
+    assert client.stats()["requests"] == 0
+    assert client.cache_stats()["entries"] == 0
+    client.clear_cache()
+    client.close()
+    assert client.__exit__() is False
+
+
+def test_closed_client_still_says_closed():
+    client = SecureClient(b"https://api.example.com/v1", b"sk-key")
+    client.close()
+    assert not client.wiped
+    with pytest.raises(RuntimeError, match="client is closed"):
+        client.last_request_id()
+
+
+def locked():
+    report = memory_report()
+    return report["locked_allocations"], report["locked_bytes"]
+
+
+@needs_test_hooks
+def test_resizing_keeps_locks_paired():
+    import copy
+
+    from secure_openaiapi.secure_openaiapi import _locked_memory_contains
+
+    baseline = locked()
+    secret = SecureBytes(b"sk-")
+    secret.append(b"grow")
+    secret.append(SecureBytes(b"-more"))
+    secret.append(secret)
+    assert bytes(secret) == b"sk-grow-moresk-grow-more"
+    # Each resize locks the new buffer and unlocks the old one, so only one stays locked.
+    assert locked()[0] == baseline[0] + 1
+    assert _locked_memory_contains(b"sk-grow-moresk-grow-more")
+
+    clone = copy.deepcopy(secret)
+    assert bytes(clone) == bytes(secret)
+    secret.truncate(7)
+    assert bytes(secret) == b"sk-grow"
+    secret.truncate(100)
+    assert bytes(secret) == b"sk-grow"
+    assert locked()[0] == baseline[0] + 2
+    clone.wipe()
+    del clone, secret
+    assert locked() == baseline
+
+    wiped = SecureBytes(b"gone")
+    wiped.wipe()
+    with pytest.raises(ValueError, match=WIPED):
+        wiped.append(b"more")
+    with pytest.raises(TypeError, match="data must be bytes or SecureBytes"):
+        SecureBytes(b"x").append("text")
+    assert locked() == baseline
+
+
+@needs_test_hooks
+def test_copied_message_is_locked_and_independent():
+    import copy
+
+    from secure_openaiapi.secure_openaiapi import _locked_memory_contains
+
+    baseline = locked()
+    original = SecureMessage(
+        "user",
+        [{"type": "text", "text": b"sk-copy-secret"}, {"type": "image_url", "image_url": {"url": b"https://example.com/a.png"}}],
+    )
+    copied = copy.deepcopy(original)
+    # The role and both parts each get a locked buffer of their own.
+    assert locked()[0] == baseline[0] + 6
+    assert repr(copied) == repr(original)
+    original.wipe()
+    assert not copied.wiped
+    assert _locked_memory_contains(b"sk-copy-secret")
+    assert copy.copy(original).wiped
+    del original, copied
+    assert locked() == baseline
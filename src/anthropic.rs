@@ -0,0 +1,394 @@
+use crate::json::{self, RequestBody, SecureJsonWriter};
+use crate::tool_calls::append;
+use crate::{
+    ChatCompletionRequest, ChatCompletionResponse, PromptTokensDetails, ResponseChoice, ResponseMessage, SecureContentPart,
+    SecureMessage, Usage,
+};
+use pyo3::prelude::*;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::io;
+use zeroize::Zeroizing;
+
+// --- Anthropic Messages API ---
+
+/// `max_tokens` is required by the Messages API; this is sent when the call sets neither
+/// `max_tokens` nor `max_completion_tokens`.
+const DEFAULT_MAX_TOKENS: u64 = 4096;
+
+/// Chat completion parameters the Messages API has no equivalent for. They are refused
+/// before anything is sent rather than silently dropped.
+const UNSUPPORTED_PARAMS: &[&str] = &[
+    "logit_bias",
+    "logprobs",
+    "top_logprobs",
+    "presence_penalty",
+    "frequency_penalty",
+    "seed",
+    "response_format",
+    "tools",
+    "tool_choice",
+    "parallel_tool_calls",
+    "stream_options",
+    "web_search_options",
+    "grammar",
+];
+
+/// Why a request could not be put into the Messages format.
+enum TranslateError {
+    Unsupported(String),
+    Io(io::Error),
+}
+
+impl From<io::Error> for TranslateError {
+    fn from(e: io::Error) -> Self {
+        TranslateError::Io(e)
+    }
+}
+
+fn unsupported(message: impl Into<String>) -> TranslateError {
+    TranslateError::Unsupported(message.into())
+}
+
+/// Serializes `request` as a Messages API request, into locked memory like
+/// `ChatCompletionRequest::to_json`. System messages become the top-level `system` blocks,
+/// the rest keep their order as `messages`.
+pub(crate) fn to_json(request: &ChatCompletionRequest<'_, '_>) -> PyResult<RequestBody> {
+    if request.grammar.is_some() {
+        return Err(unsupported_param("'grammar'"));
+    }
+    let params = translate_params(request.params)?;
+    let mut out = SecureJsonWriter::new()?;
+    write_request(&mut out, request, &params).map_err(|e| match e {
+        TranslateError::Unsupported(message) => PyErr::new::<pyo3::exceptions::PyValueError, _>(message),
+        TranslateError::Io(e) => json::serialize_error(e),
+    })?;
+    out.finish()
+}
+
+/// Maps the chat completion parameters onto their Messages API names. Anything not known
+/// to either API is passed through, so Anthropic-only parameters such as `top_k` work.
+fn translate_params(params: &Map<String, Value>) -> PyResult<Map<String, Value>> {
+    let mut translated = Map::new();
+    translated.insert("max_tokens".to_string(), Value::from(DEFAULT_MAX_TOKENS));
+    for (name, value) in params {
+        match name.as_str() {
+            "max_tokens" | "max_completion_tokens" => {
+                translated.insert("max_tokens".to_string(), value.clone());
+            }
+            "stop" => {
+                let sequences = if value.is_string() { Value::Array(vec![value.clone()]) } else { value.clone() };
+                translated.insert("stop_sequences".to_string(), sequences);
+            }
+            "user" => {
+                translated.insert("metadata".to_string(), serde_json::json!({ "user_id": value }));
+            }
+            "n" if value.as_u64() == Some(1) => {}
+            "n" => return Err(unsupported_param("n other than 1")),
+            name if UNSUPPORTED_PARAMS.contains(&name) => return Err(unsupported_param(&format!("'{}'", name))),
+            _ => {
+                translated.insert(name.clone(), value.clone());
+            }
+        }
+    }
+    Ok(translated)
+}
+
+fn unsupported_param(what: &str) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{} is not supported by the Anthropic Messages API", what))
+}
+
+fn write_request(
+    out: &mut SecureJsonWriter,
+    request: &ChatCompletionRequest<'_, '_>,
+    params: &Map<String, Value>,
+) -> Result<(), TranslateError> {
+    out.write_raw(br#"{"model":"#);
+    out.write_value(request.model)?;
+    let mut system = request.messages.iter().filter(|message| message.is_system()).flat_map(|message| &message.content).peekable();
+    if request.system.is_some() || system.peek().is_some() {
+        out.write_raw(br#","system":["#);
+        if let Some(prompt) = request.system {
+            write_text_block(out, prompt.bytes())?;
+        }
+        for (index, part) in system.enumerate() {
+            if index > 0 || request.system.is_some() {
+                out.write_raw(b",");
+            }
+            match part {
+                SecureContentPart::Text { text } => write_text_block(out, text.bytes())?,
+                SecureContentPart::ImageUrl { .. } => {
+                    return Err(unsupported("system messages can only hold text for the Anthropic Messages API"))
+                }
+            }
+        }
+        out.write_raw(b"]");
+    }
+    out.write_raw(br#","messages":["#);
+    for (index, message) in request.messages.iter().filter(|message| !message.is_system()).enumerate() {
+        if index > 0 {
+            out.write_raw(b",");
+        }
+        write_message(out, message)?;
+    }
+    out.write_raw(b"]");
+    if request.stream {
+        out.write_raw(br#","stream":true"#);
+    }
+    for (name, value) in params {
+        out.write_raw(b",");
+        out.write_value(name)?;
+        out.write_raw(b":");
+        out.write_value(value)?;
+    }
+    out.write_raw(b"}");
+    Ok(())
+}
+
+fn write_message(out: &mut SecureJsonWriter, message: &SecureMessage) -> Result<(), TranslateError> {
+    let role = message.role.bytes();
+    if role != b"user" && role != b"assistant" {
+        return Err(unsupported(format!(
+            "role '{}' is not supported by the Anthropic Messages API",
+            String::from_utf8_lossy(role)
+        )));
+    }
+    out.write_raw(br#"{"role":"#);
+    out.write_str(role)?;
+    out.write_raw(br#","content":["#);
+    for (index, part) in message.content.iter().enumerate() {
+        if index > 0 {
+            out.write_raw(b",");
+        }
+        match part {
+            SecureContentPart::Text { text } => write_text_block(out, text.bytes())?,
+            SecureContentPart::ImageUrl { image_url } => write_image_block(out, image_url.url.bytes())?,
+        }
+    }
+    out.write_raw(b"]}");
+    Ok(())
+}
+
+fn write_text_block(out: &mut SecureJsonWriter, text: &[u8]) -> io::Result<()> {
+    out.write_raw(br#"{"type":"text","text":"#);
+    out.write_str(text)?;
+    out.write_raw(b"}");
+    Ok(())
+}
+
+/// A `data:` URL becomes a base64 source block, written from slices of the locked URL;
+/// an http(s) URL a url source block, which Anthropic fetches itself.
+fn write_image_block(out: &mut SecureJsonWriter, url: &[u8]) -> Result<(), TranslateError> {
+    out.write_raw(br#"{"type":"image","source":"#);
+    if let Some(data_url) = url.strip_prefix(b"data:") {
+        let comma = data_url.iter().position(|&b| b == b',').ok_or_else(|| unsupported("image data URL has no ',' before its data"))?;
+        let media_type = data_url[..comma]
+            .strip_suffix(b";base64")
+            .ok_or_else(|| unsupported("image data URLs must be base64-encoded for the Anthropic Messages API"))?;
+        out.write_raw(br#"{"type":"base64","media_type":"#);
+        out.write_str(media_type)?;
+        out.write_raw(br#","data":"#);
+        out.write_str(&data_url[comma + 1..])?;
+    } else if url.starts_with(b"https://") || url.starts_with(b"http://") {
+        out.write_raw(br#"{"type":"url","url":"#);
+        out.write_str(url)?;
+    } else {
+        return Err(unsupported("image URLs must be data: or http(s) URLs for the Anthropic Messages API"));
+    }
+    out.write_raw(b"}}");
+    Ok(())
+}
+
+// --- Anthropic Responses ---
+
+#[derive(Deserialize)]
+struct MessagesResponse {
+    id: Option<String>,
+    model: Option<String>,
+    #[serde(default)]
+    content: Vec<ContentBlock>,
+    stop_reason: Option<String>,
+    usage: Option<MessagesUsage>,
+}
+
+/// Only text blocks are read; `thinking` and other block types are skipped.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentBlock {
+    Text {
+        text: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct MessagesUsage {
+    input_tokens: Option<u64>,
+    output_tokens: Option<u64>,
+    cache_read_input_tokens: Option<u64>,
+}
+
+impl MessagesUsage {
+    fn into_usage(self) -> Usage {
+        Usage {
+            prompt_tokens: self.input_tokens,
+            completion_tokens: self.output_tokens,
+            total_tokens: None,
+            prompt_tokens_details: self.cache_read_input_tokens.map(|cached| PromptTokensDetails { cached_tokens: Some(cached) }),
+            completion_tokens_details: None,
+        }
+    }
+}
+
+/// The chat completion `finish_reason` for a `stop_reason`; unknown reasons pass through.
+fn finish_reason(stop_reason: String) -> String {
+    match stop_reason.as_str() {
+        "end_turn" | "stop_sequence" => "stop".to_string(),
+        "max_tokens" => "length".to_string(),
+        "tool_use" => "tool_calls".to_string(),
+        "refusal" => "content_filter".to_string(),
+        _ => stop_reason,
+    }
+}
+
+fn completion(
+    id: Option<String>,
+    model: Option<String>,
+    content: Zeroizing<String>,
+    stop_reason: Option<String>,
+    usage: Option<Usage>,
+) -> ChatCompletionResponse {
+    let mut content = content;
+    ChatCompletionResponse {
+        id,
+        model,
+        created: None,
+        system_fingerprint: None,
+        choices: vec![ResponseChoice {
+            message: ResponseMessage {
+                content: Some(std::mem::take(&mut *content)),
+                refusal: None,
+                tool_calls: None,
+                annotations: None,
+            },
+            finish_reason: stop_reason.map(finish_reason),
+            logprobs: None,
+        }],
+        usage,
+    }
+}
+
+/// Parses a Messages API response into the shape of a chat completion: the text blocks
+/// joined as the one choice, `stop_reason` as `finish_reason`, input and output tokens as
+/// prompt and completion tokens.
+pub(crate) fn parse_response(body: &[u8]) -> PyResult<ChatCompletionResponse> {
+    let response = serde_json::from_slice::<MessagesResponse>(body)
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Failed to parse JSON response: {}", e)))?;
+    let mut content = Zeroizing::new(String::new());
+    for block in response.content {
+        if let ContentBlock::Text { text } = block {
+            let text = Zeroizing::new(text);
+            append(&mut content, &text);
+        }
+    }
+    Ok(completion(response.id, response.model, content, response.stop_reason, response.usage.map(MessagesUsage::into_usage)))
+}
+
+// --- Anthropic Streams ---
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent {
+    MessageStart { message: StreamMessage },
+    ContentBlockDelta { delta: BlockDelta },
+    MessageDelta { delta: MessageDelta, usage: Option<MessagesUsage> },
+    Error { error: StreamError },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct StreamMessage {
+    id: Option<String>,
+    model: Option<String>,
+    usage: Option<MessagesUsage>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BlockDelta {
+    TextDelta {
+        text: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize, Debug)]
+struct MessageDelta {
+    stop_reason: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct StreamError {
+    message: Option<String>,
+}
+
+/// The text of one `content_block_delta` event, for `stream::DeltaScanner`.
+pub(crate) fn delta_text(data: &[u8]) -> Option<Zeroizing<String>> {
+    match serde_json::from_slice::<StreamEvent>(data).ok()? {
+        StreamEvent::ContentBlockDelta { delta: BlockDelta::TextDelta { text } } => Some(Zeroizing::new(text)),
+        _ => None,
+    }
+}
+
+/// Parses a complete Messages API event stream like `stream::parse_events` does for chat
+/// completions. `message_start` carries the input tokens and `message_delta` the output
+/// tokens so far, so the last of each is kept.
+pub(crate) fn parse_events(body: &[u8], request_id: &str) -> PyResult<ChatCompletionResponse> {
+    let (mut id, mut model, mut stop_reason) = (None, None, None);
+    let mut usage = MessagesUsage::default();
+    let mut content = Zeroizing::new(String::new());
+    for line in body.split(|&b| b == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let Some(data) = line.strip_prefix(b"data:") else {
+            continue;
+        };
+        let data = data.strip_prefix(b" ").unwrap_or(data);
+        let event = serde_json::from_slice::<StreamEvent>(data).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Failed to parse streamed event: {}", e))
+        })?;
+        match event {
+            StreamEvent::MessageStart { message } => {
+                id = message.id;
+                model = message.model;
+                if let Some(start) = message.usage {
+                    usage.input_tokens = start.input_tokens;
+                    usage.cache_read_input_tokens = start.cache_read_input_tokens;
+                    usage.output_tokens = start.output_tokens;
+                }
+            }
+            StreamEvent::ContentBlockDelta { delta: BlockDelta::TextDelta { text } } => {
+                let text = Zeroizing::new(text);
+                append(&mut content, &text);
+            }
+            StreamEvent::MessageDelta { delta, usage: delta_usage } => {
+                stop_reason = delta.stop_reason.or(stop_reason);
+                if let Some(output_tokens) = delta_usage.and_then(|delta_usage| delta_usage.output_tokens) {
+                    usage.output_tokens = Some(output_tokens);
+                }
+            }
+            StreamEvent::Error { error } => {
+                return Err(PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+                    "API reported an error mid-stream (request id {}): {}",
+                    request_id,
+                    error.message.as_deref().unwrap_or("no message")
+                )));
+            }
+            StreamEvent::ContentBlockDelta { .. } | StreamEvent::Other => {}
+        }
+    }
+    let has_usage = usage.input_tokens.is_some() || usage.output_tokens.is_some();
+    Ok(completion(id, model, content, stop_reason, has_usage.then(|| usage.into_usage())))
+}
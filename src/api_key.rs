@@ -0,0 +1,162 @@
+use crate::SecureBytes;
+use libsodium_sys::{
+    crypto_generichash, crypto_secretbox_easy, crypto_secretbox_keygen, crypto_secretbox_open_easy, randombytes_buf, sodium_free, sodium_malloc,
+    sodium_mprotect_noaccess, sodium_mprotect_readonly, crypto_secretbox_KEYBYTES, crypto_secretbox_MACBYTES,
+    crypto_secretbox_NONCEBYTES,
+};
+use pyo3::prelude::*;
+use std::ffi::c_void;
+use std::sync::Mutex;
+
+// --- API Key Storage ---
+
+const KEY_BYTES: usize = crypto_secretbox_KEYBYTES as usize;
+const NONCE_BYTES: usize = crypto_secretbox_NONCEBYTES as usize;
+const MAC_BYTES: usize = crypto_secretbox_MACBYTES as usize;
+
+/// The API key of a client: either a locked plaintext buffer, or with `encrypt_at_rest`
+/// sealed under an ephemeral key so the plaintext only exists while a request is built.
+pub(crate) enum ApiKey {
+    Plain(SecureBytes),
+    Sealed(SealedKey),
+}
+
+impl ApiKey {
+    pub(crate) fn new(key: SecureBytes, encrypt_at_rest: bool) -> PyResult<Self> {
+        Ok(if encrypt_at_rest { Self::Sealed(SealedKey::seal(key.expose()?)?) } else { Self::Plain(key) })
+    }
+
+    /// Runs `f` on the plaintext key. A sealed key is decrypted into a fresh locked buffer
+    /// that is wiped as soon as `f` returns.
+    pub(crate) fn with_plaintext<R>(&self, f: impl FnOnce(&[u8]) -> PyResult<R>) -> PyResult<R> {
+        match self {
+            Self::Plain(key) => f(key.expose()?),
+            Self::Sealed(sealed) => f(sealed.open()?.expose()?),
+        }
+    }
+
+    /// The first 4 hex digits of the key's BLAKE2b hash: enough to tell keys apart in a
+    /// `repr`, far too little to say anything about the key.
+    pub(crate) fn fingerprint(&self) -> PyResult<String> {
+        self.with_plaintext(|key| {
+            let mut hash = [0u8; 32];
+            unsafe {
+                crypto_generichash(hash.as_mut_ptr(), hash.len(), key.as_ptr(), key.len() as u64, std::ptr::null(), 0);
+            }
+            Ok(format!("{:02x}{:02x}", hash[0], hash[1]))
+        })
+    }
+}
+
+/// Checks that an API key holds only visible ASCII, as any header value built from it must.
+/// A single trailing newline, as left by reading a key file, is trimmed; anything else is
+/// rejected naming the position of the offending byte, never its value.
+pub(crate) fn header_safe(mut key: SecureBytes, name: &str) -> PyResult<SecureBytes> {
+    let bytes = key.expose()?;
+    let trimmed = bytes.strip_suffix(b"\r\n").or_else(|| bytes.strip_suffix(b"\n")).or_else(|| bytes.strip_suffix(b"\r"));
+    let len = trimmed.map_or(bytes.len(), <[u8]>::len);
+    if let Some(position) = bytes[..len].iter().position(|byte| !byte.is_ascii_graphic()) {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "{} contains a character that is not visible ASCII at position {}; API keys cannot hold spaces, line breaks or control characters",
+            name, position
+        )));
+    }
+    if len < bytes.len() {
+        key.resize(len)?;
+    }
+    Ok(key)
+}
+
+/// The key encrypted with `crypto_secretbox`. The box key lives in its own `sodium_malloc`
+/// allocation (guard pages, locked) that stays `PROT_NONE` except while a request opens
+/// the box, so a stray read of the process finds neither the plaintext nor the box key.
+pub(crate) struct SealedKey {
+    box_key: *mut u8,
+    nonce: [u8; NONCE_BYTES],
+    sealed: Vec<u8>,
+    /// Held while `box_key` is readable: one thread making it inaccessible again must not
+    /// pull it from under another that is still decrypting.
+    access: Mutex<()>,
+}
+
+// SAFETY: `box_key` is owned by the `SealedKey` and only read while `access` is held.
+unsafe impl Send for SealedKey {}
+unsafe impl Sync for SealedKey {}
+
+impl SealedKey {
+    fn seal(plaintext: &[u8]) -> PyResult<Self> {
+        let box_key = unsafe { sodium_malloc(KEY_BYTES) } as *mut u8;
+        if box_key.is_null() {
+            return Err(PyErr::new::<pyo3::exceptions::PyMemoryError, _>("cannot allocate protected memory for the API key"));
+        }
+        let mut nonce = [0u8; NONCE_BYTES];
+        let mut sealed = vec![0u8; plaintext.len() + MAC_BYTES];
+        unsafe {
+            crypto_secretbox_keygen(box_key);
+            randombytes_buf(nonce.as_mut_ptr() as *mut c_void, nonce.len());
+            crypto_secretbox_easy(sealed.as_mut_ptr(), plaintext.as_ptr(), plaintext.len() as u64, nonce.as_ptr(), box_key);
+            sodium_mprotect_noaccess(box_key as *mut c_void);
+        }
+        Ok(Self { box_key, nonce, sealed, access: Mutex::new(()) })
+    }
+
+    fn open(&self) -> PyResult<SecureBytes> {
+        let mut plaintext = SecureBytes::zeroed(self.sealed.len() - MAC_BYTES)?;
+        let _access = self.access.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let opened = unsafe {
+            sodium_mprotect_readonly(self.box_key as *mut c_void);
+            let opened = crypto_secretbox_open_easy(
+                plaintext.bytes_mut().as_mut_ptr(),
+                self.sealed.as_ptr(),
+                self.sealed.len() as u64,
+                self.nonce.as_ptr(),
+                self.box_key,
+            );
+            sodium_mprotect_noaccess(self.box_key as *mut c_void);
+            opened
+        };
+        if opened != 0 {
+            return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("the sealed API key failed to decrypt"));
+        }
+        Ok(plaintext)
+    }
+}
+
+impl Drop for SealedKey {
+    fn drop(&mut self) {
+        // `sodium_free` makes the allocation writable again and wipes it.
+        unsafe { sodium_free(self.box_key as *mut c_void) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory;
+
+    #[test]
+    fn sealed_key_keeps_no_plaintext_between_uses() {
+        let _serial = memory::tests::SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let plaintext = b"sk-at-rest-7f3a9c01d2e4b658";
+        let key = ApiKey::new(SecureBytes::try_new(plaintext).unwrap(), true).unwrap();
+        let ApiKey::Sealed(sealed) = &key else {
+            panic!("encrypt_at_rest seals the key");
+        };
+        assert!(!sealed.sealed.windows(plaintext.len()).any(|window| window == plaintext));
+        assert!(!memory::locked_memory_contains(plaintext));
+        for _ in 0..2 {
+            key.with_plaintext(|opened| {
+                assert_eq!(opened, plaintext);
+                assert!(memory::locked_memory_contains(plaintext));
+                Ok(())
+            })
+            .unwrap();
+            assert!(!memory::locked_memory_contains(plaintext));
+        }
+
+        let plain = ApiKey::new(SecureBytes::try_new(plaintext).unwrap(), false).unwrap();
+        assert!(memory::locked_memory_contains(plaintext));
+        drop(plain);
+        assert!(!memory::locked_memory_contains(plaintext));
+    }
+}
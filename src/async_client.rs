@@ -0,0 +1,445 @@
+use crate::body::DEFAULT_MAX_RESPONSE_BYTES;
+use crate::call::{self, complete, ChatRequest};
+use crate::embeddings::{self, SecureEmbeddings};
+use crate::keyring;
+use crate::mock;
+use crate::moderation;
+use crate::redact;
+use crate::retry::DEFAULT_MAX_RETRY_WAIT;
+use crate::shares;
+use crate::transport::Http2;
+use crate::messages::Messages;
+use crate::{not_transferable, SecureBytes, SecureClient, DEFAULT_TIMEOUT};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyType};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+// --- AsyncSecureClient ---
+
+/// The asyncio flavour of `SecureClient`: same constructor, same request pipeline, and a
+/// `chat_completion` that returns an awaitable instead of blocking. Safe to share across
+/// threads and event loops like `SecureClient`.
+#[pyclass(name = "AsyncSecureClient", frozen)]
+pub(crate) struct AsyncSecureClient {
+    client: SecureClient,
+}
+
+#[pymethods]
+impl AsyncSecureClient {
+    #[new]
+    #[pyo3(signature = (
+        base_url,
+        api_key=None,
+        *,
+        auto_idempotency=false,
+        max_response_bytes=DEFAULT_MAX_RESPONSE_BYTES,
+        follow_redirects=0,
+        dns_overrides=None,
+        ip_version="auto",
+        local_address=None,
+        uds_host="localhost",
+        default_model=None,
+        defaults=None,
+        strict_params=false,
+        reasoning_models=None,
+        system_role_translation=None,
+        privacy_mode=false,
+        moderate_inputs=false,
+        moderation_model=moderation::DEFAULT_MODERATION_MODEL,
+        moderation_thresholds=None,
+        default_headers=None,
+        allow_insecure_http=false,
+        path_style="default",
+        api_version=None,
+        provider=None,
+        anthropic=false,
+        max_retries=0,
+        max_retry_wait=DEFAULT_MAX_RETRY_WAIT.as_secs_f64(),
+        timeout=Some(DEFAULT_TIMEOUT.as_secs_f64()),
+        deadline=None,
+        include_error_body=false,
+        max_error_text=redact::MAX_ERROR_TEXT,
+        on_fingerprint_change=None,
+        compression=false,
+        http2=Http2::Off,
+        encrypt_at_rest=false,
+        signer=None,
+        token_provider=None,
+        system_prompt=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        py: Python<'_>,
+        base_url: &Bound<'_, PyAny>,
+        api_key: Option<&Bound<'_, PyAny>>,
+        auto_idempotency: bool,
+        max_response_bytes: usize,
+        follow_redirects: usize,
+        dns_overrides: Option<HashMap<String, String>>,
+        ip_version: &str,
+        local_address: Option<&str>,
+        uds_host: &str,
+        default_model: Option<String>,
+        defaults: Option<&Bound<'_, PyDict>>,
+        strict_params: bool,
+        reasoning_models: Option<Vec<String>>,
+        system_role_translation: Option<&str>,
+        privacy_mode: bool,
+        moderate_inputs: bool,
+        moderation_model: &str,
+        moderation_thresholds: Option<HashMap<String, f64>>,
+        default_headers: Option<&Bound<'_, PyDict>>,
+        allow_insecure_http: bool,
+        path_style: &str,
+        api_version: Option<&str>,
+        provider: Option<&str>,
+        anthropic: bool,
+        max_retries: u32,
+        max_retry_wait: f64,
+        timeout: Option<f64>,
+        deadline: Option<f64>,
+        include_error_body: bool,
+        max_error_text: usize,
+        on_fingerprint_change: Option<Bound<'_, PyAny>>,
+        compression: bool,
+        http2: Http2,
+        encrypt_at_rest: bool,
+        signer: Option<Bound<'_, PyAny>>,
+        token_provider: Option<Bound<'_, PyAny>>,
+        system_prompt: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Self> {
+        let client = SecureClient::new(
+            py,
+            base_url,
+            api_key,
+            auto_idempotency,
+            max_response_bytes,
+            follow_redirects,
+            dns_overrides,
+            ip_version,
+            local_address,
+            uds_host,
+            default_model,
+            defaults,
+            strict_params,
+            reasoning_models,
+            system_role_translation,
+            privacy_mode,
+            moderate_inputs,
+            moderation_model,
+            moderation_thresholds,
+            default_headers,
+            allow_insecure_http,
+            path_style,
+            api_version,
+            provider,
+            anthropic,
+            max_retries,
+            max_retry_wait,
+            timeout,
+            deadline,
+            include_error_body,
+            max_error_text,
+            on_fingerprint_change,
+            compression,
+            http2,
+            encrypt_at_rest,
+            signer,
+            token_provider,
+            system_prompt,
+        )?;
+        Ok(Self { client })
+    }
+
+    /// Like `SecureClient.chat_completion`, but returns an awaitable resolving to `SecureBytes`.
+    /// Must be called from a running event loop. Cancelling the awaiting task aborts the
+    /// request in flight, and any part of the response read so far is wiped.
+    #[pyo3(signature = (messages, model=None, *, idempotency_key=None, extra_headers=None, timeout=None, strict=false, stream=false, **params))]
+    #[allow(clippy::too_many_arguments)]
+    fn chat_completion<'py>(
+        &self,
+        py: Python<'py>,
+        messages: Messages<'_>,
+        model: Option<String>,
+        idempotency_key: Option<String>,
+        extra_headers: Option<&Bound<'_, PyDict>>,
+        timeout: Option<f64>,
+        strict: bool,
+        stream: bool,
+        params: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let chat = ChatRequest::new(&self.client, messages, model, idempotency_key, extra_headers, timeout, strict, stream, params);
+        let (call, request) = chat.prepare(py)?;
+        // Cancelling the Python future drops this one, and with it the in-flight request
+        // and the partially read body.
+        pyo3_async_runtimes::tokio::future_into_py(py, async move {
+            let response = complete(chat, call, request).await?;
+            Python::with_gil(|py| response.into_content(py))
+        })
+    }
+
+    /// Like `SecureClient.chat_completion_full`, but returns an awaitable resolving to a
+    /// `SecureResponse`.
+    #[pyo3(signature = (messages, model=None, *, idempotency_key=None, extra_headers=None, timeout=None, strict=false, stream=false, **params))]
+    #[allow(clippy::too_many_arguments)]
+    fn chat_completion_full<'py>(
+        &self,
+        py: Python<'py>,
+        messages: Messages<'_>,
+        model: Option<String>,
+        idempotency_key: Option<String>,
+        extra_headers: Option<&Bound<'_, PyDict>>,
+        timeout: Option<f64>,
+        strict: bool,
+        stream: bool,
+        params: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let chat = ChatRequest::new(&self.client, messages, model, idempotency_key, extra_headers, timeout, strict, stream, params);
+        let (call, request) = chat.prepare(py)?;
+        pyo3_async_runtimes::tokio::future_into_py(py, complete(chat, call, request))
+    }
+
+    /// Like `SecureClient.embeddings`, but returns an awaitable resolving to
+    /// `SecureEmbeddings`. Cancelling it stops at the request in flight.
+    #[pyo3(signature = (input, model, *, dimensions=None, encoding_format="float", batch_size=embeddings::MAX_BATCH_SIZE, secure_output=false, extra_headers=None, timeout=None, deadline=None, **params))]
+    #[allow(clippy::too_many_arguments)]
+    fn embeddings<'py>(
+        &self,
+        py: Python<'py>,
+        input: &Bound<'_, PyAny>,
+        model: String,
+        dimensions: Option<u32>,
+        encoding_format: &str,
+        batch_size: usize,
+        secure_output: bool,
+        extra_headers: Option<&Bound<'_, PyDict>>,
+        timeout: Option<f64>,
+        deadline: Option<f64>,
+        params: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let inputs = embeddings::inputs(input)?;
+        let embeddings = call::EmbeddingsRequest::new(
+            &self.client,
+            inputs,
+            model,
+            dimensions,
+            encoding_format,
+            batch_size,
+            extra_headers,
+            timeout,
+            deadline,
+            params,
+        )?;
+        pyo3_async_runtimes::tokio::future_into_py(py, async move {
+            let mut results = Vec::with_capacity(embeddings.batches());
+            for index in 0..embeddings.batches() {
+                let (call, request, count) = Python::with_gil(|py| embeddings.prepare(py, index))?;
+                let token = call.api_key();
+                let outcome = call.send(request).await;
+                let (result, retry) = Python::with_gil(|py| {
+                    let result = call.finish_embeddings(py, outcome, count);
+                    let retry = embeddings.refreshed(py, index, &token, result.as_ref().err());
+                    (result, retry)
+                });
+                results.push(match retry {
+                    Some(retry) => {
+                        let (call, request, count) = retry?;
+                        let outcome = call.send(request).await;
+                        Python::with_gil(|py| call.finish_embeddings(py, outcome, count))?
+                    }
+                    None => result?,
+                });
+            }
+            Python::with_gil(|py| SecureEmbeddings::merge(py, results, secure_output))
+        })
+    }
+
+    /// Like `SecureClient.from_shares`.
+    #[classmethod]
+    #[pyo3(signature = (base_url, share_paths, **kwargs))]
+    fn from_shares<'py>(
+        cls: &Bound<'py, PyType>,
+        base_url: &Bound<'py, PyAny>,
+        share_paths: Vec<PathBuf>,
+        kwargs: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        shares::build_client(cls, base_url, share_paths, kwargs)
+    }
+
+    /// Like `SecureClient.from_keyring`.
+    #[classmethod]
+    #[pyo3(signature = (base_url, service, account, **kwargs))]
+    fn from_keyring<'py>(
+        cls: &Bound<'py, PyType>,
+        base_url: &Bound<'py, PyAny>,
+        service: &str,
+        account: &str,
+        kwargs: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        keyring::build_client(cls, base_url, service, account, kwargs)
+    }
+
+    /// Like `SecureClient.with_mock_transport`; the handler is still called synchronously.
+    #[classmethod]
+    #[pyo3(signature = (handler, **kwargs))]
+    fn with_mock_transport<'py>(
+        cls: &Bound<'py, PyType>,
+        handler: &Bound<'py, PyAny>,
+        kwargs: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let client = mock::build_client(cls, handler, kwargs)?;
+        mock::install(&client.downcast::<AsyncSecureClient>()?.get().client.core, handler)?;
+        Ok(client)
+    }
+
+    #[pyo3(signature = (**overrides))]
+    fn with_defaults(&self, overrides: Option<&Bound<'_, PyDict>>) -> PyResult<Self> {
+        Ok(Self { client: self.client.with_defaults(overrides)? })
+    }
+
+    fn close(&self) {
+        self.client.close();
+    }
+
+    #[getter]
+    fn closed(&self) -> bool {
+        self.client.closed()
+    }
+
+    fn wipe(&self) {
+        self.client.wipe();
+    }
+
+    #[getter]
+    fn wiped(&self) -> bool {
+        self.client.wiped()
+    }
+
+    #[getter]
+    fn privacy_mode(&self) -> bool {
+        self.client.privacy_mode()
+    }
+
+    #[getter]
+    fn moderate_inputs(&self) -> bool {
+        self.client.moderate_inputs()
+    }
+
+    fn __aenter__<'py>(slf: Bound<'py, Self>) -> PyResult<Bound<'py, PyAny>> {
+        slf.borrow().client.core.ensure_open()?;
+        let py = slf.py();
+        let client = slf.unbind();
+        pyo3_async_runtimes::tokio::future_into_py(py, async move { Ok(client) })
+    }
+
+    #[pyo3(signature = (_exc_type=None, _exc_value=None, _traceback=None))]
+    fn __aexit__<'py>(
+        &self,
+        py: Python<'py>,
+        _exc_type: Option<&Bound<'_, PyAny>>,
+        _exc_value: Option<&Bound<'_, PyAny>>,
+        _traceback: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        self.close();
+        pyo3_async_runtimes::tokio::future_into_py(py, async { Ok(false) })
+    }
+
+    fn set_api_key(&self, new_key: &Bound<'_, PyAny>) -> PyResult<()> {
+        self.client.set_api_key(new_key)
+    }
+
+    fn set_base_url(&self, new_url: &Bound<'_, PyAny>) -> PyResult<()> {
+        self.client.set_base_url(new_url)
+    }
+
+    fn set_audit_hook(&self, hook: Option<Bound<'_, PyAny>>) -> PyResult<()> {
+        self.client.set_audit_hook(hook)
+    }
+
+    fn enable_audit_log(&self, path: PathBuf, hmac_key: PyRef<'_, SecureBytes>) -> PyResult<()> {
+        self.client.enable_audit_log(path, hmac_key)
+    }
+
+    fn record_to(&self, path: PathBuf, key: PyRef<'_, SecureBytes>) -> PyResult<()> {
+        self.client.record_to(path, key)
+    }
+
+    fn replay_from(&self, path: PathBuf, key: PyRef<'_, SecureBytes>) -> PyResult<()> {
+        self.client.replay_from(path, key)
+    }
+
+    #[pyo3(signature = (rpm=None, tpm=None, max_wait=None))]
+    fn rate_limit(&self, rpm: Option<u32>, tpm: Option<u32>, max_wait: Option<f64>) -> PyResult<()> {
+        self.client.rate_limit(rpm, tpm, max_wait)
+    }
+
+    #[getter]
+    fn default_model(&self) -> Option<String> {
+        self.client.default_model()
+    }
+
+    #[getter]
+    fn defaults<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self.client.defaults(py)
+    }
+
+    fn last_idempotency_key(&self) -> PyResult<Option<String>> {
+        self.client.last_idempotency_key()
+    }
+
+    fn last_request_id(&self) -> PyResult<Option<String>> {
+        self.client.last_request_id()
+    }
+
+    fn last_rate_limits<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        self.client.last_rate_limits(py)
+    }
+
+    /// Like `SecureClient.warm_up`, but returns an awaitable.
+    fn warm_up<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let (core, connection) = (Arc::clone(&self.client.core), self.client.core.connection()?);
+        pyo3_async_runtimes::tokio::future_into_py(py, call::warm_up(core, connection))
+    }
+
+    fn stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        self.client.stats(py)
+    }
+
+    fn metrics<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        self.client.metrics(py)
+    }
+
+    fn reset_metrics(&self) {
+        self.client.reset_metrics();
+    }
+
+    fn enable_cache(&self, max_entries: usize, ttl_seconds: f64) -> PyResult<()> {
+        self.client.enable_cache(max_entries, ttl_seconds)
+    }
+
+    fn cache_stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        self.client.cache_stats(py)
+    }
+
+    fn clear_cache(&self) {
+        self.client.clear_cache();
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Async{}", self.client.__repr__())
+    }
+
+    fn __reduce__(&self) -> PyResult<()> {
+        Err(not_transferable("AsyncSecureClient", "pickled"))
+    }
+
+    fn __copy__(&self) -> PyResult<()> {
+        Err(not_transferable("AsyncSecureClient", "copied"))
+    }
+
+    fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> PyResult<()> {
+        Err(not_transferable("AsyncSecureClient", "copied"))
+    }
+}
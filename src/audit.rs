@@ -0,0 +1,298 @@
+use crate::logging::LogEvent;
+use crate::SecureBytes;
+use libsodium_sys::{crypto_auth, crypto_auth_verify, crypto_generichash, crypto_auth_BYTES, crypto_auth_KEYBYTES};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// --- Audit Hook ---
+
+/// Token counts reported by the provider for one call.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct TokenCounts {
+    pub(crate) prompt: Option<u64>,
+    pub(crate) completion: Option<u64>,
+    pub(crate) total: Option<u64>,
+}
+
+/// Metadata about one API call. It only ever holds values that are safe to log:
+/// message content, keys and response bodies are never copied into it.
+pub(crate) struct AuditRecord {
+    timestamp: SystemTime,
+    started: Instant,
+    endpoint: String,
+    model: String,
+    pub(crate) estimated_tokens: u64,
+    pub(crate) status: Option<u16>,
+    pub(crate) request_id: Option<String>,
+    pub(crate) tokens: TokenCounts,
+    /// Header names only, collected while logging is enabled.
+    pub(crate) request_headers: Vec<String>,
+    pub(crate) response_headers: Vec<String>,
+    /// Content hashes, computed while an audit log is enabled.
+    pub(crate) request_hash: Option<ContentHash>,
+    pub(crate) response_hash: Option<ContentHash>,
+    /// Set on the second attempt of a call whose token was rejected with a 401.
+    pub(crate) token_refreshed: bool,
+    /// The role system messages were sent with under `system_role_translation`, if any.
+    pub(crate) system_role: Option<&'static str>,
+}
+
+impl AuditRecord {
+    pub(crate) fn start(endpoint: &str, model: &str) -> Self {
+        Self {
+            timestamp: SystemTime::now(),
+            started: Instant::now(),
+            endpoint: endpoint.to_string(),
+            model: model.to_string(),
+            estimated_tokens: 0,
+            status: None,
+            request_id: None,
+            tokens: TokenCounts::default(),
+            request_headers: Vec::new(),
+            response_headers: Vec::new(),
+            request_hash: None,
+            response_hash: None,
+            token_refreshed: false,
+            system_role: None,
+        }
+    }
+
+    fn to_dict<'py>(&self, py: Python<'py>, latency: Duration, error: Option<&PyErr>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        let timestamp = self.timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+        dict.set_item("timestamp", timestamp.as_secs_f64())?;
+        dict.set_item("endpoint", &self.endpoint)?;
+        dict.set_item("model", &self.model)?;
+        dict.set_item("status", self.status)?;
+        dict.set_item("latency", latency.as_secs_f64())?;
+        dict.set_item("request_id", &self.request_id)?;
+        dict.set_item("estimated_tokens", self.estimated_tokens)?;
+        dict.set_item("prompt_tokens", self.tokens.prompt)?;
+        dict.set_item("completion_tokens", self.tokens.completion)?;
+        dict.set_item("total_tokens", self.tokens.total)?;
+        dict.set_item("token_refreshed", self.token_refreshed)?;
+        dict.set_item("system_role", self.system_role)?;
+        // Only the exception type: messages can quote the response body.
+        let error = error.map(|e| e.get_type(py).name()).transpose()?;
+        dict.set_item("error", error)?;
+        Ok(dict)
+    }
+
+    /// The record as the logging module gets it.
+    pub(crate) fn log_event(&self, py: Python<'_>, attempts: u32, error: Option<&PyErr>) -> LogEvent {
+        LogEvent {
+            endpoint: self.endpoint.clone(),
+            model: self.model.clone(),
+            status: self.status,
+            latency: self.started.elapsed().as_secs_f64(),
+            attempts,
+            request_id: self.request_id.clone(),
+            prompt_tokens: self.tokens.prompt,
+            completion_tokens: self.tokens.completion,
+            total_tokens: self.tokens.total,
+            error: error.and_then(|e| e.get_type(py).name().ok()).map(|name| name.to_string()),
+            request_headers: self.request_headers.clone(),
+            response_headers: self.response_headers.clone(),
+        }
+    }
+
+    pub(crate) fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    pub(crate) fn model(&self) -> &str {
+        &self.model
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Passes the finished record to `hook`. A failing hook is reported through
+    /// `sys.unraisablehook` and never changes the outcome of the call.
+    pub(crate) fn finish(self, py: Python<'_>, hook: &Bound<'_, PyAny>, error: Option<&PyErr>) {
+        let latency = self.started.elapsed();
+        if let Err(e) = self.to_dict(py, latency, error).and_then(|record| hook.call1((record,))) {
+            e.write_unraisable(py, Some(hook));
+        }
+    }
+}
+
+// --- Tamper-Evident Audit Log ---
+
+const MAC_BYTES: usize = crypto_auth_BYTES as usize;
+
+/// Keyed BLAKE2b of request or response content, as written to the audit log.
+pub(crate) type ContentHash = [u8; 32];
+
+/// An append-only file with one JSON line per call. Each line carries the MAC of the line
+/// before it as `prev` and ends with its own `mac`, HMAC-SHA-512/256 (`crypto_auth`) over
+/// everything before it, so editing, reordering or removing a line breaks the chain from
+/// there on. Content is only ever written as `request_hash` and `response_hash`, BLAKE2b
+/// keyed with the same key, so the log cannot be used to confirm guessed content without it.
+pub(crate) struct AuditLog {
+    file: File,
+    key: SecureBytes,
+    previous: [u8; MAC_BYTES],
+    entries: u64,
+}
+
+impl AuditLog {
+    /// Opens `path` for appending. An existing log is verified first, and the chain
+    /// continues from its last entry.
+    pub(crate) fn open(path: &Path, key: &SecureBytes) -> PyResult<Self> {
+        let key = SecureBytes::try_new(audit_key(key)?)?;
+        let (previous, entries) = match File::open(path) {
+            Ok(file) => {
+                let verification = verify(BufReader::new(file), key.expose()?)?;
+                if let Some((line, reason)) = verification.broken {
+                    return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                        "audit log fails verification at line {}: {}; refusing to append to it",
+                        line, reason
+                    )));
+                }
+                (verification.last_mac, verification.entries)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => ([0; MAC_BYTES], 0),
+            Err(e) => return Err(e.into()),
+        };
+        let mut options = OpenOptions::new();
+        options.append(true).create(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        Ok(Self { file: options.open(path)?, key, previous, entries })
+    }
+
+    pub(crate) fn hash(&self, content: &[u8]) -> ContentHash {
+        let mut hash = ContentHash::default();
+        let key = self.key.as_ref();
+        unsafe {
+            crypto_generichash(hash.as_mut_ptr(), hash.len(), content.as_ptr(), content.len() as u64, key.as_ptr(), key.len());
+        }
+        hash
+    }
+
+    /// Appends the entry for a finished call; `error` is the exception type, if it failed.
+    pub(crate) fn append(&mut self, record: &AuditRecord, error: Option<&str>) -> std::io::Result<()> {
+        let timestamp = record.timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let entry = serde_json::json!({
+            "seq": self.entries + 1,
+            "timestamp": timestamp.as_secs_f64(),
+            "endpoint": record.endpoint,
+            "model": record.model,
+            "status": record.status,
+            "request_id": record.request_id,
+            "prompt_tokens": record.tokens.prompt,
+            "completion_tokens": record.tokens.completion,
+            "total_tokens": record.tokens.total,
+            "error": error,
+            "request_hash": record.request_hash.as_ref().map(|hash| hex(hash)),
+            "response_hash": record.response_hash.as_ref().map(|hash| hex(hash)),
+            "prev": hex(&self.previous),
+        });
+        let mut line = serde_json::to_string(&entry)?;
+        line.pop();
+        let mac = entry_mac(self.key.as_ref(), line.as_bytes());
+        line.push_str(&format!(r#"{}{}"}}"#, MAC_FIELD, hex(&mac)));
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+        self.file.flush()?;
+        self.previous = mac;
+        self.entries += 1;
+        Ok(())
+    }
+}
+
+const MAC_FIELD: &str = r#","mac":""#;
+
+fn audit_key(key: &SecureBytes) -> PyResult<&[u8]> {
+    let key = key.expose()?;
+    if key.len() != crypto_auth_KEYBYTES as usize {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "hmac_key must be {} bytes",
+            crypto_auth_KEYBYTES
+        )));
+    }
+    Ok(key)
+}
+
+fn entry_mac(key: &[u8], entry: &[u8]) -> [u8; MAC_BYTES] {
+    let mut mac = [0u8; MAC_BYTES];
+    unsafe { crypto_auth(mac.as_mut_ptr(), entry.as_ptr(), entry.len() as u64, key.as_ptr()) };
+    mac
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub(crate) fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok()).collect()
+}
+
+struct Verification {
+    entries: u64,
+    last_mac: [u8; MAC_BYTES],
+    /// 1-based line number of the first bad entry, and what is wrong with it.
+    broken: Option<(u64, &'static str)>,
+}
+
+fn verify(reader: impl BufRead, key: &[u8]) -> std::io::Result<Verification> {
+    let mut verification = Verification { entries: 0, last_mac: [0; MAC_BYTES], broken: None };
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        match check_entry(&line, key, &verification.last_mac, verification.entries + 1) {
+            Ok(mac) => {
+                verification.last_mac = mac;
+                verification.entries += 1;
+            }
+            Err(reason) => {
+                verification.broken = Some((index as u64 + 1, reason));
+                break;
+            }
+        }
+    }
+    Ok(verification)
+}
+
+/// Checks one line against the key and the entry before it, returning its MAC.
+fn check_entry(line: &str, key: &[u8], previous: &[u8; MAC_BYTES], seq: u64) -> Result<[u8; MAC_BYTES], &'static str> {
+    let at = line.rfind(MAC_FIELD).ok_or("not an audit log entry")?;
+    let (entry, mac) = (&line[..at], line[at + MAC_FIELD.len()..].strip_suffix("\"}").ok_or("not an audit log entry")?);
+    let mac: [u8; MAC_BYTES] = unhex(mac).and_then(|mac| mac.try_into().ok()).ok_or("not an audit log entry")?;
+    if unsafe { crypto_auth_verify(mac.as_ptr(), entry.as_ptr(), entry.len() as u64, key.as_ptr()) } != 0 {
+        return Err("MAC mismatch: the entry was modified or written with another key");
+    }
+    let fields: serde_json::Value = serde_json::from_str(&format!("{}}}", entry)).map_err(|_| "not an audit log entry")?;
+    if fields["prev"].as_str().and_then(unhex).as_deref() != Some(previous.as_slice()) {
+        return Err("chain broken: prev does not match the entry before it");
+    }
+    if fields["seq"].as_u64() != Some(seq) {
+        return Err("chain broken: entries are missing or out of order");
+    }
+    Ok(mac)
+}
+
+/// Checks an audit log written through `enable_audit_log` with `hmac_key`. Returns a dict
+/// with `valid`, the number of good `entries` before the first broken one, that entry's
+/// 1-based `broken_line` and the `reason` (both `None` for a valid log), and `last_mac`,
+/// the hex MAC of the last good entry. The chain cannot tell that entries were cut off the
+/// end, so keep `last_mac` somewhere else to compare against if that matters.
+#[pyfunction]
+pub(crate) fn verify_audit_log<'py>(py: Python<'py>, path: PathBuf, hmac_key: PyRef<'_, SecureBytes>) -> PyResult<Bound<'py, PyDict>> {
+    let verification = verify(BufReader::new(File::open(path)?), audit_key(&hmac_key)?)?;
+    let report = PyDict::new(py);
+    report.set_item("valid", verification.broken.is_none())?;
+    report.set_item("entries", verification.entries)?;
+    report.set_item("broken_line", verification.broken.map(|(line, _)| line))?;
+    report.set_item("reason", verification.broken.map(|(_, reason)| reason))?;
+    report.set_item("last_mac", hex(&verification.last_mac))?;
+    Ok(report)
+}
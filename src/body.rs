@@ -0,0 +1,245 @@
+use crate::memory;
+use crate::transport::Response;
+use miniz_oxide::inflate::core::{decompress, inflate_flags, DecompressorOxide};
+use miniz_oxide::inflate::TINFLStatus;
+use pyo3::PyResult;
+use reqwest::header::{HeaderMap, CONTENT_ENCODING};
+use std::ops::{Deref, DerefMut};
+use zeroize::Zeroize;
+
+// --- Response Body Reading ---
+
+/// Default cap on response bodies: large enough for any chat completion, small enough
+/// that a misbehaving gateway cannot exhaust memory.
+pub(crate) const DEFAULT_MAX_RESPONSE_BYTES: usize = 32 * 1024 * 1024;
+
+/// The body was larger than the configured limit. `observed` is exact when the server sent
+/// a `Content-Length`, otherwise it is the number of bytes read before giving up.
+#[derive(Debug)]
+pub(crate) enum BodyError {
+    TooLarge { limit: usize, observed: u64, exact: bool },
+    Io(std::io::Error),
+    /// Under `strict_memory()`, the buffer for the body could not be locked.
+    Memory(pyo3::PyErr),
+}
+
+/// Reads the whole body into locked memory, refusing anything beyond `limit` bytes without
+/// buffering it. The `Content-Length` is checked first and sizes the buffer up front; the
+/// limit is also enforced while reading since the header may be missing or wrong. The
+/// buffer is wiped on every early exit, including the future being dropped when an async
+/// call is cancelled.
+pub(crate) async fn read_limited(response: &mut Response, limit: usize) -> Result<LockedBuffer, BodyError> {
+    read_observed(response, limit, |_| {}).await
+}
+
+/// `read_limited`, calling `observe` with the body read so far after every chunk.
+pub(crate) async fn read_observed(
+    response: &mut Response,
+    limit: usize,
+    mut observe: impl FnMut(&[u8]),
+) -> Result<LockedBuffer, BodyError> {
+    let mut body = match response.content_length() {
+        Some(length) if length > limit as u64 => {
+            return Err(BodyError::TooLarge { limit, observed: length, exact: true });
+        }
+        Some(length) => LockedBuffer::with_capacity(length as usize),
+        None => LockedBuffer::with_capacity(INITIAL_CAPACITY),
+    }
+    .map_err(BodyError::Memory)?;
+    while let Some(chunk) = response.body.chunk().await.map_err(BodyError::from_read)? {
+        if body.len() + chunk.len() > limit {
+            let observed = (body.len() + chunk.len()) as u64;
+            return Err(BodyError::TooLarge { limit, observed, exact: false });
+        }
+        body.extend_from_slice(&chunk).map_err(BodyError::Memory)?;
+        observe(&body);
+    }
+    Ok(body)
+}
+
+/// Starting size for bodies sent without a `Content-Length`.
+const INITIAL_CAPACITY: usize = 8 * 1024;
+
+/// A growable buffer whose whole allocation is locked. Growing moves the contents into a
+/// larger locked allocation and wipes the old one, so no partial copy of the body is left
+/// behind; the buffer is wiped and unlocked on drop. Only the transport's own chunk buffers
+/// ever hold the body outside of it, and only until the next read.
+pub(crate) struct LockedBuffer {
+    data: Vec<u8>,
+}
+
+impl LockedBuffer {
+    /// Fails only under `strict_memory()`, with the `MemoryLockError` that `SecureBytes`
+    /// raises there.
+    pub(crate) fn with_capacity(capacity: usize) -> PyResult<Self> {
+        let mut data = Vec::with_capacity(capacity);
+        memory::lock(data.as_mut_ptr(), data.capacity())?;
+        Ok(Self { data })
+    }
+
+    /// A buffer of `len` zero bytes, to be filled in place.
+    pub(crate) fn zeroed(len: usize) -> PyResult<Self> {
+        let mut buffer = Self::with_capacity(len)?;
+        buffer.data.resize(len, 0);
+        Ok(buffer)
+    }
+
+    /// Appends `bytes`. If the larger allocation cannot be locked, the buffer is left as is.
+    pub(crate) fn extend_from_slice(&mut self, bytes: &[u8]) -> PyResult<()> {
+        let needed = self.data.len() + bytes.len();
+        if needed > self.data.capacity() {
+            let mut grown = Self::with_capacity(needed.max(2 * self.data.capacity()))?;
+            grown.data.extend_from_slice(&self.data);
+            *self = grown;
+        }
+        self.data.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Grows or shrinks the buffer to `len` bytes, zero-filling any new tail.
+    fn resize(&mut self, len: usize) -> PyResult<()> {
+        if len > self.data.capacity() {
+            let mut grown = Self::with_capacity(len)?;
+            grown.data.extend_from_slice(&self.data);
+            *self = grown;
+        }
+        self.data.resize(len, 0);
+        Ok(())
+    }
+}
+
+impl Deref for LockedBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl DerefMut for LockedBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+}
+
+impl AsRef<[u8]> for LockedBuffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl Zeroize for LockedBuffer {
+    fn zeroize(&mut self) {
+        // Wipes the full capacity, not just the bytes in use.
+        self.data.zeroize();
+    }
+}
+
+impl Drop for LockedBuffer {
+    fn drop(&mut self) {
+        self.data.zeroize();
+        memory::unlock(self.data.as_mut_ptr(), self.data.capacity());
+    }
+}
+
+// --- Content Decoding ---
+
+/// Undoes the response's `Content-Encoding`. gzip is inflated straight into locked memory,
+/// which the inflater also uses as its window, so the plaintext never lands anywhere else;
+/// the compressed input is wiped once decoded. `limit` caps the decoded size, so a small
+/// compressed body cannot expand without bound.
+pub(crate) fn decode(body: LockedBuffer, headers: &HeaderMap, limit: usize) -> Result<LockedBuffer, BodyError> {
+    let encoding = headers.get(CONTENT_ENCODING).map(|value| value.to_str().unwrap_or("").trim().to_ascii_lowercase());
+    match encoding.as_deref() {
+        None | Some("") | Some("identity") => Ok(body),
+        Some("gzip") | Some("x-gzip") => gunzip(&body, limit),
+        Some(other) => Err(invalid_data(format!("unsupported Content-Encoding '{}'", other))),
+    }
+}
+
+/// Whether the body has a `Content-Encoding` that `decode` has to undo.
+pub(crate) fn is_encoded(headers: &HeaderMap) -> bool {
+    headers.get(CONTENT_ENCODING).is_some_and(|value| {
+        let value = value.to_str().unwrap_or("?").trim();
+        !(value.is_empty() || value.eq_ignore_ascii_case("identity"))
+    })
+}
+
+impl BodyError {
+    /// A failed read, which is a lock failure if a recording body could not capture it.
+    fn from_read(error: std::io::Error) -> Self {
+        match error.downcast::<pyo3::PyErr>() {
+            Ok(error) => BodyError::Memory(error),
+            Err(error) => BodyError::Io(error),
+        }
+    }
+}
+
+fn invalid_data(message: String) -> BodyError {
+    BodyError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, message))
+}
+
+fn gunzip(compressed: &[u8], limit: usize) -> Result<LockedBuffer, BodyError> {
+    let invalid = |what: &str| invalid_data(format!("invalid gzip response body: {}", what));
+    let deflate_start = gzip_header_len(compressed).ok_or_else(|| invalid("bad header"))?;
+    let mut input = &compressed[deflate_start..];
+    let initial = compressed.len().saturating_mul(4).max(INITIAL_CAPACITY).min(limit);
+    let mut output = LockedBuffer::zeroed(initial).map_err(BodyError::Memory)?;
+    let mut inflater = Box::<DecompressorOxide>::default();
+    let mut written = 0;
+    loop {
+        let flags = inflate_flags::TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF;
+        let (status, consumed, produced) = decompress(&mut inflater, input, &mut output, written, flags);
+        input = input.get(consumed..).ok_or_else(|| invalid("corrupt data"))?;
+        written += produced;
+        match status {
+            TINFLStatus::Done => break,
+            TINFLStatus::HasMoreOutput if output.len() >= limit => {
+                return Err(BodyError::TooLarge { limit, observed: (written + 1) as u64, exact: false });
+            }
+            TINFLStatus::HasMoreOutput => {
+                let grown = output.len().saturating_mul(2).min(limit);
+                output.resize(grown).map_err(BodyError::Memory)?;
+            }
+            _ => return Err(invalid("corrupt or truncated data")),
+        }
+    }
+    output.resize(written).map_err(BodyError::Memory)?;
+    let trailer = input.get(..8).ok_or_else(|| invalid("missing trailer"))?;
+    let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+    if crc32fast::hash(&output) != crc || written as u32 != size {
+        return Err(invalid("checksum mismatch"));
+    }
+    if input.len() > 8 {
+        return Err(invalid("unexpected data after the gzip member"));
+    }
+    Ok(output)
+}
+
+/// Length of the gzip member header (RFC 1952, section 2.3), or `None` if it is not a valid
+/// deflate-compressed member.
+fn gzip_header_len(data: &[u8]) -> Option<usize> {
+    const FHCRC: u8 = 0x02;
+    const FEXTRA: u8 = 0x04;
+    const FNAME: u8 = 0x08;
+    const FCOMMENT: u8 = 0x10;
+    if data.len() < 10 || data[..3] != [0x1f, 0x8b, 8] {
+        return None;
+    }
+    let flags = data[3];
+    let mut len = 10;
+    if flags & FEXTRA != 0 {
+        let extra = data.get(len..len + 2)?;
+        len += 2 + u16::from_le_bytes([extra[0], extra[1]]) as usize;
+    }
+    for field in [FNAME, FCOMMENT] {
+        if flags & field != 0 {
+            len += data.get(len..)?.iter().position(|&b| b == 0)? + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        len += 2;
+    }
+    (len <= data.len()).then_some(len)
+}
@@ -0,0 +1,195 @@
+use crate::body::LockedBuffer;
+use crate::SecureBytes;
+use libsodium_sys::{
+    crypto_generichash_final, crypto_generichash_init, crypto_generichash_state, crypto_generichash_update,
+    crypto_secretbox_easy, crypto_secretbox_open_easy, randombytes_buf, crypto_generichash_BYTES,
+    crypto_generichash_KEYBYTES, crypto_secretbox_KEYBYTES, crypto_secretbox_MACBYTES, crypto_secretbox_NONCEBYTES,
+};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use reqwest::header::HeaderMap;
+use reqwest::{StatusCode, Version};
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::time::{Duration, Instant};
+use zeroize::Zeroize;
+
+// --- Encrypted Response Cache ---
+
+const KEY_BYTES: usize = crypto_secretbox_KEYBYTES as usize;
+const NONCE_BYTES: usize = crypto_secretbox_NONCEBYTES as usize;
+const MAC_BYTES: usize = crypto_secretbox_MACBYTES as usize;
+
+/// Keyed BLAKE2b of the request path and body.
+pub(crate) type CacheKey = [u8; crypto_generichash_BYTES as usize];
+
+/// A cached successful response: everything needed to build the `SecureResponse` again,
+/// with the body sealed by `crypto_secretbox`.
+pub(crate) struct CachedResponse {
+    pub(crate) status: StatusCode,
+    pub(crate) version: Version,
+    pub(crate) headers: HeaderMap,
+    pub(crate) body: LockedBuffer,
+}
+
+struct Entry {
+    status: StatusCode,
+    version: Version,
+    headers: HeaderMap,
+    nonce: [u8; NONCE_BYTES],
+    sealed: Vec<u8>,
+    stored: Instant,
+    /// `ResponseCache::clock` at the last hit or insert, for LRU eviction.
+    used: u64,
+}
+
+/// Responses of a client, off until `enable_cache()`. Both keys are random per client and
+/// live in locked memory; entries only ever hold ciphertext, and lookups hash the request
+/// body where it already is, in its locked buffer.
+pub(crate) struct ResponseCache {
+    box_key: SecureBytes,
+    hash_key: SecureBytes,
+    max_entries: usize,
+    ttl: Duration,
+    entries: HashMap<CacheKey, Entry>,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl ResponseCache {
+    pub(crate) fn new() -> Self {
+        Self {
+            box_key: random_key(KEY_BYTES),
+            hash_key: random_key(crypto_generichash_KEYBYTES as usize),
+            max_entries: 0,
+            ttl: Duration::ZERO,
+            entries: HashMap::new(),
+            clock: 0,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+        }
+    }
+
+    /// Turns the cache on, or resizes it; entries over the new limit are evicted.
+    pub(crate) fn enable(&mut self, max_entries: usize, ttl: Duration) {
+        self.max_entries = max_entries;
+        self.ttl = ttl;
+        while self.entries.len() > max_entries {
+            self.evict_least_recent();
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.max_entries > 0
+    }
+
+    /// The cache key for a request to `base_url`, `None` while the cache is off. The base URL
+    /// is hashed in so that a request still in flight when `set_base_url` switches servers
+    /// cannot answer for the new one.
+    pub(crate) fn key(&self, base_url: &[u8], path: &str, body: &[u8]) -> Option<CacheKey> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let hash_key = self.hash_key.expose().ok()?;
+        let mut key = CacheKey::default();
+        let mut state = crypto_generichash_state { opaque: [0; 384] };
+        unsafe {
+            crypto_generichash_init(&mut state, hash_key.as_ptr(), hash_key.len(), key.len());
+            crypto_generichash_update(&mut state, base_url.as_ptr(), base_url.len() as u64);
+            crypto_generichash_update(&mut state, [0u8].as_ptr(), 1);
+            crypto_generichash_update(&mut state, path.as_ptr(), path.len() as u64);
+            crypto_generichash_update(&mut state, [0u8].as_ptr(), 1);
+            crypto_generichash_update(&mut state, body.as_ptr(), body.len() as u64);
+            crypto_generichash_final(&mut state, key.as_mut_ptr(), key.len());
+        }
+        state.opaque.zeroize();
+        Some(key)
+    }
+
+    /// Decrypts a live entry into a fresh locked buffer, counting a hit or a miss. Fails only
+    /// if that buffer could not be locked under `strict_memory()`.
+    pub(crate) fn get(&mut self, key: &CacheKey) -> PyResult<Option<CachedResponse>> {
+        let expired = self.entries.get(key).is_some_and(|entry| entry.stored.elapsed() >= self.ttl);
+        if expired {
+            self.entries.remove(key);
+            self.evictions += 1;
+        }
+        let Ok(box_key) = self.box_key.expose() else {
+            return Ok(None);
+        };
+        self.clock += 1;
+        let Some(entry) = self.entries.get_mut(key) else {
+            self.misses += 1;
+            return Ok(None);
+        };
+        entry.used = self.clock;
+        let mut body = LockedBuffer::zeroed(entry.sealed.len() - MAC_BYTES)?;
+        let opened = unsafe {
+            crypto_secretbox_open_easy(
+                body.as_mut_ptr(),
+                entry.sealed.as_ptr(),
+                entry.sealed.len() as u64,
+                entry.nonce.as_ptr(),
+                box_key.as_ptr(),
+            )
+        };
+        if opened != 0 {
+            self.entries.remove(key);
+            self.misses += 1;
+            return Ok(None);
+        }
+        self.hits += 1;
+        Ok(Some(CachedResponse { status: entry.status, version: entry.version, headers: entry.headers.clone(), body }))
+    }
+
+    /// Encrypts and stores a response, evicting the least recently used entry when full.
+    pub(crate) fn insert(&mut self, key: CacheKey, status: StatusCode, version: Version, headers: &HeaderMap, body: &[u8]) {
+        let Ok(box_key) = self.box_key.expose() else {
+            return;
+        };
+        let mut nonce = [0u8; NONCE_BYTES];
+        let mut sealed = vec![0u8; body.len() + MAC_BYTES];
+        unsafe {
+            randombytes_buf(nonce.as_mut_ptr() as *mut c_void, nonce.len());
+            crypto_secretbox_easy(sealed.as_mut_ptr(), body.as_ptr(), body.len() as u64, nonce.as_ptr(), box_key.as_ptr());
+        }
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.max_entries {
+            self.evict_least_recent();
+        }
+        self.clock += 1;
+        let entry = Entry { status, version, headers: headers.clone(), nonce, sealed, stored: Instant::now(), used: self.clock };
+        self.entries.insert(key, entry);
+    }
+
+    fn evict_least_recent(&mut self) {
+        if let Some(key) = self.entries.iter().min_by_key(|(_, entry)| entry.used).map(|(key, _)| *key) {
+            self.entries.remove(&key);
+            self.evictions += 1;
+        }
+    }
+
+    /// Drops every entry; the counters are kept.
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub(crate) fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        dict.set_item("hits", self.hits)?;
+        dict.set_item("misses", self.misses)?;
+        dict.set_item("evictions", self.evictions)?;
+        dict.set_item("entries", self.entries.len())?;
+        Ok(dict)
+    }
+}
+
+fn random_key(len: usize) -> SecureBytes {
+    let mut key = SecureBytes::zeroed(len).unwrap_or_else(|e| panic!("{}", e));
+    unsafe {
+        randombytes_buf(key.bytes_mut().as_mut_ptr() as *mut c_void, len);
+    }
+    key
+}
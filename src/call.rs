@@ -0,0 +1,1242 @@
+use crate::anthropic;
+use crate::api_key::ApiKey;
+use crate::audit::AuditRecord;
+use crate::body::{self, BodyError, LockedBuffer};
+use crate::embeddings;
+use crate::errors::{self, AuthenticationError, ErrorContext};
+use crate::json::RequestBody;
+use crate::logging;
+use crate::messages::{Converted, Messages};
+use crate::moderation;
+use crate::providers::Api;
+use crate::rate_limit::{AcquireError, RateLimiter, RateLimits};
+use crate::response::{SecureRawResponse, SecureResponse};
+use crate::retry;
+use crate::signing::SigningRequest;
+use crate::stream;
+use crate::transport::{self, TransportError};
+use crate::{
+    api_key_header, bearer_header, estimate_request_tokens, headers, ids, is_address_not_available, params,
+    validate_idempotency_key, ChatCompletionRequest, ChatCompletionResponse, ClientCore, Connection, SecureClient,
+    SecureBytes, SecureContentPart, SecureMessage, CONNECT_TIMEOUT,
+};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT_ENCODING, AUTHORIZATION, CONTENT_TYPE};
+use reqwest::{Method, StatusCode, Version};
+use serde_json::Value;
+use std::future::Future;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use zeroize::Zeroize;
+
+// --- Request Execution ---
+
+/// Why a call never got a response from the server.
+pub(crate) enum SendError {
+    RateLimited(AcquireError),
+    Transport(TransportError),
+    /// The moderation check did not let the request go; `finish` raises its verdict.
+    Moderated,
+    /// The call's `deadline` ran out before it got a final response.
+    DeadlineExceeded(retry::Spent),
+    /// The base URL could not be read (a fork invalidated it or its canaries broke), or a
+    /// cached body could not be locked under `strict_memory()`.
+    Unreadable(PyErr),
+}
+
+/// A response with its body already read (or the error that stopped the read).
+pub(crate) struct Received {
+    status: StatusCode,
+    version: Version,
+    headers: HeaderMap,
+    body: Result<LockedBuffer, BodyError>,
+}
+
+/// A response that came back with a success status, read in full.
+struct Success {
+    request_id: String,
+    headers: HeaderMap,
+    version: Version,
+    body: LockedBuffer,
+}
+
+/// A request ready to go, with the guard over its serialized body: `ChatCall::send` drops
+/// it, wiping the body, on every way out.
+pub(crate) struct PreparedRequest {
+    request: transport::Request,
+    body: RequestBody,
+    /// The moderations request screening this one, sent first under `moderate_inputs`.
+    moderation: Option<Box<(ChatCall, PreparedRequest)>>,
+}
+
+/// One chat completion, built while holding the GIL. `send` touches no Python state, so the
+/// blocking client runs it with the GIL released and the async client on the runtime;
+/// `finish` turns the outcome into the Python result in both cases.
+pub(crate) struct ChatCall {
+    core: Arc<ClientCore>,
+    connection: Connection,
+    client_request_id: String,
+    limiter: Option<Arc<RateLimiter>>,
+    estimated_tokens: u64,
+    timeout: Option<Duration>,
+    strict: bool,
+    stream: bool,
+    audit: AuditRecord,
+    /// HTTP attempts made by `send`, retries included.
+    attempts: AtomicU32,
+    /// Bounds every attempt and wait of `send` together; `None` leaves only `timeout`.
+    deadline: Option<retry::Deadline>,
+    /// The moderation call `send` made first, with its outcome, for `finish` to report.
+    moderated: Mutex<Option<Box<Moderated>>>,
+    /// Set once the moderation check has passed, so a retry after a token refresh does
+    /// not screen the same input again.
+    screened: Arc<AtomicBool>,
+}
+
+impl SecureClient {
+    /// Resolves defaults, validates every argument and serializes the body, so a call that
+    /// gets past this point only fails for reasons on the wire.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn prepare_chat(
+        &self,
+        messages: Vec<PyRef<SecureMessage>>,
+        model: Option<String>,
+        idempotency_key: Option<String>,
+        extra_headers: Option<&Bound<'_, PyDict>>,
+        timeout: Option<f64>,
+        strict: bool,
+        stream: bool,
+        params: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<(ChatCall, PreparedRequest)> {
+        // Snapshot the credentials: a concurrent rotation swaps in new ones for later
+        // requests while this one finishes with the values it started with.
+        let mut connection = self.core.connection()?;
+        validate_messages(&messages)?;
+        let model = model.or_else(|| self.default_model.clone()).ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>("model is required: pass model= or set default_model on the client")
+        })?;
+        check_model(&model)?;
+        let timeout = parse_timeout(timeout)?;
+        let (params, options) = params::call_kwargs(params)?;
+        let mut params = params::merge(&self.defaults, params);
+        let reasoning = params::is_reasoning_model(&model, &self.core.reasoning_models);
+        params::resolve_token_limit(&mut params, &model, reasoning, self.core.strict_params)?;
+        if self.core.privacy_mode {
+            let opt_outs = self.core.provider.map_or(&[][..], |provider| provider.privacy_params);
+            params::enforce_privacy(&mut params, self.core.api() == Api::OpenAi, opt_outs)?;
+        }
+        if let Some(provider) = self.core.provider {
+            params.retain(|name, _| !provider.stripped_params.contains(&name.as_str()));
+            for (ours, theirs) in provider.renamed_params {
+                if let Some(value) = params.remove(*ours) {
+                    params.insert(theirs.to_string(), value);
+                }
+            }
+        }
+        let max_tokens = params.get("max_tokens").or_else(|| params.get("max_completion_tokens")).and_then(Value::as_u64);
+
+        // One key per logical call: it must stay the same for every attempt of this call.
+        let idempotency_key = match idempotency_key {
+            Some(key) => Some(validate_idempotency_key(key)?),
+            None if self.core.auto_idempotency => Some(ids::random_uuid()),
+            None => None,
+        };
+        *self.core.last_idempotency_key.lock().unwrap() = idempotency_key.clone();
+
+        let system = self.core.system_prompt.as_ref().filter(|_| {
+            options.include_default_system && !messages.first().is_some_and(|message| message.is_system())
+        });
+        // The Messages API takes no system messages at all; they go in its `system` field.
+        let system_role = self.core.role_translation.system_role(reasoning).filter(|_| self.core.api() == Api::OpenAi);
+        let request_body = ChatCompletionRequest {
+            system,
+            messages: &messages,
+            model: &model,
+            stream,
+            params: &params,
+            grammar: options.grammar.as_ref(),
+            system_role,
+        };
+
+        let path = self.core.path_style.chat_completions(&model)?;
+        self.authorize(&mut connection, &path, &model)?;
+        let mut audit = AuditRecord::start(&path, &model);
+        let limiter = self.core.rate_limiter.read().unwrap().clone();
+        let estimated_tokens = estimate_request_tokens(system, &messages) + max_tokens.unwrap_or(0);
+        audit.estimated_tokens = estimated_tokens;
+        audit.system_role = system_role;
+
+        let client_request_id = ids::random_uuid();
+        let headers = self.request_headers(&connection, &client_request_id, idempotency_key.as_deref(), extra_headers)?;
+        let body = match self.core.api() {
+            Api::OpenAi => request_body.to_json()?,
+            Api::Anthropic => anthropic::to_json(&request_body)?,
+        };
+        let mut request = self.seal(path, headers, body, &mut audit, timeout)?;
+        let deadline = options.deadline.or(self.core.deadline).map(retry::Deadline::new);
+        if options.moderate_inputs.unwrap_or(self.core.moderate_inputs) {
+            let moderation = self.prepare_moderation(&connection, &messages, extra_headers, timeout)?;
+            request.moderation = moderation.map(|(call, request)| Box::new((ChatCall { deadline: deadline.clone(), ..call }, request)));
+        }
+
+        let call = ChatCall {
+            limiter,
+            estimated_tokens,
+            strict,
+            stream,
+            deadline,
+            ..ChatCall::new(&self.core, connection, client_request_id, audit, timeout)
+        };
+        Ok((call, request))
+    }
+
+    /// The moderations request screening the user text of `messages`, or `None` if they
+    /// have none. It goes out with the same headers and credentials as the call itself.
+    fn prepare_moderation(
+        &self,
+        connection: &Connection,
+        messages: &[PyRef<'_, SecureMessage>],
+        extra_headers: Option<&Bound<'_, PyDict>>,
+        timeout: Option<Duration>,
+    ) -> PyResult<Option<(ChatCall, PreparedRequest)>> {
+        let path = self.core.path_style.moderations().ok_or_else(|| moderation::no_endpoint(self.core.provider))?;
+        let Some(text) = moderation::user_text(messages)? else {
+            return Ok(None);
+        };
+        let model = &self.core.moderation.model;
+        let mut audit = AuditRecord::start(&path, model);
+        let client_request_id = ids::random_uuid();
+        let headers = self.request_headers(connection, &client_request_id, None, extra_headers)?;
+        let body = moderation::to_json(&text, model)?;
+        let request = self.seal(path, headers, body, &mut audit, timeout)?;
+        Ok(Some((ChatCall::new(&self.core, connection.clone(), client_request_id, audit, timeout), request)))
+    }
+
+    /// Puts a token from the client's `token_provider`, if any, in place of the API key.
+    fn authorize(&self, connection: &mut Connection, path: &str, model: &str) -> PyResult<()> {
+        if let Some(provider) = &self.core.token_provider {
+            connection.api_key = Python::with_gil(|py| {
+                provider.token(py).map_err(|cause| errors::token_provider_failed(py, cause, path, model))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// The headers every request carries: content negotiation, the request ids, the
+    /// client's default and the call's extra headers, and the credentials.
+    fn request_headers(
+        &self,
+        connection: &Connection,
+        client_request_id: &str,
+        idempotency_key: Option<&str>,
+        extra_headers: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        let accept_encoding = if self.core.compression { "gzip" } else { "identity" };
+        headers.insert(ACCEPT_ENCODING, HeaderValue::from_static(accept_encoding));
+        headers.insert("X-Client-Request-Id", HeaderValue::from_str(client_request_id).expect("UUIDs are valid header values"));
+        if let Some(key) = idempotency_key {
+            headers.insert("Idempotency-Key", HeaderValue::from_str(key).expect("idempotency keys are validated"));
+        }
+        headers::render(&mut headers, &self.core.default_headers, extra_headers)?;
+        let key_header = match self.core.provider {
+            Some(provider) => provider.key_header,
+            None => self.core.path_style.uses_api_key_header().then_some("api-key"),
+        };
+        let keyless = self.core.provider.is_some_and(|provider| provider.key_optional)
+            && connection.api_key.with_plaintext(|key| Ok(key.is_empty()))?;
+        match key_header {
+            _ if keyless => None,
+            Some(name) => headers.insert(name, connection.api_key.with_plaintext(api_key_header)?),
+            None => headers.insert(AUTHORIZATION, connection.api_key.with_plaintext(bearer_header)?),
+        };
+        Ok(headers)
+    }
+
+    /// Signs the serialized request if the client has a signer and notes it in the audit
+    /// record, leaving it ready to send.
+    fn seal(
+        &self,
+        path: String,
+        mut headers: HeaderMap,
+        body: RequestBody,
+        audit: &mut AuditRecord,
+        timeout: Option<Duration>,
+    ) -> PyResult<PreparedRequest> {
+        if let Some(signer) = &self.core.signer {
+            let signing_request = SigningRequest::new(Method::POST.as_str(), &path, &headers, &body.view());
+            let signed = Python::with_gil(|py| signer.sign(py, &signing_request))?;
+            headers.extend(signed.into_iter().map(|(name, value)| (Some(name), value)));
+        }
+        if logging::is_enabled() {
+            audit.request_headers = headers.keys().map(|name| name.to_string()).collect();
+        }
+        audit.request_hash = self.core.audit_log.lock().unwrap().as_ref().map(|log| log.hash(&body.view()));
+        // Unix sockets have no client-wide timeout of their own, so it goes on each request.
+        let request = transport::Request { method: Method::POST, path, headers, body: body.view(), timeout: timeout.or(self.core.timeout) };
+        Ok(PreparedRequest { request, body, moderation: None })
+    }
+}
+
+/// A moderation call and what `send` got back from it.
+type Moderated = (ChatCall, Result<Received, SendError>);
+
+/// One request of an `embeddings` call, with the number of inputs it carries.
+pub(crate) type EmbeddingsBatch = (ChatCall, PreparedRequest, usize);
+
+/// The arguments of one `embeddings` call, checked once and kept like a `ChatRequest`'s,
+/// so that each batch is built just before it is sent, and built again with a fresh token
+/// if it gets a 401 under a `token_provider`. The batches are `batch_size` inputs each, in
+/// input order.
+pub(crate) struct EmbeddingsRequest {
+    client: SecureClient,
+    inputs: Vec<SecureBytes>,
+    model: String,
+    dimensions: Option<u32>,
+    encoding_format: embeddings::EncodingFormat,
+    batch_size: usize,
+    extra_headers: Option<Py<PyDict>>,
+    timeout: Option<Duration>,
+    params: serde_json::Map<String, Value>,
+    path: String,
+    limiter: Option<Arc<RateLimiter>>,
+    /// The batches are one call to the caller, so they share one deadline.
+    deadline: Option<retry::Deadline>,
+}
+
+impl EmbeddingsRequest {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        client: &SecureClient,
+        inputs: Vec<SecureBytes>,
+        model: String,
+        dimensions: Option<u32>,
+        encoding_format: &str,
+        batch_size: usize,
+        extra_headers: Option<&Bound<'_, PyDict>>,
+        timeout: Option<f64>,
+        deadline: Option<f64>,
+        params: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Self> {
+        check_model(&model)?;
+        let encoding_format = embeddings::EncodingFormat::parse(encoding_format)?;
+        let batch_size = embeddings::check_batch_size(batch_size)?;
+        if dimensions == Some(0) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("dimensions must be a positive number"));
+        }
+        let timeout = parse_timeout(timeout)?;
+        let deadline = parse_deadline(deadline)?.or(client.core.deadline);
+        let mut params = params::from_kwargs(params)?;
+        if client.core.privacy_mode {
+            let opt_outs = client.core.provider.map_or(&[][..], |provider| provider.privacy_params);
+            params::enforce_privacy(&mut params, false, opt_outs)?;
+        }
+        let path = client.core.path_style.embeddings(&model)?.ok_or_else(|| {
+            let name = client.core.provider.map_or("", |provider| provider.name);
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("provider '{}' has no embeddings endpoint", name))
+        })?;
+        Ok(Self {
+            client: SecureClient {
+                core: Arc::clone(&client.core),
+                default_model: client.default_model.clone(),
+                defaults: client.defaults.clone(),
+            },
+            inputs,
+            model,
+            dimensions,
+            encoding_format,
+            batch_size,
+            extra_headers: extra_headers.map(|headers| headers.clone().unbind()),
+            timeout,
+            params,
+            path,
+            limiter: client.core.rate_limiter.read().unwrap().clone(),
+            deadline: deadline.map(retry::Deadline::new),
+        })
+    }
+
+    pub(crate) fn batches(&self) -> usize {
+        self.inputs.len().div_ceil(self.batch_size)
+    }
+
+    pub(crate) fn prepare(&self, py: Python<'_>, index: usize) -> PyResult<EmbeddingsBatch> {
+        let start = index * self.batch_size;
+        let inputs = &self.inputs[start..self.inputs.len().min(start + self.batch_size)];
+        let mut connection = self.client.core.connection()?;
+        self.client.authorize(&mut connection, &self.path, &self.model)?;
+        let mut audit = AuditRecord::start(&self.path, &self.model);
+        let estimated_tokens = inputs.iter().map(|input| (input.bytes().len() as u64).div_ceil(4)).sum();
+        audit.estimated_tokens = estimated_tokens;
+
+        let client_request_id = ids::random_uuid();
+        let extra_headers = self.extra_headers.as_ref().map(|headers| headers.bind(py));
+        let headers = self.client.request_headers(&connection, &client_request_id, None, extra_headers)?;
+        let request_body = embeddings::EmbeddingsRequest {
+            inputs,
+            model: &self.model,
+            dimensions: self.dimensions,
+            encoding_format: self.encoding_format,
+            params: &self.params,
+        };
+        let body = request_body.to_json()?;
+        let request = self.client.seal(self.path.clone(), headers, body, &mut audit, self.timeout)?;
+        let call = ChatCall {
+            limiter: self.limiter.clone(),
+            estimated_tokens,
+            deadline: self.deadline.clone(),
+            ..ChatCall::new(&self.client.core, connection, client_request_id, audit, self.timeout)
+        };
+        Ok((call, request, inputs.len()))
+    }
+
+    /// Like `ChatRequest::refreshed`, for batch `index`.
+    pub(crate) fn refreshed(
+        &self,
+        py: Python<'_>,
+        index: usize,
+        token: &Arc<ApiKey>,
+        error: Option<&PyErr>,
+    ) -> Option<PyResult<EmbeddingsBatch>> {
+        if !self.client.refresh_token(py, token, error) {
+            return None;
+        }
+        Some(self.prepare(py, index).map(|(mut call, request, inputs)| {
+            call.audit.token_refreshed = true;
+            (call, request, inputs)
+        }))
+    }
+}
+
+/// Longer than any model or deployment name a provider hands out.
+const MAX_MODEL_LEN: usize = 256;
+
+fn check_model(model: &str) -> PyResult<()> {
+    if model.is_empty() || model.len() > MAX_MODEL_LEN {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "model must be a non-empty name of at most {} bytes",
+            MAX_MODEL_LEN
+        )));
+    }
+    Ok(())
+}
+
+/// Rejects what every server would answer with a 400 anyway: no messages, a message
+/// without content parts or an empty text part. Errors name the message and part by index,
+/// never their content.
+fn validate_messages(messages: &[PyRef<'_, SecureMessage>]) -> PyResult<()> {
+    let invalid = |message: String| PyErr::new::<pyo3::exceptions::PyValueError, _>(message);
+    if messages.is_empty() {
+        return Err(invalid("messages must not be empty".to_string()));
+    }
+    for (index, message) in messages.iter().enumerate() {
+        message.ensure_usable()?;
+        if message.content.is_empty() {
+            return Err(invalid(format!("messages[{}] has no content parts", index)));
+        }
+        for (part, content) in message.content.iter().enumerate() {
+            if matches!(content, SecureContentPart::Text { text } if text.bytes().is_empty()) {
+                return Err(invalid(format!("messages[{}] content part {} is an empty text", index, part)));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Validates a `timeout` in seconds, per call or for the client.
+pub(crate) fn parse_timeout(timeout: Option<f64>) -> PyResult<Option<Duration>> {
+    match timeout {
+        Some(seconds) if !(seconds.is_finite() && seconds > 0.0) => {
+            Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("timeout must be a positive number of seconds"))
+        }
+        Some(seconds) => Ok(Some(Duration::from_secs_f64(seconds))),
+        None => Ok(None),
+    }
+}
+
+/// Validates a `deadline` in seconds, per call or for the client.
+pub(crate) fn parse_deadline(deadline: Option<f64>) -> PyResult<Option<Duration>> {
+    match deadline {
+        Some(seconds) if !(seconds.is_finite() && seconds > 0.0) => {
+            Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("deadline must be a positive number of seconds"))
+        }
+        Some(seconds) => Ok(Some(Duration::from_secs_f64(seconds))),
+        None => Ok(None),
+    }
+}
+
+impl ChatCall {
+    /// A call sent without rate limiting, streaming or strict checks; the chat and
+    /// embeddings calls set those on top.
+    fn new(core: &Arc<ClientCore>, connection: Connection, client_request_id: String, audit: AuditRecord, timeout: Option<Duration>) -> Self {
+        ChatCall {
+            core: Arc::clone(core),
+            connection,
+            client_request_id,
+            limiter: None,
+            estimated_tokens: 0,
+            timeout,
+            strict: false,
+            stream: false,
+            audit,
+            attempts: AtomicU32::new(0),
+            deadline: core.deadline.map(retry::Deadline::new),
+            moderated: Mutex::new(None),
+            screened: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// The API key or token this call authenticates with.
+    pub(crate) fn api_key(&self) -> Arc<ApiKey> {
+        Arc::clone(&self.connection.api_key)
+    }
+
+    /// Waits for the rate limiter, sends the request and reads the body. Must run on
+    /// `transport::runtime()`.
+    pub(crate) async fn send(&self, prepared: PreparedRequest) -> Result<Received, SendError> {
+        self.send_with(prepared, None).await
+    }
+
+    /// `send`, passing each text delta of a successful stream to `tokens` as soon as its
+    /// line has arrived. A compressed stream can only be scanned once it is decoded, so its
+    /// deltas all come at the end, as do those of a cached response.
+    async fn send_with(&self, prepared: PreparedRequest, tokens: Option<&mpsc::Sender<Streamed>>) -> Result<Received, SendError> {
+        let PreparedRequest { request, body: _body, moderation } = prepared;
+        if let Some(deadline) = &self.deadline {
+            deadline.start();
+        }
+        let mut scanner = stream::DeltaScanner::new(self.core.api());
+        let mut emit = |body: &[u8], complete: bool| {
+            if let Some(tokens) = tokens {
+                scanner.scan(body, complete, |token| {
+                    let _ = tokens.send(Streamed::Token(token));
+                });
+            }
+        };
+        let cache_key = self.core.cache.lock().unwrap().key(self.connection.base_url.bytes(), &request.path, &request.body);
+        if let Some(key) = &cache_key {
+            if let Some(hit) = self.core.cache.lock().unwrap().get(key).map_err(SendError::Unreadable)? {
+                if hit.status.is_success() {
+                    emit(&hit.body, true);
+                }
+                return Ok(Received { status: hit.status, version: hit.version, headers: hit.headers, body: Ok(hit.body) });
+            }
+        }
+        // Only input that is about to leave for the completion endpoint is screened, so a
+        // cached response needs no check.
+        if let Some(moderation) = moderation {
+            let (call, request) = *moderation;
+            let outcome = Box::pin(call.send(request)).await;
+            let passed = match &outcome {
+                Ok(Received { status, body: Ok(body), .. }) if status.is_success() => {
+                    self.core.moderation.flagged(body).is_ok_and(|flagged| flagged.is_empty())
+                }
+                _ => false,
+            };
+            *self.moderated.lock().unwrap() = Some(Box::new((call, outcome)));
+            if !passed {
+                return Err(SendError::Moderated);
+            }
+            self.screened.store(true, Ordering::Relaxed);
+        }
+        let base_url = self.connection.base_url.as_str().map_err(SendError::Unreadable)?;
+        let policy = self.core.retry;
+        let mut retry = 0;
+        let mut spent = retry::Spent::default();
+        loop {
+            // Every attempt is a request against the rpm budget; the tokens are charged once.
+            // Under a deadline the wait for it is bounded by what is left, and running out of
+            // that comes back as the deadline error.
+            if let Some(limiter) = self.limiter.clone() {
+                let tokens = if retry == 0 { self.estimated_tokens } else { 0 };
+                let remaining = self.deadline.as_ref().map(|deadline| deadline.remaining());
+                let started = Instant::now();
+                let acquired = tokio::task::spawn_blocking(move || limiter.acquire(tokens, remaining))
+                    .await
+                    .expect("the rate limiter does not panic");
+                spent.waited += started.elapsed();
+                match acquired {
+                    Err(AcquireError::Deadline { .. }) => return Err(SendError::DeadlineExceeded(spent)),
+                    acquired => acquired.map_err(SendError::RateLimited)?,
+                }
+            }
+            let mut attempt = request.clone();
+            // Under a deadline each attempt gets what is left of it, if that is less than its
+            // own timeout; running out of that comes back as the deadline error.
+            let mut bounded = false;
+            if let Some(deadline) = &self.deadline {
+                let remaining = deadline.remaining();
+                if remaining.is_zero() {
+                    return Err(SendError::DeadlineExceeded(spent));
+                }
+                if attempt.timeout.is_none_or(|timeout| timeout > remaining) {
+                    attempt.timeout = Some(remaining);
+                    bounded = true;
+                }
+            }
+            self.attempts.store(retry + 1, Ordering::Relaxed);
+            let started = Instant::now();
+            let mut response = match self.connection.transport.send(base_url, attempt).await {
+                Ok(response) => response,
+                Err(e) => {
+                    self.core.stats.record(request.body.len(), 0, started.elapsed());
+                    self.core.metrics.record_attempt(None, started.elapsed(), e.is_timeout());
+                    if bounded && e.is_timeout() {
+                        spent.attempts.push(started.elapsed());
+                        return Err(SendError::DeadlineExceeded(spent));
+                    }
+                    return Err(SendError::Transport(e));
+                }
+            };
+            let live = tokens.is_some() && response.status.is_success() && !body::is_encoded(&response.headers);
+            let body = if live {
+                body::read_observed(&mut response, self.core.max_response_bytes, |body| emit(body, false)).await
+            } else {
+                body::read_limited(&mut response, self.core.max_response_bytes).await
+            };
+            let received = body.as_ref().map_or(0, |body| body.len());
+            self.core.stats.record(request.body.len(), received, started.elapsed());
+            let timed_out = matches!(&body, Err(BodyError::Io(e)) if e.kind() == std::io::ErrorKind::TimedOut);
+            self.core.metrics.record_attempt(Some(response.status), started.elapsed(), timed_out);
+            spent.attempts.push(started.elapsed());
+            if bounded && timed_out {
+                return Err(SendError::DeadlineExceeded(spent));
+            }
+            let limit = self.core.max_response_bytes;
+            let body = body.and_then(|body| body::decode(body, &response.headers, limit));
+            if let (Ok(body), true) = (&body, response.status.is_success()) {
+                emit(body, true);
+            }
+            let mut delay = None;
+            if retry < policy.max_retries && retry::is_retryable(response.status) {
+                let wait = policy.delay(response.status, &response.headers, retry);
+                delay = match self.deadline.as_ref().map(|deadline| deadline.remaining()) {
+                    // A server that asks for longer than is left won't take the request in
+                    // time, so its answer is returned instead of sleeping into the deadline.
+                    Some(remaining) if wait > remaining && retry::server_delay(response.status, &response.headers).is_some() => None,
+                    Some(remaining) => Some(wait.min(remaining)),
+                    None => Some(wait),
+                };
+            }
+            let Some(delay) = delay else {
+                if let (Some(key), Ok(body), StatusCode::OK) = (cache_key, &body, response.status) {
+                    self.core.cache.lock().unwrap().insert(key, response.status, response.version, &response.headers, body);
+                }
+                return Ok(Received { status: response.status, version: response.version, headers: response.headers, body });
+            };
+            drop(body);
+            self.core.metrics.record_retry();
+            tokio::time::sleep(delay).await;
+            spent.waited += delay;
+            retry += 1;
+        }
+    }
+
+    /// Converts the outcome of `send` into the call's result, logs it and reports it to
+    /// the audit log and hook.
+    pub(crate) fn finish(self, py: Python<'_>, outcome: Result<Received, SendError>) -> PyResult<SecureResponse> {
+        self.finish_with(py, outcome, Self::complete)
+    }
+
+    /// `finish` for an embeddings request, whose response is one batch of vectors.
+    pub(crate) fn finish_embeddings(
+        self,
+        py: Python<'_>,
+        outcome: Result<Received, SendError>,
+        inputs: usize,
+    ) -> PyResult<embeddings::Batch> {
+        self.finish_with(py, outcome, |call, py, outcome| {
+            let success = call.read_success(py, outcome)?;
+            let batch = embeddings::parse(&success.body, &success.request_id, inputs)?;
+            call.audit.tokens = batch.usage;
+            if let (Some(limiter), Some(total)) = (&call.limiter, batch.usage.total) {
+                limiter.reconcile(call.estimated_tokens, total);
+            }
+            Ok(batch)
+        })
+    }
+
+    /// `finish` for the moderation call of a chat completion: raises `ModerationBlockedError`
+    /// if the input was flagged, as any other call would on failure.
+    fn finish_moderation(self, py: Python<'_>, outcome: Result<Received, SendError>) -> PyResult<()> {
+        self.finish_with(py, outcome, |call, py, outcome| {
+            let success = call.read_success(py, outcome)?;
+            let flagged = call.core.moderation.flagged(&success.body).map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)?;
+            if flagged.is_empty() {
+                Ok(())
+            } else {
+                Err(moderation::blocked(py, flagged, &success.request_id))
+            }
+        })
+    }
+
+    fn finish_with<T>(
+        mut self,
+        py: Python<'_>,
+        outcome: Result<Received, SendError>,
+        complete: impl FnOnce(&mut Self, Python<'_>, Result<Received, SendError>) -> PyResult<T>,
+    ) -> PyResult<T> {
+        let moderated = self.moderated.lock().unwrap().take();
+        let screened = match moderated {
+            Some(moderated) => {
+                let (call, outcome) = *moderated;
+                call.finish_moderation(py, outcome)
+            }
+            None => Ok(()),
+        };
+        let result = screened.and_then(|()| complete(&mut self, py, outcome));
+        self.core.metrics.record_tokens(&self.audit.tokens);
+        if logging::is_enabled() {
+            logging::emit(py, &self.audit.log_event(py, self.attempts.load(Ordering::Relaxed), result.as_ref().err()));
+        }
+        if let Some(log) = self.core.audit_log.lock().unwrap().as_mut() {
+            let error = result.as_ref().err().and_then(|e| e.get_type(py).name().ok()).map(|name| name.to_string());
+            if let Err(e) = log.append(&self.audit, error.as_deref()) {
+                PyErr::from(e).write_unraisable(py, None);
+            }
+        }
+        let hook = self.core.audit_hook.read().unwrap().as_ref().map(|hook| hook.clone_ref(py));
+        if let Some(hook) = hook {
+            self.audit.finish(py, hook.bind(py), result.as_ref().err());
+        }
+        result
+    }
+
+    /// `finish`, keeping the envelope: a response that was read in full becomes a
+    /// `SecureRawResponse` whatever its status, holding what `finish` made of it. A call
+    /// that got no response, or only part of one, raises as `finish` does.
+    pub(crate) fn finish_raw(self, py: Python<'_>, outcome: Result<Received, SendError>) -> PyResult<SecureRawResponse> {
+        let envelope = match &outcome {
+            Ok(Received { status, headers, body: Ok(body), .. }) => {
+                Some((status.as_u16(), headers::visible(headers), SecureBytes::try_new(body)))
+            }
+            _ => None,
+        };
+        let parsed = self.finish(py, outcome);
+        match envelope {
+            Some((status, headers, body)) => SecureRawResponse::new(py, status, headers, body?, parsed),
+            None => Err(parsed.err().expect("a call without a response body fails")),
+        }
+    }
+
+    /// Remembers the fingerprint for the requested model and reports a change to
+    /// `on_fingerprint_change`. A failing callback is reported through `sys.unraisablehook`.
+    fn track_fingerprint(&self, py: Python<'_>, fingerprint: &str) {
+        let previous = self.core.fingerprints.lock().unwrap().insert(self.audit.model().to_string(), fingerprint.to_string());
+        let (Some(callback), Some(previous)) = (&self.core.on_fingerprint_change, previous) else {
+            return;
+        };
+        if previous != fingerprint {
+            if let Err(e) = callback.call1(py, (previous, fingerprint)) {
+                e.write_unraisable(py, Some(callback.bind(py)));
+            }
+        }
+    }
+
+    fn error_context(&self) -> ErrorContext<'_> {
+        ErrorContext {
+            endpoint: self.audit.endpoint(),
+            model: self.audit.model(),
+            elapsed: self.audit.elapsed(),
+            api_key: &self.connection.api_key,
+            provider: self.core.provider,
+            include_error_body: self.core.include_error_body,
+            max_error_text: self.core.max_error_text,
+        }
+    }
+
+    /// Names the limit that fired: the connect timeout while `connecting`, unless the overall
+    /// timeout is the shorter one, else the per-request or the client's overall timeout.
+    fn timed_out(&self, request_id: &str, connecting: bool) -> PyErr {
+        let overall = self.timeout.or(self.core.timeout);
+        let message = match self.timeout {
+            _ if connecting && overall.is_none_or(|overall| overall > CONNECT_TIMEOUT) => format!(
+                "Connecting timed out after the client connect timeout of {:?} (client request id {}); the host is unreachable \
+                 or not accepting connections. Pass timeout=None to SecureClient to wait indefinitely",
+                CONNECT_TIMEOUT, request_id
+            ),
+            Some(timeout) => format!("Request timed out after the per-request timeout of {:?} (client request id {})", timeout, request_id),
+            None => format!(
+                "Request timed out after the client timeout of {:?} (client request id {}); pass timeout= to change it for one call, \
+                 or to SecureClient for every call (None waits indefinitely)",
+                overall.unwrap_or_default(),
+                request_id
+            ),
+        };
+        PyErr::new::<pyo3::exceptions::PyTimeoutError, _>(message)
+    }
+
+    /// Maps a failed send, an unreadable body, a redirect or an error status to its
+    /// exception, and hands back the body of a successful response.
+    fn read_success(&mut self, py: Python<'_>, outcome: Result<Received, SendError>) -> PyResult<Success> {
+        let client_request_id = &self.client_request_id;
+        let res = match outcome {
+            Ok(res) => res,
+            Err(SendError::RateLimited(e)) => return Err(errors::client_rate_limited(py, e.to_string(), &self.error_context())),
+            Err(SendError::Moderated) => {
+                return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("the moderation check stopped the request"))
+            }
+            Err(SendError::Unreadable(e)) => return Err(e),
+            Err(SendError::Transport(TransportError::Memory(e))) => return Err(e),
+            Err(SendError::DeadlineExceeded(spent)) => {
+                *self.core.last_request_id.lock().unwrap() = Some(client_request_id.clone());
+                self.audit.request_id = Some(client_request_id.clone());
+                let deadline = self.deadline.as_ref().expect("only a call with a deadline runs out of it");
+                return Err(errors::deadline_exceeded(py, deadline, &spent, client_request_id));
+            }
+            Err(SendError::Transport(e)) => {
+                *self.core.last_request_id.lock().unwrap() = Some(client_request_id.clone());
+                self.audit.request_id = Some(client_request_id.clone());
+                if e.is_timeout() {
+                    return Err(self.timed_out(client_request_id, e.is_connect()));
+                }
+                if let (Some(address), true) = (self.core.local_address, is_address_not_available(&e)) {
+                    return Err(PyErr::new::<pyo3::exceptions::PyConnectionError, _>(format!(
+                        "Failed to send request (client request id {}): cannot bind local_address {}, it is not assigned to an interface on this host",
+                        client_request_id, address
+                    )));
+                }
+                return Err(PyErr::new::<pyo3::exceptions::PyConnectionError, _>(format!(
+                    "Failed to send request (client request id {}): {}",
+                    client_request_id, e
+                )));
+            }
+        };
+
+        *self.core.last_rate_limits.lock().unwrap() = RateLimits::from_headers(&res.headers);
+        if logging::is_enabled() {
+            self.audit.response_headers = res.headers.keys().map(|name| name.to_string()).collect();
+        }
+        let request_id = res
+            .headers
+            .get("x-request-id")
+            .or_else(|| res.headers.get("request-id"))
+            .or_else(|| res.headers.get("cf-ray"))
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+            .unwrap_or_else(|| client_request_id.clone());
+        *self.core.last_request_id.lock().unwrap() = Some(request_id.clone());
+        self.audit.request_id = Some(request_id.clone());
+        let status = res.status;
+        self.audit.status = Some(status.as_u16());
+        let mut raw_body = res.body.map_err(|e| match e {
+            BodyError::TooLarge { limit, observed, exact } => errors::response_too_large(py, limit, observed, exact),
+            BodyError::Io(e) if e.kind() == std::io::ErrorKind::TimedOut => self.timed_out(&request_id, false),
+            BodyError::Io(e) => PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to read response body (request id {}): {}", request_id, e)),
+            BodyError::Memory(e) => e,
+        })?;
+        self.audit.response_hash = self.core.audit_log.lock().unwrap().as_ref().map(|log| log.hash(&raw_body));
+        if status.is_redirection() {
+            raw_body.zeroize();
+            return Err(PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+                "API responded with redirect status {} (request id {}); redirects are not followed by default \
+                 because they can forward credentials elsewhere. Pass follow_redirects=N to SecureClient to allow them.",
+                status, request_id
+            )));
+        }
+        if !status.is_success() {
+            let retry_after = retry::server_delay(status, &res.headers);
+            let err = errors::api_error(py, status, &request_id, &self.error_context(), &raw_body, retry_after);
+            raw_body.zeroize();
+            return Err(err);
+        }
+        Ok(Success { request_id, headers: res.headers, version: res.version, body: raw_body })
+    }
+
+    fn complete(&mut self, py: Python<'_>, outcome: Result<Received, SendError>) -> PyResult<SecureResponse> {
+        let Success { request_id, headers, version, body: mut raw_body } = self.read_success(py, outcome)?;
+        let parsed = match (self.core.api(), self.stream) {
+            (Api::OpenAi, true) => {
+                let stream_usage = self.core.provider.map(|provider| provider.stream_usage).unwrap_or_default();
+                stream::parse_events(&raw_body, &request_id, stream_usage)
+            }
+            (Api::OpenAi, false) => serde_json::from_slice::<ChatCompletionResponse>(&raw_body)
+                .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Failed to parse JSON response: {}", e))),
+            (Api::Anthropic, true) => anthropic::parse_events(&raw_body, &request_id),
+            (Api::Anthropic, false) => anthropic::parse_response(&raw_body),
+        };
+        raw_body.zeroize();
+        let mut body = parsed?;
+        if let Some(provider) = self.core.provider {
+            for choice in &mut body.choices {
+                choice.finish_reason = choice.finish_reason.take().map(|reason| provider.finish_reason(reason));
+            }
+        }
+        if let Some(usage) = &body.usage {
+            self.audit.tokens = usage.counts();
+            if let (Some(limiter), Some(total)) = (&self.limiter, usage.total()) {
+                limiter.reconcile(self.estimated_tokens, total);
+            }
+        }
+        let Some(choice) = body.choices.first() else {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("API returned no choices."));
+        };
+        if let Some(fingerprint) = &body.system_fingerprint {
+            self.track_fingerprint(py, fingerprint);
+        }
+        if self.strict {
+            errors::check_finish_reason(py, choice.finish_reason.as_deref(), body.usage.as_ref(), &request_id)?;
+        }
+        let message = &choice.message;
+        if message.content.is_none() && message.refusal.is_none() && message.tool_calls.is_none() {
+            // A blocked completion has nothing to return, so it is an error in any mode.
+            if choice.finish_reason.as_deref() == Some("content_filter") {
+                errors::check_finish_reason(py, choice.finish_reason.as_deref(), body.usage.as_ref(), &request_id)?;
+            }
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "API returned a choice with no content, refusal or tool calls (request id {})",
+                request_id
+            )));
+        }
+        SecureResponse::new(py, body, &headers, version)
+    }
+}
+
+// --- Token Refresh ---
+
+/// The arguments of one `chat_completion` call, kept so that a 401 under a `token_provider`
+/// can build the call again with a fresh token. The body is serialized anew from the
+/// messages (which must still be usable) rather than kept around from the first attempt.
+pub(crate) struct ChatRequest {
+    client: SecureClient,
+    messages: Vec<Py<SecureMessage>>,
+    /// Messages converted from dicts, wiped once the call and any retry are done.
+    _converted: Converted,
+    model: Option<String>,
+    idempotency_key: Option<String>,
+    extra_headers: Option<Py<PyDict>>,
+    timeout: Option<f64>,
+    strict: bool,
+    stream: bool,
+    params: Option<Py<PyDict>>,
+    /// Shared with every call built from this request; see `ChatCall::screened`.
+    screened: Arc<AtomicBool>,
+    /// The deadline of the first call built, which a rebuilt call keeps.
+    deadline: OnceLock<Option<retry::Deadline>>,
+}
+
+impl ChatRequest {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        client: &SecureClient,
+        messages: Messages<'_>,
+        model: Option<String>,
+        idempotency_key: Option<String>,
+        extra_headers: Option<&Bound<'_, PyDict>>,
+        timeout: Option<f64>,
+        strict: bool,
+        stream: bool,
+        params: Option<&Bound<'_, PyDict>>,
+    ) -> Self {
+        let (messages, converted) = messages.into_parts();
+        Self {
+            client: SecureClient {
+                core: Arc::clone(&client.core),
+                default_model: client.default_model.clone(),
+                defaults: client.defaults.clone(),
+            },
+            messages,
+            _converted: converted,
+            model,
+            idempotency_key,
+            extra_headers: extra_headers.map(|headers| headers.clone().unbind()),
+            timeout,
+            strict,
+            stream,
+            params: params.map(|params| params.clone().unbind()),
+            screened: Arc::new(AtomicBool::new(false)),
+            deadline: OnceLock::new(),
+        }
+    }
+
+    pub(crate) fn prepare(&self, py: Python<'_>) -> PyResult<(ChatCall, PreparedRequest)> {
+        let (mut call, mut request) = self.client.prepare_chat(
+            self.messages.iter().map(|message| message.borrow(py)).collect(),
+            self.model.clone(),
+            self.idempotency_key.clone(),
+            self.extra_headers.as_ref().map(|headers| headers.bind(py)),
+            self.timeout,
+            self.strict,
+            self.stream,
+            self.params.as_ref().map(|params| params.bind(py)),
+        )?;
+        call.screened = Arc::clone(&self.screened);
+        call.deadline = self.deadline.get_or_init(|| call.deadline.clone()).clone();
+        if self.screened.load(Ordering::Relaxed) {
+            request.moderation = None;
+        }
+        if let Some(moderation) = &mut request.moderation {
+            moderation.0.deadline = call.deadline.clone();
+        }
+        Ok((call, request))
+    }
+
+    /// After an attempt sent with `token` that failed with `error`: the call to make instead,
+    /// if that is a 401 and the client has a `token_provider`. The rejected token is dropped
+    /// from the cache first, so the rebuilt call asks the provider for a new one; a call
+    /// that gets a 401 with the new token too fails with it.
+    pub(crate) fn refreshed(
+        &self,
+        py: Python<'_>,
+        token: &Arc<ApiKey>,
+        error: Option<&PyErr>,
+    ) -> Option<PyResult<(ChatCall, PreparedRequest)>> {
+        if !self.client.refresh_token(py, token, error) {
+            return None;
+        }
+        Some(self.prepare(py).map(|(mut call, request)| {
+            call.audit.token_refreshed = true;
+            (call, request)
+        }))
+    }
+}
+
+impl SecureClient {
+    /// Whether a call sent with `token` that failed with `error` is to be rebuilt, as
+    /// `ChatRequest::refreshed` describes; if so, the token is dropped from the cache.
+    fn refresh_token(&self, py: Python<'_>, token: &Arc<ApiKey>, error: Option<&PyErr>) -> bool {
+        let Some(provider) = &self.core.token_provider else {
+            return false;
+        };
+        let unauthorized = error.filter(|e| e.is_instance_of::<AuthenticationError>(py)).is_some_and(|e| {
+            e.value(py).getattr("status").and_then(|status| status.extract::<Option<u16>>()).ok().flatten() == Some(401)
+        });
+        if unauthorized {
+            provider.invalidate(py, token);
+            self.core.stats.record_token_refresh();
+        }
+        unauthorized
+    }
+}
+
+/// Sends `call`, and once more with a fresh token if `chat.refreshed` asks for it. Takes
+/// the GIL only to finish each attempt, so it can run on any runtime task.
+pub(crate) async fn complete(chat: ChatRequest, call: ChatCall, request: PreparedRequest) -> PyResult<SecureResponse> {
+    let token = call.api_key();
+    let outcome = call.send(request).await;
+    let (result, retry) = Python::with_gil(|py| {
+        let result = call.finish(py, outcome);
+        let retry = chat.refreshed(py, &token, result.as_ref().err());
+        (result, retry)
+    });
+    let Some(retry) = retry else {
+        return result;
+    };
+    let (call, request) = retry?;
+    let outcome = call.send(request).await;
+    Python::with_gil(|py| call.finish(py, outcome))
+}
+
+// --- Streaming Events ---
+
+/// What the task behind `stream_with_events` sends back to the calling thread.
+enum Streamed {
+    Token(SecureBytes),
+    Done(Box<(ChatCall, Result<Received, SendError>)>),
+}
+
+/// The callbacks of `stream_chat_with_events`; see there.
+pub(crate) struct StreamCallbacks<'py> {
+    on_token: Option<Bound<'py, PyAny>>,
+    on_tool_call: Option<Bound<'py, PyAny>>,
+    on_finish: Option<Bound<'py, PyAny>>,
+    on_error: Option<Bound<'py, PyAny>>,
+}
+
+impl<'py> StreamCallbacks<'py> {
+    pub(crate) fn new(
+        on_token: Option<Bound<'py, PyAny>>,
+        on_tool_call: Option<Bound<'py, PyAny>>,
+        on_finish: Option<Bound<'py, PyAny>>,
+        on_error: Option<Bound<'py, PyAny>>,
+    ) -> PyResult<Self> {
+        for (name, callback) in [("on_token", &on_token), ("on_tool_call", &on_tool_call), ("on_finish", &on_finish), ("on_error", &on_error)] {
+            if callback.as_ref().is_some_and(|callback| !callback.is_callable()) {
+                return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!("{} must be callable or None", name)));
+            }
+        }
+        Ok(Self { on_token, on_tool_call, on_finish, on_error })
+    }
+}
+
+/// Sends `chat` as a stream and reports it to `callbacks` as it goes. The request runs on
+/// the runtime without the GIL; every callback runs on the calling thread, which only
+/// holds the GIL while one is running. A callback that raises aborts the task, which drops
+/// the connection and wipes the body read so far and any deltas not yet delivered, and
+/// its exception propagates as is.
+pub(crate) fn stream_with_events(py: Python<'_>, chat: &ChatRequest, callbacks: &StreamCallbacks<'_>) -> PyResult<SecureResponse> {
+    let (call, request) = chat.prepare(py)?;
+    let token = call.api_key();
+    let mut result = stream_once(py, call, request, callbacks)?;
+    if let Some(retry) = chat.refreshed(py, &token, result.as_ref().err()) {
+        let (call, request) = retry?;
+        result = stream_once(py, call, request, callbacks)?;
+    }
+    match result {
+        Ok(response) => {
+            response.report_finish(py, callbacks.on_tool_call.as_ref(), callbacks.on_finish.as_ref())?;
+            Ok(response)
+        }
+        Err(err) => {
+            if let Some(on_error) = &callbacks.on_error {
+                on_error.call1((err.value(py),))?;
+            }
+            Err(err)
+        }
+    }
+}
+
+/// One attempt of `stream_with_events`. The outer error is a failing `on_token` or an
+/// interrupt, either of which abandons the call; the inner result is the call's own.
+fn stream_once(py: Python<'_>, call: ChatCall, request: PreparedRequest, callbacks: &StreamCallbacks<'_>) -> PyResult<PyResult<SecureResponse>> {
+    let (tx, mut rx) = mpsc::channel();
+    let task = transport::runtime().spawn(async move {
+        let outcome = call.send_with(request, Some(&tx)).await;
+        let _ = tx.send(Streamed::Done(Box::new((call, outcome))));
+    });
+    let _task = AbortOnDrop(vec![task.abort_handle()]);
+    loop {
+        match receive(py, &mut rx) {
+            Ok(Streamed::Token(token)) => {
+                if let Some(on_token) = &callbacks.on_token {
+                    on_token.call1((Py::new(py, token)?,))?;
+                }
+            }
+            Ok(Streamed::Done(done)) => {
+                let (call, outcome) = *done;
+                return Ok(call.finish(py, outcome));
+            }
+            Err(RecvTimeoutError::Timeout) => py.check_signals()?,
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("request task stopped unexpectedly"))
+            }
+        }
+    }
+}
+
+/// Sends the `HEAD` behind `warm_up()` and reads its (empty) body, leaving the connection in
+/// the pool. Must run on `transport::runtime()`.
+pub(crate) async fn warm_up(core: Arc<ClientCore>, connection: Connection) -> PyResult<()> {
+    let base_url = connection.base_url.as_str()?;
+    let path = if matches!(*connection.transport, transport::Transport::Http(_)) { "" } else { "/" };
+    let request =
+        transport::Request { method: Method::HEAD, path: path.to_string(), headers: HeaderMap::new(), body: Default::default(), timeout: None };
+    let started = Instant::now();
+    let mut response = connection.transport.send(base_url, request).await.map_err(|e| {
+        core.stats.record(0, 0, started.elapsed());
+        PyErr::new::<pyo3::exceptions::PyConnectionError, _>(format!("Failed to warm up the connection: {}", e))
+    })?;
+    let received = body::read_limited(&mut response, core.max_response_bytes).await.map_or(0, |body| body.len());
+    core.stats.record(0, received, started.elapsed());
+    Ok(())
+}
+
+/// How often a blocking wait checks for Python signals such as Ctrl-C.
+const SIGNAL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Runs `future` on the runtime and waits for it with the GIL released, checking for
+/// signals in between. When a signal handler raises (Ctrl-C's `KeyboardInterrupt`), the
+/// task is aborted, which drops the in-flight request and any partially read body, and
+/// the exception propagates. Every blocking endpoint waits through this.
+pub(crate) fn wait_interruptible<T: Send + 'static>(
+    py: Python<'_>,
+    future: impl Future<Output = T> + Send + 'static,
+) -> PyResult<T> {
+    let (tx, mut rx) = mpsc::channel();
+    let task = transport::runtime().spawn(async move {
+        let _ = tx.send(future.await);
+    });
+    let _task = AbortOnDrop(vec![task.abort_handle()]);
+    loop {
+        match receive(py, &mut rx) {
+            Ok(value) => return Ok(value),
+            Err(RecvTimeoutError::Timeout) => py.check_signals()?,
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("request task stopped unexpectedly"))
+            }
+        }
+    }
+}
+
+/// Waits up to `SIGNAL_POLL_INTERVAL` for the next message without holding the GIL.
+/// A `Receiver` is `Send` but not `Sync`, so the closure gets exclusive access.
+fn receive<T: Send>(py: Python<'_>, rx: &mut mpsc::Receiver<T>) -> Result<T, RecvTimeoutError> {
+    py.allow_threads(move || rx.recv_timeout(SIGNAL_POLL_INTERVAL))
+}
+
+/// Aborts every task still running when dropped, so an early return (a failing progress
+/// callback, an interrupt) never leaves requests running in the background.
+struct AbortOnDrop(Vec<tokio::task::AbortHandle>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        for task in &self.0 {
+            task.abort();
+        }
+    }
+}
+
+/// Sends prepared calls with at most `concurrency` in flight and returns their results in
+/// input order: `SecureBytes` for successes, the exception instance for failures. Each
+/// call is sent through `complete`, so a 401 under a `token_provider` is retried once
+/// with a fresh token. `progress` is called on the calling thread with `(done, total)` as
+/// results arrive; the GIL is released while waiting, and an interrupt aborts whatever
+/// is still in flight.
+pub(crate) fn run_many(
+    py: Python<'_>,
+    calls: Vec<(ChatRequest, ChatCall, PreparedRequest)>,
+    concurrency: usize,
+    progress: Option<&Bound<'_, PyAny>>,
+) -> PyResult<Vec<PyObject>> {
+    let total = calls.len();
+    let semaphore = Arc::new(tokio::sync::Semaphore::new(concurrency));
+    let (tx, mut rx) = mpsc::channel();
+    let mut tasks = AbortOnDrop(Vec::with_capacity(total));
+    for (index, (chat, call, request)) in calls.into_iter().enumerate() {
+        let (semaphore, tx) = (Arc::clone(&semaphore), tx.clone());
+        let task = transport::runtime().spawn(async move {
+            let _permit = semaphore.acquire_owned().await.expect("the semaphore is never closed");
+            let result = complete(chat, call, request).await;
+            let _ = tx.send((index, result));
+        });
+        tasks.0.push(task.abort_handle());
+    }
+    drop(tx);
+
+    let mut results: Vec<Option<PyObject>> = (0..total).map(|_| None).collect();
+    let mut done = 0;
+    while done < total {
+        let (index, result) = match receive(py, &mut rx) {
+            Ok(message) => message,
+            Err(RecvTimeoutError::Timeout) => {
+                py.check_signals()?;
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        done += 1;
+        results[index] = Some(match result {
+            Ok(response) => match response.into_content(py) {
+                Ok(content) => content.into_any(),
+                Err(e) => e.into_value(py).into_any(),
+            },
+            Err(e) => e.into_value(py).into_any(),
+        });
+        if let Some(progress) = progress {
+            progress.call1((done, total))?;
+        }
+    }
+    drop(tasks);
+    // A task that died without reporting back, such as one that panicked, fails on its own.
+    Ok(results
+        .into_iter()
+        .map(|result| {
+            result.unwrap_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("request task stopped unexpectedly").into_value(py).into_any()
+            })
+        })
+        .collect())
+}
@@ -0,0 +1,339 @@
+use crate::audit::{hex, unhex};
+use crate::body::LockedBuffer;
+use crate::headers;
+use crate::mock::MockBody;
+use crate::transport::{Request, Response, ResponseBody, Transport, TransportError};
+use crate::SecureBytes;
+use hyper::body::Bytes;
+use libsodium_sys::{
+    crypto_generichash_final, crypto_generichash_init, crypto_generichash_state, crypto_generichash_update,
+    crypto_secretbox_easy, crypto_secretbox_open_easy, randombytes_buf, crypto_secretbox_KEYBYTES,
+    crypto_secretbox_MACBYTES, crypto_secretbox_NONCEBYTES,
+};
+use pyo3::prelude::*;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{StatusCode, Version};
+use serde_json::Value;
+use std::ffi::c_void;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use zeroize::Zeroize;
+
+// --- Record/Replay Cassettes ---
+
+const KEY_BYTES: usize = crypto_secretbox_KEYBYTES as usize;
+const NONCE_BYTES: usize = crypto_secretbox_NONCEBYTES as usize;
+const MAC_BYTES: usize = crypto_secretbox_MACBYTES as usize;
+
+/// Cassette layout, version 1: a header line `{"cassette": "secure_openaiapi", "version": 1}`,
+/// then one JSON line per interaction. The request's `method`, `path` and `request_hash` and
+/// the response's `status` and `headers` (without credentials) are in the clear; `request`
+/// and `response` are the bodies, each sealed with `crypto_secretbox` under the cassette key
+/// behind a fresh nonce, in hex. The clear fields are not authenticated, so a cassette from
+/// an untrusted source can change what status a replay reports, but not what it says.
+const FORMAT: &str = "secure_openaiapi";
+const VERSION: u64 = 1;
+
+fn cassette_key(key: &SecureBytes) -> PyResult<SecureBytes> {
+    let key = key.expose()?;
+    if key.len() != KEY_BYTES {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("key must be {} bytes", KEY_BYTES)));
+    }
+    SecureBytes::try_new(key)
+}
+
+/// The one error for a cassette that does not open, so a wrong key is never told apart
+/// from a damaged file.
+fn undecryptable() -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyValueError, _>("cannot decrypt cassette: wrong key or corrupted file")
+}
+
+/// BLAKE2b keyed with the cassette key over the method, the path and the body as canonical
+/// JSON (keys sorted, no whitespace), so requests match whatever order their parameters were
+/// given in, and the hash cannot confirm a guessed prompt without the key. The body is
+/// hashed as it is serialized, and the parsed copy is wiped afterwards.
+fn request_hash(key: &SecureBytes, request: &Request) -> String {
+    struct Hasher(crypto_generichash_state);
+    impl Write for Hasher {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            unsafe { crypto_generichash_update(&mut self.0, bytes.as_ptr(), bytes.len() as u64) };
+            Ok(bytes.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let key = key.as_ref();
+    let mut hash = [0u8; 32];
+    let mut hasher = Hasher(crypto_generichash_state { opaque: [0; 384] });
+    unsafe { crypto_generichash_init(&mut hasher.0, key.as_ptr(), key.len(), hash.len()) };
+    let _ = writeln!(hasher, "{} {}", request.method, request.path);
+    match serde_json::from_slice::<Value>(&request.body) {
+        Ok(mut body) => {
+            serde_json::to_writer(&mut hasher, &body).expect("JSON values serialize");
+            wipe(&mut body);
+        }
+        Err(_) => {
+            let _ = hasher.write(&request.body);
+        }
+    }
+    unsafe { crypto_generichash_final(&mut hasher.0, hash.as_mut_ptr(), hash.len()) };
+    hasher.0.opaque.zeroize();
+    hex(&hash)
+}
+
+fn wipe(value: &mut Value) {
+    match value {
+        Value::String(text) => text.zeroize(),
+        Value::Array(items) => items.iter_mut().for_each(wipe),
+        Value::Object(fields) => fields.values_mut().for_each(wipe),
+        _ => {}
+    }
+}
+
+/// `nonce || crypto_secretbox_easy(plaintext)`.
+fn seal(key: &SecureBytes, plaintext: &[u8]) -> Vec<u8> {
+    let mut sealed = vec![0u8; NONCE_BYTES + MAC_BYTES + plaintext.len()];
+    let (nonce, boxed) = sealed.split_at_mut(NONCE_BYTES);
+    unsafe {
+        randombytes_buf(nonce.as_mut_ptr() as *mut c_void, NONCE_BYTES);
+        crypto_secretbox_easy(boxed.as_mut_ptr(), plaintext.as_ptr(), plaintext.len() as u64, nonce.as_ptr(), key.as_ref().as_ptr());
+    }
+    sealed
+}
+
+/// Opens what `seal` made, straight into locked memory. `None` if it does not open under
+/// `key`; an error only if the buffer could not be locked under `strict_memory()`.
+fn open(key: &SecureBytes, sealed: &[u8]) -> PyResult<Option<LockedBuffer>> {
+    if sealed.len() < NONCE_BYTES + MAC_BYTES {
+        return Ok(None);
+    }
+    let (nonce, boxed) = sealed.split_at(NONCE_BYTES);
+    let mut plaintext = LockedBuffer::zeroed(boxed.len() - MAC_BYTES)?;
+    let opened = unsafe {
+        crypto_secretbox_open_easy(plaintext.as_mut_ptr(), boxed.as_ptr(), boxed.len() as u64, nonce.as_ptr(), key.as_ref().as_ptr())
+    };
+    Ok((opened == 0).then_some(plaintext))
+}
+
+/// One request/response pair as a line of the cassette.
+struct Interaction {
+    request_hash: String,
+    method: String,
+    path: String,
+    status: u16,
+    headers: Vec<(String, String)>,
+    request: Vec<u8>,
+    response: Vec<u8>,
+}
+
+impl Interaction {
+    fn to_line(&self) -> String {
+        let headers: serde_json::Map<String, Value> =
+            self.headers.iter().map(|(name, value)| (name.clone(), Value::from(value.as_str()))).collect();
+        let line = serde_json::json!({
+            "request_hash": self.request_hash,
+            "method": self.method,
+            "path": self.path,
+            "status": self.status,
+            "headers": headers,
+            "request": hex(&self.request),
+            "response": hex(&self.response),
+        });
+        format!("{}\n", line)
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let fields: Value = serde_json::from_str(line).ok()?;
+        let text = |name: &str| fields.get(name)?.as_str().map(str::to_string);
+        let headers = fields.get("headers")?.as_object()?;
+        Some(Self {
+            request_hash: text("request_hash")?,
+            method: text("method")?,
+            path: text("path")?,
+            status: u16::try_from(fields.get("status")?.as_u64()?).ok()?,
+            headers: headers.iter().map(|(name, value)| Some((name.clone(), value.as_str()?.to_string()))).collect::<Option<_>>()?,
+            request: unhex(&text("request")?)?,
+            response: unhex(&text("response")?)?,
+        })
+    }
+
+    fn describe(&self) -> String {
+        format!("{} ({} {})", self.request_hash, self.method, self.path)
+    }
+}
+
+/// A cassette being recorded: the key and the file, shared by every response still being read.
+struct Cassette {
+    key: SecureBytes,
+    file: Mutex<File>,
+}
+
+/// Sends through the client's real transport and appends every interaction to a cassette.
+/// An interaction is written once its response body has been read to the end, so a stream
+/// the caller abandoned is not recorded.
+pub(crate) struct Recorder {
+    inner: Arc<Transport>,
+    cassette: Arc<Cassette>,
+}
+
+impl Recorder {
+    /// Starts a new cassette at `path`, replacing any file there, created readable by the
+    /// owner only on unix.
+    pub(crate) fn create(path: &Path, key: &SecureBytes, inner: Arc<Transport>) -> PyResult<Self> {
+        let key = cassette_key(key)?;
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(path)?;
+        writeln!(file, "{}", serde_json::json!({ "cassette": FORMAT, "version": VERSION }))?;
+        file.flush()?;
+        Ok(Self { inner, cassette: Arc::new(Cassette { key, file: Mutex::new(file) }) })
+    }
+
+    /// The transport requests really go through, so recording again does not record twice.
+    pub(crate) fn inner(&self) -> Arc<Transport> {
+        Arc::clone(&self.inner)
+    }
+
+    pub(crate) fn is_unix(&self) -> bool {
+        self.inner.is_unix()
+    }
+
+    pub(crate) async fn send(&self, base_url: &str, request: Request) -> Result<Response, TransportError> {
+        let key = &self.cassette.key;
+        let mut interaction = Interaction {
+            request_hash: request_hash(key, &request),
+            method: request.method.to_string(),
+            path: request.path.clone(),
+            status: 0,
+            headers: Vec::new(),
+            request: seal(key, &request.body),
+            response: Vec::new(),
+        };
+        let response = Box::pin(self.inner.send(base_url, request)).await?;
+        interaction.status = response.status.as_u16();
+        interaction.headers = headers::visible(&response.headers);
+        let body = RecordingBody {
+            body: response.body,
+            captured: LockedBuffer::with_capacity(0).map_err(TransportError::Memory)?,
+            interaction: Some(interaction),
+            cassette: Arc::clone(&self.cassette),
+        };
+        Ok(Response { body: ResponseBody::Recording(Box::new(body)), ..response })
+    }
+}
+
+/// A response body passed through as it is read, and copied into locked memory to be sealed
+/// into the cassette at its end.
+pub(crate) struct RecordingBody {
+    body: ResponseBody,
+    captured: LockedBuffer,
+    interaction: Option<Interaction>,
+    cassette: Arc<Cassette>,
+}
+
+impl RecordingBody {
+    pub(crate) async fn chunk(&mut self) -> std::io::Result<Option<Bytes>> {
+        let chunk = Box::pin(self.body.chunk()).await?;
+        match &chunk {
+            Some(chunk) => self.captured.extend_from_slice(chunk).map_err(std::io::Error::other)?,
+            None => {
+                if let Some(mut interaction) = self.interaction.take() {
+                    interaction.response = seal(&self.cassette.key, &self.captured);
+                    let mut file = self.cassette.file.lock().unwrap();
+                    file.write_all(interaction.to_line().as_bytes())?;
+                    file.flush()?;
+                }
+            }
+        }
+        Ok(chunk)
+    }
+}
+
+/// Serves requests from a cassette without any network. Each recorded interaction is
+/// played once, in order among those with the same request hash, so a conversation that
+/// sent the same request twice gets both of its answers back.
+pub(crate) struct Replayer {
+    key: SecureBytes,
+    interactions: Vec<Interaction>,
+    played: Mutex<Vec<bool>>,
+}
+
+impl Replayer {
+    /// Reads the cassette at `path` and checks that every body in it opens under `key`.
+    pub(crate) fn open(path: &Path, key: &SecureBytes) -> PyResult<Self> {
+        let key = cassette_key(key)?;
+        let invalid = |message: String| PyErr::new::<pyo3::exceptions::PyValueError, _>(message);
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let header: Value = match lines.next().transpose()? {
+            Some(line) => serde_json::from_str(&line).map_err(|_| invalid(format!("{} is not a cassette", path.display())))?,
+            None => return Err(invalid(format!("{} is not a cassette", path.display()))),
+        };
+        if header.get("cassette").and_then(Value::as_str) != Some(FORMAT) {
+            return Err(invalid(format!("{} is not a cassette", path.display())));
+        }
+        match header.get("version").and_then(Value::as_u64) {
+            Some(VERSION) => {}
+            version => {
+                return Err(invalid(format!(
+                    "unsupported cassette version {}: this version reads version {}",
+                    version.map_or("(missing)".to_string(), |version| version.to_string()),
+                    VERSION
+                )))
+            }
+        }
+        let mut interactions = Vec::new();
+        for (index, line) in lines.enumerate() {
+            let line = line?;
+            let interaction = Interaction::from_line(&line)
+                .ok_or_else(|| invalid(format!("cassette line {} is not a recorded interaction", index + 2)))?;
+            if open(&key, &interaction.request)?.is_none() || open(&key, &interaction.response)?.is_none() {
+                return Err(undecryptable());
+            }
+            interactions.push(interaction);
+        }
+        let played = Mutex::new(vec![false; interactions.len()]);
+        Ok(Self { key, interactions, played })
+    }
+
+    pub(crate) fn send(&self, request: &Request) -> Result<Response, TransportError> {
+        let hash = request_hash(&self.key, request);
+        let mut played = self.played.lock().unwrap();
+        let Some(index) = (0..self.interactions.len()).find(|&i| !played[i] && self.interactions[i].request_hash == hash) else {
+            return Err(TransportError::Cassette(self.mismatch(request, &hash, &played)));
+        };
+        played[index] = true;
+        let interaction = &self.interactions[index];
+        let body = open(&self.key, &interaction.response)
+            .map_err(TransportError::Memory)?
+            .expect("bodies are checked when the cassette is opened");
+        let mut headers = HeaderMap::new();
+        for (name, value) in &interaction.headers {
+            if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(value)) {
+                headers.append(name, value);
+            }
+        }
+        Ok(Response {
+            status: StatusCode::from_u16(interaction.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            version: Version::HTTP_11,
+            headers,
+            body: ResponseBody::Mock(MockBody::Complete(Some(Bytes::from_owner(body)))),
+        })
+    }
+
+    fn mismatch(&self, request: &Request, hash: &str, played: &[bool]) -> String {
+        let unplayed: Vec<String> =
+            self.interactions.iter().zip(played).filter(|(_, &played)| !played).map(|(interaction, _)| interaction.describe()).collect();
+        let expected = if unplayed.is_empty() {
+            format!("all {} recorded interactions have already been played", self.interactions.len())
+        } else {
+            format!("the cassette expects one of: {}", unplayed.join(", "))
+        };
+        format!("no recorded interaction matches {} {} with request hash {}; {}", request.method, request.path, hash, expected)
+    }
+}
@@ -0,0 +1,196 @@
+use crate::{SecureBytes, SecureClient, SecureContentPart, SecureMessage};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+// --- Conversation History ---
+
+/// A multi-turn chat history kept in `SecureMessage`s, with an optional fixed `system`
+/// prompt in front. Turns are added with `add_user`, `add_assistant` and
+/// `add_tool_result`, whose text may be str, bytes or `SecureBytes`, or by `send`, which
+/// asks the next question and records the answer. `messages()` is the list to pass to
+/// `chat_completion`; `len()` and `approx_tokens()` count it, system prompt included.
+///
+/// `trim_to` keeps the history within a token budget by dropping the oldest turns.
+#[pyclass(name = "SecureConversation")]
+pub(crate) struct SecureConversation {
+    system: Option<Py<SecureMessage>>,
+    turns: Vec<Py<SecureMessage>>,
+    /// How many turns the placeholder left by `truncate_middle` stands for. While it is
+    /// non-zero, the placeholder is `turns[0]`.
+    replaced: usize,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum TrimStrategy {
+    DropOldest,
+    TruncateMiddle,
+}
+
+impl TrimStrategy {
+    fn parse(strategy: &str) -> PyResult<Self> {
+        match strategy {
+            "drop_oldest" => Ok(Self::DropOldest),
+            "truncate_middle" => Ok(Self::TruncateMiddle),
+            _ => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Unknown trim strategy '{}': expected 'drop_oldest' or 'truncate_middle'",
+                strategy
+            ))),
+        }
+    }
+}
+
+impl SecureConversation {
+    fn all(&self) -> impl Iterator<Item = &Py<SecureMessage>> {
+        self.system.iter().chain(&self.turns)
+    }
+
+    /// Like `approx_tokens()`, but counting wiped messages as empty instead of failing, so
+    /// a history holding one can still be trimmed.
+    fn tokens(&self, py: Python<'_>) -> u64 {
+        self.all().map(|message| message.borrow(py).estimate_tokens()).sum()
+    }
+
+    /// The oldest turn that may go: not the placeholder, and not the most recent user turn.
+    fn oldest_removable(&self, py: Python<'_>) -> Option<usize> {
+        let latest_user = self.turns.iter().rposition(|turn| turn.borrow(py).role.bytes() == b"user");
+        let first = usize::from(self.replaced > 0);
+        (first..self.turns.len()).find(|&index| Some(index) != latest_user)
+    }
+
+    fn trim(&mut self, py: Python<'_>, max_tokens: u64, strategy: TrimStrategy) -> PyResult<usize> {
+        let mut removed = 0;
+        while self.tokens(py) > max_tokens {
+            let Some(index) = self.oldest_removable(py) else {
+                break;
+            };
+            self.turns.remove(index).borrow_mut(py).wipe();
+            removed += 1;
+            if strategy == TrimStrategy::TruncateMiddle {
+                let text = format!("[{} earlier messages were removed to fit the context window]", self.replaced + 1);
+                let placeholder = SecureMessage {
+                    role: SecureBytes::try_new(b"user")?,
+                    content: vec![SecureContentPart::Text { text: SecureBytes::try_new(text.as_bytes())? }],
+                    tool_call_id: None,
+                    name: None,
+                };
+                let placeholder = Py::new(py, placeholder)?;
+                if self.replaced > 0 {
+                    std::mem::replace(&mut self.turns[0], placeholder).borrow_mut(py).wipe();
+                } else {
+                    self.turns.insert(0, placeholder);
+                }
+                self.replaced += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    fn push(&mut self, py: Python<'_>, message: SecureMessage) -> PyResult<()> {
+        self.turns.push(Py::new(py, message)?);
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl SecureConversation {
+    #[new]
+    #[pyo3(signature = (system=None))]
+    fn new(py: Python<'_>, system: Option<&Bound<'_, PyAny>>) -> PyResult<Self> {
+        let system = system.map(|text| Py::new(py, SecureMessage::text(b"system", text, "system")?)).transpose()?;
+        Ok(Self { system, turns: Vec::new(), replaced: 0 })
+    }
+
+    fn add_user(&mut self, py: Python<'_>, text: &Bound<'_, PyAny>) -> PyResult<()> {
+        self.push(py, SecureMessage::text(b"user", text, "text")?)
+    }
+
+    fn add_assistant(&mut self, py: Python<'_>, text: &Bound<'_, PyAny>) -> PyResult<()> {
+        self.push(py, SecureMessage::text(b"assistant", text, "text")?)
+    }
+
+    /// Records the result of the tool call `tool_call_id` (see `SecureToolCall.id`).
+    fn add_tool_result(&mut self, py: Python<'_>, tool_call_id: String, text: &Bound<'_, PyAny>) -> PyResult<()> {
+        let mut message = SecureMessage::text(b"tool", text, "text")?;
+        message.tool_call_id = Some(tool_call_id);
+        self.push(py, message)
+    }
+
+    /// The history as a new list, system prompt first. The messages are the stored ones,
+    /// not copies, so wiping one of them wipes it from the conversation too.
+    fn messages(&self, py: Python<'_>) -> Vec<Py<SecureMessage>> {
+        self.all().map(|message| message.clone_ref(py)).collect()
+    }
+
+    /// Adds `text` as a user turn, sends the history through `client.chat_completion` with
+    /// `model` and `params`, and adds and returns the answer. When the call fails, the user
+    /// turn is wiped and removed again, so the history never ends on an unanswered question.
+    /// With `auto_trim`, the history is first trimmed to that many tokens as by `trim_to`.
+    #[pyo3(signature = (client, text, model=None, *, auto_trim=None, **params))]
+    fn send(
+        &mut self,
+        py: Python<'_>,
+        client: PyRef<'_, SecureClient>,
+        text: &Bound<'_, PyAny>,
+        model: Option<String>,
+        auto_trim: Option<u64>,
+        params: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Py<SecureBytes>> {
+        self.add_user(py, text)?;
+        if let Some(max_tokens) = auto_trim {
+            self.trim(py, max_tokens, TrimStrategy::DropOldest)?;
+        }
+        let messages = self.all().map(|message| message.bind(py).clone()).collect::<Vec<_>>().into();
+        let answer = client.chat_completion(py, messages, model, None, None, None, false, false, params);
+        let answer = match answer {
+            Ok(answer) => answer,
+            Err(err) => {
+                if let Some(turn) = self.turns.pop() {
+                    turn.borrow_mut(py).wipe();
+                }
+                return Err(err);
+            }
+        };
+        self.add_assistant(py, answer.bind(py))?;
+        Ok(answer)
+    }
+
+    /// Removes the oldest turns until `approx_tokens()` is at most `max_tokens`, wiping each
+    /// as it goes. The system prompt and the most recent user turn are always kept, so the
+    /// result can still be over budget. `"drop_oldest"` just removes turns; with
+    /// `"truncate_middle"` a single user message saying how many were removed takes their
+    /// place, and later trims update it. Returns the counts: `removed`, `tokens_before`,
+    /// `tokens_after` and whether the history now `fits`.
+    #[pyo3(signature = (max_tokens, strategy="drop_oldest"))]
+    fn trim_to<'py>(&mut self, py: Python<'py>, max_tokens: u64, strategy: &str) -> PyResult<Bound<'py, PyDict>> {
+        let strategy = TrimStrategy::parse(strategy)?;
+        let before = self.tokens(py);
+        let removed = self.trim(py, max_tokens, strategy)?;
+        let after = self.tokens(py);
+        let report = PyDict::new(py);
+        report.set_item("removed", removed)?;
+        report.set_item("tokens_before", before)?;
+        report.set_item("tokens_after", after)?;
+        report.set_item("fits", after <= max_tokens)?;
+        Ok(report)
+    }
+
+    /// Wipes every message, the system prompt included, leaving an empty conversation.
+    fn clear(&mut self, py: Python<'_>) {
+        for message in self.all() {
+            message.borrow_mut(py).wipe();
+        }
+        self.system = None;
+        self.turns.clear();
+        self.replaced = 0;
+    }
+
+    /// Rough token count of the history, as `SecureMessage.approx_tokens()` summed over
+    /// `messages()`.
+    fn approx_tokens(&self, py: Python<'_>) -> PyResult<u64> {
+        self.all().map(|message| message.borrow(py).approx_tokens()).sum()
+    }
+
+    fn __len__(&self) -> usize {
+        self.all().count()
+    }
+}
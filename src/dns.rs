@@ -0,0 +1,93 @@
+use pyo3::prelude::*;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+
+// --- Name Resolution ---
+
+/// Which address family outgoing connections may use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum IpVersion {
+    Auto,
+    V4,
+    V6,
+}
+
+impl IpVersion {
+    pub(crate) fn parse(value: &str) -> PyResult<Self> {
+        match value {
+            "auto" => Ok(IpVersion::Auto),
+            "v4" => Ok(IpVersion::V4),
+            "v6" => Ok(IpVersion::V6),
+            _ => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "ip_version must be 'auto', 'v4' or 'v6', got '{}'",
+                value
+            ))),
+        }
+    }
+
+    fn allows(self, ip: &IpAddr) -> bool {
+        match self {
+            IpVersion::Auto => true,
+            IpVersion::V4 => ip.is_ipv4(),
+            IpVersion::V6 => ip.is_ipv6(),
+        }
+    }
+
+    pub(crate) fn check_local_address(self, local_address: &IpAddr) -> PyResult<()> {
+        if self.allows(local_address) {
+            Ok(())
+        } else {
+            Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "local_address {} does not match ip_version={:?}",
+                local_address, self
+            )))
+        }
+    }
+}
+
+/// System resolver that only returns addresses of one family, so dual-stack hosts
+/// never attempt a connection over the unwanted protocol.
+pub(crate) struct FamilyResolver {
+    version: IpVersion,
+}
+
+impl FamilyResolver {
+    pub(crate) fn new(version: IpVersion) -> Self {
+        Self { version }
+    }
+}
+
+impl Resolve for FamilyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let version = self.version;
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let lookup = host.clone();
+            let addrs = tokio::task::spawn_blocking(move || (lookup.as_str(), 0).to_socket_addrs())
+                .await
+                .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)??;
+            let filtered: Vec<SocketAddr> = addrs.filter(|addr| version.allows(&addr.ip())).collect();
+            if filtered.is_empty() {
+                return Err(format!("no {:?} addresses found for the host", version).into());
+            }
+            Ok(Box::new(filtered.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Parses a `dns_overrides` entry. Both `"ip:port"` and a bare `"ip"` are accepted; as with any
+/// DNS answer, the port actually used is the one from the base URL.
+pub(crate) fn parse_dns_override(host: &str, address: &str) -> PyResult<SocketAddr> {
+    if host.is_empty() {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("dns_overrides hostnames must not be empty"));
+    }
+    address
+        .parse::<SocketAddr>()
+        .or_else(|_| address.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, 0)))
+        .map_err(|_| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Invalid dns_overrides address for '{}': expected 'ip:port' or 'ip', got '{}'",
+                host, address
+            ))
+        })
+}
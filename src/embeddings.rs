@@ -0,0 +1,485 @@
+use crate::audit::TokenCounts;
+use crate::body::LockedBuffer;
+use crate::json::{self, RequestBody, SecureJsonWriter};
+use crate::SecureBytes;
+use libsodium_sys::{sodium_base642bin, sodium_base64_VARIANT_ORIGINAL};
+use pyo3::buffer::PyBuffer;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList, PyTuple};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::io;
+
+// --- Embeddings ---
+
+/// The most inputs OpenAI takes in one embeddings request, and the default batch size.
+pub(crate) const MAX_BATCH_SIZE: usize = 2048;
+
+/// How the vectors come back: JSON numbers, or each vector as base64 of its little-endian
+/// float32 values, a quarter of the size on the wire.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum EncodingFormat {
+    Float,
+    Base64,
+}
+
+impl EncodingFormat {
+    pub(crate) fn parse(format: &str) -> PyResult<Self> {
+        match format {
+            "float" => Ok(EncodingFormat::Float),
+            "base64" => Ok(EncodingFormat::Base64),
+            _ => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Invalid encoding_format '{}': expected 'float' or 'base64'",
+                format
+            ))),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            EncodingFormat::Float => "float",
+            EncodingFormat::Base64 => "base64",
+        }
+    }
+}
+
+/// Copies the `input` argument into locked buffers: one str, bytes or `SecureBytes`, or a
+/// list or tuple of them. Errors name an input by index, never its text.
+pub(crate) fn inputs(input: &Bound<'_, PyAny>) -> PyResult<Vec<SecureBytes>> {
+    let invalid = |message: String| PyErr::new::<pyo3::exceptions::PyValueError, _>(message);
+    if !(input.is_instance_of::<PyList>() || input.is_instance_of::<PyTuple>()) {
+        let text = SecureBytes::from_py_text(input, "input")?;
+        if text.bytes().is_empty() {
+            return Err(invalid("input is empty".to_string()));
+        }
+        return Ok(vec![text]);
+    }
+    let mut inputs = Vec::new();
+    for (index, item) in input.try_iter()?.enumerate() {
+        let text = SecureBytes::from_py_text(&item?, &format!("input[{}]", index))?;
+        if text.bytes().is_empty() {
+            return Err(invalid(format!("input[{}] is empty", index)));
+        }
+        inputs.push(text);
+    }
+    if inputs.is_empty() {
+        return Err(invalid("input must not be empty".to_string()));
+    }
+    Ok(inputs)
+}
+
+/// Validates `batch_size`: at least one input, and no more than any provider takes.
+pub(crate) fn check_batch_size(batch_size: usize) -> PyResult<usize> {
+    if batch_size == 0 || batch_size > MAX_BATCH_SIZE {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "batch_size must be between 1 and {}",
+            MAX_BATCH_SIZE
+        )));
+    }
+    Ok(batch_size)
+}
+
+/// One embeddings request. The inputs are escaped from their locked buffers straight into
+/// the body, like message content.
+pub(crate) struct EmbeddingsRequest<'a> {
+    pub(crate) inputs: &'a [SecureBytes],
+    pub(crate) model: &'a str,
+    pub(crate) dimensions: Option<u32>,
+    pub(crate) encoding_format: EncodingFormat,
+    pub(crate) params: &'a Map<String, Value>,
+}
+
+impl EmbeddingsRequest<'_> {
+    pub(crate) fn to_json(&self) -> PyResult<RequestBody> {
+        let mut out = SecureJsonWriter::new()?;
+        self.write_json(&mut out).map_err(json::serialize_error)?;
+        out.finish()
+    }
+
+    fn write_json(&self, out: &mut SecureJsonWriter) -> io::Result<()> {
+        out.write_raw(b"{\"input\":[");
+        for (index, input) in self.inputs.iter().enumerate() {
+            if index > 0 {
+                out.write_raw(b",");
+            }
+            out.write_str(input.bytes())?;
+        }
+        out.write_raw(b"],\"model\":");
+        out.write_value(self.model)?;
+        out.write_raw(b",\"encoding_format\":");
+        out.write_value(self.encoding_format.name())?;
+        if let Some(dimensions) = self.dimensions {
+            out.write_raw(b",\"dimensions\":");
+            out.write_value(&dimensions)?;
+        }
+        for (name, value) in self.params {
+            out.write_raw(b",");
+            out.write_value(name)?;
+            out.write_raw(b":");
+            out.write_value(value)?;
+        }
+        out.write_raw(b"}");
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<Embedding>,
+    model: Option<String>,
+    usage: Option<EmbeddingsUsage>,
+}
+
+#[derive(Deserialize)]
+struct Embedding {
+    index: usize,
+    embedding: Vector,
+}
+
+/// A vector as it arrived, already in locked memory: float32 values from a JSON array, or
+/// the base64 string still to be decoded. Servers that ignore `encoding_format` send
+/// numbers either way, so both are accepted.
+enum Vector {
+    Floats(SecureBytes),
+    Base64(SecureBytes),
+}
+
+impl<'de> Deserialize<'de> for Vector {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct VectorVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for VectorVisitor {
+            type Value = Vector;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("an array of numbers or a base64 string")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Vector, E> {
+                SecureBytes::try_new(value.as_bytes()).map(Vector::Base64).map_err(E::custom)
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Vector, A::Error> {
+                let mut floats = LockedBuffer::with_capacity(INITIAL_VECTOR_BYTES).map_err(serde::de::Error::custom)?;
+                while let Some(value) = seq.next_element::<f32>()? {
+                    floats.extend_from_slice(&value.to_le_bytes()).map_err(serde::de::Error::custom)?;
+                }
+                SecureBytes::try_new(&floats).map(Vector::Floats).map_err(serde::de::Error::custom)
+            }
+        }
+
+        deserializer.deserialize_any(VectorVisitor)
+    }
+}
+
+/// Room for 2048 dimensions before the buffer has to grow.
+const INITIAL_VECTOR_BYTES: usize = 8 * 1024;
+
+#[derive(Deserialize)]
+struct EmbeddingsUsage {
+    prompt_tokens: Option<u64>,
+    total_tokens: Option<u64>,
+}
+
+/// The vectors of one request, in input order.
+pub(crate) struct Batch {
+    vectors: Vec<SecureBytes>,
+    model: Option<String>,
+    pub(crate) usage: TokenCounts,
+}
+
+/// Parses the response to a request of `expected` inputs, putting the vectors back in
+/// input order by their `index`.
+pub(crate) fn parse(body: &[u8], request_id: &str, expected: usize) -> PyResult<Batch> {
+    let invalid = |message: String| PyErr::new::<pyo3::exceptions::PyValueError, _>(message);
+    let mut response = serde_json::from_slice::<EmbeddingsResponse>(body)
+        .map_err(|e| invalid(format!("Failed to parse JSON response: {}", e)))?;
+    response.data.sort_by_key(|embedding| embedding.index);
+    if response.data.len() != expected || response.data.iter().enumerate().any(|(index, embedding)| embedding.index != index) {
+        return Err(invalid(format!(
+            "API returned {} embeddings for {} inputs, or with indices out of range (request id {})",
+            response.data.len(),
+            expected,
+            request_id
+        )));
+    }
+    let vectors = response
+        .data
+        .into_iter()
+        .map(|embedding| match embedding.embedding {
+            Vector::Floats(floats) => Ok(floats),
+            Vector::Base64(encoded) => decode_base64(encoded.bytes())?.map_err(|reason| {
+                invalid(format!("embedding {} is not valid base64 float32 data: {} (request id {})", embedding.index, reason, request_id))
+            }),
+        })
+        .collect::<PyResult<_>>()?;
+    let usage = response.usage.map_or_else(TokenCounts::default, |usage| TokenCounts {
+        prompt: usage.prompt_tokens,
+        completion: None,
+        total: usage.total_tokens.or(usage.prompt_tokens),
+    });
+    Ok(Batch { vectors, model: response.model, usage })
+}
+
+/// Decodes a base64 vector straight into a locked buffer of little-endian float32 values.
+fn decode_base64(encoded: &[u8]) -> PyResult<Result<SecureBytes, &'static str>> {
+    let mut floats = SecureBytes::zeroed(encoded.len() / 4 * 3 + 3)?;
+    let mut len = 0;
+    let status = unsafe {
+        let out = floats.bytes_mut();
+        sodium_base642bin(
+            out.as_mut_ptr(),
+            out.len(),
+            encoded.as_ptr().cast(),
+            encoded.len(),
+            std::ptr::null(),
+            &mut len,
+            std::ptr::null_mut(),
+            sodium_base64_VARIANT_ORIGINAL as i32,
+        )
+    };
+    if status != 0 {
+        return Ok(Err("malformed base64"));
+    }
+    if len % 4 != 0 {
+        return Ok(Err("length is not a multiple of 4 bytes"));
+    }
+    floats.resize(len)?;
+    Ok(Ok(floats))
+}
+
+fn floats(bytes: &[u8]) -> impl Iterator<Item = f32> + '_ {
+    bytes.chunks_exact(4).map(|value| f32::from_le_bytes(value.try_into().expect("chunks of 4")))
+}
+
+/// The embeddings of every input of a call, in input order, with the token usage summed
+/// over the requests it took. The vectors stay in locked memory until read: as lists of
+/// floats, or as `SecureFloatArray`s with `secure_output=True`.
+#[pyclass(name = "SecureEmbeddings", frozen)]
+pub(crate) struct SecureEmbeddings {
+    vectors: Vec<Py<SecureFloatArray>>,
+    secure_output: bool,
+    #[pyo3(get)]
+    model: Option<String>,
+    usage: TokenCounts,
+    /// How many requests the inputs were split into.
+    #[pyo3(get)]
+    requests: usize,
+}
+
+impl SecureEmbeddings {
+    /// Joins the batches of one call, which are in input order.
+    pub(crate) fn merge(py: Python<'_>, batches: Vec<Batch>, secure_output: bool) -> PyResult<Self> {
+        let add = |a: Option<u64>, b: Option<u64>| Some(a? + b?);
+        let mut merged = Self {
+            vectors: Vec::new(),
+            secure_output,
+            model: None,
+            usage: TokenCounts::default(),
+            requests: batches.len(),
+        };
+        merged.usage.prompt = Some(0);
+        merged.usage.total = Some(0);
+        for batch in batches {
+            for values in batch.vectors {
+                merged.vectors.push(Py::new(py, SecureFloatArray { values })?);
+            }
+            merged.model = merged.model.or(batch.model);
+            merged.usage.prompt = add(merged.usage.prompt, batch.usage.prompt);
+            merged.usage.total = add(merged.usage.total, batch.usage.total);
+        }
+        Ok(merged)
+    }
+}
+
+#[pymethods]
+impl SecureEmbeddings {
+    /// One vector per input, in the order of the inputs: a list of floats, or the
+    /// `SecureFloatArray` itself with `secure_output=True`.
+    #[getter]
+    fn embeddings(&self, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        self.vectors
+            .iter()
+            .map(|vector| {
+                if self.secure_output {
+                    Ok(vector.clone_ref(py).into_any())
+                } else {
+                    Ok(vector.borrow(py).tolist()?.into_pyobject(py)?.into_any().unbind())
+                }
+            })
+            .collect()
+    }
+
+    /// `prompt_tokens` and `total_tokens` over all requests; `None` where a response didn't
+    /// report them.
+    #[getter]
+    fn usage<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        dict.set_item("prompt_tokens", self.usage.prompt)?;
+        dict.set_item("total_tokens", self.usage.total)?;
+        Ok(dict)
+    }
+
+    fn __len__(&self) -> usize {
+        self.vectors.len()
+    }
+
+    fn __repr__(&self, py: Python<'_>) -> String {
+        let dimensions = self.vectors.first().map_or(0, |vector| vector.borrow(py).__len__());
+        format!(
+            "SecureEmbeddings(<{} vectors of {} dimensions>, model={:?})",
+            self.vectors.len(),
+            dimensions,
+            self.model.as_deref().unwrap_or("")
+        )
+    }
+}
+
+// --- SecureFloatArray ---
+
+/// A vector of float32 values in locked memory, for embeddings of sensitive text, which
+/// can be inverted back to much of it. Reading it out (`tolist()`, `to_numpy()`, indexing)
+/// is explicit; the buffer is wiped on `wipe()` or when the array is dropped.
+#[pyclass(name = "SecureFloatArray")]
+pub(crate) struct SecureFloatArray {
+    /// Little-endian float32 values.
+    values: SecureBytes,
+}
+
+#[pymethods]
+impl SecureFloatArray {
+    fn __len__(&self) -> usize {
+        self.values.bytes().len() / 4
+    }
+
+    fn __getitem__(&self, index: isize) -> PyResult<f32> {
+        let values = self.values.expose()?;
+        let len = (values.len() / 4) as isize;
+        let position = if index < 0 { index + len } else { index };
+        if !(0..len).contains(&position) {
+            return Err(PyErr::new::<pyo3::exceptions::PyIndexError, _>("SecureFloatArray index out of range"));
+        }
+        Ok(floats(values).nth(position as usize).expect("checked against the length"))
+    }
+
+    /// The values as a list of Python floats, outside locked memory.
+    fn tolist(&self) -> PyResult<Vec<f32>> {
+        Ok(floats(self.values.expose()?).collect())
+    }
+
+    /// A float32 numpy array holding a copy of the values, owned by the caller and outside
+    /// locked memory. Needs numpy installed.
+    fn to_numpy<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let values = self.values.expose()?;
+        let numpy = py.import("numpy").map_err(|_| {
+            PyErr::new::<pyo3::exceptions::PyImportError, _>("SecureFloatArray.to_numpy() needs numpy installed")
+        })?;
+        // Filled as raw bytes and viewed as float32, so no intermediate copy is made.
+        let array = numpy.call_method1("empty", (values.len(), "u1"))?;
+        PyBuffer::<u8>::get(&array)?.copy_from_slice(py, values)?;
+        array.call_method1("view", ("<f4",))
+    }
+
+    /// The dot product with `other`, computed over both locked buffers.
+    fn dot(&self, other: PyRef<'_, SecureFloatArray>) -> PyResult<f32> {
+        Ok(products(self.values.expose()?, other.values.expose()?)?.dot)
+    }
+
+    /// The cosine similarity with `other`, computed over both locked buffers. It is 0.0
+    /// when either vector is all zeros.
+    fn cosine(&self, other: PyRef<'_, SecureFloatArray>) -> PyResult<f32> {
+        Ok(products(self.values.expose()?, other.values.expose()?)?.cosine())
+    }
+
+    /// Zeroes, unlocks and frees the values now; any later read raises `ValueError`.
+    fn wipe(&mut self) {
+        self.values.wipe();
+    }
+
+    #[getter]
+    fn wiped(&self) -> bool {
+        self.values.wiped()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("SecureFloatArray(<{} floats>)", self.__len__())
+    }
+}
+
+// --- Vector Similarity ---
+
+/// The dot product of two vectors and their squared norms, from one pass over both.
+struct Products {
+    dot: f32,
+    left: f32,
+    right: f32,
+}
+
+impl Products {
+    fn cosine(&self) -> f32 {
+        let norms = (self.left * self.right).sqrt();
+        if norms == 0.0 {
+            0.0
+        } else {
+            self.dot / norms
+        }
+    }
+}
+
+/// Floats summed side by side: independent accumulators break the dependency between
+/// additions, so the compiler can keep them in one vector register.
+const LANES: usize = 8;
+
+fn products(left: &[u8], right: &[u8]) -> PyResult<Products> {
+    if left.len() != right.len() {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "vectors have different dimensions: {} and {}",
+            left.len() / 4,
+            right.len() / 4
+        )));
+    }
+    let (mut dot, mut left_sq, mut right_sq) = ([0f32; LANES], [0f32; LANES], [0f32; LANES]);
+    let mut lhs = left.chunks_exact(4 * LANES);
+    let mut rhs = right.chunks_exact(4 * LANES);
+    for (l, r) in (&mut lhs).zip(&mut rhs) {
+        for lane in 0..LANES {
+            let x = f32::from_le_bytes(l[4 * lane..4 * lane + 4].try_into().expect("4 bytes"));
+            let y = f32::from_le_bytes(r[4 * lane..4 * lane + 4].try_into().expect("4 bytes"));
+            dot[lane] += x * y;
+            left_sq[lane] += x * x;
+            right_sq[lane] += y * y;
+        }
+    }
+    for (x, y) in floats(lhs.remainder()).zip(floats(rhs.remainder())) {
+        dot[0] += x * y;
+        left_sq[0] += x * x;
+        right_sq[0] += y * y;
+    }
+    Ok(Products { dot: dot.iter().sum(), left: left_sq.iter().sum(), right: right_sq.iter().sum() })
+}
+
+/// The `k` vectors of `corpus` most similar to `query` by cosine similarity, as
+/// `(index, score)` pairs from the most similar down. Scores are plain floats; the vectors
+/// are only read in place.
+#[pyfunction]
+pub(crate) fn top_k(
+    query: PyRef<'_, SecureFloatArray>,
+    corpus: Vec<PyRef<'_, SecureFloatArray>>,
+    k: usize,
+) -> PyResult<Vec<(usize, f32)>> {
+    let query = query.values.expose()?;
+    let mut scores = corpus
+        .iter()
+        .enumerate()
+        .map(|(index, vector)| Ok((index, products(query, vector.values.expose()?)?.cosine())))
+        .collect::<PyResult<Vec<_>>>()?;
+    let by_score = |a: &(usize, f32), b: &(usize, f32)| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0));
+    if k < scores.len() {
+        if k > 0 {
+            scores.select_nth_unstable_by(k - 1, by_score);
+        }
+        scores.truncate(k);
+    }
+    scores.sort_unstable_by(by_score);
+    Ok(scores)
+}
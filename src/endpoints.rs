@@ -0,0 +1,122 @@
+use crate::providers::Provider;
+use pyo3::prelude::*;
+
+// --- Endpoint Paths ---
+
+/// How endpoint paths and credentials are laid out for a provider.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum PathStyle {
+    /// `/openai/v1/...`, what the client has always used.
+    Default,
+    /// `/v1/...` as served by api.openai.com and most compatible servers.
+    OpenAi,
+    /// `/openai/deployments/{model}/...?api-version=...` with an `api-key` header.
+    Azure { api_version: String },
+    /// The chat endpoint of a `provider` preset.
+    Preset(&'static str),
+}
+
+/// Azure's current GA data-plane API version.
+const DEFAULT_AZURE_API_VERSION: &str = "2024-10-21";
+
+impl PathStyle {
+    pub(crate) fn parse(style: &str, api_version: Option<&str>) -> PyResult<Self> {
+        let style = match style {
+            "default" => PathStyle::Default,
+            "openai" => PathStyle::OpenAi,
+            "azure" => {
+                let api_version = api_version.unwrap_or(DEFAULT_AZURE_API_VERSION);
+                if api_version.is_empty() || !api_version.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-') {
+                    return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                        "Invalid api_version '{}'",
+                        api_version
+                    )));
+                }
+                return Ok(PathStyle::Azure { api_version: api_version.to_string() });
+            }
+            _ => {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "Invalid path_style '{}': expected 'default', 'openai' or 'azure'",
+                    style
+                )))
+            }
+        };
+        if api_version.is_some() {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "api_version is only used with path_style='azure'",
+            ));
+        }
+        Ok(style)
+    }
+
+    /// The layout of a `provider` preset, which leaves no `path_style` to choose.
+    pub(crate) fn for_provider(provider: &Provider, style: &str, api_version: Option<&str>) -> PyResult<Self> {
+        if style != "default" || api_version.is_some() {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "provider '{}' sets the endpoint paths; path_style and api_version cannot be combined with it",
+                provider.name
+            )));
+        }
+        Ok(PathStyle::Preset(provider.path))
+    }
+
+    /// Path of the chat completions endpoint.
+    pub(crate) fn chat_completions(&self, model: &str) -> PyResult<String> {
+        Ok(match self {
+            PathStyle::Default => "/openai/v1/chat/completions".to_string(),
+            PathStyle::OpenAi => "/v1/chat/completions".to_string(),
+            PathStyle::Preset(path) => path.to_string(),
+            PathStyle::Azure { api_version } => {
+                format!("/openai/deployments/{}/chat/completions?api-version={}", azure_deployment(model)?, api_version)
+            }
+        })
+    }
+
+    /// Path of the embeddings endpoint. `None` for a preset whose API has no such endpoint.
+    pub(crate) fn embeddings(&self, model: &str) -> PyResult<Option<String>> {
+        Ok(Some(match self {
+            PathStyle::Default => "/openai/v1/embeddings".to_string(),
+            PathStyle::OpenAi => "/v1/embeddings".to_string(),
+            PathStyle::Preset(path) => match sibling(path, "embeddings") {
+                Some(path) => path,
+                None => return Ok(None),
+            },
+            PathStyle::Azure { api_version } => {
+                format!("/openai/deployments/{}/embeddings?api-version={}", azure_deployment(model)?, api_version)
+            }
+        }))
+    }
+
+    /// Path of the moderations endpoint; `None` where there is none, as on Azure.
+    pub(crate) fn moderations(&self) -> Option<String> {
+        match self {
+            PathStyle::Default => Some("/openai/v1/moderations".to_string()),
+            PathStyle::OpenAi => Some("/v1/moderations".to_string()),
+            PathStyle::Preset(path) => sibling(path, "moderations"),
+            PathStyle::Azure { .. } => None,
+        }
+    }
+
+    /// Azure API keys go in an `api-key` header instead of `Authorization: Bearer`.
+    pub(crate) fn uses_api_key_header(&self) -> bool {
+        matches!(self, PathStyle::Azure { .. })
+    }
+}
+
+/// Azure addresses the deployment in the path, so the model name has to be a plain
+/// deployment name there.
+fn azure_deployment(model: &str) -> PyResult<&str> {
+    if model.is_empty() || !model.bytes().all(|b| b.is_ascii_alphanumeric() || b"-_.".contains(&b)) {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "'{}' is not a valid Azure deployment name",
+            model
+        )));
+    }
+    Ok(model)
+}
+
+/// The endpoint `name` next to a preset's chat endpoint, if that is an OpenAI-style
+/// `.../chat/completions`.
+fn sibling(chat_path: &str, name: &str) -> Option<String> {
+    chat_path.strip_suffix("/chat/completions").map(|prefix| format!("{}/{}", prefix, name))
+}
@@ -0,0 +1,420 @@
+use crate::api_key::ApiKey;
+use crate::providers::{ErrorShape, Provider};
+use crate::redact;
+use crate::retry::{Deadline, Spent};
+use crate::{SecureBytes, SecureToolCall, Usage};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyIOError, PyMemoryError, PyTimeoutError, PyValueError};
+use pyo3::prelude::*;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::Duration;
+
+// --- Exceptions ---
+
+create_exception!(
+    secure_openaiapi,
+    APIError,
+    PyIOError,
+    "Raised when the API answers with an error status. Carries `status`, `code`, `param`, `type` and `request_id`."
+);
+
+create_exception!(secure_openaiapi, BadRequestError, APIError, "Raised on HTTP 400.");
+create_exception!(secure_openaiapi, AuthenticationError, APIError, "Raised on HTTP 401.");
+create_exception!(secure_openaiapi, PermissionDeniedError, APIError, "Raised on HTTP 403.");
+create_exception!(secure_openaiapi, NotFoundError, APIError, "Raised on HTTP 404.");
+create_exception!(secure_openaiapi, InternalServerError, APIError, "Raised on HTTP 5xx.");
+
+create_exception!(
+    secure_openaiapi,
+    RateLimitError,
+    APIError,
+    "Raised on HTTP 429, and when the client-side limiter gives up (then `status` is None)."
+);
+
+create_exception!(
+    secure_openaiapi,
+    ResponseTooLargeError,
+    PyException,
+    "Raised when a response body exceeds the client's max_response_bytes."
+);
+
+create_exception!(
+    secure_openaiapi,
+    TruncatedResponseError,
+    PyException,
+    "Raised in strict mode when a completion stopped at max_tokens (finish_reason 'length')."
+);
+
+create_exception!(
+    secure_openaiapi,
+    ContentFilterError,
+    PyException,
+    "Raised in strict mode when a completion was blocked by a content filter (finish_reason 'content_filter')."
+);
+
+create_exception!(
+    secure_openaiapi,
+    RefusalError,
+    PyException,
+    "Raised by chat_completion when the model declined; the text is in the `refusal` attribute as SecureBytes."
+);
+
+create_exception!(
+    secure_openaiapi,
+    ToolCallNotSupportedError,
+    PyException,
+    "Raised by chat_completion when the model answered with tool calls, which only chat_completion_full returns; they are in the `tool_calls` attribute."
+);
+
+create_exception!(
+    secure_openaiapi,
+    PrivacyModeError,
+    PyValueError,
+    "Raised before sending when a call on a client built with privacy_mode=True would let the provider retain data."
+);
+
+create_exception!(
+    secure_openaiapi,
+    ModerationBlockedError,
+    PyException,
+    "Raised before sending under moderate_inputs when the moderation check flags the user input; the flagged categories are in the `categories` attribute."
+);
+
+create_exception!(
+    secure_openaiapi,
+    DeadlineExceededError,
+    PyTimeoutError,
+    "Raised when a call runs out of its deadline= across all its attempts; carries `deadline`, `elapsed`, `attempts`, `attempt_times` and `waited`."
+);
+
+create_exception!(
+    secure_openaiapi,
+    MemoryLockError,
+    PyMemoryError,
+    "Raised under strict_memory() when secrets cannot be locked into RAM, or the host cannot guarantee it."
+);
+
+create_exception!(
+    secure_openaiapi,
+    MemoryCorruptionError,
+    PyMemoryError,
+    "Raised when the canaries around a SecureBytes buffer were overwritten; every use of the buffer raises it from then on."
+);
+
+/// The standard error envelope, `{"error": {"message", "type", "code", "param"}}`.
+/// Some gateways send `{"error": "message"}` instead.
+#[derive(Deserialize)]
+struct ErrorEnvelope {
+    error: ErrorBody,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ErrorBody {
+    Detailed {
+        message: Option<String>,
+        #[serde(rename = "type")]
+        kind: Option<String>,
+        code: Option<Value>,
+        param: Option<String>,
+    },
+    Message(String),
+}
+
+/// Google's error envelope, sent as a one-element list by the Gemini compatibility layer.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum GoogleEnvelope {
+    List(Vec<GoogleEnvelope>),
+    Single { error: GoogleError },
+}
+
+#[derive(Deserialize)]
+struct GoogleError {
+    message: Option<String>,
+    /// The canonical status name, such as `INVALID_ARGUMENT`.
+    status: Option<String>,
+    #[serde(default)]
+    details: Vec<GoogleErrorDetail>,
+}
+
+#[derive(Deserialize)]
+struct GoogleErrorDetail {
+    /// Set on `ErrorInfo` details, e.g. `API_KEY_INVALID`.
+    reason: Option<String>,
+}
+
+impl GoogleEnvelope {
+    fn into_error(self) -> Option<GoogleError> {
+        match self {
+            GoogleEnvelope::List(list) => list.into_iter().next()?.into_error(),
+            GoogleEnvelope::Single { error } => Some(error),
+        }
+    }
+}
+
+/// `(message, type, code, param)` of an error body. A body that isn't the expected
+/// envelope becomes the message as a whole.
+fn parse_error_body(body: &[u8], shape: ErrorShape) -> (String, Option<String>, Option<String>, Option<String>) {
+    if shape == ErrorShape::Google {
+        if let Some(error) = serde_json::from_slice::<GoogleEnvelope>(body).ok().and_then(GoogleEnvelope::into_error) {
+            let reason = error.details.into_iter().find_map(|detail| detail.reason);
+            return (error.message.unwrap_or_default(), error.status, reason, None);
+        }
+    }
+    match serde_json::from_slice::<ErrorEnvelope>(body).map(|envelope| envelope.error) {
+        Ok(ErrorBody::Detailed { message, kind, code, param }) => {
+            // Codes are usually strings, but some providers send numbers.
+            let code = code.and_then(|code| match code {
+                Value::String(code) => Some(code),
+                Value::Null => None,
+                code => Some(code.to_string()),
+            });
+            (message.unwrap_or_default(), kind, code, param)
+        }
+        Ok(ErrorBody::Message(message)) => (message, None, None, None),
+        Err(_) => (String::from_utf8_lossy(body).into_owned(), None, None, None),
+    }
+}
+
+/// Where a failed call was going. Everything here is safe to put in an exception; the API
+/// key is only carried so it can be redacted from what the server sent back.
+pub(crate) struct ErrorContext<'a> {
+    pub(crate) endpoint: &'a str,
+    pub(crate) model: &'a str,
+    pub(crate) elapsed: Duration,
+    pub(crate) api_key: &'a ApiKey,
+    pub(crate) provider: Option<&'static Provider>,
+    /// Include the server's message in errors rejecting the request too; see `api_error`.
+    pub(crate) include_error_body: bool,
+    /// Cap on the server's message, in bytes.
+    pub(crate) max_error_text: usize,
+}
+
+/// Maps an error response to the `APIError` subclass for its status. A body that isn't
+/// the standard envelope still gets the right class, its text standing in for the message.
+/// `retry_after` is how long the server asked us to wait, kept as the `retry_after`
+/// attribute (seconds) so callers that give up can still schedule the next attempt.
+/// The server's message is length-capped and redacted before it is included, and left out
+/// of every 4xx error unless `include_error_body` is set: that is where gateways echo the
+/// offending request content back. Their type, code and param are given instead, redacted
+/// like the message. Only the first `parse_limit` bytes of the body are ever copied out of
+/// its locked buffer.
+pub(crate) fn api_error(
+    py: Python<'_>,
+    status: StatusCode,
+    request_id: &str,
+    context: &ErrorContext<'_>,
+    body: &[u8],
+    retry_after: Option<Duration>,
+) -> PyErr {
+    let error_shape = context.provider.map(|provider| provider.error_shape).unwrap_or_default();
+    let (message, kind, code, param) = parse_error_body(&body[..body.len().min(parse_limit(context.max_error_text))], error_shape);
+    let redacted = |text: &str, max_len: usize| context.api_key.with_plaintext(|api_key| Ok(redact::redact(text, &[api_key], max_len)));
+    let [kind, code, param] = [kind, code, param].map(|field| field.and_then(|field| redacted(&field, MAX_FIELD_TEXT).ok()));
+    // Google answers a malformed API key with 400 INVALID_ARGUMENT.
+    let bad_key = error_shape == ErrorShape::Google && code.as_deref() == Some("API_KEY_INVALID");
+    let message = if status.is_client_error() && !bad_key && !context.include_error_body {
+        let fields = [("type", &kind), ("code", &code), ("param", &param)]
+            .into_iter()
+            .filter_map(|(name, value)| Some(format!("{} {}", name, value.as_deref()?)))
+            .collect::<Vec<_>>();
+        let fields = if fields.is_empty() { String::new() } else { format!("{}; ", fields.join(", ")) };
+        format!("{}server message withheld, pass include_error_body=True to SecureClient to include it", fields)
+    } else {
+        redacted(&message, context.max_error_text)
+            .unwrap_or_else(|_| "(message withheld: the API key needed to redact it is unavailable)".to_string())
+    };
+    let mut message = format!(
+        "API request failed with status {} (request id {}, endpoint {}, model {}, after {:.2}s): {}",
+        status,
+        request_id,
+        context.endpoint,
+        context.model,
+        context.elapsed.as_secs_f64(),
+        message
+    );
+    if let Some(retry_after) = retry_after {
+        message.push_str(&format!(" (the server asked to retry after {:?})", retry_after));
+    }
+    if let (StatusCode::NOT_FOUND, Some(hint)) = (status, context.provider.and_then(|provider| provider.not_found_hint)) {
+        message.push_str(&format!(" ({})", hint.replace("{model}", context.model)));
+    }
+    let err = match status.as_u16() {
+        400 if bad_key => AuthenticationError::new_err(message),
+        400 => BadRequestError::new_err(message),
+        401 => AuthenticationError::new_err(message),
+        403 => PermissionDeniedError::new_err(message),
+        404 => NotFoundError::new_err(message),
+        429 => RateLimitError::new_err(message),
+        500..=599 => InternalServerError::new_err(message),
+        _ => APIError::new_err(message),
+    };
+    set_api_attributes(py, &err, Some(status.as_u16()), code, param, kind, Some(request_id), context);
+    let _ = err.value(py).setattr("retry_after", retry_after.map(|wait| wait.as_secs_f64()));
+    err
+}
+
+/// How much of an error body is parsed for a message capped at `max_error_text`. Masking
+/// shrinks text at most fourfold (a 16-character token becomes `****`), so this still
+/// leaves whole tokens to mask in whatever survives the cap; a longer body is cut and, as
+/// it no longer parses, stands in for the message as text.
+fn parse_limit(max_error_text: usize) -> usize {
+    max_error_text.saturating_mul(4).max(MIN_PARSED_BODY)
+}
+
+/// Room for the envelope, type, code and param even when `max_error_text` is tiny.
+const MIN_PARSED_BODY: usize = 1024;
+
+/// Cap on each of the type, code and param of an error, which are identifiers.
+const MAX_FIELD_TEXT: usize = 128;
+
+/// `RateLimitError` raised by the client-side limiter, before anything was sent.
+pub(crate) fn client_rate_limited(py: Python<'_>, message: String, context: &ErrorContext<'_>) -> PyErr {
+    let err = RateLimitError::new_err(message);
+    set_api_attributes(py, &err, None, None, None, None, None, context);
+    let _ = err.value(py).setattr("retry_after", None::<f64>);
+    err
+}
+
+/// `DeadlineExceededError` for a call that used up `deadline` over `spent.attempts`.
+pub(crate) fn deadline_exceeded(py: Python<'_>, deadline: &Deadline, spent: &Spent, request_id: &str) -> PyErr {
+    let attempt_times: Vec<f64> = spent.attempts.iter().map(Duration::as_secs_f64).collect();
+    let elapsed = deadline.elapsed().as_secs_f64();
+    let err = DeadlineExceededError::new_err(format!(
+        "Call ran out of its deadline of {:?} after {} attempt(s) (client request id {}): {:.3}s in requests, {:.3}s waiting \
+         for retries and the rate limiter, {:.3}s elapsed in all",
+        deadline.budget(),
+        attempt_times.len(),
+        request_id,
+        attempt_times.iter().sum::<f64>(),
+        spent.waited.as_secs_f64(),
+        elapsed
+    ));
+    let value = err.value(py);
+    let _ = value.setattr("deadline", deadline.budget().as_secs_f64());
+    let _ = value.setattr("elapsed", elapsed);
+    let _ = value.setattr("attempts", attempt_times.len());
+    let _ = value.setattr("attempt_times", attempt_times);
+    let _ = value.setattr("waited", spent.waited.as_secs_f64());
+    err
+}
+
+/// `AuthenticationError` for a `token_provider` that raised or returned something unusable,
+/// chained to the original exception. Nothing was sent, so there is no status or request id.
+pub(crate) fn token_provider_failed(py: Python<'_>, cause: PyErr, endpoint: &str, model: &str) -> PyErr {
+    let kind = cause.get_type(py).name().map(|name| name.to_string()).unwrap_or_else(|_| "an exception".to_string());
+    let err = AuthenticationError::new_err(format!(
+        "token_provider failed with {} (endpoint {}, model {}); see __cause__",
+        kind, endpoint, model
+    ));
+    err.set_cause(py, Some(cause));
+    let value = err.value(py);
+    for name in ["status", "code", "param", "type", "request_id"] {
+        let _ = value.setattr(name, py.None());
+    }
+    let _ = value.setattr("endpoint", endpoint);
+    let _ = value.setattr("model", model);
+    let _ = value.setattr("elapsed", 0.0);
+    err
+}
+
+#[allow(clippy::too_many_arguments)]
+fn set_api_attributes(
+    py: Python<'_>,
+    err: &PyErr,
+    status: Option<u16>,
+    code: Option<String>,
+    param: Option<String>,
+    kind: Option<String>,
+    request_id: Option<&str>,
+    context: &ErrorContext<'_>,
+) {
+    let value = err.value(py);
+    let _ = value.setattr("status", status);
+    let _ = value.setattr("code", code);
+    let _ = value.setattr("param", param);
+    let _ = value.setattr("type", kind);
+    let _ = value.setattr("request_id", request_id);
+    let _ = value.setattr("endpoint", context.endpoint);
+    let _ = value.setattr("model", context.model);
+    let _ = value.setattr("elapsed", context.elapsed.as_secs_f64());
+}
+
+/// Builds a `ResponseTooLargeError` carrying the limit and observed size as attributes.
+/// The body itself is never included.
+pub(crate) fn response_too_large(py: Python<'_>, limit: usize, observed: u64, exact: bool) -> PyErr {
+    let message = if exact {
+        format!("Response body of {} bytes exceeds max_response_bytes={}", observed, limit)
+    } else {
+        format!("Response body exceeds max_response_bytes={} (read at least {} bytes)", limit, observed)
+    };
+    let err = ResponseTooLargeError::new_err(message);
+    let value = err.value(py);
+    let _ = value.setattr("limit", limit);
+    let _ = value.setattr("observed", observed);
+    err
+}
+
+/// Strict mode: refuses truncated and filtered completions. The error carries
+/// `finish_reason` and the `usage` dict (or `None`) as attributes, never the content.
+pub(crate) fn check_finish_reason(py: Python<'_>, finish_reason: Option<&str>, usage: Option<&Usage>, request_id: &str) -> PyResult<()> {
+    let err = match finish_reason {
+        Some("length") => TruncatedResponseError::new_err(format!(
+            "Response was truncated at the token limit (finish_reason 'length', request id {})",
+            request_id
+        )),
+        Some("content_filter") => ContentFilterError::new_err(format!(
+            "Response was blocked by a content filter (finish_reason 'content_filter', request id {})",
+            request_id
+        )),
+        _ => return Ok(()),
+    };
+    let value = err.value(py);
+    value.setattr("finish_reason", finish_reason)?;
+    value.setattr("usage", usage.map(|usage| usage.to_dict(py)).transpose()?)?;
+    Err(err)
+}
+
+/// Builds a `RefusalError`. The refusal text is kept out of the message since it can
+/// quote the prompt.
+pub(crate) fn refusal(py: Python<'_>, refusal: Py<SecureBytes>) -> PyErr {
+    let err = RefusalError::new_err("The model refused the request; see the refusal attribute");
+    let _ = err.value(py).setattr("refusal", refusal);
+    err
+}
+
+/// Builds a `ToolCallNotSupportedError` for a response that would otherwise look like an
+/// empty answer. The calls are attached so that nothing the model asked for is lost.
+pub(crate) fn tool_calls_not_supported(py: Python<'_>, tool_calls: Vec<Py<SecureToolCall>>) -> PyErr {
+    let err = ToolCallNotSupportedError::new_err(format!(
+        "The model answered with {} tool call(s), which chat_completion cannot return; use chat_completion_full() \
+         and read its tool_calls, or see the tool_calls attribute",
+        tool_calls.len()
+    ));
+    let _ = err.value(py).setattr("tool_calls", tool_calls);
+    err
+}
+
+pub(crate) fn register(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("APIError", m.py().get_type::<APIError>())?;
+    m.add("BadRequestError", m.py().get_type::<BadRequestError>())?;
+    m.add("AuthenticationError", m.py().get_type::<AuthenticationError>())?;
+    m.add("PermissionDeniedError", m.py().get_type::<PermissionDeniedError>())?;
+    m.add("NotFoundError", m.py().get_type::<NotFoundError>())?;
+    m.add("InternalServerError", m.py().get_type::<InternalServerError>())?;
+    m.add("RateLimitError", m.py().get_type::<RateLimitError>())?;
+    m.add("ResponseTooLargeError", m.py().get_type::<ResponseTooLargeError>())?;
+    m.add("TruncatedResponseError", m.py().get_type::<TruncatedResponseError>())?;
+    m.add("ContentFilterError", m.py().get_type::<ContentFilterError>())?;
+    m.add("RefusalError", m.py().get_type::<RefusalError>())?;
+    m.add("ToolCallNotSupportedError", m.py().get_type::<ToolCallNotSupportedError>())?;
+    m.add("PrivacyModeError", m.py().get_type::<PrivacyModeError>())?;
+    m.add("ModerationBlockedError", m.py().get_type::<ModerationBlockedError>())?;
+    m.add("DeadlineExceededError", m.py().get_type::<DeadlineExceededError>())?;
+    m.add("MemoryLockError", m.py().get_type::<MemoryLockError>())?;
+    m.add("MemoryCorruptionError", m.py().get_type::<MemoryCorruptionError>())?;
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use crate::SecureBytes;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyString};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+// --- Custom Headers ---
+
+/// Headers owned by the client or the transport. Authorization is refused so a stray
+/// header can never replace (or leak next to) the managed credentials, Accept-Encoding
+/// so the server never sends an encoding the client cannot decode.
+const MANAGED_HEADERS: &[&str] = &["authorization", "host", "content-length", "transfer-encoding", "accept-encoding"];
+
+/// A header value kept in locked memory until it is rendered for a request.
+pub(crate) struct SecureHeader {
+    name: HeaderName,
+    value: SecureBytes,
+    sensitive: bool,
+}
+
+fn header_name(name: &str, source: &str) -> PyResult<HeaderName> {
+    let parsed = HeaderName::from_bytes(name.as_bytes())
+        .map_err(|_| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid header name in {}: '{}'", source, name)))?;
+    if MANAGED_HEADERS.contains(&parsed.as_str()) {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "'{}' cannot be set through {}; it is managed by the client",
+            name, source
+        )));
+    }
+    Ok(parsed)
+}
+
+fn header_value(name: &HeaderName, bytes: &[u8], sensitive: bool) -> PyResult<HeaderValue> {
+    // The value is never echoed: it may be a secret.
+    let mut value = HeaderValue::from_bytes(bytes).map_err(|_| {
+        PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid value for header '{}'", name))
+    })?;
+    value.set_sensitive(sensitive);
+    Ok(value)
+}
+
+/// Calls `f` with the raw bytes of a str, bytes or SecureBytes header value and whether it is secret.
+fn with_value_bytes<T>(value: &Bound<'_, PyAny>, name: &str, f: impl FnOnce(&[u8], bool) -> PyResult<T>) -> PyResult<T> {
+    if let Ok(secure) = value.downcast::<SecureBytes>() {
+        f(secure.borrow().expose()?, true)
+    } else if let Ok(bytes) = value.downcast::<PyBytes>() {
+        f(bytes.as_bytes(), false)
+    } else if let Ok(text) = value.downcast::<PyString>() {
+        f(text.to_str()?.as_bytes(), false)
+    } else {
+        Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!(
+            "Header '{}' must be str, bytes or SecureBytes",
+            name
+        )))
+    }
+}
+
+/// Parses client-level `default_headers`, copying every value into locked memory.
+pub(crate) fn parse_default_headers(headers: Option<&Bound<'_, PyDict>>) -> PyResult<Vec<SecureHeader>> {
+    let mut parsed = Vec::new();
+    for (name, value) in headers.into_iter().flat_map(|d| d.iter()) {
+        let name: String = name.extract()?;
+        let header = header_name(&name, "default_headers")?;
+        parsed.push(with_value_bytes(&value, &name, |bytes, sensitive| {
+            header_value(&header, bytes, sensitive)?;
+            Ok(SecureHeader { name: header.clone(), value: SecureBytes::try_new(bytes)?, sensitive })
+        })?);
+    }
+    Ok(parsed)
+}
+
+/// The headers of a `provider` preset. They go ahead of `default_headers`, so a default
+/// header of the same name replaces the preset's.
+pub(crate) fn preset_headers(headers: &[(&str, &str)]) -> PyResult<Vec<SecureHeader>> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            Ok(SecureHeader {
+                name: HeaderName::from_bytes(name.as_bytes()).expect("preset header names are valid"),
+                value: SecureBytes::try_new(value.as_bytes())?,
+                sensitive: false,
+            })
+        })
+        .collect()
+}
+
+/// Renders default headers, then per-call `extra_headers` over them (per-call values win).
+/// Called right before sending so secret values only exist in the header map for the
+/// lifetime of the request.
+pub(crate) fn render(
+    headers: &mut HeaderMap,
+    defaults: &[SecureHeader],
+    extra: Option<&Bound<'_, PyDict>>,
+) -> PyResult<()> {
+    for header in defaults {
+        headers.insert(header.name.clone(), header_value(&header.name, header.value.expose()?, header.sensitive)?);
+    }
+    insert_all(headers, extra, "extra_headers")
+}
+
+/// Adds a dict of headers from Python, such as `extra_headers`, refusing the managed ones.
+pub(crate) fn insert_all(headers: &mut HeaderMap, extra: Option<&Bound<'_, PyDict>>, source: &str) -> PyResult<()> {
+    for (name, value) in extra.into_iter().flat_map(|d| d.iter()) {
+        let name: String = name.extract()?;
+        let header = header_name(&name, source)?;
+        let value = with_value_bytes(&value, &name, |bytes, sensitive| header_value(&header, bytes, sensitive))?;
+        headers.insert(header, value);
+    }
+    Ok(())
+}
+
+/// Response headers that can carry credentials or session state, left out of
+/// `SecureRawResponse.headers`.
+pub(crate) const CREDENTIAL_HEADERS: &[&str] = &["authorization", "proxy-authorization", "cookie", "set-cookie", "x-api-key", "api-key"];
+
+/// The headers of a response as plain strings, without the credential-bearing ones.
+/// Repeated headers are joined with `", "`.
+pub(crate) fn visible(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .keys()
+        .filter(|name| !CREDENTIAL_HEADERS.contains(&name.as_str()))
+        .filter(|name| headers.get_all(*name).iter().all(|value| !value.is_sensitive()))
+        .map(|name| {
+            let values: Vec<_> = headers.get_all(name).iter().map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned()).collect();
+            (name.to_string(), values.join(", "))
+        })
+        .collect()
+}
@@ -0,0 +1,17 @@
+use libsodium_sys::randombytes_buf;
+use std::ffi::c_void;
+
+// --- Client-Generated Identifiers ---
+
+/// Random RFC 4122 version 4 UUID drawn from libsodium's CSPRNG.
+pub(crate) fn random_uuid() -> String {
+    let mut bytes = [0u8; 16];
+    unsafe {
+        randombytes_buf(bytes.as_mut_ptr() as *mut c_void, bytes.len());
+    }
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
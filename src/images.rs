@@ -0,0 +1,76 @@
+use crate::SecureBytes;
+use libsodium_sys::{sodium_base64_VARIANT_ORIGINAL, sodium_base64_encoded_len, sodium_bin2base64};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+// --- Image Data URLs ---
+
+/// Leading bytes of the formats every vision API takes, and their MIME types. WebP is a
+/// RIFF container, told apart by the `WEBP` form type after the chunk size.
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+];
+
+fn sniff(image: &[u8]) -> Option<&'static str> {
+    if image.len() >= 12 && image.starts_with(b"RIFF") && &image[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+    SIGNATURES.iter().find(|(magic, _)| image.starts_with(magic)).map(|(_, mime)| *mime)
+}
+
+/// A `type/subtype` of token characters, which can go into a data URL as it is.
+fn valid_mime(mime: &str) -> bool {
+    let token = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_alphanumeric() || b"!#$&-^_.+".contains(&b));
+    mime.split_once('/').is_some_and(|(kind, subtype)| token(kind) && token(subtype))
+}
+
+/// `data:<mime>;base64,<image>` in a locked buffer. The base64 is written by libsodium
+/// straight into that buffer, behind the prefix, so no encoded copy exists outside it.
+pub(crate) fn data_url(image: &[u8], mime: Option<&str>) -> PyResult<SecureBytes> {
+    let invalid = |message: String| PyErr::new::<pyo3::exceptions::PyValueError, _>(message);
+    if image.is_empty() {
+        return Err(invalid("image is empty".to_string()));
+    }
+    let mime = match mime {
+        Some(mime) if valid_mime(mime) => mime,
+        Some(mime) => return Err(invalid(format!("Invalid mime '{}': expected a type/subtype such as 'image/png'", mime))),
+        None => sniff(image).ok_or_else(|| {
+            invalid("cannot tell the image format from its first bytes (png, jpeg, webp and gif are recognized); pass mime=".to_string())
+        })?,
+    };
+    let prefix = format!("data:{};base64,", mime);
+    let variant = sodium_base64_VARIANT_ORIGINAL as i32;
+    // Includes the NUL libsodium terminates the encoding with.
+    let encoded_len = unsafe { sodium_base64_encoded_len(image.len(), variant) };
+    let mut url = SecureBytes::zeroed(prefix.len() + encoded_len)?;
+    let (head, tail) = url.bytes_mut().split_at_mut(prefix.len());
+    head.copy_from_slice(prefix.as_bytes());
+    unsafe {
+        sodium_bin2base64(tail.as_mut_ptr().cast(), tail.len(), image.as_ptr(), image.len(), variant);
+    }
+    url.resize(prefix.len() + encoded_len - 1)?;
+    Ok(url)
+}
+
+/// Runs `f` over the bytes of an image argument, `bytes` or `SecureBytes`, without copying them.
+pub(crate) fn with_image<R>(image: &Bound<'_, PyAny>, f: impl FnOnce(&[u8]) -> PyResult<R>) -> PyResult<R> {
+    if let Ok(secure) = image.downcast::<SecureBytes>() {
+        f(secure.borrow().expose()?)
+    } else if let Ok(bytes) = image.downcast::<PyBytes>() {
+        f(bytes.as_bytes())
+    } else {
+        Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>("image_bytes must be bytes or SecureBytes"))
+    }
+}
+
+/// Builds the `data:` URL of an image for an `image_url` content part, base64-encoded into
+/// locked memory so no encoded copy is left in Python. `mime` is sniffed from the leading
+/// bytes of PNG, JPEG, WebP and GIF images when not given.
+#[pyfunction]
+#[pyo3(signature = (image_bytes, mime=None))]
+pub(crate) fn image_to_data_url(image_bytes: &Bound<'_, PyAny>, mime: Option<&str>) -> PyResult<SecureBytes> {
+    with_image(image_bytes, |image| data_url(image, mime))
+}
@@ -0,0 +1,222 @@
+use crate::body::LockedBuffer;
+use crate::SecureBytes;
+use hyper::body::Bytes;
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyFloat, PyInt, PyList, PyString, PyTuple};
+use serde::Serialize;
+use std::io;
+use std::sync::Arc;
+use zeroize::Zeroize;
+
+// --- Locked JSON Writer ---
+
+/// Builds a JSON document in locked memory. Secrets go through `write_str`, which escapes
+/// straight from their locked buffer into the output, so no escaped or unescaped copy is
+/// made anywhere else; everything else (field names, model, parameters) may go through
+/// serde via `write_value`, which writes into the same buffer.
+pub(crate) struct SecureJsonWriter {
+    buffer: LockedBuffer,
+    /// Set if the buffer could not grow into locked memory under `strict_memory()`. Later
+    /// writes are dropped and the error is raised when the document is taken.
+    failed: Option<PyErr>,
+}
+
+impl SecureJsonWriter {
+    pub(crate) fn new() -> PyResult<Self> {
+        Ok(Self { buffer: LockedBuffer::with_capacity(4096)?, failed: None })
+    }
+
+    /// Writes pre-formatted JSON such as punctuation and literal keys.
+    pub(crate) fn write_raw(&mut self, json: &[u8]) {
+        if self.failed.is_none() {
+            self.failed = self.buffer.extend_from_slice(json).err();
+        }
+    }
+
+    /// Writes `value` as a JSON string. It must be UTF-8; the error never includes it.
+    /// Quotes, backslashes and control characters are escaped, `\n`-style where JSON has a
+    /// short form and `\u00XX` otherwise. Everything else, non-ASCII included, is copied as is.
+    pub(crate) fn write_str(&mut self, value: &[u8]) -> io::Result<()> {
+        std::str::from_utf8(value)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("string is not valid UTF-8 ({})", e)))?;
+        self.write_raw(b"\"");
+        let mut start = 0;
+        let mut unicode = *b"\\u0000";
+        for (index, &byte) in value.iter().enumerate() {
+            let escape: &[u8] = match byte {
+                b'"' => b"\\\"",
+                b'\\' => b"\\\\",
+                b'\n' => b"\\n",
+                b'\r' => b"\\r",
+                b'\t' => b"\\t",
+                0x08 => b"\\b",
+                0x0c => b"\\f",
+                0x00..=0x1f => {
+                    unicode[4] = HEX[(byte >> 4) as usize];
+                    unicode[5] = HEX[(byte & 0xf) as usize];
+                    &unicode
+                }
+                _ => continue,
+            };
+            self.write_raw(&value[start..index]);
+            self.write_raw(escape);
+            start = index + 1;
+        }
+        self.write_raw(&value[start..]);
+        self.write_raw(b"\"");
+        Ok(())
+    }
+
+    /// Writes a non-secret value with serde_json.
+    pub(crate) fn write_value<T: Serialize + ?Sized>(&mut self, value: &T) -> io::Result<()> {
+        serde_json::to_writer(&mut *self, value).map_err(io::Error::from)
+    }
+
+    /// Writes a Python value as JSON, converting it like `params::to_json` but without an
+    /// intermediate copy: str and `SecureBytes` values are escaped from their own buffers.
+    pub(crate) fn write_py(&mut self, value: &Bound<'_, PyAny>) -> PyResult<()> {
+        self.write_py_nested(value, 0)
+    }
+
+    fn write_py_nested(&mut self, value: &Bound<'_, PyAny>, depth: usize) -> PyResult<()> {
+        let invalid = |e: io::Error| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string());
+        if depth > MAX_PY_DEPTH {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "value is nested more than {} levels deep",
+                MAX_PY_DEPTH
+            )));
+        }
+        if value.is_none() {
+            self.write_raw(b"null");
+        } else if let Ok(b) = value.downcast::<PyBool>() {
+            self.write_raw(if b.is_true() { b"true" } else { b"false" });
+        } else if value.is_instance_of::<PyInt>() {
+            match value.extract::<i64>() {
+                Ok(i) => self.write_value(&i).map_err(invalid)?,
+                Err(_) => self.write_value(&value.extract::<u64>()?).map_err(invalid)?,
+            }
+        } else if let Ok(f) = value.downcast::<PyFloat>() {
+            if !f.value().is_finite() {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("NaN and infinity are not valid JSON"));
+            }
+            self.write_value(&f.value()).map_err(invalid)?;
+        } else if let Ok(s) = value.downcast::<PyString>() {
+            self.write_str(s.to_str()?.as_bytes()).map_err(invalid)?;
+        } else if let Ok(secure) = value.downcast::<SecureBytes>() {
+            self.write_str(secure.borrow().expose()?).map_err(invalid)?;
+        } else if let Ok(dict) = value.downcast::<PyDict>() {
+            self.write_raw(b"{");
+            for (index, (k, v)) in dict.iter().enumerate() {
+                let k = k.downcast::<PyString>().map_err(|_| PyErr::new::<pyo3::exceptions::PyTypeError, _>("dict keys must be str"))?;
+                if index > 0 {
+                    self.write_raw(b",");
+                }
+                self.write_str(k.to_str()?.as_bytes()).map_err(invalid)?;
+                self.write_raw(b":");
+                self.write_py_nested(&v, depth + 1)?;
+            }
+            self.write_raw(b"}");
+        } else if value.is_instance_of::<PyList>() || value.is_instance_of::<PyTuple>() {
+            self.write_raw(b"[");
+            for (index, item) in value.try_iter()?.enumerate() {
+                if index > 0 {
+                    self.write_raw(b",");
+                }
+                self.write_py_nested(&item?, depth + 1)?;
+            }
+            self.write_raw(b"]");
+        } else {
+            return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!(
+                "unsupported type '{}'",
+                value.get_type().name()?
+            )));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// The finished document as `SecureBytes`, for JSON that is content rather than a request.
+    pub(crate) fn into_secure_bytes(self) -> PyResult<SecureBytes> {
+        if let Some(e) = self.failed {
+            return Err(e);
+        }
+        SecureBytes::try_new(&self.buffer)
+    }
+
+    /// The finished document, behind a guard that wipes it when the call is done.
+    pub(crate) fn finish(self) -> PyResult<RequestBody> {
+        if let Some(e) = self.failed {
+            return Err(e);
+        }
+        Ok(RequestBody::new(self.buffer))
+    }
+}
+
+/// The error for a request that could not be serialized.
+pub(crate) fn serialize_error(e: io::Error) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Failed to serialize request: {}", e))
+}
+
+// --- Request Body Guard ---
+
+/// A serialized request, owned by the call that sends it. The transport only ever gets
+/// `view()`s. Dropping the guard wipes the bytes in place whether the send succeeded,
+/// failed, was retried, cancelled or unwound by a panic, even if reqwest or hyper still
+/// hold a view in some connection state they have yet to drop (they then hold zeros). The
+/// allocation is unlocked and freed with the last view, as before.
+pub(crate) struct RequestBody {
+    buffer: Arc<LockedBuffer>,
+    data: *mut u8,
+    len: usize,
+}
+
+// SAFETY: `data` points into the heap allocation of `buffer`, which is `Send`.
+unsafe impl Send for RequestBody {}
+
+impl RequestBody {
+    fn new(mut buffer: LockedBuffer) -> Self {
+        let (data, len) = (buffer.as_mut_ptr(), buffer.len());
+        Self { buffer: Arc::new(buffer), data, len }
+    }
+
+    /// A read-only view for the transport, valid for as long as the transport keeps it.
+    pub(crate) fn view(&self) -> Bytes {
+        Bytes::from_owner(BodyView(Arc::clone(&self.buffer)))
+    }
+}
+
+impl Drop for RequestBody {
+    fn drop(&mut self) {
+        // SAFETY: the allocation outlives every view. The guard lives until the send future
+        // has resolved or been dropped, so a connection still writing from a view belongs
+        // to an abandoned request and at worst puts zeros on the wire.
+        unsafe { std::slice::from_raw_parts_mut(self.data, self.len) }.zeroize();
+    }
+}
+
+struct BodyView(Arc<LockedBuffer>);
+
+impl AsRef<[u8]> for BodyView {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+const HEX: &[u8; 16] = b"0123456789abcdef";
+
+/// How deeply `write_py` nests, which also stops it at a dict or list that contains itself.
+const MAX_PY_DEPTH: usize = 128;
+
+impl io::Write for SecureJsonWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.write_raw(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
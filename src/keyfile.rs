@@ -0,0 +1,222 @@
+use crate::SecureBytes;
+use libsodium_sys::{
+    crypto_pwhash, crypto_pwhash_memlimit_max, crypto_pwhash_opslimit_max, crypto_secretbox_easy,
+    crypto_secretbox_open_easy, randombytes_buf, crypto_pwhash_ALG_ARGON2ID13, crypto_pwhash_MEMLIMIT_INTERACTIVE,
+    crypto_pwhash_MEMLIMIT_MIN, crypto_pwhash_MEMLIMIT_MODERATE, crypto_pwhash_MEMLIMIT_SENSITIVE,
+    crypto_pwhash_OPSLIMIT_INTERACTIVE, crypto_pwhash_OPSLIMIT_MIN, crypto_pwhash_OPSLIMIT_MODERATE,
+    crypto_pwhash_OPSLIMIT_SENSITIVE, crypto_pwhash_SALTBYTES, crypto_secretbox_KEYBYTES, crypto_secretbox_MACBYTES,
+    crypto_secretbox_NONCEBYTES,
+};
+use pyo3::prelude::*;
+use std::ffi::c_void;
+use std::io::Write;
+
+// --- Passphrase-Protected Keyfiles ---
+
+const SALT_BYTES: usize = crypto_pwhash_SALTBYTES as usize;
+const KEY_BYTES: usize = crypto_secretbox_KEYBYTES as usize;
+const NONCE_BYTES: usize = crypto_secretbox_NONCEBYTES as usize;
+const MAC_BYTES: usize = crypto_secretbox_MACBYTES as usize;
+
+/// Keyfile layout, version 1: `MAGIC`, a version byte, the Argon2id ops and memory limits
+/// as little-endian `u64`s, the salt, the secretbox nonce, then the sealed secret. The
+/// header is not authenticated separately: every value it can hold is either rejected or
+/// derives another key, so tampering with it fails like a wrong passphrase.
+const MAGIC: &[u8; 6] = b"SOAKEY";
+const VERSION: u8 = 1;
+const HEADER_BYTES: usize = MAGIC.len() + 1 + 8 + 8 + SALT_BYTES + NONCE_BYTES;
+
+/// Highest costs a keyfile may ask for, so a damaged header cannot demand hours of work.
+const MAX_KEYFILE_OPS: u64 = 64;
+const MAX_KEYFILE_MEM: u64 = 16 << 30;
+
+/// The one error for anything that stops a keyfile from opening, so the message never
+/// tells a wrong passphrase apart from a damaged or foreign file.
+fn undecryptable() -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyValueError, _>("cannot decrypt keyfile: wrong passphrase or corrupted file")
+}
+
+/// An Argon2id cost argument: a libsodium preset name or an explicit number.
+#[derive(FromPyObject)]
+pub(crate) enum Cost {
+    Preset(String),
+    Value(u64),
+}
+
+const MODERATE_OPS: Cost = Cost::Value(crypto_pwhash_OPSLIMIT_MODERATE as u64);
+const MODERATE_MEM: Cost = Cost::Value(crypto_pwhash_MEMLIMIT_MODERATE as u64);
+
+impl Cost {
+    fn resolve(&self, name: &str, presets: [u32; 3], min: u32, max: usize) -> PyResult<u64> {
+        let cost = match self {
+            Cost::Preset(preset) => match preset.as_str() {
+                "interactive" => presets[0],
+                "moderate" => presets[1],
+                "sensitive" => presets[2],
+                _ => {
+                    return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                        "{} must be 'interactive', 'moderate', 'sensitive' or an integer",
+                        name
+                    )))
+                }
+            }
+            .into(),
+            Cost::Value(value) => *value,
+        };
+        if !(u64::from(min)..=max as u64).contains(&cost) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{} must be between {} and {}", name, min, max)));
+        }
+        Ok(cost)
+    }
+
+    fn ops_limit(&self) -> PyResult<u64> {
+        let presets = [crypto_pwhash_OPSLIMIT_INTERACTIVE, crypto_pwhash_OPSLIMIT_MODERATE, crypto_pwhash_OPSLIMIT_SENSITIVE];
+        self.resolve("ops_limit", presets, crypto_pwhash_OPSLIMIT_MIN, unsafe { crypto_pwhash_opslimit_max() })
+    }
+
+    fn mem_limit(&self) -> PyResult<u64> {
+        let presets = [crypto_pwhash_MEMLIMIT_INTERACTIVE, crypto_pwhash_MEMLIMIT_MODERATE, crypto_pwhash_MEMLIMIT_SENSITIVE];
+        self.resolve("mem_limit", presets, crypto_pwhash_MEMLIMIT_MIN, unsafe { crypto_pwhash_memlimit_max() })
+    }
+}
+
+/// Runs Argon2id with the GIL released; the key is written straight into locked memory.
+fn pwhash(py: Python<'_>, passphrase: &[u8], salt: &[u8; SALT_BYTES], ops_limit: u64, mem_limit: u64) -> PyResult<SecureBytes> {
+    let mut key = SecureBytes::zeroed(KEY_BYTES)?;
+    let status = py.allow_threads(|| unsafe {
+        crypto_pwhash(
+            key.bytes_mut().as_mut_ptr(),
+            KEY_BYTES as u64,
+            passphrase.as_ptr() as *const _,
+            passphrase.len() as u64,
+            salt.as_ptr(),
+            ops_limit,
+            mem_limit as usize,
+            crypto_pwhash_ALG_ARGON2ID13 as i32,
+        )
+    });
+    if status != 0 {
+        return Err(PyErr::new::<pyo3::exceptions::PyMemoryError, _>("key derivation ran out of memory"));
+    }
+    Ok(key)
+}
+
+/// Derives a 32-byte key from `passphrase` with Argon2id (`crypto_pwhash`). `salt` must be
+/// 16 random bytes, stored alongside whatever the key protects. `ops_limit` and `mem_limit`
+/// take libsodium's `"interactive"`, `"moderate"` or `"sensitive"` presets or explicit
+/// values (passes, and bytes of memory). The key is returned as a `SecureBytes`, so it only
+/// ever exists in locked memory.
+#[pyfunction]
+#[pyo3(signature = (passphrase, salt, ops_limit=MODERATE_OPS, mem_limit=MODERATE_MEM))]
+pub(crate) fn derive_key(
+    py: Python<'_>,
+    passphrase: PyRef<'_, SecureBytes>,
+    salt: &[u8],
+    ops_limit: Cost,
+    mem_limit: Cost,
+) -> PyResult<SecureBytes> {
+    let salt: &[u8; SALT_BYTES] = salt
+        .try_into()
+        .map_err(|_| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("salt must be {} bytes", SALT_BYTES)))?;
+    let (ops_limit, mem_limit) = (ops_limit.ops_limit()?, mem_limit.mem_limit()?);
+    pwhash(py, passphrase.expose()?, salt, ops_limit, mem_limit)
+}
+
+/// Encrypts `secret` (an API key, say) under `passphrase` into a keyfile at `path`, replacing
+/// any file there, created readable by the owner only on unix. A fresh salt and nonce are
+/// drawn for every file and the Argon2id costs are stored in it, so `load_keyfile` needs
+/// only the passphrase.
+#[pyfunction]
+#[pyo3(signature = (path, secret, passphrase, *, ops_limit=MODERATE_OPS, mem_limit=MODERATE_MEM))]
+pub(crate) fn encrypt_keyfile(
+    py: Python<'_>,
+    path: std::path::PathBuf,
+    secret: PyRef<'_, SecureBytes>,
+    passphrase: PyRef<'_, SecureBytes>,
+    ops_limit: Cost,
+    mem_limit: Cost,
+) -> PyResult<()> {
+    // Argon2 counts memory in whole KiB, and the file stores exactly what it used.
+    let (ops_limit, mem_limit) = (ops_limit.ops_limit()?, mem_limit.mem_limit()? / 1024 * 1024);
+    if ops_limit > MAX_KEYFILE_OPS || mem_limit > MAX_KEYFILE_MEM {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "keyfiles allow at most ops_limit={} and mem_limit={}",
+            MAX_KEYFILE_OPS, MAX_KEYFILE_MEM
+        )));
+    }
+    let secret = secret.expose()?;
+    let mut salt = [0u8; SALT_BYTES];
+    let mut nonce = [0u8; NONCE_BYTES];
+    unsafe {
+        randombytes_buf(salt.as_mut_ptr() as *mut c_void, salt.len());
+        randombytes_buf(nonce.as_mut_ptr() as *mut c_void, nonce.len());
+    }
+    let key = pwhash(py, passphrase.expose()?, &salt, ops_limit, mem_limit)?;
+
+    let mut file = Vec::with_capacity(HEADER_BYTES + secret.len() + MAC_BYTES);
+    file.extend_from_slice(MAGIC);
+    file.push(VERSION);
+    file.extend_from_slice(&ops_limit.to_le_bytes());
+    file.extend_from_slice(&mem_limit.to_le_bytes());
+    file.extend_from_slice(&salt);
+    file.extend_from_slice(&nonce);
+    file.resize(HEADER_BYTES + secret.len() + MAC_BYTES, 0);
+    unsafe {
+        crypto_secretbox_easy(
+            file[HEADER_BYTES..].as_mut_ptr(),
+            secret.as_ptr(),
+            secret.len() as u64,
+            nonce.as_ptr(),
+            key.bytes().as_ptr(),
+        );
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(&path)?.write_all(&file)?;
+    Ok(())
+}
+
+/// Decrypts a keyfile written by `encrypt_keyfile`. The secret is decrypted straight into
+/// the returned `SecureBytes`, and the derived key is wiped before returning. A wrong
+/// passphrase and a truncated, tampered or foreign file all raise the same `ValueError`.
+#[pyfunction]
+pub(crate) fn load_keyfile(
+    py: Python<'_>,
+    path: std::path::PathBuf,
+    passphrase: PyRef<'_, SecureBytes>,
+) -> PyResult<SecureBytes> {
+    let file = std::fs::read(&path)?;
+    if file.len() < HEADER_BYTES + MAC_BYTES || &file[..MAGIC.len()] != MAGIC || file[MAGIC.len()] != VERSION {
+        return Err(undecryptable());
+    }
+    let field = |start: usize| u64::from_le_bytes(file[start..start + 8].try_into().expect("8-byte field"));
+    let (ops_limit, mem_limit) = (field(MAGIC.len() + 1), field(MAGIC.len() + 9));
+    let salt: &[u8; SALT_BYTES] = file[MAGIC.len() + 17..][..SALT_BYTES].try_into().expect("salt-sized field");
+    let nonce = &file[HEADER_BYTES - NONCE_BYTES..HEADER_BYTES];
+    let sealed = &file[HEADER_BYTES..];
+    // Out-of-range costs can only come from a damaged file.
+    Cost::Value(ops_limit).ops_limit().map_err(|_| undecryptable())?;
+    Cost::Value(mem_limit).mem_limit().map_err(|_| undecryptable())?;
+    if ops_limit > MAX_KEYFILE_OPS || mem_limit > MAX_KEYFILE_MEM || mem_limit % 1024 != 0 {
+        return Err(undecryptable());
+    }
+
+    let key = pwhash(py, passphrase.expose()?, salt, ops_limit, mem_limit)?;
+    let mut secret = SecureBytes::zeroed(sealed.len() - MAC_BYTES)?;
+    let opened = unsafe {
+        crypto_secretbox_open_easy(
+            secret.bytes_mut().as_mut_ptr(),
+            sealed.as_ptr(),
+            sealed.len() as u64,
+            nonce.as_ptr(),
+            key.bytes().as_ptr(),
+        )
+    };
+    if opened != 0 {
+        return Err(undecryptable());
+    }
+    Ok(secret)
+}
@@ -0,0 +1,142 @@
+use crate::SecureBytes;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyTuple, PyType};
+use zeroize::Zeroizing;
+
+// --- OS Keyring ---
+
+/// Reads the secret stored for `service` and `account` into locked memory. Entries are the
+/// ones the Python `keyring` package reads and writes too, so keys provisioned with either
+/// work with both: a generic password in the macOS Keychain, and a Secret Service item with
+/// `service` and `username` attributes on Linux.
+pub(crate) fn load(service: &str, account: &str) -> PyResult<SecureBytes> {
+    let secret = platform::lookup(service, account)?.ok_or_else(|| {
+        PyErr::new::<pyo3::exceptions::PyKeyError, _>(format!("no keyring entry for service '{}', account '{}'", service, account))
+    })?;
+    SecureBytes::try_new(&secret)
+}
+
+/// Stores `secret` for `service` and `account`, replacing an existing entry.
+#[pyfunction]
+pub(crate) fn store_in_keyring(service: &str, account: &str, secret: PyRef<'_, SecureBytes>) -> PyResult<()> {
+    platform::store(service, account, secret.expose()?)
+}
+
+/// `from_keyring()` of both client classes: the key is passed to `cls` as `api_key` along
+/// with `kwargs`, like `shares::build_client`.
+pub(crate) fn build_client<'py>(
+    cls: &Bound<'py, PyType>,
+    base_url: &Bound<'py, PyAny>,
+    service: &str,
+    account: &str,
+    kwargs: Option<&Bound<'py, PyDict>>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let api_key = Bound::new(cls.py(), load(service, account)?)?;
+    cls.call(PyTuple::new(cls.py(), [base_url.clone(), api_key.into_any()])?, kwargs)
+}
+
+/// The Keychain on macOS and the Secret Service (GNOME Keyring, KWallet) elsewhere, through
+/// the `keyring` crate. The secret comes back in a buffer of the crate's own, which is
+/// wiped here once copied.
+#[cfg(unix)]
+mod platform {
+    use super::*;
+    use keyring::{Entry, Error};
+
+    pub(super) fn lookup(service: &str, account: &str) -> PyResult<Option<Zeroizing<Vec<u8>>>> {
+        match entry(service, account)?.get_secret() {
+            Ok(secret) => Ok(Some(Zeroizing::new(secret))),
+            Err(Error::NoEntry) => Ok(None),
+            Err(e) => Err(keyring_error(e)),
+        }
+    }
+
+    pub(super) fn store(service: &str, account: &str, secret: &[u8]) -> PyResult<()> {
+        entry(service, account)?.set_secret(secret).map_err(keyring_error)
+    }
+
+    fn entry(service: &str, account: &str) -> PyResult<Entry> {
+        #[cfg(feature = "test-hooks")]
+        if memory_store::ENABLED.load(std::sync::atomic::Ordering::Relaxed) {
+            return Ok(memory_store::entry(service, account));
+        }
+        Entry::new(service, account).map_err(keyring_error)
+    }
+
+    fn keyring_error(e: Error) -> PyErr {
+        PyErr::new::<pyo3::exceptions::PyOSError, _>(format!("keyring error: {}", e))
+    }
+}
+
+/// An in-process credential store standing in for the OS keyring, so the Python tests need
+/// neither a Keychain nor a Secret Service daemon.
+#[cfg(all(unix, feature = "test-hooks"))]
+mod memory_store {
+    use keyring::credential::{Credential, CredentialApi};
+    use keyring::{Entry, Error, Result};
+    use std::collections::HashMap;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::{Mutex, OnceLock};
+    use zeroize::Zeroizing;
+
+    pub(super) static ENABLED: AtomicBool = AtomicBool::new(false);
+
+    type Items = Mutex<HashMap<(String, String), Zeroizing<Vec<u8>>>>;
+
+    fn items() -> &'static Items {
+        static ITEMS: OnceLock<Items> = OnceLock::new();
+        ITEMS.get_or_init(Default::default)
+    }
+
+    pub(super) fn entry(service: &str, account: &str) -> Entry {
+        let credential: Box<Credential> = Box::new(MemoryCredential((service.to_owned(), account.to_owned())));
+        Entry::new_with_credential(credential)
+    }
+
+    #[derive(Debug)]
+    struct MemoryCredential((String, String));
+
+    impl CredentialApi for MemoryCredential {
+        fn set_secret(&self, secret: &[u8]) -> Result<()> {
+            items().lock().unwrap().insert(self.0.clone(), Zeroizing::new(secret.to_vec()));
+            Ok(())
+        }
+
+        fn get_secret(&self) -> Result<Vec<u8>> {
+            items().lock().unwrap().get(&self.0).map(|secret| secret.to_vec()).ok_or(Error::NoEntry)
+        }
+
+        fn delete_credential(&self) -> Result<()> {
+            items().lock().unwrap().remove(&self.0).map(drop).ok_or(Error::NoEntry)
+        }
+
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+    }
+}
+
+/// Switches `from_keyring()` and `store_in_keyring()` between the OS keyring and an
+/// in-process store whose entries last until the interpreter exits.
+#[cfg(all(unix, feature = "test-hooks"))]
+#[pyfunction]
+pub(crate) fn _use_memory_keyring(enabled: bool) {
+    memory_store::ENABLED.store(enabled, std::sync::atomic::Ordering::Relaxed);
+}
+
+#[cfg(not(unix))]
+mod platform {
+    use super::*;
+
+    fn unsupported() -> PyErr {
+        PyErr::new::<pyo3::exceptions::PyNotImplementedError, _>("keyring access is only implemented for macOS and Linux")
+    }
+
+    pub(super) fn lookup(_service: &str, _account: &str) -> PyResult<Option<Zeroizing<Vec<u8>>>> {
+        Err(unsupported())
+    }
+
+    pub(super) fn store(_service: &str, _account: &str, _secret: &[u8]) -> PyResult<()> {
+        Err(unsupported())
+    }
+}
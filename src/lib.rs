@@ -1,61 +1,391 @@
 use pyo3::prelude::*;
-use pyo3::types::{PyBytes, PyDict, PyList};
-use reqwest::blocking::Client;
-use serde::ser::{SerializeStruct, Serializer};
+use pyo3::types::{PyByteArray, PyBytes, PyDict, PyList, PyString, PyTuple, PyType};
+use reqwest::Client;
+use reqwest::redirect::Policy;
 use serde::{Deserialize, Serialize};
-use std::ffi::c_void;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::io;
+use std::net::IpAddr;
+use std::path::PathBuf;
 use std::str;
-use libsodium_sys::{sodium_init, sodium_mlock, sodium_munlock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 use zeroize::{Zeroize, ZeroizeOnDrop};
 
+mod anthropic;
+mod api_key;
+mod async_client;
+mod audit;
+mod body;
+mod cache;
+mod call;
+mod cassette;
+mod conversation;
+mod dns;
+mod embeddings;
+mod endpoints;
+mod errors;
+mod headers;
+mod ids;
+mod images;
+mod json;
+mod keyfile;
+mod keyring;
+mod logging;
+mod memory;
+mod messages;
+mod metrics;
+mod mock;
+mod moderation;
+mod params;
+mod providers;
+mod rate_limit;
+mod redact;
+mod response;
+mod retry;
+mod router;
+mod shares;
+mod signing;
+mod stats;
+mod stream;
+mod template;
+mod testing;
+mod token;
+mod tool_calls;
+mod transport;
+
+use audit::TokenCounts;
+use body::DEFAULT_MAX_RESPONSE_BYTES;
+use dns::{FamilyResolver, IpVersion};
+use endpoints::PathStyle;
+use json::{RequestBody, SecureJsonWriter};
+use messages::Messages;
+use rate_limit::{RateLimiter, RateLimits};
+use response::{SecureRawResponse, SecureResponse};
+use retry::{RetryPolicy, DEFAULT_MAX_RETRY_WAIT};
+use metrics::ClientMetrics;
+use stats::ClientStats;
+use reqwest::header::HeaderValue;
+use tool_calls::{ResponseToolCall, SecureToolCall};
+use transport::{Http2, Transport, TransportError};
+
 // --- SecureBytes Wrapper ---
 #[pyclass(name = "SecureBytes")]
-#[derive(Zeroize, Clone, Debug)]
 pub struct SecureBytes {
-    inner: Vec<u8>,
+    /// The secret, between two copies of `memory::canary()` unless canaries were off
+    /// when the buffer was made. Allocated once at its final size and locked as a whole,
+    /// so the locked range is exactly this allocation for its whole life; a new size means
+    /// a new buffer (see `resize`).
+    buffer: Box<[u8]>,
+    /// Length of each canary: 0 or `memory::CANARY_BYTES`.
+    guard: usize,
+    dump_protected: bool,
+    /// See `memory::fork_generation`.
+    generation: u64,
+    /// Set by `wipe()`, which also frees `buffer`.
+    wiped: bool,
 }
 
 impl SecureBytes {
     pub fn new(data: &[u8]) -> Self {
-        let mut inner = data.to_vec();
-        unsafe {
-            if sodium_init() < 0 {
-                panic!("Failed to initialize libsodium");
-            }
-            if !inner.is_empty() {
-                sodium_mlock(inner.as_mut_ptr() as *mut c_void, inner.len());
-            }
+        Self::try_new(data).unwrap_or_else(|e| panic!("{}", e))
+    }
+    /// Like `new`, but under `strict_memory()` a buffer that cannot be locked is a
+    /// `MemoryLockError` rather than a panic. Used wherever Python hands us a secret.
+    pub fn try_new(data: &[u8]) -> PyResult<Self> {
+        // Lock first and copy second, so the data never sits in pageable memory.
+        let mut secure = Self::zeroed(data.len())?;
+        secure.bytes_mut().copy_from_slice(data);
+        Ok(secure)
+    }
+    /// `len` locked zero bytes, for libsodium to write a key or plaintext into.
+    pub(crate) fn zeroed(len: usize) -> PyResult<Self> {
+        let guard = if memory::canaries_enabled() { memory::CANARY_BYTES } else { 0 };
+        // A boxed slice has exactly this length as its capacity and can never reallocate.
+        let mut buffer = vec![0u8; len + 2 * guard].into_boxed_slice();
+        memory::ensure_sodium();
+        let dump_protected = memory::lock(buffer.as_mut_ptr(), buffer.len())?;
+        let canary = &memory::canary()[..guard];
+        buffer[..guard].copy_from_slice(canary);
+        buffer[guard + len..].copy_from_slice(canary);
+        Ok(Self { buffer, guard, dump_protected, generation: memory::fork_generation(), wiped: false })
+    }
+    /// Moves the secret into a new locked buffer of `len` bytes, keeping what fits and
+    /// zero-filling the rest. The new buffer is locked before anything is copied into it,
+    /// and the old one is wiped before it is unlocked and freed.
+    pub(crate) fn resize(&mut self, len: usize) -> PyResult<()> {
+        let kept = self.expose()?.len().min(len);
+        let mut resized = Self::zeroed(len)?;
+        resized.bytes_mut()[..kept].copy_from_slice(&self.bytes()[..kept]);
+        // Dropping the old buffer releases it: wipe first, then unlock.
+        *self = resized;
+        Ok(())
+    }
+    /// `clone`, returning `MemoryLockError` instead of panicking. The new buffer is locked
+    /// before the secret is copied into it; a wiped buffer clones to a wiped one.
+    pub(crate) fn try_clone(&self) -> PyResult<Self> {
+        let mut clone = Self::try_new(self.bytes())?;
+        if self.wiped {
+            clone.wipe();
+        }
+        Ok(clone)
+    }
+    /// Appends `data` by way of `resize`.
+    pub(crate) fn extend_from_slice(&mut self, data: &[u8]) -> PyResult<()> {
+        let start = self.expose()?.len();
+        self.resize(start + data.len())?;
+        self.bytes_mut()[start..].copy_from_slice(data);
+        Ok(())
+    }
+    /// The secret, unless a fork wiped it (see `set_fork_policy`) or its canaries show
+    /// that something wrote over the buffer. Behind `&self` the buffer cannot be wiped, so
+    /// a corrupted one is only refused here; `scrub_if_corrupted` and dropping wipe it.
+    pub fn expose(&self) -> PyResult<&[u8]> {
+        if self.wiped {
+            return Err(secret_wiped());
+        }
+        if self.generation != memory::fork_generation() {
+            return Err(memory::invalidated_by_fork());
+        }
+        if !self.canaries_intact() {
+            return Err(memory::corrupted());
         }
-        Self { inner }
+        Ok(self.bytes())
     }
+    /// Zeroes the buffer, canaries included, if they show it was written over. It stays
+    /// corrupted, so every later use still raises `MemoryCorruptionError`.
+    fn scrub_if_corrupted(&mut self) {
+        if self.generation == memory::fork_generation() && !self.canaries_intact() {
+            self.buffer.zeroize();
+        }
+    }
+    /// The secret without any checks, for lengths and for code that already called `expose`.
+    pub(crate) fn bytes(&self) -> &[u8] {
+        &self.buffer[self.guard..self.buffer.len() - self.guard]
+    }
+    pub(crate) fn bytes_mut(&mut self) -> &mut [u8] {
+        let end = self.buffer.len() - self.guard;
+        &mut self.buffer[self.guard..end]
+    }
+    fn release(&mut self) {
+        // A fork wipe zeroes the canaries too, which is not corruption.
+        if self.generation == memory::fork_generation() && !self.canaries_intact() {
+            memory::count_corruption();
+        }
+        self.buffer.zeroize();
+        memory::unlock(self.buffer.as_mut_ptr(), self.buffer.len());
+    }
+    fn canaries_intact(&self) -> bool {
+        let canary = &memory::canary()[..self.guard];
+        let (head, tail) = (&self.buffer[..self.guard], &self.buffer[self.buffer.len() - self.guard..]);
+        // Constant time, so timing doesn't reveal the canary value.
+        let differences = head.iter().chain(tail).zip(canary.iter().chain(canary)).fold(0, |acc, (a, b)| acc | (a ^ b));
+        differences == 0
+    }
+    /// Copies a Python `bytes` or `SecureBytes` argument into a new locked buffer.
+    pub fn from_py(value: &Bound<'_, PyAny>, name: &str) -> PyResult<Self> {
+        if let Ok(secure) = value.downcast::<SecureBytes>() {
+            Self::try_new(secure.borrow().expose()?)
+        } else if let Ok(bytes) = value.downcast::<PyBytes>() {
+            Self::try_new(bytes.as_bytes())
+        } else {
+            Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!("{} must be bytes or SecureBytes", name)))
+        }
+    }
+    /// `from_py`, also taking a str, whose UTF-8 goes straight into locked memory.
+    pub(crate) fn from_py_text(value: &Bound<'_, PyAny>, name: &str) -> PyResult<Self> {
+        match value.downcast::<PyString>() {
+            Ok(text) => Self::try_new(text.to_str()?.as_bytes()),
+            Err(_) => Self::from_py(value, name)
+                .map_err(|_| PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!("{} must be str, bytes or SecureBytes", name))),
+        }
+    }
+    /// The content as text. Invalid UTF-8 is a `ValueError` naming the offset of the first
+    /// bad byte; a `UnicodeDecodeError` would have to carry the bytes themselves.
     pub fn as_str(&self) -> Result<&str, PyErr> {
-        str::from_utf8(&self.inner).map_err(|e| {
-            PyErr::new::<pyo3::exceptions::PyUnicodeDecodeError, _>(format!("UTF-8 decode error: {}", e))
+        str::from_utf8(self.expose()?).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "SecureBytes is not valid UTF-8: invalid byte sequence at offset {}",
+                e.valid_up_to()
+            ))
         })
     }
 }
 
+/// Only the length shows, so `{:?}` on anything holding a secret never prints it.
+impl std::fmt::Debug for SecureBytes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.wiped {
+            return f.write_str("SecureBytes(wiped)");
+        }
+        write!(f, "SecureBytes(<{} bytes>)", self.bytes().len())
+    }
+}
+
+impl AsRef<[u8]> for SecureBytes {
+    fn as_ref(&self) -> &[u8] {
+        self.bytes()
+    }
+}
+
+/// A clone is a new locked allocation, never a plain copy of the buffer, made like `new`:
+/// under `strict_memory()` a clone that cannot be locked panics. `SecureMessage` and
+/// everything else holding `SecureBytes` clone through this.
+impl Clone for SecureBytes {
+    fn clone(&self) -> Self {
+        self.try_clone().unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+/// Wipes the secret and leaves the canaries, so a later `Drop` still finds them intact.
+impl Zeroize for SecureBytes {
+    fn zeroize(&mut self) {
+        self.bytes_mut().zeroize();
+    }
+}
+
 impl Drop for SecureBytes {
     fn drop(&mut self) {
-        self.inner.zeroize();
-        unsafe {
-            if !self.inner.is_empty() {
-                sodium_munlock(self.inner.as_mut_ptr() as *mut c_void, self.inner.len());
-            }
+        if !self.wiped {
+            self.release();
         }
     }
 }
 
+fn secret_wiped() -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyValueError, _>("secret has been wiped")
+}
+
 #[pymethods]
 impl SecureBytes {
     #[new]
-    fn pynew(data: &[u8]) -> Self { Self::new(data) }
+    fn pynew(data: &[u8]) -> PyResult<Self> { Self::try_new(data) }
+
+    /// Moves the contents of a `bytearray` into a new locked buffer and zeroes the
+    /// `bytearray` in place, so the locked copy is the only one left. Immutable `bytes`
+    /// cannot be scrubbed and are rejected; use `SecureBytes(data)` to copy them instead.
+    #[staticmethod]
+    fn consume(source: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let source = source.downcast::<PyByteArray>().map_err(|_| {
+            PyErr::new::<pyo3::exceptions::PyTypeError, _>(
+                "consume() needs a bytearray; immutable bytes cannot be scrubbed, use SecureBytes(data) to copy them",
+            )
+        })?;
+        // SAFETY: the GIL is held and nothing below runs Python code, so the bytearray
+        // cannot be resized or freed while its buffer is borrowed.
+        unsafe {
+            let secure = Self::try_new(source.as_bytes())?;
+            source.as_bytes_mut().zeroize();
+            Ok(secure)
+        }
+    }
+
+    /// Splits the secret into `n` shares, returned as plain `bytes`, of which `k` (default
+    /// all `n`) are needed to rebuild it with `combine`; fewer reveal nothing about it. Only
+    /// `k == n` is implemented so far, which raises `NotImplementedError` otherwise.
+    #[pyo3(signature = (n, k=None))]
+    fn split<'py>(&self, py: Python<'py>, n: usize, k: Option<usize>) -> PyResult<Vec<Bound<'py, PyBytes>>> {
+        let shares = shares::split(self.expose()?, n, k.unwrap_or(n))?;
+        Ok(shares.iter().map(|share| PyBytes::new(py, share)).collect())
+    }
+
+    /// Rebuilds a secret from shares made by `split`, directly into locked memory. Shares
+    /// from different splits, duplicates or too few of them raise `ValueError`.
+    #[staticmethod]
+    fn combine(shares: Vec<Vec<u8>>) -> PyResult<Self> {
+        let shares = shares.into_iter().map(zeroize::Zeroizing::new).collect::<Vec<_>>();
+        shares::combine(&shares.iter().map(|share| share.as_slice()).collect::<Vec<_>>())
+    }
+
+    /// Reads the secret stored for `service` and `account` in the OS keyring (the macOS
+    /// Keychain, or the Secret Service on Linux) straight into locked memory. A missing
+    /// entry raises `KeyError`; `store_in_keyring` provisions one.
+    #[staticmethod]
+    fn from_keyring(service: &str, account: &str) -> PyResult<Self> {
+        keyring::load(service, account)
+    }
 
-    fn __bytes__<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
-        PyBytes::new(py, &self.inner)
+    fn __bytes__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        Ok(PyBytes::new(py, self.expose()?))
     }
     fn __str__(&self) -> PyResult<String> { Ok(self.as_str()?.to_string()) }
     fn __repr__(&self) -> String { "SecureBytes(b'****')".to_string() }
+
+    /// Checks the canaries around the buffer, which every use of the secret also does, and
+    /// raises `MemoryCorruptionError` after wiping it if something wrote over them. A buffer
+    /// made while canaries were off (see `set_canaries`) has none and always passes.
+    fn check_integrity(&mut self) -> PyResult<()> {
+        let checked = self.expose().map(|_| ());
+        if checked.is_err() {
+            self.scrub_if_corrupted();
+        }
+        checked
+    }
+
+    /// Appends `data` (bytes or `SecureBytes`, this one included) in place. The secret
+    /// moves to a new locked buffer of the combined size and the old one is wiped.
+    fn append(slf: &Bound<'_, Self>, data: &Bound<'_, PyAny>) -> PyResult<()> {
+        if data.is(slf) {
+            let mut secret = slf.borrow_mut();
+            let len = secret.expose()?.len();
+            secret.resize(2 * len)?;
+            secret.bytes_mut().copy_within(..len, len);
+            return Ok(());
+        }
+        if let Ok(other) = data.downcast::<SecureBytes>() {
+            return slf.borrow_mut().extend_from_slice(other.borrow().expose()?);
+        }
+        let bytes = data
+            .downcast::<PyBytes>()
+            .map_err(|_| PyErr::new::<pyo3::exceptions::PyTypeError, _>("data must be bytes or SecureBytes"))?;
+        slf.borrow_mut().extend_from_slice(bytes.as_bytes())
+    }
+
+    /// Keeps the first `len` bytes, moving them to a new locked buffer and wiping the old
+    /// one. A `len` past the end leaves the secret as it is.
+    fn truncate(&mut self, len: usize) -> PyResult<()> {
+        if len < self.expose()?.len() {
+            self.resize(len)?;
+        }
+        Ok(())
+    }
+
+    /// A copy in a new locked buffer.
+    fn __copy__(&self) -> PyResult<Self> {
+        self.expose()?;
+        self.try_clone()
+    }
+
+    fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> PyResult<Self> {
+        self.__copy__()
+    }
+
+    /// Zeroes, unlocks and frees the secret now instead of when the last reference goes
+    /// away. Any later use raises `ValueError("secret has been wiped")`; wiping again does
+    /// nothing.
+    fn wipe(&mut self) {
+        if !self.wiped {
+            self.release();
+            self.buffer = Box::default();
+            self.guard = 0;
+            self.wiped = true;
+        }
+    }
+
+    #[getter]
+    fn wiped(&self) -> bool {
+        self.wiped
+    }
+
+    /// Whether this buffer's pages are excluded from core dumps (`MADV_DONTDUMP`). Only
+    /// Linux supports that; elsewhere it is `False` and `disable_core_dumps()` is the way
+    /// to keep secrets out of core files.
+    #[getter]
+    fn is_dump_protected(&self) -> bool {
+        self.dump_protected
+    }
 }
 
 impl Serialize for SecureBytes {
@@ -63,62 +393,159 @@ impl Serialize for SecureBytes {
     where
         S: serde::Serializer,
     {
-        let s = str::from_utf8(&self.inner).map_err(serde::ser::Error::custom)?;
+        let bytes = self.expose().map_err(serde::ser::Error::custom)?;
+        let s = str::from_utf8(bytes).map_err(serde::ser::Error::custom)?;
         serializer.serialize_str(s)
     }
 }
 
+/// Reads a JSON string, or an array of byte values, straight into a locked buffer so
+/// response fragments never sit in a plain `String`.
+impl<'de> Deserialize<'de> for SecureBytes {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct SecureBytesVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for SecureBytesVisitor {
+            type Value = SecureBytes;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a string or an array of bytes")
+            }
+
+            // A buffer that cannot be locked under `strict_memory()` fails the parse rather
+            // than panicking.
+            fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<SecureBytes, E> {
+                SecureBytes::try_new(value.as_bytes()).map_err(E::custom)
+            }
+
+            fn visit_string<E: serde::de::Error>(self, mut value: String) -> Result<SecureBytes, E> {
+                let secure = SecureBytes::try_new(value.as_bytes());
+                value.zeroize();
+                secure.map_err(E::custom)
+            }
+
+            fn visit_bytes<E: serde::de::Error>(self, value: &[u8]) -> Result<SecureBytes, E> {
+                SecureBytes::try_new(value).map_err(E::custom)
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<SecureBytes, A::Error> {
+                let mut bytes = zeroize::Zeroizing::new(Vec::with_capacity(seq.size_hint().unwrap_or(16)));
+                while let Some(byte) = seq.next_element::<u8>()? {
+                    bytes.push(byte);
+                }
+                SecureBytes::try_new(&bytes).map_err(serde::de::Error::custom)
+            }
+        }
+
+        deserializer.deserialize_any(SecureBytesVisitor)
+    }
+}
+
 // --- API Message Structures (Internal & Serializable) ---
-#[derive(Serialize, Clone, Debug, Zeroize, ZeroizeOnDrop)]
+#[derive(Clone, Debug, Zeroize, ZeroizeOnDrop)]
 struct ImageUrlDetail {
     url: SecureBytes,
+    /// `"auto"`, `"low"` or `"high"`, sent as `detail` when set.
+    detail: Option<String>,
+}
+
+/// The `detail` values of an image part.
+const IMAGE_DETAILS: &[&str] = &["auto", "low", "high"];
+
+impl ImageUrlDetail {
+    fn new(url: SecureBytes, detail: Option<String>) -> PyResult<Self> {
+        if let Some(detail) = detail.as_deref().filter(|detail| !IMAGE_DETAILS.contains(detail)) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Invalid image detail '{}': expected 'auto', 'low' or 'high'",
+                detail
+            )));
+        }
+        Ok(Self { url, detail })
+    }
 }
 
-#[derive(Serialize, Clone, Debug, Zeroize, ZeroizeOnDrop)]
-#[serde(tag = "type", rename_all = "snake_case")]
+#[derive(Clone, Debug, Zeroize, ZeroizeOnDrop)]
 enum SecureContentPart {
     Text { text: SecureBytes },
     ImageUrl { image_url: ImageUrlDetail },
 }
 
+impl SecureContentPart {
+    fn write_json(&self, out: &mut SecureJsonWriter) -> io::Result<()> {
+        match self {
+            SecureContentPart::Text { text } => {
+                out.write_raw(br#"{"type":"text","text":"#);
+                out.write_str(text.bytes())?;
+            }
+            SecureContentPart::ImageUrl { image_url } => {
+                out.write_raw(br#"{"type":"image_url","image_url":{"url":"#);
+                out.write_str(image_url.url.bytes())?;
+                if let Some(detail) = &image_url.detail {
+                    out.write_raw(br#","detail":"#);
+                    out.write_value(detail)?;
+                }
+                out.write_raw(b"}");
+            }
+        }
+        out.write_raw(b"}");
+        Ok(())
+    }
+}
+
 #[pyclass(name = "SecureMessage")]
 #[derive(Clone, Debug, Zeroize, ZeroizeOnDrop)]
 pub struct SecureMessage {
     role: SecureBytes,
     content: Vec<SecureContentPart>,
+    /// Set on `tool` messages, to the id of the tool call they answer.
+    tool_call_id: Option<String>,
+    /// The optional participant name, sent as `name`.
+    name: Option<String>,
 }
 
-// FIX: Custom Serialize implementation for SecureMessage to handle the API's
-// requirement for string content in simple text messages.
-impl Serialize for SecureMessage {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        let mut state = serializer.serialize_struct("SecureMessage", 2)?;
-        state.serialize_field("role", &self.role)?;
-
-        // If content has one item and it's text, serialize it as a plain string.
-        // Otherwise, serialize it as a list of content parts.
-        if self.content.len() == 1 {
-            if let Some(SecureContentPart::Text { text }) = self.content.first() {
-                state.serialize_field("content", text)?;
-            } else {
-                state.serialize_field("content", &self.content)?;
-            }
-        } else {
-            state.serialize_field("content", &self.content)?;
-        }
+impl SecureMessage {
+    /// A message with a single text part, copied from a str, bytes or `SecureBytes`
+    /// argument straight into locked memory.
+    fn text(role: &[u8], text: &Bound<'_, PyAny>, name: &str) -> PyResult<Self> {
+        let text = SecureBytes::from_py_text(text, name)?;
+        Ok(Self { role: SecureBytes::try_new(role)?, content: vec![SecureContentPart::Text { text }], tool_call_id: None, name: None })
+    }
 
-        state.end()
+    /// `clone`, returning `MemoryLockError` instead of panicking when a copy cannot be locked.
+    fn try_clone(&self) -> PyResult<Self> {
+        let content = self
+            .content
+            .iter()
+            .map(|part| {
+                Ok(match part {
+                    SecureContentPart::Text { text } => SecureContentPart::Text { text: text.try_clone()? },
+                    SecureContentPart::ImageUrl { image_url } => SecureContentPart::ImageUrl {
+                        image_url: ImageUrlDetail { url: image_url.url.try_clone()?, detail: image_url.detail.clone() },
+                    },
+                })
+            })
+            .collect::<PyResult<_>>()?;
+        Ok(Self { role: self.role.try_clone()?, content, tool_call_id: self.tool_call_id.clone(), name: self.name.clone() })
     }
-}
 
+    /// Copies a `role` argument, str or bytes, into locked memory.
+    fn role_from_py(role: &Bound<'_, PyAny>) -> PyResult<SecureBytes> {
+        if let Ok(text) = role.downcast::<PyString>() {
+            SecureBytes::try_new(text.to_str()?.as_bytes())
+        } else if let Ok(bytes) = role.downcast::<PyBytes>() {
+            SecureBytes::try_new(bytes.as_bytes())
+        } else {
+            Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>("role must be str or bytes"))
+        }
+    }
 
-#[pymethods]
-impl SecureMessage {
-    #[new]
-    fn new(_py: Python, role: &[u8], content_list: &Bound<PyList>) -> PyResult<Self> {
+    /// Copies a list of content part dicts, `{"type": "text", "text": ...}` or
+    /// `{"type": "image_url", "image_url": {"url": ...}}`, into locked memory. Text and URLs
+    /// may be str, bytes or `SecureBytes`.
+    fn parse_content(content_list: &Bound<PyList>) -> PyResult<Vec<SecureContentPart>> {
         let mut content: Vec<SecureContentPart> = Vec::new();
 
         for item in content_list.iter() {
@@ -134,9 +561,8 @@ impl SecureMessage {
                     let text_item = dict
                         .get_item("text")?
                         .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyKeyError, _>("'text' key missing for type 'text'"))?;
-                    let text_bytes: &Bound<PyBytes> = text_item.downcast()?;
                     content.push(SecureContentPart::Text {
-                        text: SecureBytes::new(text_bytes.as_bytes()),
+                        text: SecureBytes::from_py_text(&text_item, "'text'")?,
                     });
                 }
                 "image_url" => {
@@ -148,110 +574,1663 @@ impl SecureMessage {
                     let url_item = image_url_dict
                         .get_item("url")?
                         .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyKeyError, _>("'url' key missing in image_url object"))?;
-                    let url_bytes: &Bound<PyBytes> = url_item.downcast()?;
-
+                    let detail = image_url_dict.get_item("detail")?.map(|detail| detail.extract()).transpose()?;
                     content.push(SecureContentPart::ImageUrl {
-                        image_url: ImageUrlDetail {
-                            url: SecureBytes::new(url_bytes.as_bytes()),
-                        },
+                        image_url: ImageUrlDetail::new(SecureBytes::from_py_text(&url_item, "'url'")?, detail)?,
                     });
                 }
                 _ => return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Unsupported content type: {}", content_type))),
             }
         }
 
+        Ok(content)
+    }
+
+    /// A `system` or `developer` message, the role newer OpenAI models use for it.
+    fn is_system(&self) -> bool {
+        matches!(self.role.bytes(), b"system" | b"developer")
+    }
+
+    /// Fails if the message was wiped, or a fork wiped any part of it.
+    fn ensure_usable(&self) -> PyResult<()> {
+        self.role.expose()?;
+        for part in &self.content {
+            match part {
+                SecureContentPart::Text { text } => text.expose()?,
+                SecureContentPart::ImageUrl { image_url } => image_url.url.expose()?,
+            };
+        }
+        Ok(())
+    }
+
+    /// A single text part is sent as a plain string, which every compatible API accepts;
+    /// anything else as the list of content parts. `system_role`, when set, replaces the
+    /// role of a system or developer message.
+    fn write_json(&self, out: &mut SecureJsonWriter, system_role: Option<&str>) -> io::Result<()> {
+        out.write_raw(br#"{"role":"#);
+        match system_role.filter(|_| self.is_system()) {
+            Some(role) => out.write_value(role)?,
+            None => out.write_str(self.role.bytes())?,
+        }
+        out.write_raw(br#","content":"#);
+        match self.content.as_slice() {
+            [SecureContentPart::Text { text }] => out.write_str(text.bytes())?,
+            parts => {
+                out.write_raw(b"[");
+                for (index, part) in parts.iter().enumerate() {
+                    if index > 0 {
+                        out.write_raw(b",");
+                    }
+                    part.write_json(out)?;
+                }
+                out.write_raw(b"]");
+            }
+        }
+        if let Some(tool_call_id) = &self.tool_call_id {
+            out.write_raw(br#","tool_call_id":"#);
+            out.write_value(tool_call_id)?;
+        }
+        if let Some(name) = &self.name {
+            out.write_raw(br#","name":"#);
+            out.write_value(name)?;
+        }
+        out.write_raw(b"}");
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl SecureMessage {
+    /// `role` is a str or bytes, such as `"user"` or `b"user"`. `tool_call_id` marks a
+    /// `tool` message as the result of that tool call; `name` is the optional participant name.
+    #[new]
+    #[pyo3(signature = (role, content_list, *, tool_call_id=None, name=None))]
+    fn new(
+        _py: Python,
+        role: &Bound<'_, PyAny>,
+        content_list: &Bound<PyList>,
+        tool_call_id: Option<String>,
+        name: Option<String>,
+    ) -> PyResult<Self> {
         Ok(SecureMessage {
-            role: SecureBytes::new(role),
-            content,
+            role: Self::role_from_py(role)?,
+            content: Self::parse_content(content_list)?,
+            tool_call_id,
+            name,
+        })
+    }
+
+    /// Rough token count of this message (about 4 bytes per token plus framing),
+    /// good enough for budgeting without a tokenizer.
+    fn approx_tokens(&self) -> PyResult<u64> {
+        self.ensure_usable()?;
+        Ok(self.estimate_tokens())
+    }
+
+    /// Wipes the role and every content part now, like `SecureBytes.wipe()`. Sending the
+    /// message afterwards raises `ValueError("secret has been wiped")`; wiping again does
+    /// nothing.
+    fn wipe(&mut self) {
+        self.role.wipe();
+        for part in &mut self.content {
+            match part {
+                SecureContentPart::Text { text } => text.wipe(),
+                SecureContentPart::ImageUrl { image_url } => image_url.url.wipe(),
+            }
+        }
+    }
+
+    #[getter]
+    fn wiped(&self) -> bool {
+        self.role.wiped
+    }
+
+    /// Appends an image part holding `data`, raw image bytes or `SecureBytes`, as a data URL
+    /// built by `image_to_data_url`, so the encoded image never reaches Python. `detail` is
+    /// `"auto"`, `"low"` or `"high"`; `mime` is sniffed when not given.
+    #[pyo3(signature = (data, detail=None, *, mime=None))]
+    fn add_image_bytes(&mut self, data: &Bound<'_, PyAny>, detail: Option<String>, mime: Option<&str>) -> PyResult<()> {
+        self.ensure_usable()?;
+        let url = images::with_image(data, |image| images::data_url(image, mime))?;
+        self.content.push(SecureContentPart::ImageUrl { image_url: ImageUrlDetail::new(url, detail)? });
+        Ok(())
+    }
+
+    /// A `tool` message answering `tool_call`, a `SecureToolCall` from an earlier response
+    /// or its id. `result` is str, bytes or `SecureBytes`, sent as it is, or a dict or list,
+    /// serialized to JSON straight into locked memory. A result over `max_bytes` raises
+    /// `ValueError` instead of being sent.
+    #[staticmethod]
+    #[pyo3(signature = (tool_call, result, *, max_bytes=tool_calls::MAX_TOOL_RESULT_BYTES))]
+    fn tool_result(tool_call: &Bound<'_, PyAny>, result: &Bound<'_, PyAny>, max_bytes: usize) -> PyResult<Self> {
+        let tool_call_id = tool_calls::tool_call_id(tool_call)?;
+        let text = tool_calls::result_text(result, max_bytes)?;
+        Ok(Self {
+            role: SecureBytes::try_new(b"tool")?,
+            content: vec![SecureContentPart::Text { text }],
+            tool_call_id: Some(tool_call_id),
+            name: None,
         })
     }
+
+    /// A `developer` message with a single text part, the role newer OpenAI models take in
+    /// place of `system`. `text` is a str, bytes or `SecureBytes`, copied into locked memory.
+    #[staticmethod]
+    fn developer(text: &Bound<'_, PyAny>) -> PyResult<Self> {
+        Self::text(b"developer", text, "text")
+    }
+
+    /// A copy whose role and parts are each in a new locked buffer; wiping one copy leaves
+    /// the other intact. A wiped message copies to a wiped one.
+    fn __copy__(&self) -> PyResult<Self> {
+        self.try_clone()
+    }
+
+    fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> PyResult<Self> {
+        self.try_clone()
+    }
+
+    /// The role and the kind and size of each part, never their content.
+    fn __repr__(&self) -> String {
+        if self.role.wiped {
+            return "SecureMessage(wiped)".to_string();
+        }
+        let parts = self
+            .content
+            .iter()
+            .map(|part| match part {
+                SecureContentPart::Text { text } => format!("text({} bytes)", text.bytes().len()),
+                SecureContentPart::ImageUrl { image_url } => format!("image_url({} bytes)", image_url.url.bytes().len()),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("SecureMessage(role='{}', parts=[{}])", String::from_utf8_lossy(self.role.bytes()), parts)
+    }
+}
+
+// Per-message framing overhead and the fixed cost of a low-detail image,
+// following OpenAI's published counting rules.
+const MESSAGE_OVERHEAD_TOKENS: u64 = 4;
+const IMAGE_PART_TOKENS: u64 = 85;
+const REPLY_PRIMING_TOKENS: u64 = 3;
+
+impl SecureMessage {
+    fn estimate_tokens(&self) -> u64 {
+        let content: u64 = self
+            .content
+            .iter()
+            .map(|part| match part {
+                SecureContentPart::Text { text } => (text.bytes().len() as u64).div_ceil(4),
+                SecureContentPart::ImageUrl { .. } => IMAGE_PART_TOKENS,
+            })
+            .sum();
+        MESSAGE_OVERHEAD_TOKENS + (self.role.bytes().len() as u64).div_ceil(4) + content
+    }
 }
 
+fn estimate_request_tokens(system: Option<&SecureBytes>, messages: &[PyRef<'_, SecureMessage>]) -> u64 {
+    // The role of the system prompt, "system", is two tokens by the same reckoning.
+    let system = system.map_or(0, |prompt| MESSAGE_OVERHEAD_TOKENS + 2 + (prompt.bytes().len() as u64).div_ceil(4));
+    system + messages.iter().map(|m| m.estimate_tokens()).sum::<u64>() + REPLY_PRIMING_TOKENS
+}
 
 // --- API Request/Response Structs ---
 
-#[derive(Serialize, Debug)]
-struct ChatCompletionRequest<'a> {
-    messages: &'a Vec<SecureMessage>,
+/// Borrows the messages straight from their Python objects, so building a request
+/// never copies the conversation; it is only ever serialized while the GIL is held.
+struct ChatCompletionRequest<'a, 'py> {
+    /// The client's `system_prompt`, sent as a system message ahead of `messages`.
+    system: Option<&'a SecureBytes>,
+    messages: &'a [PyRef<'py, SecureMessage>],
     model: &'a str,
+    stream: bool,
+    params: &'a Map<String, Value>,
+    /// A `SecureBytes` grammar, written as the `grammar` parameter.
+    grammar: Option<&'a SecureBytes>,
+    /// The role system messages are sent with, if translated; see `RoleTranslation`.
+    system_role: Option<&'static str>,
 }
 
-#[derive(Deserialize, Debug)]
+impl ChatCompletionRequest<'_, '_> {
+    /// Serializes the request into locked memory. Message text is escaped by
+    /// `SecureJsonWriter` directly from its locked buffer; the parameters go through serde.
+    fn to_json(&self) -> PyResult<RequestBody> {
+        let mut out = SecureJsonWriter::new()?;
+        self.write_json(&mut out).map_err(json::serialize_error)?;
+        out.finish()
+    }
+
+    fn write_json(&self, out: &mut SecureJsonWriter) -> io::Result<()> {
+        out.write_raw(br#"{"messages":["#);
+        if let Some(system) = self.system {
+            out.write_raw(br#"{"role":"#);
+            out.write_value(self.system_role.unwrap_or("system"))?;
+            out.write_raw(br#","content":"#);
+            out.write_str(system.bytes())?;
+            out.write_raw(b"}");
+            if !self.messages.is_empty() {
+                out.write_raw(b",");
+            }
+        }
+        for (index, message) in self.messages.iter().enumerate() {
+            if index > 0 {
+                out.write_raw(b",");
+            }
+            message.write_json(out, self.system_role)?;
+        }
+        out.write_raw(br#"],"model":"#);
+        out.write_value(self.model)?;
+        if self.stream {
+            out.write_raw(br#","stream":true"#);
+        }
+        for (name, value) in self.params {
+            out.write_raw(b",");
+            out.write_value(name)?;
+            out.write_raw(b":");
+            out.write_value(value)?;
+        }
+        if let Some(grammar) = self.grammar {
+            out.write_raw(br#","grammar":"#);
+            out.write_str(grammar.bytes())?;
+        }
+        out.write_raw(b"}");
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
 struct ResponseChoice {
     message: ResponseMessage,
+    finish_reason: Option<String>,
+    logprobs: Option<Logprobs>,
 }
 
 #[derive(Deserialize, Debug)]
+struct Logprobs {
+    content: Option<Vec<TokenLogprob>>,
+}
+
+/// One sampled token. Tokens are fragments of the completion, so they are read into
+/// `SecureBytes`; `bytes`, when sent, is the exact token and wins over the lossy string.
+#[derive(Deserialize, Debug)]
+struct TokenLogprob {
+    token: SecureBytes,
+    logprob: f64,
+    bytes: Option<SecureBytes>,
+    #[serde(default)]
+    top_logprobs: Vec<TopLogprob>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TopLogprob {
+    token: SecureBytes,
+    logprob: f64,
+    bytes: Option<SecureBytes>,
+}
+
+impl TokenLogprob {
+    /// `(token, logprob, [(token, logprob), ...])`
+    fn to_py<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyTuple>> {
+        let top = self
+            .top_logprobs
+            .iter()
+            .map(|top| Ok((exact_token(&top.token, &top.bytes)?, top.logprob)))
+            .collect::<PyResult<Vec<_>>>()?;
+        (exact_token(&self.token, &self.bytes)?, self.logprob, top).into_pyobject(py)
+    }
+}
+
+fn exact_token(token: &SecureBytes, bytes: &Option<SecureBytes>) -> PyResult<SecureBytes> {
+    SecureBytes::try_new(bytes.as_ref().unwrap_or(token).bytes())
+}
+
+#[derive(Deserialize)]
 struct ResponseMessage {
+    #[serde(default, deserialize_with = "text_content")]
     content: Option<String>,
+    refusal: Option<String>,
+    tool_calls: Option<Vec<ResponseToolCall>>,
+    annotations: Option<Vec<Annotation>>,
+}
+
+/// An entry of a message's `annotations`. Search-preview models cite their sources with
+/// `url_citation` ones; annotations of any other type have none and are skipped.
+#[derive(Deserialize, Debug)]
+struct Annotation {
+    url_citation: Option<UrlCitation>,
+}
+
+/// A cited source and the span of the content, in characters, that cites it. Citations
+/// point at public pages, so they are plain metadata rather than `SecureBytes`.
+#[derive(Deserialize, Debug)]
+struct UrlCitation {
+    url: String,
+    title: Option<String>,
+    start_index: Option<u64>,
+    end_index: Option<u64>,
+}
+
+impl UrlCitation {
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        dict.set_item("url", &self.url)?;
+        dict.set_item("title", &self.title)?;
+        dict.set_item("start_index", self.start_index)?;
+        dict.set_item("end_index", self.end_index)?;
+        Ok(dict)
+    }
+}
+
+/// `content` as a string, or as the list of parts some self-hosted servers (vLLM among
+/// them) and Responses API bridges (`output_text` parts) send instead: the text parts are
+/// joined and any other part is skipped.
+fn text_content<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    struct TextContentVisitor;
+
+    #[derive(Deserialize)]
+    struct ContentPart {
+        #[serde(rename = "type")]
+        kind: Option<String>,
+        text: Option<String>,
+    }
+
+    impl<'de> serde::de::Visitor<'de> for TextContentVisitor {
+        type Value = Option<String>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("a string, a list of content parts or null")
+        }
+
+        fn visit_unit<E: serde::de::Error>(self) -> Result<Option<String>, E> {
+            Ok(None)
+        }
+
+        fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Option<String>, E> {
+            Ok(Some(value.to_owned()))
+        }
+
+        fn visit_string<E: serde::de::Error>(self, value: String) -> Result<Option<String>, E> {
+            Ok(Some(value))
+        }
+
+        fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Option<String>, A::Error> {
+            let mut content = zeroize::Zeroizing::new(String::new());
+            while let Some(part) = seq.next_element::<ContentPart>()? {
+                if let Some(mut text) = part.text.filter(|_| part.kind.as_deref().is_none_or(|kind| matches!(kind, "text" | "output_text"))) {
+                    tool_calls::append(&mut content, &text);
+                    text.zeroize();
+                }
+            }
+            Ok(Some(std::mem::take(&mut *content)))
+        }
+    }
+
+    deserializer.deserialize_any(TextContentVisitor)
 }
 
+/// The `usage` block. Every field is optional since proxies trim it in different ways.
 #[derive(Deserialize, Debug)]
+struct Usage {
+    prompt_tokens: Option<u64>,
+    completion_tokens: Option<u64>,
+    total_tokens: Option<u64>,
+    prompt_tokens_details: Option<PromptTokensDetails>,
+    completion_tokens_details: Option<CompletionTokensDetails>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PromptTokensDetails {
+    cached_tokens: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CompletionTokensDetails {
+    reasoning_tokens: Option<u64>,
+}
+
+impl Usage {
+    fn counts(&self) -> TokenCounts {
+        TokenCounts { prompt: self.prompt_tokens, completion: self.completion_tokens, total: self.total() }
+    }
+
+    /// `total_tokens`, or the sum of its parts when only those were sent.
+    fn total(&self) -> Option<u64> {
+        self.total_tokens.or_else(|| Some(self.prompt_tokens? + self.completion_tokens?))
+    }
+
+    /// The token counts as a flat dict; the `*_details` counts appear as `cached_tokens`
+    /// and `reasoning_tokens`. Counts the provider didn't report are `None`.
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        dict.set_item("prompt_tokens", self.prompt_tokens)?;
+        dict.set_item("completion_tokens", self.completion_tokens)?;
+        dict.set_item("total_tokens", self.total())?;
+        dict.set_item("cached_tokens", self.prompt_tokens_details.as_ref().and_then(|d| d.cached_tokens))?;
+        dict.set_item("reasoning_tokens", self.completion_tokens_details.as_ref().and_then(|d| d.reasoning_tokens))?;
+        Ok(dict)
+    }
+}
+
+#[derive(Deserialize)]
 struct ChatCompletionResponse {
+    id: Option<String>,
+    model: Option<String>,
+    created: Option<u64>,
+    system_fingerprint: Option<String>,
     choices: Vec<ResponseChoice>,
+    usage: Option<Usage>,
 }
 
 // --- SecureClient ---
 
-#[pyclass(name = "SecureClient")]
+/// Overall per-request timeout unless the constructor's `timeout=` says otherwise. Long
+/// enough for a slow completion, short enough that a black-holed connection doesn't hang
+/// a worker for good.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// How long establishing a connection may take, whenever there is an overall timeout.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Everything needed to reach the API. Requests take a snapshot (cheap `Arc` clones) so
+/// rotation and `close()` never tear a value out from under a request in flight.
+#[derive(Clone)]
+struct Connection {
+    base_url: Arc<SecureBytes>,
+    api_key: Arc<api_key::ApiKey>,
+    transport: Arc<Transport>,
+}
+
+/// State shared by a client and every view created from it with `with_defaults()`:
+/// one copy of the locked credentials and one connection pool.
+struct ClientCore {
+    /// `None` once the client has been closed.
+    connection: RwLock<Option<Connection>>,
+    last_rate_limits: Mutex<RateLimits>,
+    rate_limiter: RwLock<Option<Arc<RateLimiter>>>,
+    last_request_id: Mutex<Option<String>>,
+    auto_idempotency: bool,
+    last_idempotency_key: Mutex<Option<String>>,
+    max_response_bytes: usize,
+    /// Reject `max_tokens` for a reasoning model instead of sending it as `max_completion_tokens`.
+    strict_params: bool,
+    /// The model families taking `max_completion_tokens`; see `params::is_reasoning_model`.
+    reasoning_models: Vec<String>,
+    /// Which role system messages go out with; by `reasoning_models` when translated.
+    role_translation: messages::RoleTranslation,
+    /// Send `store: false` and the provider's opt-outs with every call; see `params::enforce_privacy`.
+    privacy_mode: bool,
+    /// Screen the user text of every call through the moderations endpoint first, unless
+    /// the call passes `moderate_inputs=False`.
+    moderate_inputs: bool,
+    moderation: moderation::ModerationPolicy,
+    /// The overall timeout of every call that doesn't pass its own; `None` waits forever.
+    timeout: Option<Duration>,
+    /// The time every call that doesn't pass its own `deadline=` gets for all its attempts;
+    /// see `retry::Deadline`.
+    deadline: Option<Duration>,
+    /// Put the server's message into errors rejecting the request as well; see `errors::api_error`.
+    include_error_body: bool,
+    max_error_text: usize,
+    local_address: Option<IpAddr>,
+    default_headers: Vec<headers::SecureHeader>,
+    allow_insecure_http: bool,
+    path_style: PathStyle,
+    provider: Option<&'static providers::Provider>,
+    retry: RetryPolicy,
+    audit_hook: RwLock<Option<Py<PyAny>>>,
+    /// Called as `(old, new)` when a model's `system_fingerprint` changes.
+    on_fingerprint_change: Option<Py<PyAny>>,
+    /// Last `system_fingerprint` seen per requested model.
+    fingerprints: Mutex<HashMap<String, String>>,
+    stats: ClientStats,
+    metrics: ClientMetrics,
+    /// Ask for gzip-compressed responses instead of `identity`.
+    compression: bool,
+    /// Keep the API key sealed between requests; see `api_key::SealedKey`.
+    encrypt_at_rest: bool,
+    /// Set by `wipe()`: the client is closed, and says so with the wiped error.
+    wiped: AtomicBool,
+    cache: Mutex<cache::ResponseCache>,
+    audit_log: Mutex<Option<audit::AuditLog>>,
+    /// Adds authentication headers computed over each request; see `signing`.
+    signer: Option<Box<dyn signing::RequestSigner>>,
+    /// Supplies the bearer token in place of a fixed API key.
+    token_provider: Option<token::TokenProvider>,
+    /// Sent as the first message of every call whose messages don't start with a system
+    /// message of their own, unless it passes `include_default_system=False`.
+    system_prompt: Option<SecureBytes>,
+}
+
+impl ClientCore {
+    fn connection(&self) -> PyResult<Connection> {
+        let connection = self.connection.read().unwrap().clone().ok_or_else(|| self.closed_error())?;
+        // Fails in a forked child that wiped the credentials it inherited.
+        connection.base_url.expose()?;
+        Ok(connection)
+    }
+
+    fn ensure_open(&self) -> PyResult<()> {
+        self.connection.read().unwrap().as_ref().map(|_| ()).ok_or_else(|| self.closed_error())
+    }
+
+    /// Swaps in new credentials, a new base URL or a new transport for later requests.
+    /// Cached responses came from the old ones, so they are dropped.
+    fn update_connection(&self, update: impl FnOnce(&mut Connection)) -> PyResult<()> {
+        let mut connection = self.connection.write().unwrap();
+        update(connection.as_mut().ok_or_else(|| self.closed_error())?);
+        drop(connection);
+        self.cache.lock().unwrap().clear();
+        Ok(())
+    }
+
+    fn api(&self) -> providers::Api {
+        self.provider.map_or(providers::Api::OpenAi, |provider| provider.api)
+    }
+
+    fn closed_error(&self) -> PyErr {
+        if self.wiped.load(Ordering::Relaxed) {
+            return secret_wiped();
+        }
+        PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("client is closed")
+    }
+}
+
+/// A client for an OpenAI-compatible chat API that keeps the API key and every message in
+/// locked memory. One client may be shared by any number of threads: it is immutable from
+/// Python, what changes at runtime (rotated credentials, rate limits, stats, the cache)
+/// sits behind locks in the shared core, and requests release the GIL while on the wire,
+/// so concurrent calls proceed in parallel over one connection pool.
+#[pyclass(name = "SecureClient", frozen)]
 struct SecureClient {
-    base_url: SecureBytes,
-    api_key: SecureBytes,
-    http_client: Client,
+    core: Arc<ClientCore>,
+    default_model: Option<String>,
+    defaults: Map<String, Value>,
 }
 
 #[pymethods]
 impl SecureClient {
     #[new]
-    fn new(base_url: &[u8], api_key: &[u8]) -> PyResult<Self> {
+    #[pyo3(signature = (
+        base_url,
+        api_key=None,
+        *,
+        auto_idempotency=false,
+        max_response_bytes=DEFAULT_MAX_RESPONSE_BYTES,
+        follow_redirects=0,
+        dns_overrides=None,
+        ip_version="auto",
+        local_address=None,
+        uds_host="localhost",
+        default_model=None,
+        defaults=None,
+        strict_params=false,
+        reasoning_models=None,
+        system_role_translation=None,
+        privacy_mode=false,
+        moderate_inputs=false,
+        moderation_model=moderation::DEFAULT_MODERATION_MODEL,
+        moderation_thresholds=None,
+        default_headers=None,
+        allow_insecure_http=false,
+        path_style="default",
+        api_version=None,
+        provider=None,
+        anthropic=false,
+        max_retries=0,
+        max_retry_wait=DEFAULT_MAX_RETRY_WAIT.as_secs_f64(),
+        timeout=Some(DEFAULT_TIMEOUT.as_secs_f64()),
+        deadline=None,
+        include_error_body=false,
+        max_error_text=redact::MAX_ERROR_TEXT,
+        on_fingerprint_change=None,
+        compression=false,
+        http2=Http2::Off,
+        encrypt_at_rest=false,
+        signer=None,
+        token_provider=None,
+        system_prompt=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        py: Python<'_>,
+        base_url: &Bound<'_, PyAny>,
+        api_key: Option<&Bound<'_, PyAny>>,
+        auto_idempotency: bool,
+        max_response_bytes: usize,
+        follow_redirects: usize,
+        dns_overrides: Option<HashMap<String, String>>,
+        ip_version: &str,
+        local_address: Option<&str>,
+        uds_host: &str,
+        default_model: Option<String>,
+        defaults: Option<&Bound<'_, PyDict>>,
+        strict_params: bool,
+        reasoning_models: Option<Vec<String>>,
+        system_role_translation: Option<&str>,
+        privacy_mode: bool,
+        moderate_inputs: bool,
+        moderation_model: &str,
+        moderation_thresholds: Option<HashMap<String, f64>>,
+        default_headers: Option<&Bound<'_, PyDict>>,
+        allow_insecure_http: bool,
+        path_style: &str,
+        api_version: Option<&str>,
+        provider: Option<&str>,
+        anthropic: bool,
+        max_retries: u32,
+        max_retry_wait: f64,
+        timeout: Option<f64>,
+        deadline: Option<f64>,
+        include_error_body: bool,
+        max_error_text: usize,
+        on_fingerprint_change: Option<Bound<'_, PyAny>>,
+        compression: bool,
+        http2: Http2,
+        encrypt_at_rest: bool,
+        signer: Option<Bound<'_, PyAny>>,
+        token_provider: Option<Bound<'_, PyAny>>,
+        system_prompt: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Self> {
+        let base_url = normalize_base_url(py, SecureBytes::from_py(base_url, "base_url")?, allow_insecure_http)?;
+        let api_key = match (api_key, &token_provider) {
+            (Some(_), Some(_)) => {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("pass either api_key or token_provider, not both"))
+            }
+            (Some(api_key), None) => api_key::header_safe(SecureBytes::from_py(api_key, "api_key")?, "api_key")?,
+            // Never sent: every request fetches its token from the provider.
+            (None, Some(_)) => SecureBytes::try_new(b"")?,
+            (None, None) => return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("api_key or token_provider is required")),
+        };
+        let token_provider = token_provider.map(|provider| token::TokenProvider::new(provider, encrypt_at_rest)).transpose()?;
+        let reasoning_models = params::reasoning_models(reasoning_models)?;
+        let role_translation = messages::RoleTranslation::parse(system_role_translation)?;
+        let provider = match (provider, anthropic) {
+            (Some(provider), true) if provider != "anthropic" => {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "anthropic=True cannot be combined with provider '{}'",
+                    provider
+                )))
+            }
+            (_, true) => Some("anthropic"),
+            (provider, false) => provider,
+        };
+        let provider = provider.map(providers::lookup).transpose()?;
+        let path_style = match provider {
+            Some(provider) => PathStyle::for_provider(provider, path_style, api_version)?,
+            None => PathStyle::parse(path_style, api_version)?,
+        };
+        let moderation = moderation::ModerationPolicy::new(moderation_model.to_string(), moderation_thresholds)?;
+        if moderate_inputs && path_style.moderations().is_none() {
+            return Err(moderation::no_endpoint(provider));
+        }
+        let retry = RetryPolicy::new(max_retries, max_retry_wait).map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)?;
+        if on_fingerprint_change.as_ref().is_some_and(|callback| !callback.is_callable()) {
+            return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>("on_fingerprint_change must be callable or None"));
+        }
+        if let Some(provider) = provider.filter(|provider| on_fingerprint_change.is_some() && !provider.reports_fingerprint) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "provider '{}' does not report system_fingerprint, so on_fingerprint_change would never be called",
+                provider.name
+            )));
+        }
+        let signer = signer.as_ref().map(signing::from_py).transpose()?;
+        let system_prompt = system_prompt.map(|prompt| SecureBytes::from_py(prompt, "system_prompt")).transpose()?;
+        let defaults = params::from_kwargs(defaults)?;
+        let mut preset_headers = headers::preset_headers(provider.map_or(&[], |provider| provider.headers))?;
+        preset_headers.extend(headers::parse_default_headers(default_headers)?);
+        let default_headers = preset_headers;
+        if max_response_bytes == 0 {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("max_response_bytes must be positive"));
+        }
+        // `timeout=None` waits forever, for connecting too, as the client once always did.
+        let timeout = call::parse_timeout(timeout)?;
+        let deadline = call::parse_deadline(deadline)?;
+        let mut builder = Client::builder().redirect(redirect_policy(follow_redirects));
+        if let Some(timeout) = timeout {
+            builder = builder.timeout(timeout).connect_timeout(CONNECT_TIMEOUT);
+        }
+        for (host, address) in dns_overrides.unwrap_or_default() {
+            let address = dns::parse_dns_override(&host, &address)?;
+            builder = builder.resolve(&host, address);
+        }
+        let ip_version = IpVersion::parse(ip_version)?;
+        if ip_version != IpVersion::Auto {
+            builder = builder.dns_resolver(Arc::new(FamilyResolver::new(ip_version)));
+        }
+        let local_address = local_address
+            .map(|address| {
+                address.parse::<IpAddr>().map_err(|_| {
+                    PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid local_address '{}'", address))
+                })
+            })
+            .transpose()?;
+        if let Some(address) = local_address {
+            ip_version.check_local_address(&address)?;
+            builder = builder.local_address(address);
+        }
+        let transport = if transport::is_unix_socket_url(base_url.bytes()) {
+            if http2 != Http2::Off {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("http2 is not supported for unix socket base URLs"));
+            }
+            unix_transport(base_url.bytes(), uds_host)?
+        } else {
+            Transport::Http(http2.configure(builder).build().map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("Failed to build HTTP client: {}", e))
+            })?)
+        };
+        let core = ClientCore {
+            connection: RwLock::new(Some(Connection {
+                base_url: Arc::new(base_url),
+                api_key: Arc::new(api_key::ApiKey::new(api_key, encrypt_at_rest)?),
+                transport: Arc::new(transport),
+            })),
+            last_rate_limits: Mutex::new(RateLimits::default()),
+            rate_limiter: RwLock::new(None),
+            last_request_id: Mutex::new(None),
+            auto_idempotency,
+            last_idempotency_key: Mutex::new(None),
+            max_response_bytes,
+            strict_params,
+            reasoning_models,
+            role_translation,
+            privacy_mode,
+            moderate_inputs,
+            moderation,
+            timeout,
+            deadline,
+            include_error_body,
+            max_error_text,
+            local_address,
+            default_headers,
+            allow_insecure_http,
+            path_style,
+            provider,
+            retry,
+            audit_hook: RwLock::new(None),
+            on_fingerprint_change: on_fingerprint_change.map(Bound::unbind),
+            fingerprints: Mutex::new(HashMap::new()),
+            stats: ClientStats::default(),
+            metrics: ClientMetrics::default(),
+            compression,
+            encrypt_at_rest,
+            wiped: AtomicBool::new(false),
+            cache: Mutex::new(cache::ResponseCache::new()),
+            audit_log: Mutex::new(None),
+            signer,
+            token_provider,
+            system_prompt,
+        };
+        Ok(Self { core: Arc::new(core), default_model, defaults })
+    }
+
+    /// Builds a client whose API key is combined from share files written out from
+    /// `SecureBytes.split`, so no single file holds the key. The files are read, combined
+    /// into locked memory and wiped; other keyword arguments go to the constructor.
+    #[classmethod]
+    #[pyo3(signature = (base_url, share_paths, **kwargs))]
+    fn from_shares<'py>(
+        cls: &Bound<'py, PyType>,
+        base_url: &Bound<'py, PyAny>,
+        share_paths: Vec<PathBuf>,
+        kwargs: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        shares::build_client(cls, base_url, share_paths, kwargs)
+    }
+
+    /// Builds a client whose API key is read from the OS keyring with
+    /// `SecureBytes.from_keyring(service, account)`; other keyword arguments go to the
+    /// constructor.
+    #[classmethod]
+    #[pyo3(signature = (base_url, service, account, **kwargs))]
+    fn from_keyring<'py>(
+        cls: &Bound<'py, PyType>,
+        base_url: &Bound<'py, PyAny>,
+        service: &str,
+        account: &str,
+        kwargs: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        keyring::build_client(cls, base_url, service, account, kwargs)
+    }
+
+    /// Builds a client that sends nothing over the network: every request goes to
+    /// `handler`, for testing code built on the client offline. `handler` is called with a
+    /// dict of the request's `method`, `path`, `headers` (without the API key or any other
+    /// credential) and `body`, the parsed JSON with every string of message content replaced
+    /// by `"blake2b:<hex>"`, the hex BLAKE2b-256 (32-byte) hash of its UTF-8. It returns
+    /// `(status, headers, body)`, the body as bytes or an iterable of bytes chunks such as
+    /// server-sent events, which are pulled as the client reads the stream. Serialization,
+    /// retries, redaction and parsing all run as against a real server; an exception from
+    /// `handler` fails the request like a connection error (a `TimeoutError` like a
+    /// timeout). `base_url` and `api_key` default to placeholders, and keyword arguments go
+    /// to the constructor.
+    #[classmethod]
+    #[pyo3(signature = (handler, **kwargs))]
+    fn with_mock_transport<'py>(
+        cls: &Bound<'py, PyType>,
+        handler: &Bound<'py, PyAny>,
+        kwargs: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let client = mock::build_client(cls, handler, kwargs)?;
+        mock::install(&client.downcast::<SecureClient>()?.get().core, handler)?;
+        Ok(client)
+    }
+
+    /// Wipes the API key and base URL and drops the connection pool. The client (and
+    /// every `with_defaults()` view of it) is unusable afterwards; closing twice is a no-op.
+    /// A request still in flight on another thread keeps its snapshot until it finishes,
+    /// after which those buffers are wiped too. Cached responses are dropped as well, and
+    /// the audit log, if any, is closed.
+    fn close(&self) {
+        self.core.connection.write().unwrap().take();
+        self.core.cache.lock().unwrap().clear();
+        self.core.audit_log.lock().unwrap().take();
+    }
+
+    #[getter]
+    fn closed(&self) -> bool {
+        self.core.connection.read().unwrap().is_none()
+    }
+
+    /// Closes the client like `close()`, after which every use raises
+    /// `ValueError("secret has been wiped")` instead of the closed error. The API key and
+    /// base URL are wiped at once unless a request on another thread still holds them, in
+    /// which case they are wiped when it finishes. Wiping again does nothing.
+    fn wipe(&self) {
+        self.core.wiped.store(true, Ordering::Relaxed);
+        self.close();
+    }
+
+    #[getter]
+    fn wiped(&self) -> bool {
+        self.core.wiped.load(Ordering::Relaxed)
+    }
+
+    /// Whether the client was built with `privacy_mode=True`. It is fixed for the life of the
+    /// client: views from `with_defaults()` share it and no call can turn it off.
+    #[getter]
+    fn privacy_mode(&self) -> bool {
+        self.core.privacy_mode
+    }
+
+    /// Whether calls are screened by the moderations endpoint unless they pass
+    /// `moderate_inputs=False`.
+    #[getter]
+    fn moderate_inputs(&self) -> bool {
+        self.core.moderate_inputs
+    }
+
+    /// Shows where the client connects, without the path or any credentials in the base
+    /// URL, and a fingerprint of its API key.
+    fn __repr__(&self) -> String {
+        let Some(connection) = self.core.connection.read().unwrap().clone() else {
+            return "SecureClient(closed)".to_string();
+        };
+        let base_url = connection.base_url.expose().map_or_else(|_| "****".to_string(), masked_url);
+        let key = if self.core.token_provider.is_some() {
+            "token_provider".to_string()
+        } else {
+            connection.api_key.fingerprint().unwrap_or_else(|_| "****".to_string())
+        };
+        format!("SecureClient(base_url='{}', key={})", base_url, key)
+    }
+
+    /// Pickling would copy the key out of locked memory, so it is refused, as are copies.
+    fn __reduce__(&self) -> PyResult<()> {
+        Err(not_transferable("SecureClient", "pickled"))
+    }
+
+    fn __copy__(&self) -> PyResult<()> {
+        Err(not_transferable("SecureClient", "copied"))
+    }
+
+    fn __deepcopy__(&self, _memo: &Bound<'_, PyAny>) -> PyResult<()> {
+        Err(not_transferable("SecureClient", "copied"))
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyResult<PyRef<'_, Self>> {
+        slf.core.ensure_open()?;
+        Ok(slf)
+    }
+
+    #[pyo3(signature = (_exc_type=None, _exc_value=None, _traceback=None))]
+    fn __exit__(
+        &self,
+        _exc_type: Option<&Bound<'_, PyAny>>,
+        _exc_value: Option<&Bound<'_, PyAny>>,
+        _traceback: Option<&Bound<'_, PyAny>>,
+    ) -> bool {
+        self.close();
+        false
+    }
+
+    /// Replaces the API key. Requests already in flight finish with the old key, whose
+    /// locked buffer is wiped as soon as the last of them completes; no request ever sees
+    /// a partially written key.
+    /// With `encrypt_at_rest` the new key is sealed just like the first.
+    fn set_api_key(&self, new_key: &Bound<'_, PyAny>) -> PyResult<()> {
+        if self.core.token_provider.is_some() {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "set_api_key cannot be used on a client with a token_provider",
+            ));
+        }
+        let new_key = api_key::header_safe(SecureBytes::from_py(new_key, "new_key")?, "new_key")?;
+        let new_key = Arc::new(api_key::ApiKey::new(new_key, self.core.encrypt_at_rest)?);
+        self.core.update_connection(|connection| connection.api_key = new_key)
+    }
+
+    /// Replaces the base URL with the same guarantees as `set_api_key`.
+    /// Switching between a `unix://` socket and a TCP URL requires a new client.
+    fn set_base_url(&self, new_url: &Bound<'_, PyAny>) -> PyResult<()> {
+        let py = new_url.py();
+        let new_url = normalize_base_url(py, SecureBytes::from_py(new_url, "new_url")?, self.core.allow_insecure_http)?;
+        let is_unix = self.core.connection()?.transport.is_unix();
+        if transport::is_unix_socket_url(new_url.bytes()) != is_unix {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "set_base_url cannot switch between unix socket and TCP base URLs; create a new client",
+            ));
+        }
+        self.core.update_connection(|connection| connection.base_url = Arc::new(new_url))
+    }
+
+    /// Returns a view of this client with different defaults. The view shares the
+    /// connection pool, credentials and rate limiter; nothing secret is copied.
+    /// `default_model` may be overridden too; `None` removes an inherited default.
+    #[pyo3(signature = (**overrides))]
+    fn with_defaults(&self, overrides: Option<&Bound<'_, PyDict>>) -> PyResult<Self> {
+        self.core.ensure_open()?;
+        let mut default_model = self.default_model.clone();
+        let mut overrides = params::from_kwargs(overrides)?;
+        if let Some(model) = overrides.remove("default_model") {
+            default_model = match model {
+                Value::String(model) => Some(model),
+                Value::Null => None,
+                _ => return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>("default_model must be a str or None")),
+            };
+        }
         Ok(Self {
-            base_url: SecureBytes::new(base_url),
-            api_key: SecureBytes::new(api_key),
-            http_client: Client::new(),
+            core: Arc::clone(&self.core),
+            default_model,
+            defaults: params::merge(&self.defaults, overrides),
         })
     }
 
-    #[pyo3(signature = (messages, model))]
-    fn chat_completion(&self, messages: Vec<PyRef<SecureMessage>>, model: String) -> PyResult<SecureBytes> {
-        let messages_rs: Vec<SecureMessage> = messages.iter().map(|m| (**m).clone()).collect();
-        let request_body = ChatCompletionRequest {
-            messages: &messages_rs,
-            model: &model,
+    #[getter]
+    fn default_model(&self) -> Option<String> {
+        self.default_model.clone()
+    }
+
+    #[getter]
+    fn defaults<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        params::to_py(py, &Value::Object(self.defaults.clone()))
+    }
+
+    /// Returns the `Idempotency-Key` sent with the most recent call, if any, so callers can
+    /// persist it and reuse it for their own retries.
+    fn last_idempotency_key(&self) -> PyResult<Option<String>> {
+        self.core.ensure_open()?;
+        Ok(self.core.last_idempotency_key.lock().unwrap().clone())
+    }
+
+    /// Returns the request id of the most recent call: the provider's `x-request-id` (or
+    /// Cloudflare's `cf-ray`) when it sent one, otherwise the `X-Client-Request-Id` generated
+    /// for the request.
+    fn last_request_id(&self) -> PyResult<Option<String>> {
+        self.core.ensure_open()?;
+        Ok(self.core.last_request_id.lock().unwrap().clone())
+    }
+
+    /// Installs a callable invoked after every request, successful or not, with a dict of
+    /// metadata only: `timestamp`, `endpoint`, `model`, `status`, `latency` (seconds),
+    /// `request_id`, token counts and the `error` type name. Message content, keys and
+    /// response bodies never reach it. Exceptions raised by the hook are reported through
+    /// `sys.unraisablehook` and don't affect the call. Pass `None` to remove the hook.
+    fn set_audit_hook(&self, hook: Option<Bound<'_, PyAny>>) -> PyResult<()> {
+        self.core.ensure_open()?;
+        if let Some(hook) = &hook {
+            if !hook.is_callable() {
+                return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>("audit hook must be callable or None"));
+            }
+        }
+        *self.core.audit_hook.write().unwrap() = hook.map(Bound::unbind);
+        Ok(())
+    }
+
+    /// Appends one JSON line per request, successful or not, to the file at `path`: the
+    /// `timestamp`, `endpoint`, `model`, `status`, `request_id`, token counts and `error`
+    /// type, plus `request_hash` and `response_hash`, BLAKE2b hashes of the serialized
+    /// request and the raw response body keyed with `hmac_key`. Each line also holds the
+    /// MAC of the one before it as `prev` and its own `mac` (`crypto_auth` with the
+    /// 32-byte `hmac_key`), so `verify_audit_log()` finds any edited, reordered or removed
+    /// entry. An existing file is verified and continued, and refused if it doesn't verify.
+    /// A failed write is reported through `sys.unraisablehook` and doesn't affect the call.
+    fn enable_audit_log(&self, path: PathBuf, hmac_key: PyRef<'_, SecureBytes>) -> PyResult<()> {
+        self.core.ensure_open()?;
+        let log = audit::AuditLog::open(&path, &hmac_key)?;
+        *self.core.audit_log.lock().unwrap() = Some(log);
+        Ok(())
+    }
+
+    /// Records every request from now on, with its response, into a new cassette at `path`
+    /// for `replay_from()`, replacing any file there. Bodies are sealed with
+    /// `crypto_secretbox` under the 32-byte `key`; methods, paths, statuses, response headers
+    /// (without credentials) and a `request_hash` keyed with `key` are stored in the clear.
+    /// A response is written once its body has been read to the end. Requests still go to
+    /// the server as before, and calling this again starts a new cassette.
+    fn record_to(&self, path: PathBuf, key: PyRef<'_, SecureBytes>) -> PyResult<()> {
+        let transport = self.core.connection()?.transport;
+        let inner = match &*transport {
+            Transport::Record(recorder) => recorder.inner(),
+            _ => transport,
         };
+        let recorder = cassette::Recorder::create(&path, &key, inner)?;
+        self.core.update_connection(|connection| connection.transport = Arc::new(Transport::Record(recorder)))
+    }
 
-        let base_url_str = self.base_url.as_str()?;
-        let api_key_str = self.api_key.as_str()?;
-        let endpoint = format!("{}{}", base_url_str, "/openai/v1/chat/completions");
-
-        let response = self.http_client.post(&endpoint).bearer_auth(api_key_str).json(&request_body).send();
-
-        match response {
-            Ok(res) => {
-                if res.status().is_success() {
-                    let body: ChatCompletionResponse = res.json().map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Failed to parse JSON response: {}", e)))?;
-                    if let Some(choice) = body.choices.get(0) {
-                        let content = choice.message.content.as_deref().unwrap_or("");
-                        Ok(SecureBytes::new(content.as_bytes()))
-                    } else {
-                        Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("API returned no choices."))
-                    }
-                } else {
-                    let status = res.status();
-                    let error_body = res.text().unwrap_or_else(|_| "Could not read error body".to_string());
-                    Err(PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("API request failed with status {}: {}", status, error_body)))
+    /// Serves every request from now on from the cassette `record_to()` wrote at `path`,
+    /// without touching the network. A request is answered by the first interaction not yet
+    /// played whose `request_hash` matches, a BLAKE2b over its method, path and canonical
+    /// JSON body, so parameter order and per-call headers don't matter. A request without
+    /// one raises `ConnectionError` naming its hash and those the cassette still expects.
+    /// A wrong `key` or a damaged file raises `ValueError` here.
+    fn replay_from(&self, path: PathBuf, key: PyRef<'_, SecureBytes>) -> PyResult<()> {
+        self.core.ensure_open()?;
+        let replayer = cassette::Replayer::open(&path, &key)?;
+        self.core.update_connection(|connection| connection.transport = Arc::new(Transport::Replay(replayer)))
+    }
+
+    /// Configures the client-side rate limiter shared by all threads using this client.
+    /// `rpm`/`tpm` are requests and tokens per minute; `max_wait` (seconds) bounds how long
+    /// a request may block before `RateLimitError` is raised. Each retry counts against `rpm`
+    /// as well, while a call's tokens are charged once. Calling it without limits disables it.
+    #[pyo3(signature = (rpm=None, tpm=None, max_wait=None))]
+    fn rate_limit(&self, rpm: Option<u32>, tpm: Option<u32>, max_wait: Option<f64>) -> PyResult<()> {
+        self.core.ensure_open()?;
+        if rpm == Some(0) || tpm == Some(0) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("rpm and tpm must be positive"));
+        }
+        let max_wait = match max_wait {
+            Some(seconds) if !(seconds.is_finite() && seconds >= 0.0) => {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("max_wait must be a non-negative number of seconds"));
+            }
+            Some(seconds) => Some(Duration::from_secs_f64(seconds)),
+            None => None,
+        };
+        let limiter = (rpm.is_some() || tpm.is_some()).then(|| Arc::new(RateLimiter::new(rpm, tpm, max_wait)));
+        *self.core.rate_limiter.write().unwrap() = limiter;
+        Ok(())
+    }
+
+    /// Opens a pooled connection to the base URL ahead of the first real request, so the
+    /// TCP and TLS handshakes don't land on it. Sends an unauthenticated `HEAD` whose status
+    /// is ignored; only failing to reach the server raises. Over a unix socket, where every
+    /// request opens its own connection, this only checks that the socket answers.
+    fn warm_up(&self, py: Python<'_>) -> PyResult<()> {
+        let (core, connection) = (Arc::clone(&self.core), self.core.connection()?);
+        call::wait_interruptible(py, call::warm_up(core, connection))?
+    }
+
+    /// Returns counters covering this client and its `with_defaults()` views: `requests`
+    /// (HTTP requests sent, retries and warm-ups included), `bytes_sent` and `bytes_received`
+    /// (request and response bodies as transferred, so compressed when gzip is used),
+    /// `network_time` (seconds spent sending requests and reading responses) and
+    /// `token_refreshes` (tokens replaced after a 401). Nothing else is recorded, and the
+    /// counters stay readable after `close()`.
+    fn stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        self.core.stats.to_dict(py)
+    }
+
+    /// Returns a snapshot of the client's health counters, shared with its `with_defaults()`
+    /// views and counted per HTTP attempt of every API call, streaming and failed ones
+    /// included: `requests` by status class (`"1xx"` to `"5xx"`, and `"failed"` for attempts
+    /// that got no response), `retries`, `timeouts`, the `prompt_tokens` and
+    /// `completion_tokens` responses reported, and a `latency` histogram whose `buckets` map
+    /// upper bounds in seconds to the number of attempts at or below them (cumulative, ending
+    /// at `inf`), with their `count` and `sum`. Cache hits and `warm_up()` send no API call and
+    /// are not counted.
+    fn metrics<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        self.core.metrics.to_dict(py)
+    }
+
+    /// Zeroes the counters of `metrics()`. `stats()` is left alone.
+    fn reset_metrics(&self) {
+        self.core.metrics.reset();
+    }
+
+    /// Caches successful chat completions, keyed by a keyed BLAKE2b hash of the base URL,
+    /// request path and serialized body, so an identical request (same messages, model and
+    /// parameters) within `ttl_seconds` is answered without going to the network. Responses
+    /// are stored encrypted with `crypto_secretbox` under a random key held in locked
+    /// memory, and a hit decrypts straight into locked memory; the least recently used entry
+    /// is evicted beyond `max_entries`. Calling it again changes the limits and keeps
+    /// the entries. A hit returns the original response, request id included, and sends
+    /// nothing, so it is not counted in `stats()` and doesn't wait for the rate limiter.
+    /// `set_api_key` and `set_base_url` drop every entry.
+    fn enable_cache(&self, max_entries: usize, ttl_seconds: f64) -> PyResult<()> {
+        self.core.ensure_open()?;
+        if max_entries == 0 {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("max_entries must be at least 1"));
+        }
+        if !(ttl_seconds.is_finite() && ttl_seconds > 0.0) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("ttl_seconds must be a positive number of seconds"));
+        }
+        self.core.cache.lock().unwrap().enable(max_entries, Duration::from_secs_f64(ttl_seconds));
+        Ok(())
+    }
+
+    /// Returns `hits`, `misses` and `evictions` (entries dropped for space or because they
+    /// expired) since the client was created, and the current number of `entries`.
+    fn cache_stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        self.core.cache.lock().unwrap().to_dict(py)
+    }
+
+    /// Drops every cached response. The cache stays enabled.
+    fn clear_cache(&self) {
+        self.core.cache.lock().unwrap().clear();
+    }
+
+    /// Returns the rate limit headers of the most recent response as a dict.
+    /// Headers the provider did not send (or sent malformed) are absent.
+    fn last_rate_limits<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        self.core.ensure_open()?;
+        self.core.last_rate_limits.lock().unwrap().to_dict(py)
+    }
+
+    /// Sends a chat completion and returns the content of the first choice. When the model
+    /// declined, `RefusalError` is raised with the refusal text as its `refusal` attribute;
+    /// when it answered with tool calls, `ToolCallNotSupportedError` with them as its
+    /// `tool_calls` attribute (`chat_completion_full` returns them).
+    /// `model` and any keyword parameters (`temperature`, `max_tokens`, ...) fall back
+    /// to the client's `default_model` and `defaults` when omitted.
+    /// The completion limit is `max_tokens` or `max_completion_tokens`, not both. Reasoning
+    /// models (names starting with one of the client's `reasoning_models`, by default `o1`,
+    /// `o3` and `o4`) reject `max_tokens`, so it is sent to them as `max_completion_tokens`,
+    /// or raises `ValueError` on a client built with `strict_params=True`.
+    /// `messages` may mix `SecureMessage`s with dicts in the official SDK's format
+    /// (`role`, `content`, `name`, `tool_call_id`); dicts are copied into locked memory and
+    /// wiped once the call is done.
+    /// `extra_headers` values may be str, bytes or SecureBytes and override `default_headers`.
+    /// `timeout` (seconds) replaces the client's timeout for this call only.
+    /// `deadline=` (seconds) replaces the client's `deadline`, which bounds all attempts of
+    /// the call together: each gets what is left as its timeout, waits between them and for
+    /// the client-side rate limiter are cut short, a `Retry-After` longer than what is left
+    /// ends the retries, and running out raises `DeadlineExceededError`.
+    /// The client's `system_prompt` goes first unless `messages` starts with a system
+    /// message or `include_default_system=False` is passed.
+    /// With `stream=True` the response is requested as server-sent events and assembled
+    /// into the same result, tool calls included.
+    /// With `strict=True`, a response cut off by `max_tokens` raises `TruncatedResponseError`
+    /// and one blocked by a content filter raises `ContentFilterError`, instead of returning
+    /// partial content.
+    #[pyo3(signature = (messages, model=None, *, idempotency_key=None, extra_headers=None, timeout=None, strict=false, stream=false, **params))]
+    #[allow(clippy::too_many_arguments)]
+    fn chat_completion(
+        &self,
+        py: Python<'_>,
+        messages: Messages<'_>,
+        model: Option<String>,
+        idempotency_key: Option<String>,
+        extra_headers: Option<&Bound<'_, PyDict>>,
+        timeout: Option<f64>,
+        strict: bool,
+        stream: bool,
+        params: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Py<SecureBytes>> {
+        self.chat_completion_full(py, messages, model, idempotency_key, extra_headers, timeout, strict, stream, params)?
+            .into_content(py)
+    }
+
+    /// Like `chat_completion`, but returns a `SecureResponse` carrying the response
+    /// metadata (`finish_reason`, `model`, `id`, `usage`, ...) alongside the content.
+    #[pyo3(signature = (messages, model=None, *, idempotency_key=None, extra_headers=None, timeout=None, strict=false, stream=false, **params))]
+    #[allow(clippy::too_many_arguments)]
+    fn chat_completion_full(
+        &self,
+        py: Python<'_>,
+        messages: Messages<'_>,
+        model: Option<String>,
+        idempotency_key: Option<String>,
+        extra_headers: Option<&Bound<'_, PyDict>>,
+        timeout: Option<f64>,
+        strict: bool,
+        stream: bool,
+        params: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<SecureResponse> {
+        let chat = call::ChatRequest::new(self, messages, model, idempotency_key, extra_headers, timeout, strict, stream, params);
+        let (call, request) = chat.prepare(py)?;
+        // The network round trip and body read run without the GIL so other Python
+        // threads keep running; nothing borrowed from Python crosses this point.
+        let run = |call: call::ChatCall, request| {
+            let (call, outcome) = call::wait_interruptible(py, async move {
+                let outcome = call.send(request).await;
+                (call, outcome)
+            })?;
+            call.finish(py, outcome)
+        };
+        let token = call.api_key();
+        let result = run(call, request);
+        match chat.refreshed(py, &token, result.as_ref().err()) {
+            Some(retry) => {
+                let (call, request) = retry?;
+                run(call, request)
+            }
+            None => result,
+        }
+    }
+
+    /// Like `chat_completion_full`, but returns the HTTP envelope as a `SecureRawResponse`:
+    /// the `status_code`, the `headers` and the `body` in `SecureBytes`, with `parse()` for
+    /// the `SecureResponse`. An error status is not raised here but by `parse()`; only a
+    /// call that gets no complete response raises.
+    #[pyo3(signature = (messages, model=None, *, idempotency_key=None, extra_headers=None, timeout=None, strict=false, stream=false, **params))]
+    #[allow(clippy::too_many_arguments)]
+    fn chat_completion_raw(
+        &self,
+        py: Python<'_>,
+        messages: Messages<'_>,
+        model: Option<String>,
+        idempotency_key: Option<String>,
+        extra_headers: Option<&Bound<'_, PyDict>>,
+        timeout: Option<f64>,
+        strict: bool,
+        stream: bool,
+        params: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<SecureRawResponse> {
+        let chat = call::ChatRequest::new(self, messages, model, idempotency_key, extra_headers, timeout, strict, stream, params);
+        let (call, request) = chat.prepare(py)?;
+        let run = |call: call::ChatCall, request| {
+            let (call, outcome) = call::wait_interruptible(py, async move {
+                let outcome = call.send(request).await;
+                (call, outcome)
+            })?;
+            call.finish_raw(py, outcome)
+        };
+        let token = call.api_key();
+        let raw = run(call, request)?;
+        match chat.refreshed(py, &token, raw.error()) {
+            Some(retry) => {
+                let (call, request) = retry?;
+                run(call, request)
+            }
+            None => Ok(raw),
+        }
+    }
+
+    /// Embeds `input`, one str, bytes or `SecureBytes` or a list of them, with `model`.
+    /// Inputs beyond `batch_size` (at most 2048, what OpenAI takes per request) are split
+    /// over several requests, sent one after another; the vectors come back in input order
+    /// and the usage is summed. `dimensions` shortens the vectors on models that support
+    /// it. `encoding_format="base64"` has the server send each vector as packed float32,
+    /// decoded here; the result is the same as with `"float"`. The vectors are read into
+    /// locked memory; `secure_output=True` keeps them there, as `SecureFloatArray`s, instead
+    /// of returning lists of floats. `deadline` (seconds) replaces the client's for this
+    /// call, bounding all its requests together as in `chat_completion`. Raises like
+    /// `chat_completion`, at the first request that fails.
+    #[pyo3(signature = (input, model, *, dimensions=None, encoding_format="float", batch_size=embeddings::MAX_BATCH_SIZE, secure_output=false, extra_headers=None, timeout=None, deadline=None, **params))]
+    #[allow(clippy::too_many_arguments)]
+    fn embeddings(
+        &self,
+        py: Python<'_>,
+        input: &Bound<'_, PyAny>,
+        model: String,
+        dimensions: Option<u32>,
+        encoding_format: &str,
+        batch_size: usize,
+        secure_output: bool,
+        extra_headers: Option<&Bound<'_, PyDict>>,
+        timeout: Option<f64>,
+        deadline: Option<f64>,
+        params: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<embeddings::SecureEmbeddings> {
+        let inputs = embeddings::inputs(input)?;
+        let embeddings = call::EmbeddingsRequest::new(
+            self,
+            inputs,
+            model,
+            dimensions,
+            encoding_format,
+            batch_size,
+            extra_headers,
+            timeout,
+            deadline,
+            params,
+        )?;
+        let run = |call: call::ChatCall, request, count| {
+            let (call, outcome) = call::wait_interruptible(py, async move {
+                let outcome = call.send(request).await;
+                (call, outcome)
+            })?;
+            call.finish_embeddings(py, outcome, count)
+        };
+        let mut results = Vec::with_capacity(embeddings.batches());
+        for index in 0..embeddings.batches() {
+            let (call, request, count) = embeddings.prepare(py, index)?;
+            let token = call.api_key();
+            let result = run(call, request, count);
+            results.push(match embeddings.refreshed(py, index, &token, result.as_ref().err()) {
+                Some(retry) => {
+                    let (call, request, count) = retry?;
+                    run(call, request, count)?
                 }
+                None => result?,
+            });
+        }
+        embeddings::SecureEmbeddings::merge(py, results, secure_output)
+    }
+
+    /// Streams a chat completion and reports it through callbacks as it arrives, returning
+    /// the assembled `SecureResponse` as `chat_completion_full(stream=True)` would.
+    /// `on_token` gets each content delta as `SecureBytes` while the stream is read;
+    /// once it is complete, `on_tool_call` gets each assembled `SecureToolCall` and
+    /// `on_finish` gets `(finish_reason, usage)`. `on_error` gets the exception of a failed
+    /// call just before it is raised. Callbacks run on the calling thread, and the GIL is
+    /// released while waiting for the network between them. A callback that raises aborts
+    /// the stream, dropping the connection and wiping what was read, and its exception
+    /// propagates. Other arguments are as in `chat_completion`.
+    #[pyo3(signature = (
+        messages,
+        model=None,
+        *,
+        on_token=None,
+        on_tool_call=None,
+        on_finish=None,
+        on_error=None,
+        idempotency_key=None,
+        extra_headers=None,
+        timeout=None,
+        strict=false,
+        **params
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn stream_chat_with_events(
+        &self,
+        py: Python<'_>,
+        messages: Messages<'_>,
+        model: Option<String>,
+        on_token: Option<Bound<'_, PyAny>>,
+        on_tool_call: Option<Bound<'_, PyAny>>,
+        on_finish: Option<Bound<'_, PyAny>>,
+        on_error: Option<Bound<'_, PyAny>>,
+        idempotency_key: Option<String>,
+        extra_headers: Option<&Bound<'_, PyDict>>,
+        timeout: Option<f64>,
+        strict: bool,
+        params: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<SecureResponse> {
+        let callbacks = call::StreamCallbacks::new(on_token, on_tool_call, on_finish, on_error)?;
+        let chat = call::ChatRequest::new(self, messages, model, idempotency_key, extra_headers, timeout, strict, true, params);
+        call::stream_with_events(py, &chat, &callbacks)
+    }
+
+    /// Asks a single question and returns the content of the answer, for scripts and
+    /// notebooks. `prompt` and `system` may be str, bytes or SecureBytes; they become a
+    /// user message and, when `system` is given, a system message before it, both built in
+    /// locked memory and wiped before this returns. `model` and `params` work as in
+    /// `chat_completion`.
+    #[pyo3(signature = (prompt, model=None, system=None, **params))]
+    fn ask(
+        &self,
+        py: Python<'_>,
+        prompt: &Bound<'_, PyAny>,
+        model: Option<String>,
+        system: Option<&Bound<'_, PyAny>>,
+        params: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Py<SecureBytes>> {
+        let mut messages = Vec::new();
+        if let Some(system) = system {
+            messages.push(Bound::new(py, SecureMessage::text(b"system", system, "system")?)?);
+        }
+        messages.push(Bound::new(py, SecureMessage::text(b"user", prompt, "prompt")?)?);
+        let result = self.chat_completion(py, messages.clone().into(), model, None, None, None, false, false, params);
+        for message in &messages {
+            message.borrow_mut().wipe();
+        }
+        result
+    }
+
+    /// Sends one chat completion per message list, at most `concurrency` at a time, and
+    /// returns the results in input order. A failed item becomes its exception instance in
+    /// the list instead of failing the batch. `progress`, if given, is called with
+    /// `(done, total)` after each item. Other arguments apply to every item as in `chat_completion`;
+    /// an item's `deadline` runs from when it is sent, not while it waits for a slot.
+    #[pyo3(signature = (message_lists, model=None, *, concurrency=8, progress=None, extra_headers=None, timeout=None, strict=false, **params))]
+    #[allow(clippy::too_many_arguments)]
+    fn chat_completion_many(
+        &self,
+        py: Python<'_>,
+        message_lists: Vec<Messages<'_>>,
+        model: Option<String>,
+        concurrency: usize,
+        progress: Option<&Bound<'_, PyAny>>,
+        extra_headers: Option<&Bound<'_, PyDict>>,
+        timeout: Option<f64>,
+        strict: bool,
+        params: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Vec<PyObject>> {
+        if concurrency == 0 {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("concurrency must be at least 1"));
+        }
+        let calls = message_lists
+            .into_iter()
+            .map(|messages| {
+                let chat = call::ChatRequest::new(self, messages, model.clone(), None, extra_headers, timeout, strict, false, params);
+                let (call, request) = chat.prepare(py)?;
+                Ok((chat, call, request))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        call::run_many(py, calls, concurrency, progress)
+    }
+}
+
+/// Redirects are refused unless explicitly allowed. When allowed, they are only followed
+/// within the origin (scheme, host and port) the request was sent to: the key may travel
+/// in `x-api-key`, `api-key`, a secret default header or a signer's headers, none of which
+/// reqwest strips on a hop elsewhere.
+fn redirect_policy(max_redirects: usize) -> Policy {
+    if max_redirects == 0 {
+        return Policy::none();
+    }
+    Policy::custom(move |attempt| {
+        let origin = &attempt.previous()[0];
+        let url = attempt.url();
+        let same_origin = url.scheme() == origin.scheme()
+            && url.host_str() == origin.host_str()
+            && url.port_or_known_default() == origin.port_or_known_default();
+        if !same_origin {
+            attempt.error("refusing to follow a redirect to another origin")
+        } else if attempt.previous().len() > max_redirects {
+            attempt.error(format!("too many redirects (follow_redirects={})", max_redirects))
+        } else {
+            attempt.follow()
+        }
+    })
+}
+
+/// The error for pickling or copying a client, which holds its credentials in locked
+/// memory and its connections in a pool that only exist in this process.
+fn not_transferable(class: &str, action: &str) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!(
+        "{class} cannot be {action}: it holds its API key in locked memory and an OS connection pool, \
+         neither of which can leave this process. Create a client in each worker process instead, \
+         e.g. with {class}.from_keyring(), {class}.from_shares() or load_keyfile()"
+    ))
+}
+
+/// The scheme, host and port of a base URL, with `***` standing in for any credentials and
+/// path, for display.
+fn masked_url(base_url: &[u8]) -> String {
+    if transport::is_unix_socket_url(base_url) {
+        return format!("{}***", transport::UNIX_SCHEME);
+    }
+    let Some(url) = str::from_utf8(base_url).ok().and_then(|url| reqwest::Url::parse(url).ok()) else {
+        return "***".to_string();
+    };
+    let credentials = if url.username().is_empty() && url.password().is_none() { "" } else { "***@" };
+    let port = url.port().map(|port| format!(":{}", port)).unwrap_or_default();
+    let path = if url.path() == "/" && url.query().is_none() { "" } else { "/***" };
+    format!("{}://{}{}{}{}", url.scheme(), credentials, url.host_str().unwrap_or(""), port, path)
+}
+
+/// Strips trailing slashes from a base URL, so that endpoint paths join onto it with exactly
+/// one, then validates it.
+fn normalize_base_url(py: Python<'_>, mut base_url: SecureBytes, allow_insecure_http: bool) -> PyResult<SecureBytes> {
+    let len = base_url.expose()?.iter().rposition(|&b| b != b'/').map_or(0, |last| last + 1);
+    if len < base_url.bytes().len() {
+        base_url.resize(len)?;
+    }
+    validate_base_url(py, base_url.bytes(), allow_insecure_http)?;
+    Ok(base_url)
+}
+
+/// Only https base URLs are accepted by default, so the bearer token never crosses the
+/// network in cleartext. Plain http is allowed for loopback hosts (with a warning) or
+/// everywhere with `allow_insecure_http=True`. The URL itself is never echoed in errors.
+fn validate_base_url(py: Python<'_>, base_url: &[u8], allow_insecure_http: bool) -> PyResult<()> {
+    let base_url = str::from_utf8(base_url)
+        .map_err(|_| PyErr::new::<pyo3::exceptions::PyValueError, _>("base_url must be valid UTF-8"))?;
+    if transport::is_unix_socket_url(base_url.as_bytes()) {
+        return Ok(());
+    }
+    let url = reqwest::Url::parse(base_url)
+        .map_err(|_| PyErr::new::<pyo3::exceptions::PyValueError, _>("base_url is not a valid URL"))?;
+    if url.host_str().is_none() {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("base_url must include a host"));
+    }
+    if url.path().ends_with("/chat/completions") {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+            "base_url is a full endpoint URL: the client appends the endpoint path itself, so pass only the part \
+             before it, e.g. https://api.openai.com with path_style='openai' instead of https://api.openai.com/v1/chat/completions",
+        ));
+    }
+    match url.scheme() {
+        "https" => Ok(()),
+        "http" if allow_insecure_http => Ok(()),
+        "http" if is_loopback(&url) => PyErr::warn(
+            py,
+            &py.get_type::<pyo3::exceptions::PyUserWarning>(),
+            c"Sending the API key over plain http to a loopback address; pass allow_insecure_http=True to silence this warning",
+            1,
+        ),
+        "http" => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+            "base_url uses plain http, which would send the API key in cleartext; use https or pass allow_insecure_http=True",
+        )),
+        _ => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+            "base_url must use the https scheme (or unix:// for a local socket)",
+        )),
+    }
+}
+
+fn is_loopback(url: &reqwest::Url) -> bool {
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    host.eq_ignore_ascii_case("localhost") || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Builds the `Authorization` header without leaving an unlocked plaintext copy behind.
+fn bearer_header(key: &[u8]) -> PyResult<HeaderValue> {
+    let mut raw = Vec::with_capacity(7 + key.len());
+    raw.extend_from_slice(b"Bearer ");
+    raw.extend_from_slice(key);
+    let value = HeaderValue::from_bytes(&raw);
+    raw.zeroize();
+    let mut value = value.map_err(|_| PyErr::new::<pyo3::exceptions::PyValueError, _>("API key is not a valid header value"))?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+/// Builds the `api-key` header used by Azure, straight from the locked buffer.
+fn api_key_header(key: &[u8]) -> PyResult<HeaderValue> {
+    let mut value = HeaderValue::from_bytes(key)
+        .map_err(|_| PyErr::new::<pyo3::exceptions::PyValueError, _>("API key is not a valid header value"))?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+#[cfg(unix)]
+fn unix_transport(base_url: &[u8], uds_host: &str) -> PyResult<Transport> {
+    if !base_url[transport::UNIX_SCHEME.len()..].starts_with(b"/") {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+            "unix socket base URLs must use an absolute path, e.g. unix:///var/run/gateway.sock",
+        ));
+    }
+    let host = HeaderValue::from_str(uds_host)
+        .map_err(|_| PyErr::new::<pyo3::exceptions::PyValueError, _>("uds_host is not a valid Host header value"))?;
+    Ok(Transport::Unix(transport::unix::UnixTransport::new(host)))
+}
+
+#[cfg(not(unix))]
+fn unix_transport(_base_url: &[u8], _uds_host: &str) -> PyResult<Transport> {
+    Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("unix socket base URLs are only supported on unix platforms"))
+}
+
+/// Whether a send error was caused by binding to a local address the host doesn't have.
+#[cfg_attr(not(unix), allow(irrefutable_let_patterns))]
+fn is_address_not_available(error: &TransportError) -> bool {
+    let TransportError::Http(error) = error else {
+        return false;
+    };
+    let mut source = std::error::Error::source(error);
+    while let Some(err) = source {
+        if let Some(io) = err.downcast_ref::<std::io::Error>() {
+            if io.kind() == std::io::ErrorKind::AddrNotAvailable {
+                return true;
             }
-            Err(e) => Err(PyErr::new::<pyo3::exceptions::PyConnectionError, _>(format!("Failed to send request: {}", e))),
         }
+        source = err.source();
+    }
+    false
+}
+
+fn validate_idempotency_key(key: String) -> PyResult<String> {
+    if key.is_empty() || key.len() > 255 || !key.bytes().all(|b| b.is_ascii_graphic()) {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+            "idempotency_key must be 1-255 visible ASCII characters",
+        ));
     }
+    Ok(key)
 }
 
 // --- Python Module Definition ---
 
 #[pymodule]
 fn secure_openaiapi(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    memory::init_sodium()?;
     m.add_class::<SecureClient>()?;
     m.add_class::<SecureBytes>()?;
     m.add_class::<SecureMessage>()?;
+    m.add_class::<SecureResponse>()?;
+    m.add_class::<SecureRawResponse>()?;
+    m.add_class::<SecureToolCall>()?;
+    m.add_class::<embeddings::SecureEmbeddings>()?;
+    m.add_class::<embeddings::SecureFloatArray>()?;
+    m.add_class::<router::SecureClientRouter>()?;
+    m.add_class::<conversation::SecureConversation>()?;
+    m.add_class::<template::SecureTemplate>()?;
+    m.add_class::<async_client::AsyncSecureClient>()?;
+    m.add_class::<signing::HmacSigner>()?;
+    m.add_class::<testing::MockOpenAIServer>()?;
+    m.add_function(wrap_pyfunction!(memory::disable_core_dumps, m)?)?;
+    m.add_function(wrap_pyfunction!(memory::set_fork_policy, m)?)?;
+    m.add_function(wrap_pyfunction!(memory::strict_memory, m)?)?;
+    m.add_function(wrap_pyfunction!(memory::memory_report, m)?)?;
+    m.add_function(wrap_pyfunction!(memory::set_canaries, m)?)?;
+    #[cfg(feature = "test-hooks")]
+    m.add_function(wrap_pyfunction!(memory::_corrupt_canary, m)?)?;
+    #[cfg(feature = "test-hooks")]
+    m.add_function(wrap_pyfunction!(memory::_locked_memory_contains, m)?)?;
+    #[cfg(all(unix, feature = "test-hooks"))]
+    m.add_function(wrap_pyfunction!(keyring::_use_memory_keyring, m)?)?;
+    m.add_function(wrap_pyfunction!(logging::enable_logging, m)?)?;
+    m.add_function(wrap_pyfunction!(audit::verify_audit_log, m)?)?;
+    m.add_function(wrap_pyfunction!(keyfile::derive_key, m)?)?;
+    m.add_function(wrap_pyfunction!(keyfile::encrypt_keyfile, m)?)?;
+    m.add_function(wrap_pyfunction!(keyfile::load_keyfile, m)?)?;
+    m.add_function(wrap_pyfunction!(keyring::store_in_keyring, m)?)?;
+    m.add_function(wrap_pyfunction!(images::image_to_data_url, m)?)?;
+    m.add_function(wrap_pyfunction!(embeddings::top_k, m)?)?;
+    memory::install_fork_handlers();
+    errors::register(m)?;
+    transport::configure_runtime();
     Ok(())
 }
@@ -0,0 +1,106 @@
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// --- Logging ---
+
+const LOGGER_NAME: &str = "secure_openaiapi";
+
+/// Set by `enable_logging()`; until then no record is built at all.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub(crate) fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// A level for `enable_logging`: a name such as `"DEBUG"` or a number, as `logging` takes.
+#[derive(FromPyObject)]
+pub(crate) enum Level {
+    Name(String),
+    Number(i32),
+}
+
+/// Everything a log record about one call may contain. The fields are plain numbers and
+/// strings filled from request metadata, and `emit` takes nothing else, so no message
+/// content, key or response body can reach a record: there is no field to put it in.
+pub(crate) struct LogEvent {
+    pub(crate) endpoint: String,
+    pub(crate) model: String,
+    pub(crate) status: Option<u16>,
+    pub(crate) latency: f64,
+    pub(crate) attempts: u32,
+    pub(crate) request_id: Option<String>,
+    pub(crate) prompt_tokens: Option<u64>,
+    pub(crate) completion_tokens: Option<u64>,
+    pub(crate) total_tokens: Option<u64>,
+    /// The exception type only: messages can quote the response body.
+    pub(crate) error: Option<String>,
+    /// Header names, never values, sent and received; only logged at `DEBUG`.
+    pub(crate) request_headers: Vec<String>,
+    pub(crate) response_headers: Vec<String>,
+}
+
+/// Starts logging one record per call to the `secure_openaiapi` logger and sets its level;
+/// returns the logger. Successful calls log at `INFO` and failed ones at `WARNING`, with
+/// the fields of the record also attached as attributes (`endpoint`, `model`, `status`,
+/// `latency`, `attempts`, `request_id`, `prompt_tokens`, `completion_tokens`,
+/// `total_tokens`, `error`) for structured handlers; `attempts` is 0 for a cache hit. At `DEBUG` the names of the request
+/// and response headers are added as `request_headers` and `response_headers`. Handlers
+/// and formatting are left to the application's logging configuration.
+#[pyfunction]
+#[pyo3(signature = (level=Level::Name("INFO".to_string())))]
+pub(crate) fn enable_logging(py: Python<'_>, level: Level) -> PyResult<Bound<'_, PyAny>> {
+    let logger = logger(py)?;
+    match level {
+        Level::Name(name) => logger.call_method1("setLevel", (name.to_uppercase(),))?,
+        Level::Number(number) => logger.call_method1("setLevel", (number,))?,
+    };
+    ENABLED.store(true, Ordering::Relaxed);
+    Ok(logger)
+}
+
+fn logger(py: Python<'_>) -> PyResult<Bound<'_, PyAny>> {
+    py.import("logging")?.call_method1("getLogger", (LOGGER_NAME,))
+}
+
+/// Logs `event`. Failures are reported through `sys.unraisablehook` and never change the
+/// outcome of the call.
+pub(crate) fn emit(py: Python<'_>, event: &LogEvent) {
+    if let Err(e) = try_emit(py, event) {
+        e.write_unraisable(py, None);
+    }
+}
+
+fn try_emit(py: Python<'_>, event: &LogEvent) -> PyResult<()> {
+    let logging = py.import("logging")?;
+    let logger = logger(py)?;
+    let level: i32 = logging.getattr(if event.error.is_some() { "WARNING" } else { "INFO" })?.extract()?;
+    if !logger.call_method1("isEnabledFor", (level,))?.is_truthy()? {
+        return Ok(());
+    }
+    let extra = PyDict::new(py);
+    extra.set_item("endpoint", &event.endpoint)?;
+    extra.set_item("model", &event.model)?;
+    extra.set_item("status", event.status)?;
+    extra.set_item("latency", event.latency)?;
+    extra.set_item("attempts", event.attempts)?;
+    extra.set_item("request_id", &event.request_id)?;
+    extra.set_item("prompt_tokens", event.prompt_tokens)?;
+    extra.set_item("completion_tokens", event.completion_tokens)?;
+    extra.set_item("total_tokens", event.total_tokens)?;
+    extra.set_item("error", &event.error)?;
+    let debug: i32 = logging.getattr("DEBUG")?.extract()?;
+    if logger.call_method1("isEnabledFor", (debug,))?.is_truthy()? {
+        extra.set_item("request_headers", &event.request_headers)?;
+        extra.set_item("response_headers", &event.response_headers)?;
+    }
+
+    let status = event.status.map_or("-".to_string(), |status| status.to_string());
+    let outcome = event.error.as_deref().unwrap_or("ok");
+    let message = "%s model=%s status=%s latency=%.3fs attempts=%d: %s";
+    let args = (level, message, &event.endpoint, &event.model, status, event.latency, event.attempts, outcome);
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("extra", extra)?;
+    logger.call_method("log", args, Some(&kwargs))?;
+    Ok(())
+}
@@ -0,0 +1,499 @@
+use crate::errors::{MemoryCorruptionError, MemoryLockError};
+use libsodium_sys::{randombytes_buf, sodium_init, sodium_mlock, sodium_munlock};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, Once, OnceLock};
+use zeroize::Zeroize;
+
+// --- Locked Memory ---
+
+/// Locks `len` bytes at `ptr` into RAM and excludes them from core dumps. Returns whether
+/// the dump exclusion took effect, which is always the case for an empty range. A failed
+/// `mlock` is counted and otherwise tolerated, except under `strict_memory()` where it is
+/// an error and the range is left unregistered.
+///
+/// libsodium's own `madvise` call needs a page-aligned address and so never applies to heap
+/// buffers; here the range is widened to whole pages instead, which may also keep a few
+/// neighbouring bytes out of a dump but never the reverse. The exclusion is not undone on
+/// release since those pages can be shared with other secrets. `MADV_DONTDUMP` is
+/// Linux-only: elsewhere (macOS included, where core dumps are off unless `ulimit -c` and
+/// `/cores` allow them) this returns `false` and `disable_core_dumps()` is the protection.
+///
+/// `mlock` works on whole pages, so the pages the range touches are counted in the registry
+/// and only unlocked once no live range touches them anymore.
+pub(crate) fn lock(ptr: *mut u8, len: usize) -> PyResult<bool> {
+    if len == 0 {
+        return Ok(true);
+    }
+    // Held across the `mlock`, so an `unlock` of a range on the same page cannot release
+    // it in between.
+    let mut registry = registry();
+    if unsafe { sodium_mlock(ptr as *mut c_void, len) } != 0 {
+        FAILED_LOCKS.fetch_add(1, Ordering::Relaxed);
+        if STRICT.load(Ordering::Relaxed) {
+            return Err(PyErr::new::<MemoryLockError, _>(format!(
+                "failed to lock {} bytes into memory: {}",
+                len,
+                std::io::Error::last_os_error()
+            )));
+        }
+    }
+    registry.ranges.insert(ptr as usize, len);
+    for page in pages(ptr as usize, len) {
+        *registry.pages.entry(page).or_insert(0) += 1;
+    }
+    drop(registry);
+    Ok(exclude_from_dumps(ptr, len))
+}
+
+/// Unlocks a range locked by `lock`: it is zeroed, as `sodium_munlock` would, and those of
+/// its pages that no other live range touches are unlocked.
+pub(crate) fn unlock(ptr: *mut u8, len: usize) {
+    if len == 0 {
+        return;
+    }
+    let mut registry = registry();
+    registry.ranges.remove(&(ptr as usize));
+    unsafe { wipe(ptr, len) };
+    let mut unused = Vec::new();
+    for page in pages(ptr as usize, len) {
+        let count = registry.pages.get_mut(&page).expect("locked pages are registered");
+        *count -= 1;
+        if *count == 0 {
+            registry.pages.remove(&page);
+            unused.push(page);
+        }
+    }
+    for run in unused.chunk_by(|page, next| next - page == page_size()) {
+        munlock_pages(run[0], run[run.len() - 1] + page_size());
+    }
+}
+
+/// The start of every page `len` bytes at `ptr` touch.
+fn pages(ptr: usize, len: usize) -> impl Iterator<Item = usize> {
+    let page = page_size();
+    (ptr & !(page - 1)..ptr + len).step_by(page)
+}
+
+/// Unlocks the pages from `start` to `end`. Not `sodium_munlock`, which zeroes the range
+/// first and would wipe whatever else lives on those pages.
+fn munlock_pages(start: usize, end: usize) {
+    #[cfg(unix)]
+    unsafe {
+        libc::munlock(start as *const c_void, end - start);
+    }
+    // Elsewhere the pages stay locked until they are freed.
+    #[cfg(not(unix))]
+    let _ = (start, end);
+}
+
+fn page_size() -> usize {
+    static PAGE_SIZE: OnceLock<usize> = OnceLock::new();
+    *PAGE_SIZE.get_or_init(|| {
+        #[cfg(unix)]
+        if let size @ 1.. = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+            return size as usize;
+        }
+        4096
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn exclude_from_dumps(ptr: *mut u8, len: usize) -> bool {
+    let page = page_size();
+    let start = ptr as usize & !(page - 1);
+    let Some(end) = (ptr as usize).checked_add(len).and_then(|end| end.checked_next_multiple_of(page)) else {
+        return false;
+    };
+    unsafe { libc::madvise(start as *mut c_void, end - start, libc::MADV_DONTDUMP) == 0 }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn exclude_from_dumps(_ptr: *mut u8, _len: usize) -> bool {
+    false
+}
+
+/// Sets the core file size limit (soft and hard) to zero for this process, so a crash or
+/// an operator's signal never writes memory to disk, whatever `MADV_DONTDUMP` covers.
+/// The hard limit cannot be raised again without privileges. Raises `OSError` if the
+/// limit cannot be set, and `NotImplementedError` where there are no resource limits.
+#[pyfunction]
+pub(crate) fn disable_core_dumps() -> PyResult<()> {
+    #[cfg(unix)]
+    {
+        let limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+        if unsafe { libc::setrlimit(libc::RLIMIT_CORE, &limit) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    Err(PyErr::new::<pyo3::exceptions::PyNotImplementedError, _>("disable_core_dumps() needs a unix platform"))
+}
+
+// --- Fork Safety ---
+
+struct Registry {
+    /// Every live locked range, start address to length.
+    ranges: BTreeMap<usize, usize>,
+    /// How many live ranges touch each page, by its start address.
+    pages: BTreeMap<usize, usize>,
+}
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry { ranges: BTreeMap::new(), pages: BTreeMap::new() });
+
+/// Bumped in a child process whenever the fork policy wiped the inherited secrets.
+static FORK_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// `true` for the default "wipe" policy, `false` for "keep".
+static WIPE_ON_FORK: AtomicBool = AtomicBool::new(true);
+
+thread_local! {
+    /// The registry lock, held by the forking thread from the prepare handler until the
+    /// parent or child handler, so the child never inherits it mid-update.
+    static HELD_ACROSS_FORK: RefCell<Option<MutexGuard<'static, Registry>>> = const { RefCell::new(None) };
+}
+
+fn registry() -> MutexGuard<'static, Registry> {
+    REGISTRY.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// The generation secrets created now belong to; one from an earlier generation was
+/// wiped by a fork and must not be used.
+pub(crate) fn fork_generation() -> u64 {
+    FORK_GENERATION.load(Ordering::Acquire)
+}
+
+pub(crate) fn invalidated_by_fork() -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("secret invalidated by fork")
+}
+
+/// Whether libsodium is initialized. `sodium_init` takes a lock inside libsodium, so it
+/// runs once per process, on first use; later calls are a plain load.
+fn sodium_ready() -> bool {
+    static READY: OnceLock<bool> = OnceLock::new();
+    *READY.get_or_init(|| unsafe { sodium_init() } >= 0)
+}
+
+/// Initializes libsodium; called from module init, so a failure makes the import fail.
+pub(crate) fn init_sodium() -> PyResult<()> {
+    if !sodium_ready() {
+        return Err(PyErr::new::<pyo3::exceptions::PyImportError, _>("Failed to initialize libsodium"));
+    }
+    Ok(())
+}
+
+/// For code that can run without module init having succeeded first.
+pub(crate) fn ensure_sodium() {
+    if !sodium_ready() {
+        panic!("Failed to initialize libsodium");
+    }
+}
+
+/// Installs the fork handlers; called once from module init.
+pub(crate) fn install_fork_handlers() {
+    static INSTALL: Once = Once::new();
+    #[cfg(unix)]
+    INSTALL.call_once(|| unsafe {
+        libc::pthread_atfork(Some(before_fork), Some(after_fork_in_parent), Some(after_fork_in_child));
+    });
+}
+
+extern "C" fn before_fork() {
+    let guard = registry();
+    HELD_ACROSS_FORK.with(|held| *held.borrow_mut() = Some(guard));
+}
+
+extern "C" fn after_fork_in_parent() {
+    HELD_ACROSS_FORK.with(|held| held.borrow_mut().take());
+}
+
+/// Locks are not inherited across `fork`, so the child either wipes every inherited
+/// secret (and invalidates the `SecureBytes` holding them) or locks them again.
+extern "C" fn after_fork_in_child() {
+    let Some(registry) = HELD_ACROSS_FORK.with(|held| held.borrow_mut().take()) else {
+        return;
+    };
+    let wipe = WIPE_ON_FORK.load(Ordering::Relaxed);
+    for (&ptr, &len) in registry.ranges.iter() {
+        if wipe {
+            unsafe { std::slice::from_raw_parts_mut(ptr as *mut u8, len) }.zeroize();
+        } else {
+            unsafe { sodium_mlock(ptr as *mut c_void, len) };
+        }
+    }
+    if wipe {
+        FORK_GENERATION.fetch_add(1, Ordering::AcqRel);
+    }
+}
+
+/// Chooses what a forked child does with the secrets it inherits. With `"wipe"` (the
+/// default) every `SecureBytes` that existed at the fork is zeroed in the child and raises
+/// `RuntimeError("secret invalidated by fork")` when used there; the parent is unaffected.
+/// With `"keep"` the child keeps working copies, locked into memory again. Pick `"keep"`
+/// only when children are meant to use secrets loaded before the fork.
+#[pyfunction]
+pub(crate) fn set_fork_policy(policy: &str) -> PyResult<()> {
+    let wipe = match policy {
+        "wipe" => true,
+        "keep" => false,
+        _ => return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("fork policy must be 'wipe' or 'keep'")),
+    };
+    WIPE_ON_FORK.store(wipe, Ordering::Relaxed);
+    Ok(())
+}
+
+// --- Canaries ---
+
+pub(crate) const CANARY_BYTES: usize = 16;
+
+/// On by default; `set_canaries(False)` turns them off for buffers made afterwards.
+static CANARIES: AtomicBool = AtomicBool::new(true);
+
+/// Buffers found overwritten, whether on use or when dropped.
+static CORRUPTIONS: AtomicU64 = AtomicU64::new(0);
+
+pub(crate) fn canaries_enabled() -> bool {
+    CANARIES.load(Ordering::Relaxed)
+}
+
+/// The canary written before and after every guarded `SecureBytes`, random per process so
+/// that a stray write cannot happen to reproduce it.
+pub(crate) fn canary() -> &'static [u8; CANARY_BYTES] {
+    static CANARY: OnceLock<[u8; CANARY_BYTES]> = OnceLock::new();
+    CANARY.get_or_init(|| {
+        let mut canary = [0u8; CANARY_BYTES];
+        unsafe { randombytes_buf(canary.as_mut_ptr() as *mut c_void, canary.len()) };
+        canary
+    })
+}
+
+pub(crate) fn count_corruption() {
+    CORRUPTIONS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counts a corrupted buffer and returns the error for the call that found it.
+pub(crate) fn corrupted() -> PyErr {
+    count_corruption();
+    PyErr::new::<MemoryCorruptionError, _>("secret buffer was overwritten (canary check failed) and can no longer be used")
+}
+
+/// Zeroes `len` bytes at `ptr`.
+///
+/// # Safety
+/// The range must be a live allocation that nothing else reads or writes meanwhile.
+pub(crate) unsafe fn wipe(ptr: *mut u8, len: usize) {
+    std::slice::from_raw_parts_mut(ptr, len).zeroize();
+}
+
+/// Turns the canaries around `SecureBytes` buffers on (the default) or off. Each buffer is
+/// then made with a random 16-byte canary before and after the secret, checked whenever
+/// the secret is used (sent, serialized, converted to `bytes` or `str`), by
+/// `SecureBytes.check_integrity()` and when the buffer is freed. A broken canary means
+/// something wrote past its own memory into ours: the use raises `MemoryCorruptionError`,
+/// as does every later one, `check_integrity()` wipes the secret, and a corrupted buffer
+/// found when freed is counted in `memory_report()`. The switch only affects buffers created after it.
+#[pyfunction]
+pub(crate) fn set_canaries(enabled: bool) {
+    CANARIES.store(enabled, Ordering::Relaxed);
+}
+
+/// Overwrites the leading canary of `secret`, so tests can see corruption being caught.
+#[cfg(feature = "test-hooks")]
+#[pyfunction]
+pub(crate) fn _corrupt_canary(mut secret: PyRefMut<'_, crate::SecureBytes>) -> PyResult<()> {
+    if secret.guard == 0 {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("buffer has no canaries"));
+    }
+    secret.buffer[0] ^= 0xff;
+    Ok(())
+}
+
+/// Whether `needle` occurs in any locked range, so tests can check where a secret is kept.
+#[cfg(feature = "test-hooks")]
+#[pyfunction]
+pub(crate) fn _locked_memory_contains(needle: &[u8]) -> bool {
+    locked_memory_contains(needle)
+}
+
+#[cfg(any(test, feature = "test-hooks"))]
+pub(crate) fn locked_memory_contains(needle: &[u8]) -> bool {
+    registry().ranges.iter().any(|(&ptr, &len)| {
+        let range = unsafe { std::slice::from_raw_parts(ptr as *const u8, len) };
+        !needle.is_empty() && range.windows(needle.len()).any(|window| window == needle)
+    })
+}
+
+// --- Strict Mode and Reporting ---
+
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+/// `mlock` calls that failed since the module was loaded.
+static FAILED_LOCKS: AtomicU64 = AtomicU64::new(0);
+
+/// Default `min_memlock` for `strict_memory()`.
+const DEFAULT_MIN_MEMLOCK: u64 = 1024 * 1024;
+
+/// Turns strict memory mode on or off. Turning it on first verifies that the soft
+/// `RLIMIT_MEMLOCK` allows at least `min_memlock` bytes and, on Linux, that locking a canary
+/// buffer actually shows up in `/proc/self/status` `VmLck` (containers can quietly refuse
+/// it); `MemoryLockError` is raised if not. From then on a `SecureBytes` that cannot be
+/// locked raises `MemoryLockError` instead of silently staying pageable, and so does a call
+/// whose request or response buffers cannot be.
+#[pyfunction]
+#[pyo3(signature = (enabled, *, min_memlock=DEFAULT_MIN_MEMLOCK))]
+pub(crate) fn strict_memory(enabled: bool, min_memlock: u64) -> PyResult<()> {
+    if enabled {
+        let strict_error = |message: String| PyErr::new::<MemoryLockError, _>(message);
+        if let Some((Some(soft), _)) = rlimit(RlimitKind::Memlock) {
+            if soft < min_memlock {
+                return Err(strict_error(format!(
+                    "RLIMIT_MEMLOCK is {} bytes, below the required {}; raise it (ulimit -l, or --ulimit memlock for containers)",
+                    soft, min_memlock
+                )));
+            }
+        }
+        check_canary().map_err(strict_error)?;
+    }
+    STRICT.store(enabled, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Locks a fresh, page-aligned canary and checks that the kernel accounts for it.
+#[cfg(target_os = "linux")]
+fn check_canary() -> Result<(), String> {
+    let page = page_size();
+    let layout = std::alloc::Layout::from_size_align(4 * page, page).expect("page sizes are powers of two");
+    let before = status_kb("VmLck").ok_or("cannot read VmLck from /proc/self/status")?;
+    let canary = unsafe { std::alloc::alloc_zeroed(layout) };
+    if canary.is_null() {
+        return Err("cannot allocate the canary buffer".to_string());
+    }
+    let locked = unsafe { sodium_mlock(canary as *mut c_void, layout.size()) } == 0;
+    let after = status_kb("VmLck").unwrap_or(before);
+    unsafe {
+        sodium_munlock(canary as *mut c_void, layout.size());
+        std::alloc::dealloc(canary, layout);
+    }
+    if !locked {
+        return Err(format!("locking a canary buffer failed: {}", std::io::Error::last_os_error()));
+    }
+    if after < before + (layout.size() / 1024) as u64 {
+        return Err("locking a canary buffer succeeded but VmLck did not grow; memory locking is not effective here".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn check_canary() -> Result<(), String> {
+    Ok(())
+}
+
+/// A `kB` field of `/proc/self/status`, such as `VmLck` or `VmSwap`.
+fn status_kb(field: &str) -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find_map(|line| line.strip_prefix(field)?.strip_prefix(':'))?;
+    line.trim().trim_end_matches("kB").trim().parse().ok()
+}
+
+/// Whether any swap device is active, from `/proc/swaps`; `None` where that is unknown.
+fn swap_enabled() -> Option<bool> {
+    let swaps = std::fs::read_to_string("/proc/swaps").ok()?;
+    Some(swaps.lines().skip(1).any(|line| !line.trim().is_empty()))
+}
+
+enum RlimitKind {
+    Memlock,
+    Core,
+}
+
+/// `(soft, hard)` for a resource limit, `None` meaning unlimited.
+fn rlimit(kind: RlimitKind) -> Option<(Option<u64>, Option<u64>)> {
+    #[cfg(unix)]
+    {
+        let resource = match kind {
+            RlimitKind::Memlock => libc::RLIMIT_MEMLOCK,
+            RlimitKind::Core => libc::RLIMIT_CORE,
+        };
+        let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+        if unsafe { libc::getrlimit(resource, &mut limit) } != 0 {
+            return None;
+        }
+        // `rlim_t` is narrower than `u64` on some 32-bit targets.
+        #[allow(clippy::unnecessary_cast)]
+        let finite = |value: libc::rlim_t| (value != libc::RLIM_INFINITY).then_some(value as u64);
+        Some((finite(limit.rlim_cur), finite(limit.rlim_max)))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = kind;
+        None
+    }
+}
+
+/// Summarizes how well secrets are protected in this process, for operators and compliance
+/// evidence: `strict` mode, live `locked_allocations` and `locked_bytes`, `failed_locks`,
+/// the `fork_policy`, `rlimit_memlock` and `rlimit_core` as `(soft, hard)` with `None` for
+/// unlimited, and from `/proc` on Linux `vm_locked_kb`, `vm_swap_kb` and whether the host has
+/// `swap_enabled`, and whether `canaries` are on with the number of `canary_failures`
+/// (buffers found overwritten) so far. Values that cannot be determined on this platform
+/// are `None`.
+#[pyfunction]
+pub(crate) fn memory_report(py: Python<'_>) -> PyResult<Bound<'_, PyDict>> {
+    let (allocations, bytes) = {
+        let registry = registry();
+        (registry.ranges.len(), registry.ranges.values().sum::<usize>())
+    };
+    let report = PyDict::new(py);
+    report.set_item("strict", STRICT.load(Ordering::Relaxed))?;
+    report.set_item("canaries", canaries_enabled())?;
+    report.set_item("canary_failures", CORRUPTIONS.load(Ordering::Relaxed))?;
+    report.set_item("locked_allocations", allocations)?;
+    report.set_item("locked_bytes", bytes)?;
+    report.set_item("failed_locks", FAILED_LOCKS.load(Ordering::Relaxed))?;
+    report.set_item("fork_policy", if WIPE_ON_FORK.load(Ordering::Relaxed) { "wipe" } else { "keep" })?;
+    report.set_item("rlimit_memlock", rlimit(RlimitKind::Memlock))?;
+    report.set_item("rlimit_core", rlimit(RlimitKind::Core))?;
+    report.set_item("vm_locked_kb", status_kb("VmLck"))?;
+    report.set_item("vm_swap_kb", status_kb("VmSwap"))?;
+    report.set_item("swap_enabled", swap_enabled())?;
+    Ok(report)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Held by every test that locks memory, as `VmLck` counts the whole process.
+    pub(crate) static SERIAL: Mutex<()> = Mutex::new(());
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn unlocking_one_range_keeps_a_shared_page_locked() {
+        let _serial = SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let page = page_size();
+        let layout = std::alloc::Layout::from_size_align(page, page).expect("page sizes are powers of two");
+        let buffer = unsafe { std::alloc::alloc_zeroed(layout) };
+        let (first, second) = (buffer, unsafe { buffer.add(page / 2) });
+        let before = status_kb("VmLck").expect("VmLck is in /proc/self/status");
+        lock(first, 64).unwrap();
+        lock(second, 64).unwrap();
+        let locked = status_kb("VmLck").unwrap();
+        // Without the privilege or limit to lock anything there is nothing to check.
+        if locked > before {
+            assert_eq!(locked, before + (page / 1024) as u64);
+            unlock(first, 64);
+            assert_eq!(status_kb("VmLck").unwrap(), locked);
+            unlock(second, 64);
+            assert_eq!(status_kb("VmLck").unwrap(), before);
+        } else {
+            unlock(first, 64);
+            unlock(second, 64);
+        }
+        unsafe { std::alloc::dealloc(buffer, layout) };
+    }
+}
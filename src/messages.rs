@@ -0,0 +1,136 @@
+use crate::{SecureBytes, SecureMessage};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+
+// --- Message Lists ---
+
+/// The keys of an official-SDK message dict that have a `SecureMessage` equivalent.
+const MESSAGE_KEYS: &[&str] = &["role", "content", "name", "tool_call_id"];
+
+/// A `messages` argument: `SecureMessage`s, plain dicts in the official SDK's format such as
+/// `{"role": "user", "content": "..."}`, or both mixed. Dicts are converted into
+/// `SecureMessage`s on the way in, their text copied straight into locked memory.
+pub(crate) struct Messages<'py> {
+    messages: Vec<Bound<'py, SecureMessage>>,
+    converted: Converted,
+}
+
+/// The messages converted from dicts. Nothing else refers to them, so they are wiped as soon
+/// as the call that converted them drops this.
+pub(crate) struct Converted(Vec<Py<SecureMessage>>);
+
+impl Drop for Converted {
+    fn drop(&mut self) {
+        if self.0.is_empty() {
+            return;
+        }
+        Python::with_gil(|py| {
+            for message in &self.0 {
+                if let Ok(mut message) = message.bind(py).try_borrow_mut() {
+                    message.wipe();
+                }
+            }
+        });
+    }
+}
+
+impl<'py> Messages<'py> {
+    /// The messages, and the converted ones to keep alive for as long as they are used.
+    pub(crate) fn into_parts(self) -> (Vec<Py<SecureMessage>>, Converted) {
+        (self.messages.into_iter().map(Bound::unbind).collect(), self.converted)
+    }
+}
+
+impl<'py> From<Vec<Bound<'py, SecureMessage>>> for Messages<'py> {
+    fn from(messages: Vec<Bound<'py, SecureMessage>>) -> Self {
+        Self { messages, converted: Converted(Vec::new()) }
+    }
+}
+
+impl<'py> FromPyObject<'py> for Messages<'py> {
+    fn extract_bound(messages: &Bound<'py, PyAny>) -> PyResult<Self> {
+        let mut list = Self::from(Vec::new());
+        for item in messages.try_iter()? {
+            let item = item?;
+            let message = match item.downcast::<PyDict>() {
+                Ok(dict) => {
+                    let message = Bound::new(messages.py(), from_dict(dict)?)?;
+                    list.converted.0.push(message.clone().unbind());
+                    message
+                }
+                Err(_) => item.downcast_into::<SecureMessage>()?,
+            };
+            list.messages.push(message);
+        }
+        Ok(list)
+    }
+}
+
+/// Converts one message dict. `content` is a str, bytes, `SecureBytes` or a list of content
+/// parts as taken by `SecureMessage`; `name` and `tool_call_id` are optional.
+fn from_dict(dict: &Bound<'_, PyDict>) -> PyResult<SecureMessage> {
+    for key in dict.keys() {
+        let key: String = key.extract()?;
+        if !MESSAGE_KEYS.contains(&key.as_str()) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Unsupported key '{}' in message dict: expected role, content, name or tool_call_id",
+                key
+            )));
+        }
+    }
+    let required = |key: &str| {
+        dict.get_item(key)?
+            .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyKeyError, _>(format!("'{}' key missing in message dict", key)))
+    };
+    let optional = |key: &str| -> PyResult<Option<String>> {
+        dict.get_item(key)?.filter(|value| !value.is_none()).map(|value| value.extract()).transpose()
+    };
+    let role: String = required("role")?.extract()?;
+    let content = required("content")?;
+    let mut message = match content.downcast::<PyList>() {
+        Ok(parts) => SecureMessage {
+            role: SecureBytes::try_new(role.as_bytes())?,
+            content: SecureMessage::parse_content(parts)?,
+            tool_call_id: None,
+            name: None,
+        },
+        Err(_) => SecureMessage::text(role.as_bytes(), &content, "content")?,
+    };
+    message.name = optional("name")?;
+    message.tool_call_id = optional("tool_call_id")?;
+    Ok(message)
+}
+
+// --- System Role Translation ---
+
+/// The `system_role_translation` client setting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RoleTranslation {
+    /// System and developer messages go out with the role they were written with.
+    Off,
+    /// Both go out as `developer` to a reasoning model and as `system` to any other.
+    Auto,
+}
+
+impl RoleTranslation {
+    pub(crate) fn parse(setting: Option<&str>) -> PyResult<Self> {
+        match setting {
+            None => Ok(RoleTranslation::Off),
+            Some("auto") => Ok(RoleTranslation::Auto),
+            Some(setting) => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Invalid system_role_translation '{}': expected 'auto' or None",
+                setting
+            ))),
+        }
+    }
+
+    /// The role to send system and developer messages with, `None` for as written. Only the
+    /// role is replaced, by a constant, while serializing: the messages are left as they are.
+    pub(crate) fn system_role(self, reasoning: bool) -> Option<&'static str> {
+        match self {
+            RoleTranslation::Off => None,
+            RoleTranslation::Auto if reasoning => Some("developer"),
+            RoleTranslation::Auto => Some("system"),
+        }
+    }
+}
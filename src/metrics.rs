@@ -0,0 +1,102 @@
+use crate::audit::TokenCounts;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use reqwest::StatusCode;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// --- Client Metrics ---
+
+/// Upper bounds of the latency buckets, in seconds. Anything slower lands in `+Inf`.
+const LATENCY_BUCKETS: [f64; 10] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+
+const STATUS_CLASSES: [&str; 5] = ["1xx", "2xx", "3xx", "4xx", "5xx"];
+
+/// Health counters for `metrics()`, shared like `ClientStats` but resettable. Only status
+/// classes, counts and durations are kept, nothing derived from what was sent or received.
+#[derive(Default)]
+pub(crate) struct ClientMetrics {
+    /// HTTP responses by status class, `1xx` to `5xx`.
+    responses: [AtomicU64; 5],
+    /// Attempts that got no response at all.
+    failed: AtomicU64,
+    retries: AtomicU64,
+    timeouts: AtomicU64,
+    prompt_tokens: AtomicU64,
+    completion_tokens: AtomicU64,
+    /// Attempts by latency bucket, the last one unbounded; not cumulative.
+    latency: [AtomicU64; LATENCY_BUCKETS.len() + 1],
+    latency_nanos: AtomicU64,
+}
+
+impl ClientMetrics {
+    /// Records one HTTP attempt: its status, or `None` if it got no response, and the time
+    /// from sending the request to having read the whole response or given up.
+    pub(crate) fn record_attempt(&self, status: Option<StatusCode>, elapsed: Duration, timed_out: bool) {
+        match status.map(|status| status.as_u16() / 100) {
+            Some(class @ 1..=5) => self.responses[class as usize - 1].fetch_add(1, Ordering::Relaxed),
+            _ => self.failed.fetch_add(1, Ordering::Relaxed),
+        };
+        if timed_out {
+            self.timeouts.fetch_add(1, Ordering::Relaxed);
+        }
+        let seconds = elapsed.as_secs_f64();
+        let bucket = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound).unwrap_or(LATENCY_BUCKETS.len());
+        self.latency[bucket].fetch_add(1, Ordering::Relaxed);
+        self.latency_nanos.fetch_add(elapsed.as_nanos().min(u64::MAX as u128) as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_retry(&self) {
+        self.retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Adds the usage a response reported; counts the provider left out add nothing.
+    pub(crate) fn record_tokens(&self, tokens: &TokenCounts) {
+        self.prompt_tokens.fetch_add(tokens.prompt.unwrap_or(0), Ordering::Relaxed);
+        self.completion_tokens.fetch_add(tokens.completion.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    /// Zeroes every counter. Attempts finishing meanwhile may land on either side.
+    pub(crate) fn reset(&self) {
+        let counters = self.responses.iter().chain(&self.latency).chain([
+            &self.failed,
+            &self.retries,
+            &self.timeouts,
+            &self.prompt_tokens,
+            &self.completion_tokens,
+            &self.latency_nanos,
+        ]);
+        for counter in counters {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let requests = PyDict::new(py);
+        for (class, count) in STATUS_CLASSES.iter().zip(&self.responses) {
+            requests.set_item(class, count.load(Ordering::Relaxed))?;
+        }
+        requests.set_item("failed", self.failed.load(Ordering::Relaxed))?;
+
+        // Cumulative, as Prometheus histograms are: each bound counts every attempt at or below it.
+        let buckets = PyDict::new(py);
+        let mut count = 0;
+        for (bound, attempts) in LATENCY_BUCKETS.iter().chain([&f64::INFINITY]).zip(&self.latency) {
+            count += attempts.load(Ordering::Relaxed);
+            buckets.set_item(bound, count)?;
+        }
+        let latency = PyDict::new(py);
+        latency.set_item("buckets", buckets)?;
+        latency.set_item("count", count)?;
+        latency.set_item("sum", Duration::from_nanos(self.latency_nanos.load(Ordering::Relaxed)).as_secs_f64())?;
+
+        let dict = PyDict::new(py);
+        dict.set_item("requests", requests)?;
+        dict.set_item("retries", self.retries.load(Ordering::Relaxed))?;
+        dict.set_item("timeouts", self.timeouts.load(Ordering::Relaxed))?;
+        dict.set_item("prompt_tokens", self.prompt_tokens.load(Ordering::Relaxed))?;
+        dict.set_item("completion_tokens", self.completion_tokens.load(Ordering::Relaxed))?;
+        dict.set_item("latency", latency)?;
+        Ok(dict)
+    }
+}
@@ -0,0 +1,181 @@
+use crate::audit::hex;
+use crate::headers;
+use crate::params;
+use crate::transport::{Request, Response, ResponseBody, Transport};
+use crate::ClientCore;
+use hyper::body::Bytes;
+use libsodium_sys::crypto_generichash;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyIterator, PyType};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{StatusCode, Version};
+use serde_json::Value;
+use std::sync::Arc;
+use zeroize::Zeroize;
+
+// --- Mock Transport ---
+
+const MOCK_BASE_URL: &[u8] = b"https://mock.invalid";
+const MOCK_API_KEY: &[u8] = b"sk-mock";
+
+/// The keys of a content part whose values name a kind rather than carry content, and are
+/// passed to the handler as they are.
+const PART_KIND_KEYS: &[&str] = &["type", "media_type", "detail"];
+
+/// Answers requests by calling a Python handler instead of going to the network. Everything
+/// before the wire (serialization, headers, signing, retries) and after it (body limits,
+/// decompression, parsing, error mapping) runs exactly as against a real server.
+pub(crate) struct MockTransport {
+    handler: Arc<Py<PyAny>>,
+}
+
+impl MockTransport {
+    /// Calls the handler on a blocking thread, so a slow handler holds up neither the
+    /// runtime nor, beyond the call itself, the GIL.
+    pub(crate) async fn send(&self, request: Request) -> PyResult<Response> {
+        let handler = Arc::clone(&self.handler);
+        tokio::task::spawn_blocking(move || Python::with_gil(|py| call(handler.bind(py), &request)))
+            .await
+            .expect("mock handlers are called without panicking")
+    }
+}
+
+fn call(handler: &Bound<'_, PyAny>, request: &Request) -> PyResult<Response> {
+    let py = handler.py();
+    let recorded = PyDict::new(py);
+    recorded.set_item("method", request.method.as_str())?;
+    recorded.set_item("path", &request.path)?;
+    let headers = PyDict::new(py);
+    for (name, value) in headers::visible(&request.headers) {
+        headers.set_item(name, value)?;
+    }
+    recorded.set_item("headers", headers)?;
+    recorded.set_item("body", hashed_body(py, &request.body)?)?;
+    let answer = handler.call1((recorded,))?;
+    let (status, headers, body): (u16, Option<Bound<'_, PyDict>>, Bound<'_, PyAny>) = answer.extract().map_err(|_| {
+        PyErr::new::<pyo3::exceptions::PyTypeError, _>("The mock handler must return (status, headers, body)")
+    })?;
+    let invalid = |message: String| PyErr::new::<pyo3::exceptions::PyValueError, _>(message);
+    let mut header_map = HeaderMap::new();
+    for (name, value) in headers.iter().flat_map(|headers| headers.iter()) {
+        let (name, value): (String, String) = (name.extract()?, value.extract()?);
+        header_map.append(
+            HeaderName::from_bytes(name.as_bytes()).map_err(|_| invalid(format!("Invalid mock response header name '{}'", name)))?,
+            HeaderValue::from_str(&value).map_err(|_| invalid(format!("Invalid value for mock response header '{}'", name)))?,
+        );
+    }
+    let body = match body.downcast::<PyBytes>() {
+        Ok(bytes) => MockBody::Complete(Some(Bytes::copy_from_slice(bytes.as_bytes()))),
+        Err(_) => MockBody::Chunks(Arc::new(body.try_iter().map_err(|_| {
+            PyErr::new::<pyo3::exceptions::PyTypeError, _>("The mock response body must be bytes or an iterable of bytes chunks")
+        })?.unbind())),
+    };
+    Ok(Response {
+        status: StatusCode::from_u16(status).map_err(|_| invalid(format!("Invalid mock response status {}", status)))?,
+        version: Version::HTTP_11,
+        headers: header_map,
+        body: ResponseBody::Mock(body),
+    })
+}
+
+/// The request body as parsed JSON with every string of message content replaced by
+/// `"blake2b:<hex>"`, the BLAKE2b-256 hash of its UTF-8, so tests can check what was sent
+/// without the prompt ever reaching Python. `None` for an empty or non-JSON body.
+fn hashed_body<'py>(py: Python<'py>, body: &[u8]) -> PyResult<Option<Bound<'py, PyAny>>> {
+    let Ok(mut value) = serde_json::from_slice::<Value>(body) else {
+        return Ok(None);
+    };
+    if let Some(messages) = value.get_mut("messages").and_then(Value::as_array_mut) {
+        for message in messages {
+            if let Some(content) = message.get_mut("content") {
+                hash_strings(content);
+            }
+        }
+    }
+    if let Some(system) = value.get_mut("system") {
+        hash_strings(system);
+    }
+    Ok(Some(params::to_py(py, &value)?))
+}
+
+fn hash_strings(value: &mut Value) {
+    match value {
+        Value::String(text) => {
+            let hashed = content_hash(text.as_bytes());
+            text.zeroize();
+            *text = hashed;
+        }
+        Value::Array(items) => items.iter_mut().for_each(hash_strings),
+        Value::Object(fields) => fields
+            .iter_mut()
+            .filter(|(key, _)| !PART_KIND_KEYS.contains(&key.as_str()))
+            .for_each(|(_, field)| hash_strings(field)),
+        _ => {}
+    }
+}
+
+fn content_hash(text: &[u8]) -> String {
+    let mut hash = [0u8; 32];
+    unsafe {
+        crypto_generichash(hash.as_mut_ptr(), hash.len(), text.as_ptr(), text.len() as u64, std::ptr::null(), 0);
+    }
+    format!("blake2b:{}", hex(&hash))
+}
+
+/// A mock response body: bytes returned at once, or chunks pulled from the handler's
+/// iterable one at a time as the client reads, so streaming runs as it would on a socket.
+pub(crate) enum MockBody {
+    Complete(Option<Bytes>),
+    Chunks(Arc<Py<PyIterator>>),
+}
+
+impl MockBody {
+    pub(crate) async fn chunk(&mut self) -> std::io::Result<Option<Bytes>> {
+        let chunks = match self {
+            MockBody::Complete(body) => return Ok(body.take()),
+            MockBody::Chunks(chunks) => Arc::clone(chunks),
+        };
+        tokio::task::spawn_blocking(move || {
+            Python::with_gil(|py| match chunks.bind(py).clone().next() {
+                None => Ok(None),
+                Some(Ok(chunk)) => match chunk.downcast::<PyBytes>() {
+                    Ok(chunk) => Ok(Some(Bytes::copy_from_slice(chunk.as_bytes()))),
+                    Err(_) => Err(std::io::Error::other("mock response body chunks must be bytes")),
+                },
+                Some(Err(e)) => Err(std::io::Error::other(format!("mock response body raised {}", e))),
+            })
+        })
+        .await
+        .expect("mock bodies are read without panicking")
+    }
+}
+
+/// `with_mock_transport()` of both client classes: builds `cls` with `kwargs`, with a
+/// placeholder base URL and API key unless given. The caller then `install`s the handler.
+pub(crate) fn build_client<'py>(cls: &Bound<'py, PyType>, handler: &Bound<'py, PyAny>, kwargs: Option<&Bound<'py, PyDict>>) -> PyResult<Bound<'py, PyAny>> {
+    if !handler.is_callable() {
+        return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>("handler must be callable"));
+    }
+    let py = cls.py();
+    let kwargs = match kwargs {
+        Some(kwargs) => kwargs.copy()?,
+        None => PyDict::new(py),
+    };
+    let base_url = match kwargs.get_item("base_url")? {
+        Some(base_url) => {
+            kwargs.del_item("base_url")?;
+            base_url
+        }
+        None => PyBytes::new(py, MOCK_BASE_URL).into_any(),
+    };
+    if !kwargs.contains("api_key")? && !kwargs.contains("token_provider")? {
+        kwargs.set_item("api_key", PyBytes::new(py, MOCK_API_KEY))?;
+    }
+    cls.call((base_url,), Some(&kwargs))
+}
+
+/// Routes every request of `core` to `handler` from now on.
+pub(crate) fn install(core: &ClientCore, handler: &Bound<'_, PyAny>) -> PyResult<()> {
+    let transport = Arc::new(Transport::Mock(MockTransport { handler: Arc::new(handler.clone().unbind()) }));
+    core.update_connection(|connection| connection.transport = transport)
+}
@@ -0,0 +1,135 @@
+use crate::errors::ModerationBlockedError;
+use crate::json::{self, RequestBody, SecureJsonWriter};
+use crate::providers::Provider;
+use crate::{SecureBytes, SecureContentPart, SecureMessage};
+use pyo3::prelude::*;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+
+// --- Input Moderation ---
+
+/// The moderation model OpenAI recommends, which also scores images.
+pub(crate) const DEFAULT_MODERATION_MODEL: &str = "omni-moderation-latest";
+
+/// How `moderate_inputs` screens the user text of a call before it is sent.
+pub(crate) struct ModerationPolicy {
+    pub(crate) model: String,
+    /// Score at or above which a category blocks the call. Categories without one block
+    /// when the endpoint flags them.
+    thresholds: HashMap<String, f64>,
+}
+
+impl ModerationPolicy {
+    pub(crate) fn new(model: String, thresholds: Option<HashMap<String, f64>>) -> PyResult<Self> {
+        if model.is_empty() {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("moderation_model must not be empty"));
+        }
+        let thresholds = thresholds.unwrap_or_default();
+        if let Some((category, _)) = thresholds.iter().find(|(_, score)| !(0.0..=1.0).contains(*score)) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "moderation_thresholds['{}'] must be a score between 0 and 1",
+                category
+            )));
+        }
+        Ok(Self { model, thresholds })
+    }
+
+    /// The categories of a moderations response that block the call, sorted; empty if it
+    /// may go ahead. The error never quotes the body.
+    pub(crate) fn flagged(&self, body: &[u8]) -> Result<Vec<String>, String> {
+        let response = serde_json::from_slice::<ModerationResponse>(body)
+            .map_err(|e| format!("Failed to parse moderation response: {}", e))?;
+        let mut flagged = Vec::new();
+        for result in response.results {
+            for (category, hit) in result.categories {
+                let blocks = match (self.thresholds.get(&category), result.category_scores.get(&category)) {
+                    (Some(threshold), Some(score)) => score >= threshold,
+                    _ => hit == Some(true),
+                };
+                if blocks && !flagged.contains(&category) {
+                    flagged.push(category);
+                }
+            }
+        }
+        flagged.sort();
+        Ok(flagged)
+    }
+}
+
+#[derive(Deserialize)]
+struct ModerationResponse {
+    results: Vec<ModerationResult>,
+}
+
+#[derive(Deserialize)]
+struct ModerationResult {
+    /// `null` for a category the model has no verdict on.
+    categories: BTreeMap<String, Option<bool>>,
+    #[serde(default)]
+    category_scores: HashMap<String, f64>,
+}
+
+/// The text parts of the user messages, joined by blank lines into one locked buffer.
+/// `None` when the call carries no user text.
+pub(crate) fn user_text(messages: &[PyRef<'_, SecureMessage>]) -> PyResult<Option<SecureBytes>> {
+    let parts: Vec<&[u8]> = messages
+        .iter()
+        .filter(|message| message.role.bytes() == b"user")
+        .flat_map(|message| &message.content)
+        .filter_map(|part| match part {
+            SecureContentPart::Text { text } => Some(text.bytes()),
+            SecureContentPart::ImageUrl { .. } => None,
+        })
+        .collect();
+    if parts.is_empty() {
+        return Ok(None);
+    }
+    let len = parts.iter().map(|part| part.len()).sum::<usize>() + SEPARATOR.len() * (parts.len() - 1);
+    let mut text = SecureBytes::zeroed(len)?;
+    let mut offset = 0;
+    for (index, part) in parts.iter().enumerate() {
+        if index > 0 {
+            text.bytes_mut()[offset..offset + SEPARATOR.len()].copy_from_slice(SEPARATOR);
+            offset += SEPARATOR.len();
+        }
+        text.bytes_mut()[offset..offset + part.len()].copy_from_slice(part);
+        offset += part.len();
+    }
+    Ok(Some(text))
+}
+
+const SEPARATOR: &[u8] = b"\n\n";
+
+/// The moderations request for `text`, escaped from its locked buffer into the body.
+pub(crate) fn to_json(text: &SecureBytes, model: &str) -> PyResult<RequestBody> {
+    let mut out = SecureJsonWriter::new()?;
+    write_request(&mut out, text, model).map_err(json::serialize_error)?;
+    out.finish()
+}
+
+fn write_request(out: &mut SecureJsonWriter, text: &SecureBytes, model: &str) -> io::Result<()> {
+    out.write_raw(b"{\"input\":");
+    out.write_str(text.bytes())?;
+    out.write_raw(b",\"model\":");
+    out.write_value(model)?;
+    out.write_raw(b"}");
+    Ok(())
+}
+
+/// `moderate_inputs` was asked for where there is no moderations endpoint to call.
+pub(crate) fn no_endpoint(provider: Option<&Provider>) -> PyErr {
+    let target = provider.map_or_else(|| "path_style='azure'".to_string(), |provider| format!("provider '{}'", provider.name));
+    PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{} has no moderations endpoint, so moderate_inputs cannot be used", target))
+}
+
+/// Builds a `ModerationBlockedError`, naming the categories and never the content.
+pub(crate) fn blocked(py: Python<'_>, categories: Vec<String>, request_id: &str) -> PyErr {
+    let err = ModerationBlockedError::new_err(format!(
+        "The moderation check flagged the user input for {} (request id {}); nothing was sent to the completion endpoint",
+        categories.join(", "),
+        request_id
+    ));
+    let _ = err.value(py).setattr("categories", categories);
+    err
+}
@@ -0,0 +1,321 @@
+use crate::call::parse_deadline;
+use crate::SecureBytes;
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyFloat, PyInt, PyList, PyString, PyTuple};
+use serde_json::{Map, Number, Value};
+use std::time::Duration;
+
+// --- Request Parameters ---
+
+/// Body fields that are always built by the client itself and can't be passed as parameters.
+const RESERVED_PARAMS: &[&str] = &["messages", "model", "stream"];
+
+/// Converts keyword arguments (`temperature=0.2`, `max_tokens=100`, ...) into JSON body fields.
+/// Parameters are request metadata, not secrets, so they are plain JSON values.
+pub(crate) fn from_kwargs(kwargs: Option<&Bound<'_, PyDict>>) -> PyResult<Map<String, Value>> {
+    convert(kwargs, None)
+}
+
+/// What `call_kwargs` takes out of a call's keyword arguments besides the body fields.
+pub(crate) struct CallOptions {
+    /// A `SecureBytes` GBNF grammar for constrained decoding: grammars can encode business
+    /// rules, so that one stays out of the JSON values and is written into the body from
+    /// its locked buffer.
+    pub(crate) grammar: Option<SecureBytes>,
+    /// `include_default_system=False` skips the client's `system_prompt` for this call.
+    pub(crate) include_default_system: bool,
+    /// `moderate_inputs=` turns the client's moderation check on or off for this call.
+    pub(crate) moderate_inputs: Option<bool>,
+    /// `deadline=` replaces the client's deadline for this call; `None` keeps it.
+    pub(crate) deadline: Option<Duration>,
+}
+
+/// `from_kwargs` for a single call, which may also pass the options in `CallOptions`.
+pub(crate) fn call_kwargs(kwargs: Option<&Bound<'_, PyDict>>) -> PyResult<(Map<String, Value>, CallOptions)> {
+    let mut options = CallOptions { grammar: None, include_default_system: true, moderate_inputs: None, deadline: None };
+    let params = convert(kwargs, Some(&mut options))?;
+    Ok((params, options))
+}
+
+fn convert(kwargs: Option<&Bound<'_, PyDict>>, mut options: Option<&mut CallOptions>) -> PyResult<Map<String, Value>> {
+    let mut params = Map::new();
+    let Some(kwargs) = kwargs else {
+        return Ok(params);
+    };
+    for (key, value) in kwargs.iter() {
+        let key: String = key.extract()?;
+        if RESERVED_PARAMS.contains(&key.as_str()) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "'{}' cannot be passed as a request parameter",
+                key
+            )));
+        }
+        if key == "grammar" {
+            if let Ok(secure) = value.downcast::<SecureBytes>() {
+                let Some(options) = options.as_deref_mut() else {
+                    return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                        "a SecureBytes grammar can only be passed per call, not as a default",
+                    ));
+                };
+                options.grammar = Some(SecureBytes::try_new(secure.borrow().expose()?)?);
+                continue;
+            }
+        }
+        if key == "include_default_system" {
+            let Some(options) = options.as_deref_mut() else {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                    "include_default_system can only be passed per call, not as a default",
+                ));
+            };
+            options.include_default_system = value
+                .downcast::<PyBool>()
+                .map_err(|_| PyErr::new::<pyo3::exceptions::PyTypeError, _>("include_default_system must be a bool"))?
+                .is_true();
+            continue;
+        }
+        if key == "moderate_inputs" {
+            let Some(options) = options.as_deref_mut() else {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                    "moderate_inputs is a client option; pass it to SecureClient, or per call to override it",
+                ));
+            };
+            options.moderate_inputs = Some(
+                value
+                    .downcast::<PyBool>()
+                    .map_err(|_| PyErr::new::<pyo3::exceptions::PyTypeError, _>("moderate_inputs must be a bool"))?
+                    .is_true(),
+            );
+            continue;
+        }
+        if key == "deadline" {
+            let Some(options) = options.as_deref_mut() else {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                    "deadline is a client option; pass it to SecureClient, or per call to override it",
+                ));
+            };
+            let deadline = value
+                .extract::<Option<f64>>()
+                .map_err(|_| PyErr::new::<pyo3::exceptions::PyTypeError, _>("deadline must be a number of seconds or None"))?;
+            options.deadline = parse_deadline(deadline)?;
+            continue;
+        }
+        let value = to_json(&value).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!("Invalid value for parameter '{}': {}", key, e))
+        })?;
+        check_sampling_extra(&key, &value).map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)?;
+        if key == "web_search_options" {
+            check_web_search_options(&value).map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)?;
+        }
+        params.insert(key, value);
+    }
+    Ok(params)
+}
+
+/// The sampling extras of self-hosted servers such as vLLM and llama.cpp. Those tend to
+/// ignore a mistyped value rather than reject it, so types and ranges are checked here.
+fn check_sampling_extra(key: &str, value: &Value) -> Result<(), &'static str> {
+    let valid = match key {
+        _ if value.is_null() => true,
+        "top_k" => value.as_i64().is_some_and(|k| k >= -1),
+        "min_p" => value.as_f64().is_some_and(|p| (0.0..=1.0).contains(&p)),
+        "repetition_penalty" => value.as_f64().is_some_and(|p| p > 0.0),
+        _ => true,
+    };
+    if valid {
+        return Ok(());
+    }
+    Err(match key {
+        "top_k" => "top_k must be an int of at least -1 (-1 or 0 disables it)",
+        "min_p" => "min_p must be a number between 0 and 1",
+        _ => "repetition_penalty must be a positive number",
+    })
+}
+
+/// `web_search_options` of the search-preview models. It goes out as given; only its keys
+/// and `search_context_size` are checked, since a typo there would quietly search as default.
+fn check_web_search_options(options: &Value) -> Result<(), String> {
+    let options = match options {
+        Value::Null => return Ok(()),
+        Value::Object(options) => options,
+        _ => return Err("web_search_options must be a dict".to_string()),
+    };
+    for (key, value) in options {
+        match key.as_str() {
+            "search_context_size" if !matches!(value.as_str(), Some("low" | "medium" | "high")) => {
+                return Err("web_search_options search_context_size must be 'low', 'medium' or 'high'".to_string())
+            }
+            "user_location" if !value.is_object() => return Err("web_search_options user_location must be a dict".to_string()),
+            "search_context_size" | "user_location" => {}
+            _ => {
+                return Err(format!(
+                    "Unknown key '{}' in web_search_options: expected search_context_size or user_location",
+                    key
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Layers `overrides` on top of `defaults`; per-call values always win.
+/// An explicit `None` in the overrides removes a default instead of sending `null`.
+pub(crate) fn merge(defaults: &Map<String, Value>, overrides: Map<String, Value>) -> Map<String, Value> {
+    let mut merged = defaults.clone();
+    for (key, value) in overrides {
+        if value.is_null() {
+            merged.remove(&key);
+        } else {
+            merged.insert(key, value);
+        }
+    }
+    merged
+}
+
+/// The model families that reject `max_tokens` and take `max_completion_tokens` instead,
+/// unless a client passes its own `reasoning_models`.
+const REASONING_MODELS: &[&str] = &["o1", "o3", "o4"];
+
+/// The `reasoning_models` constructor argument, or the default families.
+pub(crate) fn reasoning_models(families: Option<Vec<String>>) -> PyResult<Vec<String>> {
+    let families = families.unwrap_or_else(|| REASONING_MODELS.iter().map(|family| family.to_string()).collect());
+    if families.iter().any(|family| family.is_empty()) {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("reasoning_models must be non-empty model name prefixes"));
+    }
+    Ok(families)
+}
+
+/// Whether `model` belongs to one of `families`: its name, after any `vendor/` prefix, is the
+/// family or starts with it followed by `-`, so `o3` and `o3-mini-2025-01-31` match `o3`
+/// but `o30` doesn't.
+pub(crate) fn is_reasoning_model(model: &str, families: &[String]) -> bool {
+    let name = model.rsplit('/').next().unwrap_or(model);
+    families.iter().any(|family| name.strip_prefix(family.as_str()).is_some_and(|rest| rest.is_empty() || rest.starts_with('-')))
+}
+
+/// Settles the completion token limit of a call. `max_tokens` and `max_completion_tokens`
+/// together are rejected; `max_tokens` for a reasoning model, which would reject it, is sent
+/// as `max_completion_tokens`, or rejected when `strict`.
+pub(crate) fn resolve_token_limit(params: &mut Map<String, Value>, model: &str, reasoning: bool, strict: bool) -> PyResult<()> {
+    if params.contains_key("max_tokens") && params.contains_key("max_completion_tokens") {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+            "pass either max_tokens or max_completion_tokens, not both",
+        ));
+    }
+    if !reasoning {
+        return Ok(());
+    }
+    let Some(limit) = params.remove("max_tokens") else {
+        return Ok(());
+    };
+    if strict {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "model '{}' is a reasoning model, which rejects max_tokens: pass max_completion_tokens instead \
+             (or strict_params=False to the client to translate it)",
+            model
+        )));
+    }
+    params.insert("max_completion_tokens".to_string(), limit);
+    Ok(())
+}
+
+/// Applies privacy mode to the parameters of a call: `store: false` is sent (when `send_store`,
+/// as the Messages API has no such field) and so are the provider's `opt_outs`, see
+/// `Provider::privacy_params`. A call or default asking for anything else is refused rather
+/// than overridden, so what was sent is never a surprise.
+pub(crate) fn enforce_privacy(
+    params: &mut Map<String, Value>,
+    send_store: bool,
+    opt_outs: &[(&str, &str, &str)],
+) -> PyResult<()> {
+    let refuse = |what: String| {
+        crate::errors::PrivacyModeError::new_err(format!(
+            "{} would let the provider retain data, and the client was built with privacy_mode=True",
+            what
+        ))
+    };
+    match params.get("store") {
+        None | Some(Value::Bool(false)) => {}
+        Some(store) => return Err(refuse(format!("store={}", store))),
+    }
+    if send_store {
+        params.insert("store".to_string(), Value::Bool(false));
+    }
+    for (param, field, value) in opt_outs {
+        let Value::Object(fields) = params.entry(param.to_string()).or_insert_with(|| Value::Object(Map::new())) else {
+            return Err(refuse(format!("a '{}' that is not an object", param)));
+        };
+        match fields.get(*field) {
+            Some(Value::String(given)) if given == value => {}
+            None => {
+                fields.insert(field.to_string(), Value::from(*value));
+            }
+            Some(given) => return Err(refuse(format!("{}.{}={}", param, field, given))),
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn to_json(value: &Bound<'_, PyAny>) -> PyResult<Value> {
+    if value.is_none() {
+        Ok(Value::Null)
+    } else if let Ok(b) = value.downcast::<PyBool>() {
+        Ok(Value::Bool(b.is_true()))
+    } else if value.is_instance_of::<PyInt>() {
+        if let Ok(i) = value.extract::<i64>() {
+            Ok(Value::from(i))
+        } else {
+            Ok(Value::from(value.extract::<u64>()?))
+        }
+    } else if let Ok(f) = value.downcast::<PyFloat>() {
+        Number::from_f64(f.value())
+            .map(Value::Number)
+            .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyValueError, _>("NaN and infinity are not valid JSON"))
+    } else if let Ok(s) = value.downcast::<PyString>() {
+        Ok(Value::String(s.to_str()?.to_string()))
+    } else if let Ok(list) = value.downcast::<PyList>() {
+        list.iter().map(|item| to_json(&item)).collect::<PyResult<Vec<_>>>().map(Value::Array)
+    } else if let Ok(tuple) = value.downcast::<PyTuple>() {
+        tuple.iter().map(|item| to_json(&item)).collect::<PyResult<Vec<_>>>().map(Value::Array)
+    } else if let Ok(dict) = value.downcast::<PyDict>() {
+        let mut map = Map::new();
+        for (k, v) in dict.iter() {
+            let k: String = k
+                .extract()
+                .map_err(|_| PyErr::new::<pyo3::exceptions::PyTypeError, _>("dict keys must be str"))?;
+            map.insert(k, to_json(&v)?);
+        }
+        Ok(Value::Object(map))
+    } else {
+        Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!(
+            "unsupported type '{}'",
+            value.get_type().name()?
+        )))
+    }
+}
+
+pub(crate) fn to_py<'py>(py: Python<'py>, value: &Value) -> PyResult<Bound<'py, PyAny>> {
+    Ok(match value {
+        Value::Null => py.None().into_bound(py),
+        Value::Bool(b) => PyBool::new(py, *b).to_owned().into_any(),
+        Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => i.into_pyobject(py)?.into_any(),
+            (None, Some(u)) => u.into_pyobject(py)?.into_any(),
+            _ => n.as_f64().unwrap_or(f64::NAN).into_pyobject(py)?.into_any(),
+        },
+        Value::String(s) => PyString::new(py, s).into_any(),
+        Value::Array(items) => {
+            let list = PyList::empty(py);
+            for item in items {
+                list.append(to_py(py, item)?)?;
+            }
+            list.into_any()
+        }
+        Value::Object(map) => {
+            let dict = PyDict::new(py);
+            for (k, v) in map {
+                dict.set_item(k, to_py(py, v)?)?;
+            }
+            dict.into_any()
+        }
+    })
+}
@@ -0,0 +1,164 @@
+use pyo3::prelude::*;
+
+// --- Provider Presets ---
+
+/// What differs between providers: where the API lives under the host, which headers they
+/// expect, and how their responses deviate from OpenAI's. Adding a provider is a new entry
+/// in `PROVIDERS` plus a recorded response in the tests.
+#[derive(Debug)]
+pub(crate) struct Provider {
+    pub(crate) name: &'static str,
+    pub(crate) api: Api,
+    /// Path of the chat endpoint under the base URL.
+    pub(crate) path: &'static str,
+    /// Header carrying the API key, when it is not `Authorization: Bearer`.
+    pub(crate) key_header: Option<&'static str>,
+    /// Whether the API runs without authentication, so an empty API key sends no header.
+    pub(crate) key_optional: bool,
+    /// Sent with every request; `default_headers` of the same name win.
+    pub(crate) headers: &'static [(&'static str, &'static str)],
+    /// Whether responses carry a `system_fingerprint`, without which
+    /// `on_fingerprint_change` would never fire.
+    pub(crate) reports_fingerprint: bool,
+    /// Where streamed responses put the token usage.
+    pub(crate) stream_usage: StreamUsage,
+    /// Request parameters the provider rejects, left out of the body.
+    pub(crate) stripped_params: &'static [&'static str],
+    /// Parameters sent under another name: `(ours, theirs)`.
+    pub(crate) renamed_params: &'static [(&'static str, &'static str)],
+    /// Opt-outs of provider-side data retention sent in privacy mode: `(param, field, value)`
+    /// sets `field` of the object parameter `param`.
+    pub(crate) privacy_params: &'static [(&'static str, &'static str, &'static str)],
+    /// The provider's finish reasons and the chat completion ones they stand for.
+    pub(crate) finish_reasons: &'static [(&'static str, &'static str)],
+    pub(crate) error_shape: ErrorShape,
+    /// Appended to a 404 error, with `{model}` replaced by the requested model.
+    pub(crate) not_found_hint: Option<&'static str>,
+}
+
+/// The wire format spoken by a provider.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Api {
+    /// OpenAI's chat completions.
+    OpenAi,
+    /// Anthropic's Messages API; see `anthropic`.
+    Anthropic,
+}
+
+/// How error responses are laid out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum ErrorShape {
+    /// `{"error": {"message", "type", "code", "param"}}`.
+    #[default]
+    OpenAi,
+    /// Google's `[{"error": {"code", "message", "status", "details"}}]`, with or without the list.
+    Google,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum StreamUsage {
+    /// A top-level `usage` on the last chunk, as OpenAI sends it.
+    #[default]
+    TopLevel,
+    /// `x_groq.usage` on the last chunk.
+    XGroq,
+}
+
+/// What a provider shares with OpenAI unless its entry says otherwise.
+const OPENAI_COMPATIBLE: Provider = Provider {
+    name: "",
+    api: Api::OpenAi,
+    path: "/v1/chat/completions",
+    key_header: None,
+    key_optional: false,
+    headers: &[],
+    reports_fingerprint: false,
+    stream_usage: StreamUsage::TopLevel,
+    stripped_params: &[],
+    renamed_params: &[],
+    privacy_params: &[],
+    finish_reasons: &[],
+    error_shape: ErrorShape::OpenAi,
+    not_found_hint: None,
+};
+
+pub(crate) const PROVIDERS: &[Provider] = &[
+    Provider { name: "openai", reports_fingerprint: true, ..OPENAI_COMPATIBLE },
+    Provider {
+        name: "groq",
+        path: "/openai/v1/chat/completions",
+        reports_fingerprint: true,
+        stream_usage: StreamUsage::XGroq,
+        ..OPENAI_COMPATIBLE
+    },
+    Provider {
+        name: "openrouter",
+        path: "/api/v1/chat/completions",
+        // OpenRouter attributes traffic to an app by these two headers.
+        headers: &[("HTTP-Referer", "https://github.com/AIvantGuard-AG/secure_openaiapi"), ("X-Title", "secure_openaiapi")],
+        // Routes only to upstream providers that neither store nor train on prompts.
+        privacy_params: &[("provider", "data_collection", "deny")],
+        ..OPENAI_COMPATIBLE
+    },
+    Provider { name: "together", ..OPENAI_COMPATIBLE },
+    Provider { name: "fireworks", path: "/inference/v1/chat/completions", ..OPENAI_COMPATIBLE },
+    Provider { name: "deepseek", path: "/chat/completions", reports_fingerprint: true, ..OPENAI_COMPATIBLE },
+    Provider {
+        name: "gemini",
+        path: "/v1beta/openai/chat/completions",
+        // OpenAI bookkeeping fields the compatibility layer rejects as unknown.
+        stripped_params: &["user", "store", "metadata", "service_tier"],
+        // Some API versions pass Gemini's own finish reasons through, in capitals.
+        finish_reasons: &[
+            ("stop", "stop"),
+            ("max_tokens", "length"),
+            ("safety", "content_filter"),
+            ("recitation", "content_filter"),
+            ("blocklist", "content_filter"),
+            ("prohibited_content", "content_filter"),
+            ("spii", "content_filter"),
+            ("image_safety", "content_filter"),
+        ],
+        error_shape: ErrorShape::Google,
+        ..OPENAI_COMPATIBLE
+    },
+    Provider {
+        name: "ollama",
+        key_optional: true,
+        reports_fingerprint: true,
+        // Ollama's model options (num_ctx, num_gpu, ...) go in `options`.
+        renamed_params: &[("ollama_options", "options")],
+        // Ollama answers 404 for a model that has not been pulled.
+        not_found_hint: Some("if the model is not pulled yet, run `ollama pull {model}` on the Ollama host"),
+        ..OPENAI_COMPATIBLE
+    },
+    // Self-hosted servers, which run without a key unless started with one.
+    Provider { name: "vllm", key_optional: true, ..OPENAI_COMPATIBLE },
+    Provider { name: "llamacpp", key_optional: true, reports_fingerprint: true, ..OPENAI_COMPATIBLE },
+    Provider {
+        name: "anthropic",
+        api: Api::Anthropic,
+        path: "/v1/messages",
+        key_header: Some("x-api-key"),
+        headers: &[("anthropic-version", "2023-06-01")],
+        ..OPENAI_COMPATIBLE
+    },
+];
+
+impl Provider {
+    /// `reason` in the chat completion vocabulary, matched case-insensitively against
+    /// `finish_reasons`; reasons not listed there are kept as sent.
+    pub(crate) fn finish_reason(&self, reason: String) -> String {
+        match self.finish_reasons.iter().find(|(theirs, _)| theirs.eq_ignore_ascii_case(&reason)) {
+            Some((_, ours)) => ours.to_string(),
+            None => reason,
+        }
+    }
+}
+
+pub(crate) fn lookup(name: &str) -> PyResult<&'static Provider> {
+    PROVIDERS.iter().find(|provider| provider.name == name).ok_or_else(|| {
+        let names = PROVIDERS.iter().map(|provider| format!("'{}'", provider.name)).collect::<Vec<_>>().join(", ");
+        PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Unknown provider '{}': expected one of {}", name, names))
+    })
+}
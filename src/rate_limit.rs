@@ -0,0 +1,269 @@
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use reqwest::header::HeaderMap;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// --- Rate Limit Headers ---
+
+/// Rate limit state reported by the provider in `x-ratelimit-*` response headers.
+/// Every field is optional: headers that are missing or malformed are simply left out.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RateLimits {
+    limit_requests: Option<u64>,
+    limit_tokens: Option<u64>,
+    remaining_requests: Option<u64>,
+    remaining_tokens: Option<u64>,
+    reset_requests: Option<f64>,
+    reset_tokens: Option<f64>,
+}
+
+impl RateLimits {
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim);
+        let count = |name: &str| header(name).and_then(|v| v.parse::<u64>().ok());
+        let reset = |name: &str| header(name).and_then(parse_duration);
+
+        Self {
+            limit_requests: count("x-ratelimit-limit-requests"),
+            limit_tokens: count("x-ratelimit-limit-tokens"),
+            remaining_requests: count("x-ratelimit-remaining-requests"),
+            remaining_tokens: count("x-ratelimit-remaining-tokens"),
+            reset_requests: reset("x-ratelimit-reset-requests"),
+            reset_tokens: reset("x-ratelimit-reset-tokens"),
+        }
+    }
+
+    /// Seconds until the limit that ran out resets. When no bucket is reported as empty,
+    /// the longest reported reset is used.
+    pub(crate) fn reset_after(&self) -> Option<f64> {
+        let buckets = [(self.remaining_requests, self.reset_requests), (self.remaining_tokens, self.reset_tokens)];
+        let longest = |empty_only: bool| {
+            buckets
+                .iter()
+                .filter(|(remaining, _)| !empty_only || *remaining == Some(0))
+                .filter_map(|(_, reset)| *reset)
+                .reduce(f64::max)
+        };
+        longest(true).or_else(|| longest(false))
+    }
+
+    /// Builds a dict containing only the values the provider actually reported.
+    /// Reset values are converted to seconds.
+    pub(crate) fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        let counts = [
+            ("limit_requests", self.limit_requests),
+            ("limit_tokens", self.limit_tokens),
+            ("remaining_requests", self.remaining_requests),
+            ("remaining_tokens", self.remaining_tokens),
+        ];
+        for (key, value) in counts {
+            if let Some(value) = value {
+                dict.set_item(key, value)?;
+            }
+        }
+        let resets = [("reset_requests", self.reset_requests), ("reset_tokens", self.reset_tokens)];
+        for (key, value) in resets {
+            if let Some(value) = value {
+                dict.set_item(key, value)?;
+            }
+        }
+        Ok(dict)
+    }
+}
+
+/// Parses Go-style durations as sent by OpenAI (`"1s"`, `"6m0s"`, `"20ms"`, `"1h2m3.5s"`) into seconds.
+/// A bare number is interpreted as seconds.
+pub(crate) fn parse_duration(value: &str) -> Option<f64> {
+    if value.is_empty() {
+        return None;
+    }
+    if let Ok(seconds) = value.parse::<f64>() {
+        return (seconds.is_finite() && seconds >= 0.0).then_some(seconds);
+    }
+
+    let mut total = 0.0;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let number_len = rest.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(rest.len());
+        if number_len == 0 {
+            return None;
+        }
+        let number: f64 = rest[..number_len].parse().ok()?;
+        rest = &rest[number_len..];
+
+        let unit_len = rest.find(|c: char| c.is_ascii_digit() || c == '.').unwrap_or(rest.len());
+        let scale = match &rest[..unit_len] {
+            "h" => 3600.0,
+            "m" => 60.0,
+            "s" => 1.0,
+            "ms" => 1e-3,
+            "us" | "µs" => 1e-6,
+            "ns" => 1e-9,
+            _ => return None,
+        };
+        rest = &rest[unit_len..];
+        total += number * scale;
+    }
+    total.is_finite().then_some(total)
+}
+
+// --- Client-Side Rate Limiter ---
+
+#[derive(Debug)]
+struct Bucket {
+    capacity: f64,
+    available: f64,
+    refill_per_sec: f64,
+}
+
+impl Bucket {
+    fn per_minute(limit: u32) -> Self {
+        let capacity = f64::from(limit);
+        Self { capacity, available: capacity, refill_per_sec: capacity / 60.0 }
+    }
+
+    fn refill(&mut self, elapsed: Duration) {
+        self.available = (self.available + elapsed.as_secs_f64() * self.refill_per_sec).min(self.capacity);
+    }
+
+    /// Time until `amount` units are available, zero if they already are.
+    fn wait_for(&self, amount: f64) -> Duration {
+        let missing = amount - self.available;
+        if missing <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(missing / self.refill_per_sec)
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Buckets {
+    requests: Option<Bucket>,
+    tokens: Option<Bucket>,
+    updated: Instant,
+}
+
+impl Buckets {
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated);
+        self.updated = now;
+        for bucket in [&mut self.requests, &mut self.tokens].into_iter().flatten() {
+            bucket.refill(elapsed);
+        }
+    }
+}
+
+/// Token buckets for requests and tokens per minute, shared by every thread using the client.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    buckets: Mutex<Buckets>,
+    max_wait: Option<Duration>,
+}
+
+/// Why a rate limiter acquisition failed.
+#[derive(Debug)]
+pub(crate) enum AcquireError {
+    /// The request needs more tokens than the per-minute budget can ever hold.
+    ExceedsCapacity { needed: u64, capacity: u64 },
+    /// Capacity would only become available after `max_wait`.
+    Timeout { max_wait: Duration, needed_wait: Duration },
+    /// Capacity would only become available after the call's deadline.
+    Deadline { needed_wait: Duration },
+}
+
+impl std::fmt::Display for AcquireError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AcquireError::ExceedsCapacity { needed, capacity } => write!(
+                f,
+                "client-side rate limit: request needs ~{} tokens but the limit is {} tokens per minute",
+                needed, capacity
+            ),
+            AcquireError::Timeout { max_wait, needed_wait } => write!(
+                f,
+                "client-side rate limit: capacity not available within max_wait={:.3}s (would need {:.3}s)",
+                max_wait.as_secs_f64(),
+                needed_wait.as_secs_f64()
+            ),
+            AcquireError::Deadline { needed_wait } => write!(
+                f,
+                "client-side rate limit: capacity not available before the deadline (would need {:.3}s)",
+                needed_wait.as_secs_f64()
+            ),
+        }
+    }
+}
+
+impl RateLimiter {
+    pub(crate) fn new(rpm: Option<u32>, tpm: Option<u32>, max_wait: Option<Duration>) -> Self {
+        Self {
+            buckets: Mutex::new(Buckets {
+                requests: rpm.map(Bucket::per_minute),
+                tokens: tpm.map(Bucket::per_minute),
+                updated: Instant::now(),
+            }),
+            max_wait,
+        }
+    }
+
+    /// Blocks until one request and `tokens` tokens are available, then takes them, waiting
+    /// no longer than `max_wait` or, if given, `deadline`, whichever is shorter. Must be
+    /// called without holding the GIL.
+    pub(crate) fn acquire(&self, tokens: u64, deadline: Option<Duration>) -> Result<(), AcquireError> {
+        let started = Instant::now();
+        loop {
+            let wait = {
+                let mut buckets = self.buckets.lock().unwrap();
+                if let Some(bucket) = &buckets.tokens {
+                    if tokens as f64 > bucket.capacity {
+                        return Err(AcquireError::ExceedsCapacity { needed: tokens, capacity: bucket.capacity as u64 });
+                    }
+                }
+                buckets.refill();
+                let wait = [
+                    buckets.requests.as_ref().map(|b| b.wait_for(1.0)),
+                    buckets.tokens.as_ref().map(|b| b.wait_for(tokens as f64)),
+                ]
+                .into_iter()
+                .flatten()
+                .max()
+                .unwrap_or(Duration::ZERO);
+
+                if wait.is_zero() {
+                    if let Some(bucket) = &mut buckets.requests {
+                        bucket.available -= 1.0;
+                    }
+                    if let Some(bucket) = &mut buckets.tokens {
+                        bucket.available -= tokens as f64;
+                    }
+                    return Ok(());
+                }
+                wait
+            };
+
+            let needed_wait = started.elapsed() + wait;
+            match (self.max_wait, deadline) {
+                (Some(max_wait), _) if needed_wait > max_wait && deadline.is_none_or(|deadline| max_wait <= deadline) => {
+                    return Err(AcquireError::Timeout { max_wait, needed_wait });
+                }
+                (_, Some(deadline)) if needed_wait > deadline => return Err(AcquireError::Deadline { needed_wait }),
+                _ => {}
+            }
+            thread::sleep(wait);
+        }
+    }
+
+    /// Corrects the token bucket once the real usage of a request is known.
+    /// Over-estimates are refunded; under-estimates are charged, possibly into debt.
+    pub(crate) fn reconcile(&self, estimated: u64, actual: u64) {
+        let mut buckets = self.buckets.lock().unwrap();
+        if let Some(bucket) = &mut buckets.tokens {
+            bucket.available = (bucket.available + estimated as f64 - actual as f64).min(bucket.capacity);
+        }
+    }
+}
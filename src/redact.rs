@@ -0,0 +1,146 @@
+// --- Secret Redaction ---
+
+/// Longest piece of a server-provided error text that is ever put into an exception.
+pub(crate) const MAX_ERROR_TEXT: usize = 512;
+
+const MASK: &str = "****";
+
+/// Makes text from the server safe to show: every occurrence of `secrets`, and anything
+/// shaped like an API key (`sk-...`) or bearer token, is masked, and the result is capped
+/// at `max_len` bytes. Gateways sometimes echo request headers or body fragments back in
+/// their error messages, so nothing they return is trusted.
+pub(crate) fn redact(text: &str, secrets: &[&[u8]], max_len: usize) -> String {
+    let mut text = text.to_string();
+    for secret in secrets.iter().filter(|secret| secret.len() >= 4) {
+        if let Ok(secret) = std::str::from_utf8(secret) {
+            text = text.replace(secret, MASK);
+        }
+    }
+    let text = mask_tokens(&mask_tokens(&text, "sk-", 16), "Bearer ", 8);
+    truncate(text, max_len)
+}
+
+/// Masks the token following every `prefix` when it is at least `min_len` token characters long.
+fn mask_tokens(text: &str, prefix: &str, min_len: usize) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(prefix) {
+        let after = &rest[start + prefix.len()..];
+        let token_len = after.find(|c: char| !(c.is_ascii_alphanumeric() || "-_.~+/=".contains(c))).unwrap_or(after.len());
+        out.push_str(&rest[..start + prefix.len()]);
+        if token_len >= min_len {
+            out.push_str(MASK);
+        } else {
+            out.push_str(&after[..token_len]);
+        }
+        rest = &after[token_len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn truncate(mut text: String, max_len: usize) -> String {
+    if text.len() > max_len {
+        let mut end = max_len;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text.push_str("... (truncated)");
+    }
+    text
+}
+
+// --- Transport Error Sanitizing ---
+
+/// Describes a reqwest error without the URL it carries: the target is reduced to its
+/// scheme and a hash of the host, so the message can say which kind of failure it was
+/// (DNS, connect, TLS, timeout) and still be compared across log lines without spelling
+/// out internal hostnames, paths or query strings.
+pub(crate) fn transport_error(error: &reqwest::Error) -> String {
+    let host = error.url().and_then(|url| url.host_str()).unwrap_or_default();
+    let mut causes = Vec::new();
+    let mut source = std::error::Error::source(error);
+    while let Some(err) = source {
+        causes.push(err.to_string());
+        source = err.source();
+    }
+    let kind = failure_kind(error, &causes);
+    let mut text = match error.url() {
+        Some(url) => format!("{} ({}://{})", kind, url.scheme(), host_token(host)),
+        None => kind.to_string(),
+    };
+    // The innermost cause is the one that says what actually went wrong ("Connection
+    // refused", the resolver's or TLS library's message); the layers above it only wrap it.
+    if let Some(detail) = causes.last() {
+        text.push_str(": ");
+        text.push_str(&scrub_urls(&scrub_host(detail, host)));
+    }
+    text
+}
+
+fn failure_kind(error: &reqwest::Error, causes: &[String]) -> &'static str {
+    let mentions = |needles: &[&str]| {
+        causes.iter().any(|cause| {
+            let cause = cause.to_ascii_lowercase();
+            needles.iter().any(|needle| cause.contains(needle))
+        })
+    };
+    if error.is_timeout() {
+        "request timed out"
+    } else if mentions(&["dns error"]) {
+        "DNS lookup failed"
+    } else if mentions(&["ssl", "tls", "certificate", "handshake"]) {
+        "TLS handshake failed"
+    } else if error.is_connect() {
+        "connection failed"
+    } else if error.is_redirect() {
+        "redirect failed"
+    } else if error.is_body() || error.is_decode() {
+        "reading the response body failed"
+    } else {
+        "request failed"
+    }
+}
+
+/// What error messages show in place of a host: the first 4 bytes of its BLAKE2b
+/// hash, stable across processes so the same target is recognizable in different logs.
+fn host_token(host: &str) -> String {
+    use libsodium_sys::{crypto_generichash, crypto_generichash_BYTES_MIN};
+    let mut hash = [0u8; crypto_generichash_BYTES_MIN as usize];
+    unsafe {
+        crypto_generichash(hash.as_mut_ptr(), hash.len(), host.as_ptr(), host.len() as u64, std::ptr::null(), 0);
+    }
+    let hex: String = hash[..4].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("<host {}>", hex)
+}
+
+fn scrub_host(text: &str, host: &str) -> String {
+    if host.is_empty() {
+        text.to_string()
+    } else {
+        text.replace(host, &host_token(host))
+    }
+}
+
+/// Replaces every URL in `text` with its scheme and host token, dropping credentials, port,
+/// path and query string.
+fn scrub_urls(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(separator) = rest.find("://") {
+        let after = &rest[separator + 3..];
+        let url_len = after.find(|c: char| c.is_whitespace() || "\"'()<>,".contains(c)).unwrap_or(after.len());
+        let authority = after[..url_len].split(['/', '?', '#']).next().unwrap_or_default();
+        let host_port = authority.rsplit('@').next().unwrap_or_default();
+        let host = match host_port.strip_prefix('[') {
+            Some(bracketed) => bracketed.split(']').next().unwrap_or_default(),
+            None => host_port.split(':').next().unwrap_or_default(),
+        };
+        out.push_str(&rest[..separator + 3]);
+        out.push_str(&host_token(host));
+        rest = &after[url_len..];
+    }
+    out.push_str(rest);
+    out
+}
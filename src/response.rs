@@ -0,0 +1,213 @@
+use crate::errors;
+use crate::transport;
+use crate::{ChatCompletionResponse, SecureBytes, SecureToolCall, TokenLogprob, UrlCitation, Usage};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyTuple};
+use reqwest::header::HeaderMap;
+use reqwest::Version;
+
+// --- SecureResponse ---
+
+/// A parsed chat completion: the first choice's content stays in `SecureBytes`, everything
+/// else is plain metadata.
+#[pyclass(name = "SecureResponse", frozen)]
+pub(crate) struct SecureResponse {
+    #[pyo3(get)]
+    content: Py<SecureBytes>,
+    /// Set instead of `content` when the model declined. Secret like the content, since
+    /// refusals can quote the prompt.
+    #[pyo3(get)]
+    refusal: Option<Py<SecureBytes>>,
+    #[pyo3(get)]
+    tool_calls: Vec<Py<SecureToolCall>>,
+    #[pyo3(get)]
+    finish_reason: Option<String>,
+    #[pyo3(get)]
+    model: Option<String>,
+    #[pyo3(get)]
+    id: Option<String>,
+    #[pyo3(get)]
+    created: Option<u64>,
+    #[pyo3(get)]
+    system_fingerprint: Option<String>,
+    usage: Option<Usage>,
+    logprobs: Option<Vec<TokenLogprob>>,
+    citations: Vec<UrlCitation>,
+    rate_limit_headers: Vec<(String, String)>,
+    /// The protocol that served the response, e.g. `"HTTP/1.1"` or `"HTTP/2"`.
+    #[pyo3(get)]
+    http_version: &'static str,
+}
+
+impl SecureResponse {
+    /// Builds the response from the first choice of `body`, which must have one.
+    pub(crate) fn new(py: Python<'_>, body: ChatCompletionResponse, headers: &HeaderMap, version: Version) -> PyResult<Self> {
+        let rate_limit_headers = headers
+            .iter()
+            .filter(|(name, _)| name.as_str().starts_with("x-ratelimit-"))
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let choice = body.choices.into_iter().next().expect("the caller checked for a choice");
+        let secure = |text: Option<String>| text.map(|text| Py::new(py, SecureBytes::try_new(text.as_bytes())?)).transpose();
+        Ok(Self {
+            content: Py::new(py, SecureBytes::try_new(choice.message.content.as_deref().unwrap_or("").as_bytes())?)?,
+            refusal: secure(choice.message.refusal)?,
+            tool_calls: choice
+                .message
+                .tool_calls
+                .into_iter()
+                .flatten()
+                .map(|call| Py::new(py, SecureToolCall::new(py, call)?))
+                .collect::<PyResult<_>>()?,
+            citations: choice
+                .message
+                .annotations
+                .into_iter()
+                .flatten()
+                .filter_map(|annotation| annotation.url_citation)
+                .collect(),
+            finish_reason: choice.finish_reason,
+            model: body.model,
+            id: body.id,
+            created: body.created,
+            system_fingerprint: body.system_fingerprint,
+            usage: body.usage,
+            logprobs: choice.logprobs.and_then(|logprobs| logprobs.content),
+            rate_limit_headers,
+            http_version: transport::version_name(version),
+        })
+    }
+
+    /// Reports a finished stream to the `stream_chat_with_events` callbacks: each tool call
+    /// to `on_tool_call`, then the finish reason and usage to `on_finish`.
+    pub(crate) fn report_finish(&self, py: Python<'_>, on_tool_call: Option<&Bound<'_, PyAny>>, on_finish: Option<&Bound<'_, PyAny>>) -> PyResult<()> {
+        if let Some(on_tool_call) = on_tool_call {
+            for tool_call in &self.tool_calls {
+                on_tool_call.call1((tool_call.clone_ref(py),))?;
+            }
+        }
+        if let Some(on_finish) = on_finish {
+            on_finish.call1((self.finish_reason.as_deref(), self.usage(py)?))?;
+        }
+        Ok(())
+    }
+
+    /// The content for callers that only want the text: a refusal becomes `RefusalError`,
+    /// and tool calls, which have no place in the text, `ToolCallNotSupportedError`.
+    pub(crate) fn into_content(self, py: Python<'_>) -> PyResult<Py<SecureBytes>> {
+        if let Some(refusal) = self.refusal {
+            return Err(errors::refusal(py, refusal));
+        }
+        if !self.tool_calls.is_empty() || self.finish_reason.as_deref() == Some("tool_calls") {
+            return Err(errors::tool_calls_not_supported(py, self.tool_calls));
+        }
+        Ok(self.content)
+    }
+}
+
+#[pymethods]
+impl SecureResponse {
+    /// Token counts reported by the provider, or `None` when the response had no `usage`.
+    #[getter]
+    fn usage<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyDict>>> {
+        self.usage.as_ref().map(|usage| usage.to_dict(py)).transpose()
+    }
+
+    /// Per-token log probabilities as `(token, logprob, [(token, logprob), ...])` tuples with
+    /// `SecureBytes` tokens, or `None` when they weren't requested.
+    #[getter]
+    fn logprobs<'py>(&self, py: Python<'py>) -> PyResult<Option<Vec<Bound<'py, PyTuple>>>> {
+        self.logprobs.as_ref().map(|tokens| tokens.iter().map(|token| token.to_py(py)).collect()).transpose()
+    }
+
+    /// The URL citations of the message, as dicts of `url`, `title`, and the `start_index`
+    /// and `end_index` of the cited span of the content. Empty when the model cited nothing.
+    #[getter]
+    fn annotations<'py>(&self, py: Python<'py>) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        self.citations.iter().map(|citation| citation.to_dict(py)).collect()
+    }
+
+    /// The `x-ratelimit-*` response headers exactly as the provider sent them.
+    #[getter]
+    fn rate_limit_headers<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        for (name, value) in &self.rate_limit_headers {
+            dict.set_item(name, value)?;
+        }
+        Ok(dict)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "SecureResponse(id={}, model={}, finish_reason={}, content=SecureBytes(b'****'))",
+            py_repr(&self.id),
+            py_repr(&self.model),
+            py_repr(&self.finish_reason)
+        )
+    }
+}
+
+fn py_repr(value: &Option<String>) -> String {
+    match value {
+        Some(value) => format!("'{}'", value),
+        None => "None".to_string(),
+    }
+}
+
+// --- Raw Responses ---
+
+/// The HTTP envelope of a chat completion, for archiving: the status, the headers as
+/// strings (minus any that carry credentials) and the body exactly as received, after
+/// undoing any `Content-Encoding`, in `SecureBytes`. `parse()` gives what
+/// `chat_completion_full` would have made of the same body, without a second request.
+#[pyclass(name = "SecureRawResponse", frozen)]
+pub(crate) struct SecureRawResponse {
+    #[pyo3(get)]
+    status_code: u16,
+    headers: Vec<(String, String)>,
+    #[pyo3(get)]
+    body: Py<SecureBytes>,
+    parsed: PyResult<Py<SecureResponse>>,
+}
+
+impl SecureRawResponse {
+    pub(crate) fn new(
+        py: Python<'_>,
+        status_code: u16,
+        headers: Vec<(String, String)>,
+        body: SecureBytes,
+        parsed: PyResult<SecureResponse>,
+    ) -> PyResult<Self> {
+        Ok(Self { status_code, headers, body: Py::new(py, body)?, parsed: parsed.and_then(|response| Py::new(py, response)) })
+    }
+
+    /// The exception `parse()` raises, if any.
+    pub(crate) fn error(&self) -> Option<&PyErr> {
+        self.parsed.as_ref().err()
+    }
+}
+
+#[pymethods]
+impl SecureRawResponse {
+    #[getter]
+    fn headers<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        for (name, value) in &self.headers {
+            dict.set_item(name, value)?;
+        }
+        Ok(dict)
+    }
+
+    /// The parsed `SecureResponse`, or the exception `chat_completion_full` would have
+    /// raised for this response, such as `RateLimitError` for a 429.
+    fn parse(&self, py: Python<'_>) -> PyResult<Py<SecureResponse>> {
+        match &self.parsed {
+            Ok(response) => Ok(response.clone_ref(py)),
+            Err(err) => Err(err.clone_ref(py)),
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("SecureRawResponse(status_code={}, body=SecureBytes(b'****'))", self.status_code)
+    }
+}
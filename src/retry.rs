@@ -0,0 +1,181 @@
+use crate::rate_limit::RateLimits;
+use libsodium_sys::randombytes_uniform;
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// --- Retries ---
+
+/// Default cap on a single wait between attempts, however long the server asks for.
+pub(crate) const DEFAULT_MAX_RETRY_WAIT: Duration = Duration::from_secs(60);
+
+/// First exponential backoff step, used when the server gives no hint.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RetryPolicy {
+    pub(crate) max_retries: u32,
+    pub(crate) max_wait: Duration,
+}
+
+impl RetryPolicy {
+    pub(crate) fn new(max_retries: u32, max_retry_wait: f64) -> Result<Self, String> {
+        if !(max_retry_wait.is_finite() && max_retry_wait >= 0.0) {
+            return Err("max_retry_wait must be a non-negative number of seconds".to_string());
+        }
+        Ok(Self { max_retries, max_wait: Duration::from_secs_f64(max_retry_wait) })
+    }
+
+    /// How long to wait before attempt `retry + 1`: what the server asked for if it said,
+    /// exponential backoff with jitter otherwise, capped at `max_wait` either way.
+    pub(crate) fn delay(&self, status: StatusCode, headers: &HeaderMap, retry: u32) -> Duration {
+        server_delay(status, headers).unwrap_or_else(|| backoff(retry)).min(self.max_wait)
+    }
+}
+
+/// Statuses worth retrying: rate limiting and the server being temporarily unavailable.
+pub(crate) fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE
+}
+
+/// How long the server asked us to wait, from `retry-after-ms`, `Retry-After` (seconds or
+/// an HTTP date) or, on a 429 without those, the `x-ratelimit-reset-*` headers.
+pub(crate) fn server_delay(status: StatusCode, headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim);
+    if let Some(ms) = header("retry-after-ms").and_then(|v| v.parse::<f64>().ok()) {
+        if let Some(delay) = seconds(ms / 1000.0) {
+            return Some(delay);
+        }
+    }
+    if let Some(value) = header("retry-after") {
+        if let Ok(value) = value.parse::<f64>() {
+            if let Some(delay) = seconds(value) {
+                return Some(delay);
+            }
+        } else if let Some(at) = parse_http_date(value) {
+            return Some(at.duration_since(SystemTime::now()).unwrap_or_default());
+        }
+    }
+    if status != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+    RateLimits::from_headers(headers).reset_after().and_then(seconds)
+}
+
+/// A wait the server sent in seconds, saturating at `Duration::MAX` for ones too long to
+/// represent; callers cap it at `max_wait` anyway.
+fn seconds(value: f64) -> Option<Duration> {
+    (value.is_finite() && value >= 0.0).then(|| Duration::try_from_secs_f64(value).unwrap_or(Duration::MAX))
+}
+
+// --- Deadlines ---
+
+/// The time budget of one call under `deadline=`, shared by its attempts, the waits
+/// between them and any moderation check. Its clock starts when the first of them is sent,
+/// so time spent queued behind other calls, as in `chat_completion_many`, is not counted.
+#[derive(Clone, Debug)]
+pub(crate) struct Deadline {
+    budget: Duration,
+    started: Arc<OnceLock<Instant>>,
+}
+
+impl Deadline {
+    pub(crate) fn new(budget: Duration) -> Self {
+        Self { budget, started: Arc::new(OnceLock::new()) }
+    }
+
+    /// Starts the clock, unless a request sharing this deadline already did.
+    pub(crate) fn start(&self) {
+        self.started.get_or_init(Instant::now);
+    }
+
+    pub(crate) fn budget(&self) -> Duration {
+        self.budget
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.started.get().map_or(Duration::ZERO, Instant::elapsed)
+    }
+
+    /// Zero once the budget is used up.
+    pub(crate) fn remaining(&self) -> Duration {
+        self.budget.saturating_sub(self.elapsed())
+    }
+}
+
+/// Where the time of a call that ran out of its deadline went.
+#[derive(Debug, Default)]
+pub(crate) struct Spent {
+    /// How long each attempt took, the last one included.
+    pub(crate) attempts: Vec<Duration>,
+    /// Time slept between attempts and waiting for the rate limiter.
+    pub(crate) waited: Duration,
+}
+
+/// 0.5s, 1s, 2s, ... with up to 25% random jitter so parallel clients spread out.
+fn backoff(retry: u32) -> Duration {
+    let base = INITIAL_BACKOFF.saturating_mul(2u32.saturating_pow(retry.min(16)));
+    let jitter = unsafe { randombytes_uniform(1000) };
+    base + base.mul_f64(f64::from(jitter) / 4000.0)
+}
+
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// Parses an IMF-fixdate (`Sun, 06 Nov 1994 08:49:37 GMT`), the only form RFC 9110
+/// requires senders to use.
+fn parse_http_date(value: &str) -> Option<SystemTime> {
+    let (_, rest) = value.split_once(", ")?;
+    let mut parts = rest.split(' ');
+    let day: u64 = parts.next()?.parse().ok()?;
+    let month_name = parts.next()?;
+    let month = MONTHS.iter().position(|&m| m == month_name)? as u64 + 1;
+    let year: u64 = parts.next()?.parse().ok()?;
+    let mut time = parts.next()?.split(':').map(|v| v.parse::<u64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+    if parts.next()? != "GMT" || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 || year < 1970 {
+        return None;
+    }
+    let seconds = days_since_epoch(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second;
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+/// Days from 1970-01-01 to the given civil date (Howard Hinnant's algorithm).
+fn days_since_epoch(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.insert(*name, HeaderValue::from_static(value));
+        }
+        map
+    }
+
+    #[test]
+    fn huge_server_delays_are_capped_instead_of_panicking() {
+        let policy = RetryPolicy::new(3, 10.0).unwrap();
+        let huge = [
+            headers(&[("retry-after", "1e20")]),
+            headers(&[("retry-after-ms", "1e25")]),
+            headers(&[("x-ratelimit-remaining-requests", "0"), ("x-ratelimit-reset-requests", "1e20")]),
+        ];
+        for headers in &huge {
+            assert_eq!(server_delay(StatusCode::TOO_MANY_REQUESTS, headers), Some(Duration::MAX));
+            assert_eq!(policy.delay(StatusCode::TOO_MANY_REQUESTS, headers, 0), Duration::from_secs(10));
+        }
+        let negative = headers(&[("retry-after", "-1")]);
+        assert_eq!(server_delay(StatusCode::SERVICE_UNAVAILABLE, &negative), None);
+    }
+}
@@ -0,0 +1,229 @@
+use crate::messages::Messages;
+use crate::response::SecureResponse;
+use crate::{SecureBytes, SecureClient};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::collections::HashMap;
+
+// --- Multi-Provider Router ---
+
+/// Several named `SecureClient` profiles behind one `chat_completion`, picked by model prefix.
+///
+/// `profiles` maps a name to the `SecureClient` arguments for it (`base_url`, `api_key` and any
+/// keyword such as `path_style` or `default_headers`). `routes` maps model-name prefixes to
+/// profile names: `model="azure:gpt-4o"` with `{"azure:": "azure"}` goes to the `azure`
+/// profile as `gpt-4o`. Models matching no route go to `default_profile`.
+///
+/// With `provider_prefixes=True`, a model may also name its profile litellm-style, as
+/// `"groq/llama-3.3-70b"`: everything up to the first `/` must then be a profile name, and
+/// the rest is the model sent. Names the provider itself spells with a slash keep it after
+/// the prefix (`"together/meta-llama/Llama-3.3-70B-Instruct-Turbo"`).
+#[pyclass(name = "SecureClientRouter")]
+pub(crate) struct SecureClientRouter {
+    profiles: HashMap<String, Py<SecureClient>>,
+    /// Longest prefix first, so `"az:eu:"` wins over `"az:"`.
+    routes: Vec<(String, String)>,
+    default_profile: Option<String>,
+    provider_prefixes: bool,
+}
+
+impl SecureClientRouter {
+    /// Picks the profile for `model` and strips the matched prefix from it.
+    fn route(&self, model: Option<String>) -> PyResult<(&str, Option<String>)> {
+        if let Some(model) = &model {
+            if let Some((prefix, profile)) = self.routes.iter().find(|(prefix, _)| model.starts_with(prefix.as_str())) {
+                return Ok((profile, Some(model[prefix.len()..].to_string())));
+            }
+            if let Some((prefix, rest)) = model.split_once('/').filter(|_| self.provider_prefixes) {
+                let Some((profile, _)) = self.profiles.get_key_value(prefix) else {
+                    return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                        "Unknown provider prefix '{}/' in model '{}': configured providers are {}",
+                        prefix,
+                        model,
+                        self.profile_list()
+                    )));
+                };
+                if rest.is_empty() {
+                    return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                        "model '{}' names provider '{}' but no model after it",
+                        model, prefix
+                    )));
+                }
+                return Ok((profile, Some(rest.to_string())));
+            }
+        }
+        let Some(profile) = &self.default_profile else {
+            let prefixes = self.routes.iter().map(|(prefix, _)| format!("'{}'", prefix)).collect::<Vec<_>>().join(", ");
+            let model = model.map_or_else(|| "no model".to_string(), |m| format!("model '{}'", m));
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "No route for {} and no default_profile is set (configured prefixes: {})",
+                model, prefixes
+            )));
+        };
+        Ok((profile, model))
+    }
+
+    /// The profile names, sorted and quoted for an error message.
+    fn profile_list(&self) -> String {
+        self.profiles().iter().map(|name| format!("'{}'", name)).collect::<Vec<_>>().join(", ")
+    }
+
+    fn known_profile(&self, name: &str, what: &str) -> PyResult<()> {
+        if self.profiles.contains_key(name) {
+            return Ok(());
+        }
+        Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{} refers to unknown profile '{}'", what, name)))
+    }
+}
+
+#[pymethods]
+impl SecureClientRouter {
+    #[new]
+    #[pyo3(signature = (profiles, routes=None, *, default_profile=None, provider_prefixes=false))]
+    fn new(
+        py: Python<'_>,
+        profiles: &Bound<'_, PyDict>,
+        routes: Option<HashMap<String, String>>,
+        default_profile: Option<String>,
+        provider_prefixes: bool,
+    ) -> PyResult<Self> {
+        if profiles.is_empty() {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("at least one profile is required"));
+        }
+        let mut clients = HashMap::new();
+        for (name, config) in profiles.iter() {
+            let name: String = name.extract()?;
+            let config = config.downcast::<PyDict>().map_err(|_| {
+                PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!("profile '{}' must be a dict of SecureClient arguments", name))
+            })?;
+            // Work on a copy: the caller's dict keeps its keys.
+            let kwargs = config.copy()?;
+            let required = |key: &str| -> PyResult<Bound<'_, PyAny>> {
+                let value = kwargs.get_item(key)?.ok_or_else(|| {
+                    PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("profile '{}' is missing '{}'", name, key))
+                })?;
+                kwargs.del_item(key)?;
+                Ok(value)
+            };
+            let base_url = SecureBytes::from_py(&required("base_url")?, "base_url")?;
+            let api_key = SecureBytes::from_py(&required("api_key")?, "api_key")?;
+            let client = py
+                .get_type::<SecureClient>()
+                .call((base_url, api_key), Some(&kwargs))?
+                .downcast_into::<SecureClient>()?
+                .unbind();
+            clients.insert(name, client);
+        }
+
+        let mut routes: Vec<(String, String)> = routes.unwrap_or_default().into_iter().collect();
+        routes.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+        let router = Self { profiles: clients, routes, default_profile, provider_prefixes };
+        for (prefix, profile) in &router.routes {
+            if prefix.is_empty() {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                    "route prefixes must not be empty; use default_profile instead",
+                ));
+            }
+            router.known_profile(profile, &format!("route '{}'", prefix))?;
+        }
+        if let Some(profile) = &router.default_profile {
+            router.known_profile(profile, "default_profile")?;
+        }
+        Ok(router)
+    }
+
+    /// Names of the configured profiles, sorted.
+    #[getter]
+    fn profiles(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.profiles.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Returns the `SecureClient` behind a profile, e.g. to rotate its key or read `last_request_id()`.
+    fn client(&self, py: Python<'_>, name: &str) -> PyResult<Py<SecureClient>> {
+        self.known_profile(name, "client()")?;
+        Ok(self.profiles[name].clone_ref(py))
+    }
+
+    /// Returns `(profile, model)` as `chat_completion` would send it, without sending anything.
+    #[pyo3(signature = (model=None))]
+    fn resolve(&self, model: Option<String>) -> PyResult<(String, Option<String>)> {
+        let (profile, model) = self.route(model)?;
+        Ok((profile.to_string(), model))
+    }
+
+    /// Installs (or with `None`, removes) the same audit hook on every profile's client.
+    fn set_audit_hook(&self, py: Python<'_>, hook: Option<Bound<'_, PyAny>>) -> PyResult<()> {
+        for client in self.profiles.values() {
+            client.borrow(py).set_audit_hook(hook.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Closes every profile's client.
+    fn close(&self, py: Python<'_>) {
+        for client in self.profiles.values() {
+            client.borrow(py).close();
+        }
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    #[pyo3(signature = (_exc_type=None, _exc_value=None, _traceback=None))]
+    fn __exit__(
+        &self,
+        py: Python<'_>,
+        _exc_type: Option<&Bound<'_, PyAny>>,
+        _exc_value: Option<&Bound<'_, PyAny>>,
+        _traceback: Option<&Bound<'_, PyAny>>,
+    ) -> bool {
+        self.close(py);
+        false
+    }
+
+    /// Routes by `model` and otherwise behaves exactly like `SecureClient.chat_completion`.
+    /// Without a `model`, the default profile's `default_model` is used.
+    #[pyo3(signature = (messages, model=None, *, idempotency_key=None, extra_headers=None, timeout=None, strict=false, stream=false, **params))]
+    #[allow(clippy::too_many_arguments)]
+    fn chat_completion(
+        &self,
+        py: Python<'_>,
+        messages: Messages<'_>,
+        model: Option<String>,
+        idempotency_key: Option<String>,
+        extra_headers: Option<&Bound<'_, PyDict>>,
+        timeout: Option<f64>,
+        strict: bool,
+        stream: bool,
+        params: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Py<SecureBytes>> {
+        let (profile, model) = self.route(model)?;
+        self.profiles[profile]
+            .borrow(py)
+            .chat_completion(py, messages, model, idempotency_key, extra_headers, timeout, strict, stream, params)
+    }
+
+    /// Routes by `model` like `chat_completion` and returns a `SecureResponse`.
+    #[pyo3(signature = (messages, model=None, *, idempotency_key=None, extra_headers=None, timeout=None, strict=false, stream=false, **params))]
+    #[allow(clippy::too_many_arguments)]
+    fn chat_completion_full(
+        &self,
+        py: Python<'_>,
+        messages: Messages<'_>,
+        model: Option<String>,
+        idempotency_key: Option<String>,
+        extra_headers: Option<&Bound<'_, PyDict>>,
+        timeout: Option<f64>,
+        strict: bool,
+        stream: bool,
+        params: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<SecureResponse> {
+        let (profile, model) = self.route(model)?;
+        self.profiles[profile]
+            .borrow(py)
+            .chat_completion_full(py, messages, model, idempotency_key, extra_headers, timeout, strict, stream, params)
+    }
+}
@@ -0,0 +1,116 @@
+use crate::SecureBytes;
+use libsodium_sys::randombytes_buf;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyTuple, PyType};
+use std::ffi::c_void;
+use std::path::PathBuf;
+use zeroize::Zeroizing;
+
+// --- Secret Sharing ---
+
+/// Share layout: `MAGIC`, a version byte, the scheme, the threshold `k`, the share count
+/// `n`, this share's 1-based index, an id common to every share of one split, then the
+/// share data, as long as the secret. The scheme byte leaves room for a threshold scheme
+/// (Shamir) next to all-of-n XOR sharing.
+const MAGIC: &[u8; 6] = b"SOASHR";
+const VERSION: u8 = 1;
+const SCHEME_XOR: u8 = 1;
+const SET_ID_BYTES: usize = 8;
+const HEADER_BYTES: usize = MAGIC.len() + 5 + SET_ID_BYTES;
+
+fn invalid(message: impl Into<String>) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyValueError, _>(message.into())
+}
+
+/// Splits `secret` into `n` shares of which `k` rebuild it. Only `k == n` is supported for
+/// now: `n - 1` shares are random pads and the last is the secret XORed with all of them, so
+/// any `n - 1` shares together are independent of the secret. The shares are wiped when
+/// dropped, as any full set of them is the secret.
+pub(crate) fn split(secret: &[u8], n: usize, k: usize) -> PyResult<Vec<Zeroizing<Vec<u8>>>> {
+    if !(2..=255).contains(&n) {
+        return Err(invalid("n must be between 2 and 255"));
+    }
+    if k != n {
+        return Err(PyErr::new::<pyo3::exceptions::PyNotImplementedError, _>(
+            "only k == n (every share needed) is supported so far",
+        ));
+    }
+    let mut set_id = [0u8; SET_ID_BYTES];
+    unsafe { randombytes_buf(set_id.as_mut_ptr() as *mut c_void, set_id.len()) };
+    let mut shares: Vec<Zeroizing<Vec<u8>>> = (1..=n)
+        .map(|index| {
+            // Allocated at full size up front, so no reallocation leaves a copy behind.
+            let mut share = Zeroizing::new(Vec::with_capacity(HEADER_BYTES + secret.len()));
+            share.extend_from_slice(MAGIC);
+            share.extend_from_slice(&[VERSION, SCHEME_XOR, k as u8, n as u8, index as u8]);
+            share.extend_from_slice(&set_id);
+            share.resize(HEADER_BYTES + secret.len(), 0);
+            share
+        })
+        .collect();
+    let (pads, last) = shares.split_at_mut(n - 1);
+    let last = &mut last[0][HEADER_BYTES..];
+    // The pads are folded in first and the secret last, so the share never holds a plain copy.
+    for pad in pads {
+        let pad = &mut pad[HEADER_BYTES..];
+        unsafe { randombytes_buf(pad.as_mut_ptr() as *mut c_void, pad.len()) };
+        last.iter_mut().zip(pad.iter()).for_each(|(byte, pad)| *byte ^= pad);
+    }
+    last.iter_mut().zip(secret).for_each(|(byte, secret)| *byte ^= secret);
+    Ok(shares)
+}
+
+/// Rebuilds a secret from the shares of one `split`, XORing them straight into locked memory.
+pub(crate) fn combine(shares: &[&[u8]]) -> PyResult<SecureBytes> {
+    let first = *shares.first().ok_or_else(|| invalid("no shares given"))?;
+    let header = |share: &[u8]| -> PyResult<[u8; HEADER_BYTES]> {
+        let header: [u8; HEADER_BYTES] = share
+            .get(..HEADER_BYTES)
+            .and_then(|header| header.try_into().ok())
+            .filter(|header: &[u8; HEADER_BYTES]| header.starts_with(MAGIC))
+            .ok_or_else(|| invalid("not a secret share"))?;
+        if header[MAGIC.len()] != VERSION || header[MAGIC.len() + 1] != SCHEME_XOR {
+            return Err(invalid("unsupported share version or scheme"));
+        }
+        Ok(header)
+    };
+    let expected = header(first)?;
+    let (k, n) = (expected[MAGIC.len() + 2] as usize, expected[MAGIC.len() + 3] as usize);
+    let mut seen = vec![false; n + 1];
+    for share in shares {
+        let mut header = header(share)?;
+        let index = std::mem::replace(&mut header[MAGIC.len() + 4], expected[MAGIC.len() + 4]) as usize;
+        if header != expected || share.len() != first.len() || index == 0 || index > n {
+            return Err(invalid("shares do not come from the same split"));
+        }
+        if std::mem::replace(&mut seen[index], true) {
+            return Err(invalid(format!("share {} of {} was given twice", index, n)));
+        }
+    }
+    if shares.len() < k {
+        return Err(invalid(format!("need {} of {} shares, got {}", k, n, shares.len())));
+    }
+    let mut secret = SecureBytes::zeroed(first.len() - HEADER_BYTES)?;
+    for share in shares {
+        secret.bytes_mut().iter_mut().zip(&share[HEADER_BYTES..]).for_each(|(byte, share)| *byte ^= share);
+    }
+    Ok(secret)
+}
+
+/// Reads share files and combines them; the file contents are wiped once combined.
+pub(crate) fn load(paths: &[PathBuf]) -> PyResult<SecureBytes> {
+    let files = paths.iter().map(|path| std::fs::read(path).map(Zeroizing::new)).collect::<std::io::Result<Vec<_>>>()?;
+    combine(&files.iter().map(|file| file.as_slice()).collect::<Vec<_>>())
+}
+
+/// `from_shares()` of both client classes: the combined key is passed to `cls` as `api_key`
+/// along with `kwargs`, and only ever exists in locked memory.
+pub(crate) fn build_client<'py>(
+    cls: &Bound<'py, PyType>,
+    base_url: &Bound<'py, PyAny>,
+    share_paths: Vec<PathBuf>,
+    kwargs: Option<&Bound<'py, PyDict>>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let api_key = Bound::new(cls.py(), load(&share_paths)?)?;
+    cls.call(PyTuple::new(cls.py(), [base_url.clone(), api_key.into_any()])?, kwargs)
+}
@@ -0,0 +1,165 @@
+use crate::audit::hex;
+use crate::headers;
+use crate::SecureBytes;
+use libsodium_sys::{
+    crypto_auth_hmacsha256_final, crypto_auth_hmacsha256_init, crypto_auth_hmacsha256_state, crypto_auth_hmacsha256_update,
+    crypto_generichash, crypto_auth_hmacsha256_BYTES,
+};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// --- Request Signing ---
+
+/// What a signer sees of a request. The body is only there as its unkeyed 32-byte BLAKE2b
+/// hash in hex, and `headers` leaves out every sensitive value, the credentials included.
+/// `path` is relative to the base URL, which stays secret.
+pub(crate) struct SigningRequest<'a> {
+    pub(crate) method: &'a str,
+    pub(crate) path: &'a str,
+    pub(crate) headers: &'a HeaderMap,
+    pub(crate) body_hash: String,
+}
+
+impl<'a> SigningRequest<'a> {
+    pub(crate) fn new(method: &'a str, path: &'a str, headers: &'a HeaderMap, body: &[u8]) -> Self {
+        let mut hash = [0u8; 32];
+        unsafe {
+            crypto_generichash(hash.as_mut_ptr(), hash.len(), body.as_ptr(), body.len() as u64, std::ptr::null(), 0);
+        }
+        Self { method, path, headers, body_hash: hex(&hash) }
+    }
+
+    /// The value of a non-sensitive header, if it is set and printable.
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).filter(|value| !value.is_sensitive()).and_then(|value| value.to_str().ok())
+    }
+}
+
+/// Computes the headers that authenticate a request to a gateway, in addition to (or, for
+/// schemes such as SigV4, in place of) the API key. Called once per call, before the first
+/// attempt; retries send the same signature.
+pub(crate) trait RequestSigner: Send + Sync {
+    fn sign(&self, py: Python<'_>, request: &SigningRequest<'_>) -> PyResult<Vec<(HeaderName, HeaderValue)>>;
+}
+
+/// `signer=` as passed to the constructor: an `HmacSigner` or any callable.
+pub(crate) fn from_py(signer: &Bound<'_, PyAny>) -> PyResult<Box<dyn RequestSigner>> {
+    if let Ok(hmac) = signer.downcast::<HmacSigner>() {
+        return Ok(Box::new(hmac.borrow().copy()?));
+    }
+    if signer.is_callable() {
+        return Ok(Box::new(PythonSigner(signer.clone().unbind())));
+    }
+    Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>("signer must be an HmacSigner, a callable or None"))
+}
+
+/// A Python callable, called as `signer(method, path, headers, body_hash)` with the
+/// non-sensitive headers as a dict. It returns a dict of headers to add, whose values may
+/// be str, bytes or `SecureBytes`; the managed headers cannot be set this way.
+struct PythonSigner(Py<PyAny>);
+
+impl RequestSigner for PythonSigner {
+    fn sign(&self, py: Python<'_>, request: &SigningRequest<'_>) -> PyResult<Vec<(HeaderName, HeaderValue)>> {
+        let visible = PyDict::new(py);
+        for name in request.headers.keys() {
+            if let Some(value) = request.header(name.as_str()) {
+                visible.set_item(name.as_str(), value)?;
+            }
+        }
+        let added = self.0.call1(py, (request.method, request.path, visible, &request.body_hash))?;
+        let added = added.bind(py).downcast::<PyDict>().map_err(|_| {
+            PyErr::new::<pyo3::exceptions::PyTypeError, _>("signer must return a dict of headers to add")
+        })?;
+        let mut parsed = HeaderMap::new();
+        headers::insert_all(&mut parsed, Some(added), "signer")?;
+        Ok(parsed.into_iter().filter_map(|(name, value)| Some((name?, value))).collect())
+    }
+}
+
+/// Signs requests with HMAC-SHA256 under `key`. The signed string is the method, path,
+/// Unix timestamp, `name:value` of each of `signed_headers` in the given order (empty for
+/// a header that is not set) and the body hash, joined by newlines. The hex signature is
+/// sent in `header`, the timestamp in `timestamp_header` and, when there are any, the
+/// signed header names, `;`-separated, in `X-Signed-Headers`.
+#[pyclass(name = "HmacSigner", frozen)]
+pub(crate) struct HmacSigner {
+    key: SecureBytes,
+    header: HeaderName,
+    timestamp_header: HeaderName,
+    signed_headers: Vec<HeaderName>,
+}
+
+impl HmacSigner {
+    fn copy(&self) -> PyResult<Self> {
+        Ok(Self {
+            key: SecureBytes::try_new(self.key.expose()?)?,
+            header: self.header.clone(),
+            timestamp_header: self.timestamp_header.clone(),
+            signed_headers: self.signed_headers.clone(),
+        })
+    }
+
+    fn string_to_sign(&self, request: &SigningRequest<'_>, timestamp: u64) -> String {
+        let mut lines = vec![request.method.to_string(), request.path.to_string(), timestamp.to_string()];
+        for name in &self.signed_headers {
+            lines.push(format!("{}:{}", name, request.header(name.as_str()).unwrap_or("")));
+        }
+        lines.push(request.body_hash.clone());
+        lines.join("\n")
+    }
+}
+
+#[pymethods]
+impl HmacSigner {
+    #[new]
+    #[pyo3(signature = (key, *, header="X-Signature", timestamp_header="X-Signature-Timestamp", signed_headers=Vec::new()))]
+    fn new(key: PyRef<'_, SecureBytes>, header: &str, timestamp_header: &str, signed_headers: Vec<String>) -> PyResult<Self> {
+        if key.expose()?.is_empty() {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("HmacSigner key must not be empty"));
+        }
+        let name = |name: &str| {
+            HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid header name in HmacSigner: '{}'", name)))
+        };
+        Ok(Self {
+            key: SecureBytes::try_new(key.expose()?)?,
+            header: name(header)?,
+            timestamp_header: name(timestamp_header)?,
+            signed_headers: signed_headers.iter().map(|header| name(header)).collect::<PyResult<_>>()?,
+        })
+    }
+
+    fn __repr__(&self) -> String {
+        format!("HmacSigner(header='{}')", self.header)
+    }
+}
+
+impl RequestSigner for HmacSigner {
+    fn sign(&self, _py: Python<'_>, request: &SigningRequest<'_>) -> PyResult<Vec<(HeaderName, HeaderValue)>> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let message = self.string_to_sign(request, timestamp);
+        let key = self.key.expose()?;
+        let mut mac = [0u8; crypto_auth_hmacsha256_BYTES as usize];
+        unsafe {
+            // Plain integers and bytes, for which all zeroes is a valid (unused) state.
+            let mut state: crypto_auth_hmacsha256_state = std::mem::zeroed();
+            crypto_auth_hmacsha256_init(&mut state, key.as_ptr(), key.len());
+            crypto_auth_hmacsha256_update(&mut state, message.as_ptr(), message.len() as u64);
+            crypto_auth_hmacsha256_final(&mut state, mac.as_mut_ptr());
+        }
+        let mut signature = HeaderValue::from_str(&hex(&mac)).expect("hex is a valid header value");
+        signature.set_sensitive(true);
+        let mut headers = vec![
+            (self.header.clone(), signature),
+            (self.timestamp_header.clone(), HeaderValue::from(timestamp)),
+        ];
+        if !self.signed_headers.is_empty() {
+            let names = self.signed_headers.iter().map(HeaderName::as_str).collect::<Vec<_>>().join(";");
+            let names = HeaderValue::from_str(&names).expect("header names are valid values");
+            headers.push((HeaderName::from_static("x-signed-headers"), names));
+        }
+        Ok(headers)
+    }
+}
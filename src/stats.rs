@@ -0,0 +1,43 @@
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// --- Client Statistics ---
+
+/// Counters shared by a client and its `with_defaults()` views, updated from whichever
+/// thread or task sent the request. Only counts, sizes and durations are kept.
+#[derive(Default)]
+pub(crate) struct ClientStats {
+    requests: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    network_nanos: AtomicU64,
+    /// Calls retried with a fresh token after a 401; see `token`.
+    token_refreshes: AtomicU64,
+}
+
+impl ClientStats {
+    /// Records one HTTP exchange: body sizes in bytes and the time from sending the
+    /// request to having read the whole response.
+    pub(crate) fn record(&self, sent: usize, received: usize, elapsed: Duration) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(sent as u64, Ordering::Relaxed);
+        self.bytes_received.fetch_add(received as u64, Ordering::Relaxed);
+        self.network_nanos.fetch_add(elapsed.as_nanos().min(u64::MAX as u128) as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_token_refresh(&self) {
+        self.token_refreshes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        dict.set_item("requests", self.requests.load(Ordering::Relaxed))?;
+        dict.set_item("bytes_sent", self.bytes_sent.load(Ordering::Relaxed))?;
+        dict.set_item("bytes_received", self.bytes_received.load(Ordering::Relaxed))?;
+        dict.set_item("network_time", Duration::from_nanos(self.network_nanos.load(Ordering::Relaxed)).as_secs_f64())?;
+        dict.set_item("token_refreshes", self.token_refreshes.load(Ordering::Relaxed))?;
+        Ok(dict)
+    }
+}
@@ -0,0 +1,223 @@
+use crate::anthropic;
+use crate::providers::{Api, StreamUsage};
+use crate::tool_calls::{append, SecureToolCallDelta, ToolCallAccumulator};
+use crate::{Annotation, ChatCompletionResponse, Logprobs, ResponseChoice, ResponseMessage, SecureBytes, TokenLogprob, Usage};
+use pyo3::prelude::*;
+use serde::Deserialize;
+use zeroize::{Zeroize, Zeroizing};
+
+// --- Streamed Responses ---
+
+/// One `data:` event of a streamed chat completion.
+#[derive(Deserialize)]
+struct ChatCompletionChunk {
+    id: Option<String>,
+    model: Option<String>,
+    created: Option<u64>,
+    system_fingerprint: Option<String>,
+    #[serde(default)]
+    choices: Vec<ChunkChoice>,
+    usage: Option<Usage>,
+    x_groq: Option<GroqExtension>,
+    error: Option<StreamError>,
+}
+
+/// Groq's extension object, which holds the usage on the last chunk of a stream.
+#[derive(Deserialize, Debug)]
+struct GroqExtension {
+    usage: Option<Usage>,
+}
+
+/// An error the server reported after the stream had already started. Ollama sends it as
+/// a plain string.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum StreamError {
+    Detailed { message: Option<String> },
+    Message(String),
+}
+
+#[derive(Deserialize)]
+struct ChunkChoice {
+    #[serde(default)]
+    index: usize,
+    delta: Option<ChunkDelta>,
+    finish_reason: Option<String>,
+    logprobs: Option<Logprobs>,
+}
+
+#[derive(Deserialize)]
+struct ChunkDelta {
+    #[serde(default, deserialize_with = "crate::text_content")]
+    content: Option<String>,
+    refusal: Option<String>,
+    tool_calls: Option<Vec<SecureToolCallDelta>>,
+    annotations: Option<Vec<Annotation>>,
+}
+
+/// Rebuilds the response a non-streaming request would have returned from the
+/// server-sent events of a streamed one. Only the first choice is kept, as elsewhere.
+#[derive(Default)]
+struct StreamAssembler {
+    id: Option<String>,
+    model: Option<String>,
+    created: Option<u64>,
+    system_fingerprint: Option<String>,
+    content: Option<Zeroizing<String>>,
+    refusal: Option<Zeroizing<String>>,
+    tool_calls: ToolCallAccumulator,
+    annotations: Option<Vec<Annotation>>,
+    finish_reason: Option<String>,
+    logprobs: Option<Vec<TokenLogprob>>,
+    usage: Option<Usage>,
+    saw_choice: bool,
+}
+
+impl StreamAssembler {
+    fn push(&mut self, chunk: ChatCompletionChunk, stream_usage: StreamUsage) {
+        self.id = self.id.take().or(chunk.id);
+        self.model = self.model.take().or(chunk.model);
+        self.created = self.created.or(chunk.created);
+        self.system_fingerprint = self.system_fingerprint.take().or(chunk.system_fingerprint);
+        // With `stream_options.include_usage` the last chunk has the usage and no choices.
+        let usage = match stream_usage {
+            StreamUsage::TopLevel => chunk.usage,
+            StreamUsage::XGroq => chunk.x_groq.and_then(|extension| extension.usage).or(chunk.usage),
+        };
+        if usage.is_some() {
+            self.usage = usage;
+        }
+        for choice in chunk.choices.into_iter().filter(|choice| choice.index == 0) {
+            self.saw_choice = true;
+            if let Some(delta) = choice.delta {
+                if let Some(content) = delta.content {
+                    append(self.content.get_or_insert_with(Default::default), &content);
+                }
+                if let Some(refusal) = delta.refusal {
+                    append(self.refusal.get_or_insert_with(Default::default), &refusal);
+                }
+                for tool_call in delta.tool_calls.into_iter().flatten() {
+                    self.tool_calls.push(tool_call);
+                }
+                if let Some(annotations) = delta.annotations {
+                    self.annotations.get_or_insert_with(Vec::new).extend(annotations);
+                }
+            }
+            if let Some(tokens) = choice.logprobs.and_then(|logprobs| logprobs.content) {
+                self.logprobs.get_or_insert_with(Vec::new).extend(tokens);
+            }
+            if choice.finish_reason.is_some() {
+                self.finish_reason = choice.finish_reason;
+            }
+        }
+    }
+
+    fn finish(self) -> ChatCompletionResponse {
+        let take = |text: Option<Zeroizing<String>>| text.map(|mut text| std::mem::take(&mut *text));
+        let choices = if self.saw_choice {
+            let tool_calls = (!self.tool_calls.is_empty()).then(|| self.tool_calls.finish());
+            vec![ResponseChoice {
+                message: ResponseMessage {
+                    content: take(self.content),
+                    refusal: take(self.refusal),
+                    tool_calls,
+                    annotations: self.annotations,
+                },
+                finish_reason: self.finish_reason,
+                logprobs: self.logprobs.map(|content| Logprobs { content: Some(content) }),
+            }]
+        } else {
+            Vec::new()
+        };
+        ChatCompletionResponse {
+            id: self.id,
+            model: self.model,
+            created: self.created,
+            system_fingerprint: self.system_fingerprint,
+            choices,
+            usage: self.usage,
+        }
+    }
+}
+
+/// Parses a complete `text/event-stream` body. Events other than `data:` lines, and the
+/// closing `data: [DONE]`, are skipped. `stream_usage` says where the provider puts the usage.
+pub(crate) fn parse_events(body: &[u8], request_id: &str, stream_usage: StreamUsage) -> PyResult<ChatCompletionResponse> {
+    let mut assembler = StreamAssembler::default();
+    for line in body.split(|&b| b == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let Some(data) = line.strip_prefix(b"data:") else {
+            continue;
+        };
+        let data = data.strip_prefix(b" ").unwrap_or(data);
+        if data == b"[DONE]" {
+            break;
+        }
+        let chunk = serde_json::from_slice::<ChatCompletionChunk>(data).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Failed to parse streamed chunk: {}", e))
+        })?;
+        if let Some(error) = chunk.error {
+            return Err(PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+                "API reported an error mid-stream (request id {}): {}",
+                request_id,
+                match &error {
+                    StreamError::Detailed { message } => message.as_deref().unwrap_or("no message"),
+                    StreamError::Message(message) => message,
+                }
+            )));
+        }
+        assembler.push(chunk, stream_usage);
+    }
+    Ok(assembler.finish())
+}
+
+/// Finds the text deltas of a streamed response while its body is still arriving, for
+/// `stream_chat_with_events`. Each `scan` looks at the lines completed since the last one;
+/// anything that doesn't parse is left for `parse_events` to report once the body is in.
+pub(crate) struct DeltaScanner {
+    api: Api,
+    scanned: usize,
+}
+
+impl DeltaScanner {
+    pub(crate) fn new(api: Api) -> Self {
+        Self { api, scanned: 0 }
+    }
+
+    /// Calls `emit` with the text of every new complete `data:` line of `body`, and with
+    /// the last line too once the body is `complete`.
+    pub(crate) fn scan(&mut self, body: &[u8], complete: bool, mut emit: impl FnMut(SecureBytes)) {
+        let end = if complete { body.len() } else { body.iter().rposition(|&b| b == b'\n').map_or(0, |index| index + 1) };
+        if end <= self.scanned {
+            return;
+        }
+        for line in body[self.scanned..end].split(|&b| b == b'\n') {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            let Some(data) = line.strip_prefix(b"data:") else {
+                continue;
+            };
+            let data = data.strip_prefix(b" ").unwrap_or(data);
+            let text = match self.api {
+                Api::OpenAi => delta_text(data),
+                Api::Anthropic => anthropic::delta_text(data),
+            };
+            // A delta that cannot be locked under `strict_memory()` is skipped rather than
+            // panicking the request task.
+            if let Some(Ok(token)) = text.filter(|text| !text.is_empty()).map(|text| SecureBytes::try_new(text.as_bytes())) {
+                emit(token);
+            }
+        }
+        self.scanned = end;
+    }
+}
+
+/// The content delta of the first choice in one chat completion chunk.
+fn delta_text(data: &[u8]) -> Option<Zeroizing<String>> {
+    let chunk = serde_json::from_slice::<ChatCompletionChunk>(data).ok()?;
+    let choice = chunk.choices.into_iter().find(|choice| choice.index == 0)?;
+    let mut delta = choice.delta?;
+    if let Some(refusal) = delta.refusal.as_mut() {
+        refusal.zeroize();
+    }
+    delta.content.map(Zeroizing::new)
+}
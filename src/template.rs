@@ -0,0 +1,165 @@
+use crate::{SecureBytes, SecureContentPart, SecureMessage};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyString};
+
+// --- Prompt Templates ---
+
+enum Segment {
+    Literal(String),
+    Placeholder(String),
+}
+
+/// A prompt with `{name}` placeholders, filled in by `render(**values)` with str, bytes or
+/// `SecureBytes` values straight into a locked buffer, so the rendered prompt never exists
+/// as a Python string. Nothing is escaped; `{{` and `}}` stand for literal braces. The
+/// template itself is plain text and must not hold secrets.
+#[pyclass(name = "SecureTemplate", frozen)]
+pub(crate) struct SecureTemplate {
+    segments: Vec<Segment>,
+}
+
+impl SecureTemplate {
+    fn parse(template: &str) -> PyResult<Vec<Segment>> {
+        let invalid = |message: String| PyErr::new::<pyo3::exceptions::PyValueError, _>(message);
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut rest = template;
+        while let Some(index) = rest.find(['{', '}']) {
+            literal.push_str(&rest[..index]);
+            let brace = &rest[index..];
+            if brace.starts_with("{{") || brace.starts_with("}}") {
+                literal.push_str(&brace[..1]);
+                rest = &brace[2..];
+                continue;
+            }
+            if brace.starts_with('}') {
+                return Err(invalid("Single '}' in template; write '}}' for a literal brace".to_string()));
+            }
+            let end = brace.find('}').ok_or_else(|| invalid("Unclosed '{' in template; write '{{' for a literal brace".to_string()))?;
+            let name = &brace[1..end];
+            let valid = name.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_')
+                && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+            if !valid {
+                return Err(invalid(format!("Invalid placeholder '{{{}}}' in template: names are identifiers", name)));
+            }
+            if !literal.is_empty() {
+                segments.push(Segment::Literal(std::mem::take(&mut literal)));
+            }
+            segments.push(Segment::Placeholder(name.to_string()));
+            rest = &brace[end + 1..];
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(segments)
+    }
+
+    fn placeholder_names(&self) -> impl Iterator<Item = &str> {
+        self.segments.iter().filter_map(|segment| match segment {
+            Segment::Placeholder(name) => Some(name.as_str()),
+            Segment::Literal(_) => None,
+        })
+    }
+
+    /// Renders in two passes over the values, one for the length and one to copy, so the
+    /// locked output is allocated once at its final size and nothing is copied elsewhere.
+    fn render_bytes(&self, values: Option<&Bound<'_, PyDict>>) -> PyResult<SecureBytes> {
+        let mut found = Vec::new();
+        for (name, value) in values.into_iter().flat_map(|values| values.iter()) {
+            let name: String = name.extract()?;
+            if !self.placeholder_names().any(|placeholder| placeholder == name) {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "Unexpected value for '{}': the template has no such placeholder",
+                    name
+                )));
+            }
+            found.push((name, value));
+        }
+        let value = |name: &str| {
+            found.iter().find(|(found, _)| found == name).map(|(_, value)| value).ok_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyKeyError, _>(format!("Missing value for placeholder '{}'", name))
+            })
+        };
+        let mut len = 0;
+        for segment in &self.segments {
+            len += match segment {
+                Segment::Literal(text) => text.len(),
+                Segment::Placeholder(name) => with_value_bytes(value(name)?, name, |bytes| Ok(bytes.len()))?,
+            };
+        }
+        let mut rendered = SecureBytes::zeroed(len)?;
+        let mut at = 0;
+        for segment in &self.segments {
+            let out = &mut rendered.bytes_mut()[at..];
+            at += match segment {
+                Segment::Literal(text) => {
+                    out[..text.len()].copy_from_slice(text.as_bytes());
+                    text.len()
+                }
+                Segment::Placeholder(name) => with_value_bytes(value(name)?, name, |bytes| {
+                    out[..bytes.len()].copy_from_slice(bytes);
+                    Ok(bytes.len())
+                })?,
+            };
+        }
+        Ok(rendered)
+    }
+}
+
+/// Calls `f` with the raw bytes of a str, bytes or SecureBytes template value.
+fn with_value_bytes<T>(value: &Bound<'_, PyAny>, name: &str, f: impl FnOnce(&[u8]) -> PyResult<T>) -> PyResult<T> {
+    if let Ok(secure) = value.downcast::<SecureBytes>() {
+        f(secure.borrow().expose()?)
+    } else if let Ok(bytes) = value.downcast::<PyBytes>() {
+        f(bytes.as_bytes())
+    } else if let Ok(text) = value.downcast::<PyString>() {
+        f(text.to_str()?.as_bytes())
+    } else {
+        Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!(
+            "Value for '{}' must be str, bytes or SecureBytes",
+            name
+        )))
+    }
+}
+
+#[pymethods]
+impl SecureTemplate {
+    #[new]
+    fn new(template: &str) -> PyResult<Self> {
+        Ok(Self { segments: Self::parse(template)? })
+    }
+
+    /// The placeholder names, each once, in order of first appearance.
+    #[getter]
+    fn placeholders(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for name in self.placeholder_names() {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    /// Fills in every placeholder. A placeholder without a value raises `KeyError`, and a
+    /// value without a placeholder `ValueError`.
+    #[pyo3(signature = (**values))]
+    fn render(&self, values: Option<&Bound<'_, PyDict>>) -> PyResult<SecureBytes> {
+        self.render_bytes(values)
+    }
+
+    /// `render(**values)` as the single text part of a `SecureMessage` from `role`, a str
+    /// or bytes.
+    #[pyo3(signature = (role, **values))]
+    fn render_message(&self, role: &Bound<'_, PyAny>, values: Option<&Bound<'_, PyDict>>) -> PyResult<SecureMessage> {
+        let role = SecureMessage::role_from_py(role)?;
+        let text = self.render_bytes(values)?;
+        Ok(SecureMessage { role, content: vec![SecureContentPart::Text { text }], tool_call_id: None, name: None })
+    }
+
+    fn __repr__(&self) -> String {
+        let names = self.placeholders().iter().map(|name| format!("'{}'", name)).collect::<Vec<_>>().join(", ");
+        format!("SecureTemplate(placeholders=[{}])", names)
+    }
+}
@@ -0,0 +1,434 @@
+use crate::headers::CREDENTIAL_HEADERS;
+use crate::params;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+// --- Test Server ---
+
+/// How often idle connections check whether the server is stopping.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Largest request body the server accepts, far above any test's.
+const MAX_REQUEST_BYTES: usize = 64 << 20;
+
+enum CannedBody {
+    Full(Vec<u8>),
+    /// Server-sent events, each sent and flushed as its own chunk `delay` after the last.
+    Events { events: Vec<Vec<u8>>, delay: Duration },
+}
+
+struct Canned {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: CannedBody,
+    latency: Option<Duration>,
+}
+
+struct Received {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+struct Shared {
+    queue: Mutex<VecDeque<Canned>>,
+    requests: Mutex<Vec<Received>>,
+    latency: Duration,
+    stopping: AtomicBool,
+}
+
+struct Running {
+    address: SocketAddr,
+    thread: JoinHandle<()>,
+}
+
+/// A local HTTP/1.1 server imitating the chat completions endpoint, for integration tests.
+/// It listens on an ephemeral port on 127.0.0.1 from `start()` to `stop()` (or for the
+/// length of a `with` block), on threads of its own, so tests can run in parallel with a
+/// server each. Responses are served in the order they were added with `add_completion()`,
+/// `add_stream()`, `add_error()` and `add_response()`; once none are left, every request
+/// gets a completion saying `"Hello!"`. `requests` holds what the server received, with the
+/// values of credential headers such as `Authorization` replaced by `"[redacted]"`.
+/// Every response waits `latency` seconds first unless it sets its own.
+#[pyclass(name = "MockOpenAIServer", module = "secure_openaiapi.testing", frozen)]
+pub(crate) struct MockOpenAIServer {
+    shared: Arc<Shared>,
+    running: Mutex<Option<Running>>,
+}
+
+fn seconds(value: f64, name: &str) -> PyResult<Duration> {
+    if !(value.is_finite() && value >= 0.0) {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{} must be a non-negative number of seconds", name)));
+    }
+    Ok(Duration::from_secs_f64(value))
+}
+
+fn header_list(headers: Option<&Bound<'_, PyDict>>) -> PyResult<Vec<(String, String)>> {
+    headers.into_iter().flat_map(|headers| headers.iter()).map(|(name, value)| Ok((name.extract()?, value.extract()?))).collect()
+}
+
+impl MockOpenAIServer {
+    fn add(&self, status: u16, headers: Vec<(String, String)>, body: CannedBody, latency: Option<f64>) -> PyResult<()> {
+        StatusCode::from_u16(status)
+            .map_err(|_| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid status {}", status)))?;
+        let latency = latency.map(|latency| seconds(latency, "latency")).transpose()?;
+        self.shared.queue.lock().unwrap().push_back(Canned { status, headers, body, latency });
+        Ok(())
+    }
+
+    fn address(&self) -> PyResult<SocketAddr> {
+        self.running.lock().unwrap().as_ref().map(|running| running.address).ok_or_else(|| {
+            PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("MockOpenAIServer is not running; call start() first")
+        })
+    }
+}
+
+#[pymethods]
+impl MockOpenAIServer {
+    #[new]
+    #[pyo3(signature = (*, latency=0.0))]
+    fn new(latency: f64) -> PyResult<Self> {
+        let shared = Shared {
+            queue: Mutex::new(VecDeque::new()),
+            requests: Mutex::new(Vec::new()),
+            latency: seconds(latency, "latency")?,
+            stopping: AtomicBool::new(false),
+        };
+        Ok(Self { shared: Arc::new(shared), running: Mutex::new(None) })
+    }
+
+    /// Binds a fresh port and starts serving; returns the server. A stopped server can be
+    /// started again, on a new port.
+    fn start(slf: PyRef<'_, Self>) -> PyResult<PyRef<'_, Self>> {
+        let mut running = slf.running.lock().unwrap();
+        if running.is_some() {
+            return Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("MockOpenAIServer is already running"));
+        }
+        let listener = TcpListener::bind(("127.0.0.1", 0))?;
+        let address = listener.local_addr()?;
+        slf.shared.stopping.store(false, Ordering::SeqCst);
+        let shared = Arc::clone(&slf.shared);
+        let thread = std::thread::Builder::new().name("mock-openai-server".to_string()).spawn(move || serve(listener, shared))?;
+        *running = Some(Running { address, thread });
+        drop(running);
+        Ok(slf)
+    }
+
+    /// Stops accepting connections and closes the open ones once their current response is
+    /// sent. Stopping a server that isn't running is a no-op.
+    fn stop(&self, py: Python<'_>) {
+        let Some(running) = self.running.lock().unwrap().take() else {
+            return;
+        };
+        self.shared.stopping.store(true, Ordering::SeqCst);
+        py.allow_threads(|| {
+            // Wakes the accept loop so it sees the flag.
+            let _ = TcpStream::connect(running.address);
+            let _ = running.thread.join();
+        });
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyResult<PyRef<'_, Self>> {
+        Self::start(slf)
+    }
+
+    #[pyo3(signature = (_exc_type=None, _exc_value=None, _traceback=None))]
+    fn __exit__(
+        &self,
+        py: Python<'_>,
+        _exc_type: Option<&Bound<'_, PyAny>>,
+        _exc_value: Option<&Bound<'_, PyAny>>,
+        _traceback: Option<&Bound<'_, PyAny>>,
+    ) -> bool {
+        self.stop(py);
+        false
+    }
+
+    /// `http://127.0.0.1:<port>`, to pass as a client's `base_url` (with
+    /// `allow_insecure_http=True`).
+    #[getter]
+    fn base_url(&self) -> PyResult<String> {
+        Ok(format!("http://{}", self.address()?))
+    }
+
+    /// The requests received so far, oldest first, each a dict of `method`, `path`,
+    /// `headers` (lowercase names) and the raw `body` bytes.
+    #[getter]
+    fn requests<'py>(&self, py: Python<'py>) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        let requests = self.shared.requests.lock().unwrap();
+        requests
+            .iter()
+            .map(|request| {
+                let dict = PyDict::new(py);
+                dict.set_item("method", &request.method)?;
+                dict.set_item("path", &request.path)?;
+                let headers = PyDict::new(py);
+                for (name, value) in &request.headers {
+                    headers.set_item(name, value)?;
+                }
+                dict.set_item("headers", headers)?;
+                dict.set_item("body", PyBytes::new(py, &request.body))?;
+                Ok(dict)
+            })
+            .collect()
+    }
+
+    /// The body of the `index`th request (by default the latest) parsed as JSON.
+    #[pyo3(signature = (index=-1))]
+    fn json_body<'py>(&self, py: Python<'py>, index: isize) -> PyResult<Bound<'py, PyAny>> {
+        let requests = self.shared.requests.lock().unwrap();
+        let position = if index < 0 { requests.len().checked_sub(index.unsigned_abs()) } else { Some(index as usize) };
+        let request = position
+            .and_then(|position| requests.get(position))
+            .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyIndexError, _>("request index out of range"))?;
+        let body: Value = serde_json::from_slice(&request.body)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("request body is not JSON: {}", e)))?;
+        params::to_py(py, &body)
+    }
+
+    /// Queues a successful completion answering `content`, with `usage` if given.
+    #[pyo3(signature = (content="Hello!", *, model="gpt-test", finish_reason="stop", usage=None, headers=None, latency=None))]
+    fn add_completion(
+        &self,
+        content: &str,
+        model: &str,
+        finish_reason: &str,
+        usage: Option<&Bound<'_, PyDict>>,
+        headers: Option<&Bound<'_, PyDict>>,
+        latency: Option<f64>,
+    ) -> PyResult<()> {
+        let mut body = completion(content, model, finish_reason);
+        if let Some(usage) = usage {
+            body["usage"] = params::to_json(usage)?;
+        }
+        let mut headers = header_list(headers)?;
+        headers.push(("Content-Type".to_string(), "application/json".to_string()));
+        self.add(200, headers, CannedBody::Full(body.to_string().into_bytes()), latency)
+    }
+
+    /// Queues a streamed completion: one server-sent event per token, sent `delay` seconds
+    /// apart, then `finish_reason`, a usage event if `usage` is given, and `[DONE]`.
+    #[pyo3(signature = (tokens, *, model="gpt-test", finish_reason="stop", usage=None, delay=0.0, latency=None))]
+    fn add_stream(
+        &self,
+        tokens: Vec<String>,
+        model: &str,
+        finish_reason: &str,
+        usage: Option<&Bound<'_, PyDict>>,
+        delay: f64,
+        latency: Option<f64>,
+    ) -> PyResult<()> {
+        let chunk = |delta: Value, finish_reason: Option<&str>| {
+            let chunk = json!({
+                "id": "chatcmpl-mock",
+                "object": "chat.completion.chunk",
+                "model": model,
+                "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}],
+            });
+            format!("data: {}\n\n", chunk).into_bytes()
+        };
+        let mut events: Vec<Vec<u8>> = tokens.iter().map(|token| chunk(json!({ "content": token }), None)).collect();
+        events.push(chunk(json!({}), Some(finish_reason)));
+        if let Some(usage) = usage {
+            let usage = json!({"id": "chatcmpl-mock", "object": "chat.completion.chunk", "choices": [], "usage": params::to_json(usage)?});
+            events.push(format!("data: {}\n\n", usage).into_bytes());
+        }
+        events.push(b"data: [DONE]\n\n".to_vec());
+        let headers = vec![("Content-Type".to_string(), "text/event-stream".to_string())];
+        self.add(200, headers, CannedBody::Events { events, delay: seconds(delay, "delay")? }, latency)
+    }
+
+    /// Queues an error response with `status` and an OpenAI error envelope around
+    /// `message`, `type` and `code`, e.g. `add_error(429, headers={"Retry-After": "1"})`.
+    #[pyo3(signature = (status, message="Mock error", *, r#type=None, code=None, headers=None, latency=None))]
+    fn add_error(
+        &self,
+        status: u16,
+        message: &str,
+        r#type: Option<&str>,
+        code: Option<&str>,
+        headers: Option<&Bound<'_, PyDict>>,
+        latency: Option<f64>,
+    ) -> PyResult<()> {
+        let body = json!({"error": {"message": message, "type": r#type, "code": code, "param": null}});
+        let mut headers = header_list(headers)?;
+        headers.push(("Content-Type".to_string(), "application/json".to_string()));
+        self.add(status, headers, CannedBody::Full(body.to_string().into_bytes()), latency)
+    }
+
+    /// Queues a response with exactly `body`, such as malformed JSON or a truncated stream.
+    #[pyo3(signature = (body, *, status=200, headers=None, latency=None))]
+    fn add_response(&self, body: Vec<u8>, status: u16, headers: Option<&Bound<'_, PyDict>>, latency: Option<f64>) -> PyResult<()> {
+        self.add(status, header_list(headers)?, CannedBody::Full(body), latency)
+    }
+
+    fn __repr__(&self) -> String {
+        match self.address() {
+            Ok(address) => format!("MockOpenAIServer(base_url='http://{}')", address),
+            Err(_) => "MockOpenAIServer(stopped)".to_string(),
+        }
+    }
+}
+
+fn completion(content: &str, model: &str, finish_reason: &str) -> Value {
+    json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion",
+        "created": 0,
+        "model": model,
+        "choices": [{"index": 0, "message": {"role": "assistant", "content": content}, "finish_reason": finish_reason}],
+    })
+}
+
+fn serve(listener: TcpListener, shared: Arc<Shared>) {
+    for stream in listener.incoming() {
+        if shared.stopping.load(Ordering::SeqCst) {
+            break;
+        }
+        if let Ok(stream) = stream {
+            let shared = Arc::clone(&shared);
+            // A connection the client dropped mid-response just ends its thread.
+            std::thread::spawn(move || {
+                let _ = handle(stream, &shared);
+            });
+        }
+    }
+}
+
+/// Serves the requests of one keep-alive connection until the client closes it or the
+/// server stops.
+fn handle(stream: TcpStream, shared: &Shared) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+    stream.set_nodelay(true)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    while let Some(request) = read_request(&mut reader, shared)? {
+        let head_only = request.method == "HEAD";
+        shared.requests.lock().unwrap().push(request);
+        let canned = shared.queue.lock().unwrap().pop_front();
+        let canned = canned.unwrap_or_else(|| Canned {
+            status: 200,
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body: CannedBody::Full(completion("Hello!", "gpt-test", "stop").to_string().into_bytes()),
+            latency: None,
+        });
+        respond(&mut writer, canned, head_only, shared)?;
+    }
+    Ok(())
+}
+
+/// Reads up to and including the next `\n`, waiting out read timeouts until the server
+/// stops. `false` at the end of the stream.
+fn read_line(reader: &mut BufReader<TcpStream>, line: &mut Vec<u8>, shared: &Shared) -> std::io::Result<bool> {
+    loop {
+        match reader.read_until(b'\n', line) {
+            Ok(0) => return Ok(false),
+            Ok(_) if line.ends_with(b"\n") => return Ok(true),
+            Ok(_) => return Ok(false),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted) => {
+                if shared.stopping.load(Ordering::SeqCst) {
+                    return Ok(false);
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn read_request(reader: &mut BufReader<TcpStream>, shared: &Shared) -> std::io::Result<Option<Received>> {
+    let invalid = |message: &str| std::io::Error::new(ErrorKind::InvalidData, message.to_string());
+    let mut line = Vec::new();
+    if !read_line(reader, &mut line, shared)? {
+        return Ok(None);
+    }
+    let request_line = String::from_utf8_lossy(&line).trim_end().to_string();
+    let mut parts = request_line.split(' ');
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(invalid("malformed request line"));
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+    let mut headers = Vec::new();
+    let mut length = 0;
+    loop {
+        line.clear();
+        if !read_line(reader, &mut line, shared)? {
+            return Ok(None);
+        }
+        let header = String::from_utf8_lossy(&line).trim_end().to_string();
+        if header.is_empty() {
+            break;
+        }
+        let (name, value) = header.split_once(':').ok_or_else(|| invalid("malformed header"))?;
+        let (name, value) = (name.trim().to_ascii_lowercase(), value.trim().to_string());
+        if name == "content-length" {
+            length = value.parse().map_err(|_| invalid("malformed Content-Length"))?;
+        }
+        let value = if CREDENTIAL_HEADERS.contains(&name.as_str()) { "[redacted]".to_string() } else { value };
+        headers.push((name, value));
+    }
+    if length > MAX_REQUEST_BYTES {
+        return Err(invalid("request body too large"));
+    }
+    let mut body = vec![0; length];
+    let mut read = 0;
+    while read < length {
+        match reader.read(&mut body[read..]) {
+            Ok(0) => return Ok(None),
+            Ok(n) => read += n,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted) => {
+                if shared.stopping.load(Ordering::SeqCst) {
+                    return Ok(None);
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(Some(Received { method, path, headers, body }))
+}
+
+fn respond(out: &mut TcpStream, canned: Canned, head_only: bool, shared: &Shared) -> std::io::Result<()> {
+    std::thread::sleep(canned.latency.unwrap_or(shared.latency));
+    let status = StatusCode::from_u16(canned.status).expect("statuses are checked when added");
+    let mut head = format!("HTTP/1.1 {} {}\r\n", status.as_u16(), status.canonical_reason().unwrap_or("Unknown"));
+    for (name, value) in &canned.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    let has_length = canned.headers.iter().any(|(name, _)| name.eq_ignore_ascii_case("content-length"));
+    match canned.body {
+        CannedBody::Full(body) => {
+            if !has_length {
+                head.push_str(&format!("Content-Length: {}\r\n", body.len()));
+            }
+            head.push_str("\r\n");
+            out.write_all(head.as_bytes())?;
+            if !head_only {
+                out.write_all(&body)?;
+            }
+        }
+        CannedBody::Events { events, delay } => {
+            head.push_str("Transfer-Encoding: chunked\r\n\r\n");
+            out.write_all(head.as_bytes())?;
+            for (i, event) in events.iter().enumerate() {
+                if i > 0 {
+                    std::thread::sleep(delay);
+                }
+                out.write_all(format!("{:x}\r\n", event.len()).as_bytes())?;
+                out.write_all(event)?;
+                out.write_all(b"\r\n")?;
+                out.flush()?;
+            }
+            out.write_all(b"0\r\n\r\n")?;
+        }
+    }
+    out.flush()
+}
@@ -0,0 +1,83 @@
+use crate::api_key::ApiKey;
+use crate::{bearer_header, SecureBytes};
+use pyo3::prelude::*;
+use pyo3::sync::MutexExt;
+use pyo3::types::PyTuple;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// --- Bearer Token Provider ---
+
+/// A token is refreshed once it is this close to expiring, so it cannot run out while a
+/// request is on its way.
+const REFRESH_MARGIN_SECS: f64 = 60.0;
+
+/// Short-lived bearer tokens from a Python callable, for Azure AD and OAuth gateways. The
+/// callable is called with no arguments and returns `(token, expires_at)`: the token as
+/// bytes or `SecureBytes` and its expiry in seconds since the epoch.
+pub(crate) struct TokenProvider {
+    callback: Py<PyAny>,
+    encrypt_at_rest: bool,
+    /// Held across a refresh, so concurrent requests wait for one call of the callback
+    /// rather than each making their own.
+    cached: Mutex<Option<CachedToken>>,
+}
+
+struct CachedToken {
+    token: Arc<ApiKey>,
+    expires_at: f64,
+}
+
+impl TokenProvider {
+    pub(crate) fn new(callback: Bound<'_, PyAny>, encrypt_at_rest: bool) -> PyResult<Self> {
+        if !callback.is_callable() {
+            return Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>("token_provider must be callable"));
+        }
+        Ok(Self { callback: callback.unbind(), encrypt_at_rest, cached: Mutex::new(None) })
+    }
+
+    /// The current token, calling the provider first when there is none yet or it is about
+    /// to expire. Replacing a token drops the client's reference to the old one, which is
+    /// wiped as soon as the last request still using it finishes.
+    pub(crate) fn token(&self, py: Python<'_>) -> PyResult<Arc<ApiKey>> {
+        let mut cached = self.cached.lock_py_attached(py).unwrap_or_else(|poisoned| poisoned.into_inner());
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+        if let Some(cached) = cached.as_ref().filter(|cached| cached.expires_at - REFRESH_MARGIN_SECS > now) {
+            return Ok(Arc::clone(&cached.token));
+        }
+        let (token, expires_at) = self.fetch(py)?;
+        let token = Arc::new(ApiKey::new(token, self.encrypt_at_rest)?);
+        *cached = Some(CachedToken { token: Arc::clone(&token), expires_at });
+        Ok(token)
+    }
+
+    /// Drops `token` from the cache after the server rejected it. A token that was already
+    /// replaced by a concurrent refresh is left alone, so one rejection costs one refresh.
+    pub(crate) fn invalidate(&self, py: Python<'_>, token: &Arc<ApiKey>) {
+        let mut cached = self.cached.lock_py_attached(py).unwrap_or_else(|poisoned| poisoned.into_inner());
+        if cached.as_ref().is_some_and(|cached| Arc::ptr_eq(&cached.token, token)) {
+            *cached = None;
+        }
+    }
+
+    fn fetch(&self, py: Python<'_>) -> PyResult<(SecureBytes, f64)> {
+        let returned = self.callback.call0(py)?;
+        let returned = returned.bind(py);
+        let pair = returned
+            .downcast::<PyTuple>()
+            .ok()
+            .filter(|pair| pair.len() == 2)
+            .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyTypeError, _>("token_provider must return (token, expires_at)"))?;
+        let token = SecureBytes::from_py(&pair.get_item(0)?, "token")?;
+        let expires_at: f64 = pair.get_item(1)?.extract().map_err(|_| {
+            PyErr::new::<pyo3::exceptions::PyTypeError, _>("expires_at must be a number of seconds since the epoch")
+        })?;
+        if token.expose()?.is_empty() {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("token_provider returned an empty token"));
+        }
+        bearer_header(token.expose()?).map_err(|_| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>("token_provider returned a token that is not a valid header value")
+        })?;
+        Ok((token, expires_at))
+    }
+}
@@ -0,0 +1,199 @@
+use crate::json::SecureJsonWriter;
+use crate::SecureBytes;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList, PyString};
+use serde::Deserialize;
+use zeroize::Zeroizing;
+
+// --- Tool Calls ---
+
+/// A complete tool call as it appears in `message.tool_calls`.
+#[derive(Deserialize)]
+pub(crate) struct ResponseToolCall {
+    id: String,
+    #[serde(rename = "type", default = "function_type")]
+    kind: String,
+    function: ResponseFunction,
+}
+
+#[derive(Deserialize)]
+struct ResponseFunction {
+    name: String,
+    arguments: String,
+}
+
+fn function_type() -> String {
+    "function".to_string()
+}
+
+/// A tool call requested by the model. The arguments can carry anything from the
+/// conversation, so they stay in `SecureBytes`; the id and function name are metadata.
+#[pyclass(name = "SecureToolCall", frozen)]
+pub(crate) struct SecureToolCall {
+    #[pyo3(get)]
+    id: String,
+    #[pyo3(get, name = "type")]
+    kind: String,
+    #[pyo3(get)]
+    name: String,
+    #[pyo3(get)]
+    arguments: Py<SecureBytes>,
+}
+
+impl SecureToolCall {
+    pub(crate) fn new(py: Python<'_>, call: ResponseToolCall) -> PyResult<Self> {
+        let arguments = Zeroizing::new(call.function.arguments);
+        Ok(Self {
+            id: call.id,
+            kind: call.kind,
+            name: call.function.name,
+            arguments: Py::new(py, SecureBytes::try_new(arguments.as_bytes())?)?,
+        })
+    }
+}
+
+#[pymethods]
+impl SecureToolCall {
+    fn __repr__(&self) -> String {
+        format!("SecureToolCall(id='{}', name='{}', arguments=SecureBytes(b'****'))", self.id, self.name)
+    }
+}
+
+// --- Tool Results ---
+
+/// Default cap on the result of a tool call sent back to the model, `SecureMessage.tool_result`'s
+/// `max_bytes`: a megabyte is already far more than most context windows hold.
+pub(crate) const MAX_TOOL_RESULT_BYTES: usize = 1 << 20;
+
+/// The id of the call a tool result answers: a `SecureToolCall` or its `id`.
+pub(crate) fn tool_call_id(tool_call: &Bound<'_, PyAny>) -> PyResult<String> {
+    if let Ok(call) = tool_call.downcast::<SecureToolCall>() {
+        Ok(call.get().id.clone())
+    } else if let Ok(id) = tool_call.downcast::<PyString>() {
+        let id = id.to_str()?;
+        if id.is_empty() {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("tool_call id must not be empty"));
+        }
+        Ok(id.to_string())
+    } else {
+        Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>("tool_call must be a SecureToolCall or its id as str"))
+    }
+}
+
+/// The text of a tool result in locked memory: str, bytes and `SecureBytes` as they are,
+/// dicts and lists serialized to JSON by `SecureJsonWriter`. Longer than `max_bytes` is
+/// a `ValueError`, and the serialized JSON is wiped.
+pub(crate) fn result_text(result: &Bound<'_, PyAny>, max_bytes: usize) -> PyResult<SecureBytes> {
+    let too_large = |len: usize| {
+        PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "tool result is {} bytes, over max_bytes={}; trim it or pass a larger max_bytes",
+            len, max_bytes
+        ))
+    };
+    if result.is_instance_of::<PyDict>() || result.is_instance_of::<PyList>() {
+        let mut out = SecureJsonWriter::new()?;
+        out.write_py(result)?;
+        if out.len() > max_bytes {
+            return Err(too_large(out.len()));
+        }
+        return out.into_secure_bytes();
+    }
+    let text = SecureBytes::from_py_text(result, "result")
+        .map_err(|_| PyErr::new::<pyo3::exceptions::PyTypeError, _>("result must be str, bytes, SecureBytes, dict or list"))?;
+    if text.bytes().len() > max_bytes {
+        return Err(too_large(text.bytes().len()));
+    }
+    Ok(text)
+}
+
+// --- Streaming Deltas ---
+
+/// One fragment of a tool call in a streamed chunk. The first fragment for an `index`
+/// carries the id and function name; later ones usually only carry argument text.
+#[derive(Deserialize, Debug)]
+pub(crate) struct SecureToolCallDelta {
+    index: Option<usize>,
+    id: Option<String>,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    function: Option<FunctionDelta>,
+}
+
+#[derive(Deserialize, Debug)]
+struct FunctionDelta {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+
+#[derive(Default)]
+struct PartialToolCall {
+    id: String,
+    kind: Option<String>,
+    name: String,
+    arguments: Zeroizing<String>,
+}
+
+/// Stitches streamed deltas back into complete tool calls. Parallel calls arrive
+/// interleaved and are told apart by `index`.
+#[derive(Default)]
+pub(crate) struct ToolCallAccumulator {
+    calls: Vec<PartialToolCall>,
+}
+
+impl ToolCallAccumulator {
+    pub(crate) fn push(&mut self, delta: SecureToolCallDelta) {
+        let index = match delta.index {
+            Some(index) => index,
+            // Some servers leave out the index: a new id starts a new call, anything else
+            // continues the last one.
+            None => match &delta.id {
+                Some(id) if self.calls.last().is_none_or(|call| &call.id != id) => self.calls.len(),
+                _ => self.calls.len().saturating_sub(1),
+            },
+        };
+        if index >= self.calls.len() {
+            self.calls.resize_with(index + 1, PartialToolCall::default);
+        }
+        let call = &mut self.calls[index];
+        if let Some(id) = delta.id.filter(|_| call.id.is_empty()) {
+            call.id = id;
+        }
+        if call.kind.is_none() {
+            call.kind = delta.kind;
+        }
+        if let Some(function) = delta.function {
+            if let Some(name) = function.name.filter(|_| call.name.is_empty()) {
+                call.name = name;
+            }
+            if let Some(arguments) = function.arguments {
+                append(&mut call.arguments, &arguments);
+            }
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+
+    /// The calls in index order, shaped exactly as the non-streaming response has them.
+    pub(crate) fn finish(self) -> Vec<ResponseToolCall> {
+        self.calls
+            .into_iter()
+            .map(|mut call| ResponseToolCall {
+                id: call.id,
+                kind: call.kind.unwrap_or_else(function_type),
+                function: ResponseFunction { name: call.name, arguments: std::mem::take(&mut *call.arguments) },
+            })
+            .collect()
+    }
+}
+
+/// Appends to a secret string without leaving the old allocation behind unwiped.
+pub(crate) fn append(buffer: &mut Zeroizing<String>, text: &str) {
+    if buffer.capacity() < buffer.len() + text.len() {
+        let mut grown = Zeroizing::new(String::with_capacity((buffer.len() + text.len()).max(2 * buffer.capacity())));
+        grown.push_str(buffer);
+        *buffer = grown;
+    }
+    buffer.push_str(text);
+}
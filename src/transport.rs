@@ -0,0 +1,340 @@
+use crate::cassette::{Recorder, RecordingBody, Replayer};
+use crate::mock::{MockBody, MockTransport};
+use crate::redact;
+use hyper::body::Bytes;
+use pyo3::prelude::*;
+use pyo3::exceptions::PyTimeoutError;
+use pyo3::types::PyBool;
+use reqwest::header::HeaderMap;
+use reqwest::{Client, ClientBuilder, Method, StatusCode, Version};
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+// --- HTTP Transport ---
+
+/// Configures the runtime every transport runs on; it is only started by the first request.
+/// Blocking calls `block_on` it with the GIL released, and `AsyncSecureClient` futures are
+/// driven by it through pyo3-async-runtimes.
+pub(crate) fn configure_runtime() {
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    builder.worker_threads(2).thread_name("secure-openaiapi").enable_all();
+    pyo3_async_runtimes::tokio::init(builder);
+}
+
+pub(crate) fn runtime() -> &'static Runtime {
+    pyo3_async_runtimes::tokio::get_runtime()
+}
+
+/// A fully built request, independent of how it is delivered.
+#[derive(Clone)]
+pub(crate) struct Request {
+    pub(crate) method: Method,
+    pub(crate) path: String,
+    pub(crate) headers: HeaderMap,
+    /// A view of the call's `json::RequestBody`, which wipes it once the call is done;
+    /// clones share the buffer.
+    pub(crate) body: Bytes,
+    /// Overrides the client's timeout for this request, body included.
+    pub(crate) timeout: Option<Duration>,
+}
+
+/// A response whose body has not been read yet.
+pub(crate) struct Response {
+    pub(crate) status: StatusCode,
+    /// The protocol that actually served the response.
+    pub(crate) version: Version,
+    pub(crate) headers: HeaderMap,
+    pub(crate) body: ResponseBody,
+}
+
+impl Response {
+    pub(crate) fn content_length(&self) -> Option<u64> {
+        self.headers
+            .get(reqwest::header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+    }
+}
+
+pub(crate) enum ResponseBody {
+    Http(reqwest::Response),
+    #[cfg(unix)]
+    Unix { body: hyper::body::Incoming, deadline: Option<tokio::time::Instant> },
+    Mock(MockBody),
+    Recording(Box<RecordingBody>),
+}
+
+impl ResponseBody {
+    /// The next chunk of the body, `None` once it is complete.
+    pub(crate) async fn chunk(&mut self) -> std::io::Result<Option<Bytes>> {
+        match self {
+            ResponseBody::Http(response) => response.chunk().await.map_err(|e| {
+                if e.is_timeout() {
+                    std::io::Error::new(std::io::ErrorKind::TimedOut, redact::transport_error(&e))
+                } else {
+                    std::io::Error::other(redact::transport_error(&e))
+                }
+            }),
+            #[cfg(unix)]
+            ResponseBody::Unix { body, deadline } => {
+                use http_body_util::BodyExt;
+                loop {
+                    let frame = match deadline {
+                        Some(deadline) => tokio::time::timeout_at(*deadline, body.frame())
+                            .await
+                            .map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))?,
+                        None => body.frame().await,
+                    };
+                    match frame {
+                        None => return Ok(None),
+                        Some(Ok(frame)) => {
+                            if let Ok(data) = frame.into_data() {
+                                return Ok(Some(data));
+                            }
+                        }
+                        Some(Err(e)) => return Err(std::io::Error::other(e)),
+                    }
+                }
+            }
+            ResponseBody::Mock(body) => body.chunk().await,
+            ResponseBody::Recording(body) => body.chunk().await,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum TransportError {
+    Http(reqwest::Error),
+    #[cfg(unix)]
+    UnixSocket { path: String, error: std::io::Error },
+    #[cfg(unix)]
+    Protocol(hyper::Error),
+    /// The per-request timeout elapsed on a transport without a built-in one.
+    TimedOut,
+    /// The mock transport's handler raised.
+    Mock(PyErr),
+    /// A replayed cassette has no interaction for the request.
+    Cassette(String),
+    /// Under `strict_memory()`, a buffer for a recorded or replayed body could not be locked.
+    Memory(PyErr),
+}
+
+impl TransportError {
+    pub(crate) fn is_timeout(&self) -> bool {
+        match self {
+            TransportError::Http(e) => e.is_timeout(),
+            TransportError::TimedOut => true,
+            // Lets tests of timeout handling raise TimeoutError from the handler.
+            TransportError::Mock(e) => Python::with_gil(|py| e.is_instance_of::<PyTimeoutError>(py)),
+            _ => false,
+        }
+    }
+
+    /// Whether the error happened while connecting, as opposed to on an open connection.
+    pub(crate) fn is_connect(&self) -> bool {
+        matches!(self, TransportError::Http(e) if e.is_connect())
+    }
+}
+
+impl std::fmt::Display for TransportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransportError::Http(e) => f.write_str(&redact::transport_error(e)),
+            #[cfg(unix)]
+            TransportError::UnixSocket { path, error } => match error.kind() {
+                std::io::ErrorKind::NotFound => write!(f, "unix socket {} does not exist", path),
+                std::io::ErrorKind::PermissionDenied => write!(f, "permission denied connecting to unix socket {}", path),
+                std::io::ErrorKind::ConnectionRefused => write!(f, "nothing is listening on unix socket {}", path),
+                _ => write!(f, "failed to connect to unix socket {}: {}", path, error),
+            },
+            #[cfg(unix)]
+            TransportError::Protocol(e) => write!(f, "HTTP error on unix socket: {}", e),
+            TransportError::TimedOut => write!(f, "request timed out"),
+            TransportError::Mock(e) => write!(f, "mock transport handler raised {}", e),
+            TransportError::Cassette(message) => f.write_str(message),
+            TransportError::Memory(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// The `http2` constructor argument: `False` keeps to HTTP/1.1, `True` offers h2 through
+/// TLS ALPN and falls back to HTTP/1.1 when the server doesn't take it (plain http stays on
+/// 1.1), and `"prior-knowledge"` speaks h2 from the first byte, failing against 1.1-only servers.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum Http2 {
+    Off,
+    Negotiate,
+    PriorKnowledge,
+}
+
+impl<'py> FromPyObject<'py> for Http2 {
+    fn extract_bound(value: &Bound<'py, PyAny>) -> PyResult<Self> {
+        if let Ok(enabled) = value.downcast::<PyBool>() {
+            return Ok(if enabled.is_true() { Http2::Negotiate } else { Http2::Off });
+        }
+        match value.extract::<&str>() {
+            Ok("prior-knowledge") => Ok(Http2::PriorKnowledge),
+            _ => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("http2 must be True, False or 'prior-knowledge'")),
+        }
+    }
+}
+
+impl Http2 {
+    pub(crate) fn configure(self, builder: ClientBuilder) -> ClientBuilder {
+        match self {
+            Http2::Off => builder.http1_only(),
+            Http2::Negotiate => builder,
+            Http2::PriorKnowledge => builder.http2_prior_knowledge(),
+        }
+    }
+}
+
+/// How `SecureResponse.http_version` spells the protocol.
+pub(crate) fn version_name(version: Version) -> &'static str {
+    match version {
+        Version::HTTP_09 => "HTTP/0.9",
+        Version::HTTP_10 => "HTTP/1.0",
+        Version::HTTP_11 => "HTTP/1.1",
+        Version::HTTP_2 => "HTTP/2",
+        Version::HTTP_3 => "HTTP/3",
+        _ => "unknown",
+    }
+}
+
+/// How requests reach the server: reqwest over TCP/TLS, or plain HTTP/1.1 over a
+/// unix domain socket for local sidecar gateways (`unix:///path/to.sock` base URLs), or a
+/// Python handler standing in for the server in tests (`with_mock_transport()`). Either of
+/// the first two can be wrapped to record into a cassette, which can then replace them.
+pub(crate) enum Transport {
+    Http(Client),
+    #[cfg(unix)]
+    Unix(unix::UnixTransport),
+    Mock(MockTransport),
+    Record(Recorder),
+    Replay(Replayer),
+}
+
+impl Transport {
+    /// Sends `request` to `request.path` joined onto `base_url`. For unix sockets the base URL is
+    /// the `unix://` socket address and only the path goes on the wire.
+    /// Must run on `runtime()`.
+    pub(crate) async fn send(&self, base_url: &str, request: Request) -> Result<Response, TransportError> {
+        match self {
+            Transport::Http(client) => {
+                let url = join_url(base_url, &request.path);
+                let mut builder = client.request(request.method, &url).headers(request.headers).body(request.body);
+                if let Some(timeout) = request.timeout {
+                    builder = builder.timeout(timeout);
+                }
+                // Connection errors come back without the URL; it is put back so the sanitized
+                // message can still name the scheme and (hashed) host.
+                let response = builder.send().await.map_err(|e| match reqwest::Url::parse(&url) {
+                    Ok(url) if e.url().is_none() => TransportError::Http(e.with_url(url)),
+                    _ => TransportError::Http(e),
+                })?;
+                Ok(Response {
+                    status: response.status(),
+                    version: response.version(),
+                    headers: response.headers().clone(),
+                    body: ResponseBody::Http(response),
+                })
+            }
+            #[cfg(unix)]
+            Transport::Unix(transport) => transport.send(unix_socket_path(base_url), request).await,
+            Transport::Mock(transport) => transport.send(request).await.map_err(TransportError::Mock),
+            Transport::Record(recorder) => recorder.send(base_url, request).await,
+            Transport::Replay(replayer) => replayer.send(&request),
+        }
+    }
+
+    pub(crate) fn is_unix(&self) -> bool {
+        match self {
+            #[cfg(unix)]
+            Transport::Unix(_) => true,
+            Transport::Record(recorder) => recorder.is_unix(),
+            _ => false,
+        }
+    }
+}
+
+pub(crate) const UNIX_SCHEME: &str = "unix://";
+
+/// Joins an endpoint path onto a base URL with exactly one slash between them, however many
+/// either side brings.
+pub(crate) fn join_url(base_url: &str, path: &str) -> String {
+    format!("{}/{}", base_url.trim_end_matches('/'), path.trim_start_matches('/'))
+}
+
+pub(crate) fn is_unix_socket_url(base_url: &[u8]) -> bool {
+    base_url.starts_with(UNIX_SCHEME.as_bytes())
+}
+
+fn unix_socket_path(base_url: &str) -> &str {
+    base_url.strip_prefix(UNIX_SCHEME).unwrap_or(base_url)
+}
+
+#[cfg(unix)]
+pub(crate) mod unix {
+    use super::{Request, Response, ResponseBody, TransportError};
+    use tokio::time::Instant;
+    use http_body_util::Full;
+    use hyper::client::conn::http1;
+    use hyper_util::rt::TokioIo;
+    use reqwest::header::{HeaderValue, CONTENT_LENGTH, HOST};
+    use tokio::net::UnixStream;
+
+    pub(crate) struct UnixTransport {
+        host: HeaderValue,
+    }
+
+    impl UnixTransport {
+        pub(crate) fn new(host: HeaderValue) -> Self {
+            Self { host }
+        }
+
+        /// hyper has no timeouts of its own, so a per-request timeout becomes a deadline that
+        /// covers connecting, the response head and every body frame.
+        pub(crate) async fn send(&self, socket_path: &str, request: Request) -> Result<Response, TransportError> {
+            let Some(timeout) = request.timeout else {
+                return self.exchange(socket_path, request, None).await;
+            };
+            let deadline = Instant::now() + timeout;
+            tokio::time::timeout_at(deadline, self.exchange(socket_path, request, Some(deadline)))
+                .await
+                .map_err(|_| TransportError::TimedOut)?
+        }
+
+        async fn exchange(
+            &self,
+            socket_path: &str,
+            request: Request,
+            deadline: Option<Instant>,
+        ) -> Result<Response, TransportError> {
+            let stream = UnixStream::connect(socket_path)
+                .await
+                .map_err(|error| TransportError::UnixSocket { path: socket_path.to_string(), error })?;
+            let (mut sender, connection) =
+                http1::handshake(TokioIo::new(stream)).await.map_err(TransportError::Protocol)?;
+            tokio::spawn(async move {
+                let _ = connection.await;
+            });
+
+            let content_length = HeaderValue::from(request.body.len());
+            let mut http_request = hyper::Request::new(Full::new(request.body));
+            *http_request.method_mut() = request.method;
+            *http_request.uri_mut() = hyper::Uri::try_from(request.path).expect("endpoint paths are built from validated parts");
+            *http_request.headers_mut() = request.headers;
+            http_request.headers_mut().insert(HOST, self.host.clone());
+            http_request.headers_mut().insert(CONTENT_LENGTH, content_length);
+
+            let response = sender.send_request(http_request).await.map_err(TransportError::Protocol)?;
+            let (parts, body) = response.into_parts();
+            Ok(Response {
+                status: parts.status,
+                version: parts.version,
+                headers: parts.headers,
+                body: ResponseBody::Unix { body, deadline },
+            })
+        }
+    }
+}